@@ -9,6 +9,7 @@ use rustdns::clients::tcp::Client as TcpClient;
 use rustdns::clients::udp::Client as UdpClient;
 use rustdns::clients::AsyncExchanger;
 use rustdns::clients::Exchanger;
+use rustdns::clients::ResolvConf;
 use rustdns::types::*;
 use std::env;
 use std::io;
@@ -198,8 +199,18 @@ fn parse_args(args: impl Iterator<Item = String>) -> Result<Args, String> {
         }
     }
 
+    if result.servers.is_empty() && matches!(result.client, Client::Udp | Client::Tcp) {
+        // Use the local servers, if they are configured.
+        if let Ok(conf) = ResolvConf::load() {
+            result.servers = conf
+                .nameservers
+                .iter()
+                .map(|addr| addr.to_string())
+                .collect();
+        }
+    }
+
     if result.servers.is_empty() {
-        // TODO If no servers are provided determine the local server (from /etc/nslookup.conf for example)
         eprintln!(";; No servers specified, using Google's DNS servers");
         match result.client {
             Client::Udp | Client::Tcp => {
@@ -278,9 +289,11 @@ async fn main() -> Result<(), DigError> {
 
     // TODO make all DNS client implement a Exchange trait
     let resp = match args.client {
-        Client::Udp => UdpClient::new(to_sockaddrs(&args.servers, 53)?.as_slice())?
-            .exchange(&query)
-            .expect("could not exchange message"),
+        Client::Udp => Exchanger::exchange(
+            &UdpClient::new(to_sockaddrs(&args.servers, 53)?.as_slice())?,
+            &query,
+        )
+        .expect("could not exchange message"),
 
         Client::Tcp => TcpClient::new(to_sockaddrs(&args.servers, 53)?.as_slice())?
             .exchange(&query)
@@ -0,0 +1,54 @@
+use crate::clients::AsyncExchanger;
+use crate::clients::Exchanger;
+use crate::Message;
+use tokio::runtime::Builder;
+use tokio::runtime::Runtime;
+
+/// Wraps a [`AsyncExchanger`] so it can be used as a [`Exchanger`], by running
+/// each exchange to completion on a current-thread tokio runtime, created once
+/// by [`Blocking::new`] and shared by all exchanges.
+///
+/// This allows the async clients, such as [`doh::Client`](crate::clients::doh::Client),
+/// to be used where a blocking client is needed, for example with a
+/// [`Forwarder`](crate::server::Forwarder). Exchanges may be made from many
+/// threads at once, but must not be made from within another tokio runtime.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rustdns::clients::{doh, Blocking, Exchanger};
+/// use rustdns::types::*;
+///
+/// fn main() -> Result<(), rustdns::Error> {
+///     let client = Blocking::new(doh::Client::new(doh::GOOGLE, Default::default())?)?;
+///
+///     let mut query = Message::default();
+///     query.add_question("bramp.net", Type::A, Class::Internet);
+///
+///     println!("{}", client.exchange(&query)?);
+///     Ok(())
+/// }
+/// ```
+pub struct Blocking<E> {
+    client: E,
+    runtime: Runtime,
+}
+
+impl<E> Blocking<E> {
+    /// Wraps the client, creating a new runtime to run it on.
+    pub fn new(client: E) -> Result<Blocking<E>, crate::Error> {
+        Ok(Blocking {
+            client,
+            runtime: Builder::new_current_thread().enable_all().build()?,
+        })
+    }
+}
+
+impl<E> Exchanger for Blocking<E>
+where
+    E: AsyncExchanger,
+{
+    fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+        self.runtime.block_on(self.client.exchange(query))
+    }
+}
@@ -0,0 +1,603 @@
+use crate::clients::Exchanger;
+use crate::util::find_alias;
+use crate::Class;
+use crate::Extension;
+use crate::ExtensionOption;
+use crate::Message;
+use crate::Rcode;
+use crate::Record;
+use crate::Resource;
+use crate::Type;
+use crate::QR;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The cache key, the (lowercase) name, type and class of a RRset. The type
+/// is None for a NXDOMAIN, which applies to every type of the name.
+type Key = (String, Option<Type>, Class);
+
+/// The TTL given to stale records, as recommended by rfc8767.
+const STALE_TTL: Duration = Duration::from_secs(30);
+
+/// How many times a entry must be used before it is worth prefetching.
+const PREFETCH_HITS: u64 = 2;
+
+/// The most CNAME records followed when caching, or answering from the cache.
+const MAX_CNAMES: usize = 8;
+
+/// A cache of DNS records, that respects their TTLs.
+///
+/// Each RRset in a response's answers is cached by its name, type and class,
+/// until its TTL expires. Queries are answered from the cached RRsets,
+/// following any cached CNAMEs, so the target of a CNAME learnt from one
+/// response also answers queries for it. Records returned from the cache
+/// have their TTL reduced by the time they have spent in the cache.
+///
+/// Only the RRsets for the question's name, or the names it is a alias for,
+/// are cached, so a server can't add records for unrelated names. Records in
+/// the authority and additional sections aren't trusted as answers, see
+/// [rfc2181] section 5.4.1, so aren't cached, other than the SOA of a
+/// negative response.
+///
+/// Negative responses are cached for the SOA's minimum TTL, as described in
+/// [rfc2308]. A NXDOMAIN is cached for the name, and answers queries of any
+/// type, while a NODATA (NOERROR with no answers) only answers queries of the
+/// same type. Negative responses without a SOA are not cached.
+///
+/// Once full, the least recently used RRsets are evicted.
+///
+/// Optionally expired records can be kept, so they can be served when the
+/// upstream servers are unreachable, as described in [rfc8767]. See
+/// [`Cache::with_serve_stale`].
+///
+/// [rfc2181]: https://datatracker.ietf.org/doc/html/rfc2181
+/// [rfc2308]: https://datatracker.ietf.org/doc/html/rfc2308
+/// [rfc8767]: https://datatracker.ietf.org/doc/html/rfc8767
+pub struct Cache {
+    /// The maximum number of RRsets to hold.
+    capacity: usize,
+
+    /// TTLs are clamped between these values.
+    min_ttl: Duration,
+    max_ttl: Duration,
+
+    /// How long expired records are kept, to be served stale.
+    max_stale: Option<Duration>,
+
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<Key, Entry>,
+
+    /// Keys ordered by when they were last used (the smallest being the
+    /// least recently used). Values of `Entry::used`.
+    lru: BTreeMap<u64, Key>,
+
+    /// Incremented each time a entry is used.
+    clock: u64,
+}
+
+enum Data {
+    /// The records of a RRset.
+    Records(Vec<Record>),
+
+    /// The SOA record that says the name (or just the type) doesn't exist.
+    Negative(Record),
+}
+
+struct Entry {
+    /// The data, with its records' TTLs already clamped.
+    data: Data,
+
+    inserted: Instant,
+    expires: Instant,
+
+    /// The value of the clock when this entry was last used.
+    used: u64,
+
+    /// The number of times this entry has been returned.
+    hits: u64,
+
+    /// Set once this entry has been picked to be prefetched.
+    prefetching: bool,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache::new(10_000)
+    }
+}
+
+impl Cache {
+    /// Creates a new Cache holding at most `capacity` RRsets.
+    pub fn new(capacity: usize) -> Cache {
+        Cache {
+            capacity,
+            min_ttl: Duration::default(),
+            max_ttl: Duration::from_secs(86400), // One day
+            max_stale: None,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Sets the minimum time a RRset is cached for, even if its records
+    /// have a lower TTL. Defaults to zero.
+    pub fn with_min_ttl(mut self, ttl: Duration) -> Self {
+        self.min_ttl = ttl;
+        self
+    }
+
+    /// Sets the maximum time a RRset is cached for, even if its records
+    /// have a higher TTL. Defaults to one day.
+    pub fn with_max_ttl(mut self, ttl: Duration) -> Self {
+        self.max_ttl = ttl;
+        self
+    }
+
+    /// Keeps records for up to `max_stale` after they expire, so they can
+    /// be returned by [`Cache::get_stale`]. rfc8767 suggests between one and
+    /// three days. Defaults to off.
+    pub fn with_serve_stale(mut self, max_stale: Duration) -> Self {
+        self.max_stale = Some(max_stale);
+        self
+    }
+
+    /// Returns the number of cached RRsets and negative responses, including
+    /// any expired ones that have not yet been removed.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Returns true if nothing is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes everything from the cache.
+    pub fn clear(&self) {
+        *self.inner.lock().unwrap() = Inner::default();
+    }
+
+    /// Returns the (lowercase) name, type and class of the query's question,
+    /// if it can be answered from the cache.
+    fn question(query: &Message) -> Option<(String, Type, Class)> {
+        match query.questions.as_slice() {
+            // Only single question queries, for a single type, are cached.
+            [q] if !matches!(q.r#type, Type::ANY | Type::AXFR | Type::IXFR | Type::OPT) => {
+                Some((q.name.to_lowercase(), q.r#type, q.class))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the response to the query, built from the cached records, or
+    /// None if they aren't cached (or have expired). The records' TTLs are
+    /// reduced by the time spent in the cache.
+    pub fn get(&self, query: &Message) -> Option<Message> {
+        self.lookup(query, false)
+    }
+
+    /// Returns the response to the query, even if the cached records have
+    /// expired (but are within the `max_stale` set by
+    /// [`Cache::with_serve_stale`]). This should only be used when the
+    /// upstream servers can't be reached.
+    ///
+    /// Expired records have their TTLs set to 30 seconds. If the query has a
+    /// EDNS(0) extension, a Extended DNS Error "Stale Answer" option is also
+    /// added.
+    pub fn get_stale(&self, query: &Message) -> Option<Message> {
+        self.lookup(query, true)
+    }
+
+    fn lookup(&self, query: &Message, stale: bool) -> Option<Message> {
+        let (name, r#type, class) = Self::question(query)?;
+        let now = Instant::now();
+
+        let mut inner = self.inner.lock().unwrap();
+        let keys = inner.chain(name, r#type, class)?;
+
+        let mut expired = false;
+        for key in &keys {
+            let expires = inner.entries[key].expires;
+            if now >= expires {
+                let max_stale = self.max_stale.unwrap_or_default();
+                if now >= expires + max_stale {
+                    inner.remove(key);
+                    return None;
+                }
+                if !stale {
+                    return None;
+                }
+                expired = true;
+            }
+        }
+
+        // Make a answer to this query.
+        let mut response = Message {
+            id: query.id,
+            qr: QR::Response,
+            opcode: query.opcode,
+            rd: query.rd,
+            ra: true,
+            questions: query.questions.clone(),
+            ..Default::default()
+        };
+
+        for key in &keys {
+            let entry = inner.entries.get_mut(key).unwrap();
+            entry.hits += 1;
+
+            // TTLs are whole seconds, so only whole seconds are subtracted.
+            let elapsed = Duration::from_secs(now.duration_since(entry.inserted).as_secs());
+            let ttl = |record: &Record| {
+                if now >= entry.expires {
+                    STALE_TTL
+                } else {
+                    record.ttl.saturating_sub(elapsed)
+                }
+            };
+
+            match &entry.data {
+                Data::Records(records) => {
+                    response.answers.extend(records.iter().map(|record| Record {
+                        ttl: ttl(record),
+                        ..record.clone()
+                    }))
+                }
+                Data::Negative(soa) => {
+                    if key.1.is_none() {
+                        response.rcode = Rcode::NXDomain;
+                    }
+                    response.authoritys.push(Record {
+                        ttl: ttl(soa),
+                        ..soa.clone()
+                    });
+                }
+            }
+
+            inner.touch(key);
+        }
+
+        // A OPT record is only sent in response to one, see rfc6891 section 7.
+        if query.extension.is_some() {
+            let mut extension = Extension::default();
+            if expired {
+                extension.options.push(ExtensionOption::ExtendedError {
+                    info_code: ExtensionOption::STALE_ANSWER,
+                    extra_text: String::new(),
+                });
+            }
+            response.extension = Some(extension);
+        }
+
+        Some(response)
+    }
+
+    /// Returns true if the cached records answering this query are popular,
+    /// and about to expire, and thus should be refreshed before they do. Only
+    /// returns true once for each RRset, until it is replaced.
+    ///
+    /// A RRset is popular once it has been returned a couple of times, and
+    /// about to expire in the last 10% of its TTL, the same as Unbound.
+    pub fn should_prefetch(&self, query: &Message) -> bool {
+        let (name, r#type, class) = match Self::question(query) {
+            Some(question) => question,
+            None => return false,
+        };
+        let now = Instant::now();
+
+        let mut inner = self.inner.lock().unwrap();
+        let keys = match inner.chain(name, r#type, class) {
+            Some(keys) => keys,
+            None => return false,
+        };
+
+        let expiring = keys.iter().any(|key| {
+            let entry = &inner.entries[key];
+            if entry.prefetching || entry.hits < PREFETCH_HITS || now >= entry.expires {
+                return false;
+            }
+
+            let ttl = entry.expires.duration_since(entry.inserted);
+            entry.expires.duration_since(now) <= ttl / 10
+        });
+
+        if expiring {
+            for key in &keys {
+                inner.entries.get_mut(key).unwrap().prefetching = true;
+            }
+        }
+        expiring
+    }
+
+    /// Caches the RRsets in the response to the query, if it is cacheable.
+    pub fn insert(&self, query: &Message, response: &Message) {
+        let (name, r#type, class) = match Self::question(query) {
+            Some(question) => question,
+            None => return,
+        };
+
+        if response.tc || !matches!(response.rcode, Rcode::NoError | Rcode::NXDomain) {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+
+        // The names that answer the question, its name followed by the names
+        // it is a alias for. A negative response is for the last, see rfc2308
+        // section 2.1.
+        let mut names = vec![name];
+        let mut dnames = Vec::new();
+        if !matches!(r#type, Type::CNAME | Type::DNAME) {
+            for _ in 0..MAX_CNAMES {
+                let (records, next) = match find_alias(&response.answers, names.last().unwrap()) {
+                    Some(alias) => alias,
+                    None => break,
+                };
+                let next = next.to_lowercase();
+                if names.contains(&next) {
+                    break; // A loop.
+                }
+
+                dnames.extend(
+                    records
+                        .iter()
+                        .filter(|r| r.r#type() == Type::DNAME)
+                        .map(|r| r.name.to_lowercase()),
+                );
+                names.push(next);
+            }
+        }
+
+        for records in rrsets(&response.answers) {
+            let owner = records[0].name.to_lowercase();
+            if names.contains(&owner)
+                || (records[0].r#type() == Type::DNAME && dnames.contains(&owner))
+            {
+                self.insert_records(&mut inner, records, now);
+            }
+        }
+
+        let name = names.pop().unwrap();
+        let key = match response.rcode {
+            Rcode::NXDomain => (name, None, class),
+            _ if response
+                .answers
+                .iter()
+                .any(|r| r.name.eq_ignore_ascii_case(&name) && r.r#type() == r#type) =>
+            {
+                return
+            }
+            _ => (name, Some(r#type), class),
+        };
+
+        // The negative TTL is the smaller of the SOA's TTL and its minimum
+        // field. See rfc2308 section 5.
+        let mut soa = match response
+            .authoritys
+            .iter()
+            .find(|r| matches!(r.resource, Resource::SOA(_)))
+        {
+            Some(soa) => soa.clone(),
+            None => return,
+        };
+        let minimum = match &soa.resource {
+            Resource::SOA(data) => data.minimum,
+            _ => unreachable!(),
+        };
+        soa.ttl = self.clamp(soa.ttl.min(minimum));
+
+        // A NXDOMAIN replaces everything else cached for the name.
+        if key.1.is_none() {
+            inner.remove_name(&key.0, class);
+        }
+        let ttl = soa.ttl;
+        inner.insert(key, Data::Negative(soa), ttl, now, self.capacity);
+    }
+
+    /// Caches the RRset, replacing any cached copy.
+    fn insert_records(&self, inner: &mut Inner, mut records: Vec<Record>, now: Instant) {
+        let first = &records[0];
+        let key = (first.name.to_lowercase(), Some(first.r#type()), first.class);
+
+        // The records of a RRset should have the same TTL, see rfc2181
+        // section 5.2, so the smallest is used for them all.
+        let ttl = self.clamp(records.iter().map(|r| r.ttl).min().unwrap());
+        for record in &mut records {
+            record.ttl = ttl;
+        }
+
+        // The name exists after all.
+        inner.remove(&(key.0.clone(), None, key.2));
+        inner.insert(key, Data::Records(records), ttl, now, self.capacity);
+    }
+
+    fn clamp(&self, ttl: Duration) -> Duration {
+        ttl.max(self.min_ttl).min(self.max_ttl)
+    }
+}
+
+/// Groups the records into RRsets, by their (case insensitive) name, type
+/// and class.
+fn rrsets(records: &[Record]) -> Vec<Vec<Record>> {
+    let mut rrsets: Vec<Vec<Record>> = Vec::new();
+    for record in records {
+        match rrsets.iter_mut().find(|rrset| {
+            rrset[0].name.eq_ignore_ascii_case(&record.name)
+                && rrset[0].r#type() == record.r#type()
+                && rrset[0].class == record.class
+        }) {
+            Some(rrset) => rrset.push(record.clone()),
+            None => rrsets.push(vec![record.clone()]),
+        }
+    }
+    rrsets
+}
+
+impl Inner {
+    /// Returns the keys of the entries that answer the query, following any
+    /// CNAMEs, or None if they aren't all cached.
+    fn chain(&self, mut name: String, r#type: Type, class: Class) -> Option<Vec<Key>> {
+        let mut keys = Vec::new();
+
+        for _ in 0..=MAX_CNAMES {
+            for key in [
+                (name.clone(), None, class),
+                (name.clone(), Some(r#type), class),
+            ] {
+                if self.entries.contains_key(&key) {
+                    keys.push(key);
+                    return Some(keys);
+                }
+            }
+
+            let key = (name, Some(Type::CNAME), class);
+            name = match &self.entries.get(&key)?.data {
+                Data::Records(records) => records.iter().find_map(|r| match &r.resource {
+                    Resource::CNAME(target) => Some(target.to_lowercase()),
+                    _ => None,
+                })?,
+                Data::Negative(_) => return None,
+            };
+            keys.push(key);
+        }
+
+        None // Too many CNAMEs.
+    }
+
+    fn insert(&mut self, key: Key, data: Data, ttl: Duration, now: Instant, capacity: usize) {
+        self.remove(&key);
+
+        while self.entries.len() >= capacity {
+            if !self.evict() {
+                return; // The capacity is zero.
+            }
+        }
+
+        self.entries.insert(
+            key.clone(),
+            Entry {
+                data,
+                inserted: now,
+                expires: now + ttl,
+                used: 0,
+                hits: 0,
+                prefetching: false,
+            },
+        );
+        self.touch(&key);
+    }
+
+    /// Marks the entry as the most recently used.
+    fn touch(&mut self, key: &Key) {
+        self.clock += 1;
+        let clock = self.clock;
+
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.lru.remove(&entry.used);
+            entry.used = clock;
+            self.lru.insert(clock, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.used);
+        }
+    }
+
+    /// Removes every entry for the (lowercase) name.
+    fn remove_name(&mut self, name: &str, class: Class) {
+        let keys: Vec<Key> = self
+            .entries
+            .keys()
+            .filter(|key| key.0 == name && key.2 == class)
+            .cloned()
+            .collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    /// Removes the least recently used entry, returning false if there
+    /// were no entries.
+    fn evict(&mut self) -> bool {
+        match self.lru.iter().next() {
+            Some((_, key)) => {
+                let key = key.clone();
+                self.remove(&key);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A [`Exchanger`] that answers queries from a [`Cache`], only sending them to
+/// the wrapped client when they aren't cached.
+///
+/// If the client fails (with an error, SERVFAIL or REFUSED), and the cache is
+/// serving stale responses, a stale response is returned instead.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rustdns::clients::udp::Client;
+/// use rustdns::clients::{Cache, Cached, Exchanger};
+/// use rustdns::types::*;
+///
+/// fn main() -> Result<(), rustdns::Error> {
+///     let client = Cached::new(Client::new("8.8.8.8:53")?, Cache::default());
+///
+///     let mut query = Message::default();
+///     query.add_question("bramp.net", Type::A, Class::Internet);
+///
+///     client.exchange(&query)?; // Sent to 8.8.8.8
+///     client.exchange(&query)?; // Answered from the cache
+///     Ok(())
+/// }
+/// ```
+pub struct Cached<E> {
+    client: E,
+    cache: Cache,
+}
+
+impl<E> Cached<E> {
+    /// Wraps the client with the cache.
+    pub fn new(client: E, cache: Cache) -> Cached<E> {
+        Cached { client, cache }
+    }
+
+    /// Returns the cache.
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+}
+
+impl<E> Exchanger for Cached<E>
+where
+    E: Exchanger,
+{
+    fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+        if let Some(response) = self.cache.get(query) {
+            return Ok(response);
+        }
+
+        let result = self.client.exchange(query);
+        match &result {
+            Ok(response) if !matches!(response.rcode, Rcode::ServFail | Rcode::Refused) => {
+                self.cache.insert(query, response);
+                result
+            }
+
+            // Failed, so try serving a stale response.
+            _ => self.cache.get_stale(query).map_or(result, Ok),
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! A hyper connector that speaks plain TCP or TLS (negotiating HTTP/2 or HTTP/1.1
+//! via ALPN), optionally tunnelling through a HTTP CONNECT proxy.
+
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::Service;
+use hyper::Uri;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{ClientConfig, Session};
+use tokio_rustls::TlsConnector;
+use url::Url;
+use webpki::DNSNameRef;
+
+const ALPN_H2: &[u8] = b"h2";
+const ALPN_HTTP_1_1: &[u8] = b"http/1.1";
+
+// The largest CONNECT response header we are willing to read from a proxy.
+const MAX_PROXY_RESPONSE_SIZE: usize = 8192;
+
+/// The address of the peer we connected to, attached to each response's
+/// extensions. When using a proxy, this is the proxy's address.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RemoteAddr(pub SocketAddr);
+
+#[derive(Clone)]
+pub(crate) struct Connector {
+    tls: Arc<ClientConfig>,
+    proxy: Option<Url>,
+}
+
+impl Connector {
+    /// Creates a new connector. `h1` and `h2` control which protocols are offered
+    /// during the TLS handshake.
+    pub fn new(h1: bool, h2: bool, proxy: Option<Url>) -> Connector {
+        let mut tls = ClientConfig::new();
+        if h2 {
+            tls.alpn_protocols.push(ALPN_H2.to_vec());
+        }
+        if h1 {
+            tls.alpn_protocols.push(ALPN_HTTP_1_1.to_vec());
+        }
+        tls.root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+
+        Connector {
+            tls: Arc::new(tls),
+            proxy,
+        }
+    }
+
+    async fn connect(tls: Arc<ClientConfig>, proxy: Option<Url>, dst: Uri) -> io::Result<Stream> {
+        let https = match dst.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err(invalid_input(format!("unsupported scheme in '{}'", dst))),
+        };
+
+        let host = match dst.host() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => return Err(invalid_input(format!("missing host in '{}'", dst))),
+        };
+        let port = dst.port_u16().unwrap_or(if https { 443 } else { 80 });
+
+        let tcp = match &proxy {
+            Some(proxy) => Self::tunnel(proxy, host, port).await?,
+            None => TcpStream::connect((host, port)).await?,
+        };
+        tcp.set_nodelay(true)?;
+
+        if !https {
+            return Ok(Stream::Plain(tcp));
+        }
+
+        let name = DNSNameRef::try_from_ascii_str(host)
+            .map_err(|e| invalid_input(format!("invalid host '{}': {:?}", host, e)))?;
+
+        let tls = TlsConnector::from(tls).connect(name, tcp).await?;
+        Ok(Stream::Tls(Box::new(tls)))
+    }
+
+    /// Connects to the `proxy` and issues a CONNECT request, returning a stream
+    /// tunnelled to `host`:`port`.
+    async fn tunnel(proxy: &Url, host: &str, port: u16) -> io::Result<TcpStream> {
+        let proxy_host = match proxy.host_str() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => return Err(invalid_input(format!("missing host in proxy '{}'", proxy))),
+        };
+        let proxy_port = proxy.port_or_known_default().unwrap_or(3128);
+
+        let mut tcp = TcpStream::connect((proxy_host, proxy_port)).await?;
+
+        let authority = if host.contains(':') {
+            format!("[{}]:{}", host, port) // IPv6 literal
+        } else {
+            format!("{}:{}", host, port)
+        };
+
+        let mut req = format!(
+            "CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n",
+            authority = authority
+        );
+        if !proxy.username().is_empty() {
+            let credentials = format!(
+                "{}:{}",
+                proxy.username(),
+                proxy.password().unwrap_or_default()
+            );
+            req.push_str(&format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                base64::encode(credentials)
+            ));
+        }
+        req.push_str("\r\n");
+
+        tcp.write_all(req.as_bytes()).await?;
+
+        // Read the response headers, one byte at a time, so we don't consume
+        // any bytes that belong to the tunnelled connection.
+        let mut resp = Vec::new();
+        while !resp.ends_with(b"\r\n\r\n") {
+            if resp.len() >= MAX_PROXY_RESPONSE_SIZE {
+                return Err(invalid_data("proxy response too large".to_string()));
+            }
+            resp.push(tcp.read_u8().await?);
+        }
+
+        let resp = String::from_utf8_lossy(&resp);
+        let status = resp.lines().next().unwrap_or_default();
+        match status.split_whitespace().nth(1) {
+            Some("200") => Ok(tcp),
+            _ => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("proxy refused CONNECT to {}: '{}'", authority, status),
+            )),
+        }
+    }
+}
+
+impl Service<Uri> for Connector {
+    type Response = Stream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Stream>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        Box::pin(Self::connect(self.tls.clone(), self.proxy.clone(), dst))
+    }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub(crate) enum Stream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Stream {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Stream::Plain(tcp) => tcp,
+            Stream::Tls(tls) => tls.get_ref().0,
+        }
+    }
+}
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        let mut connected = Connected::new();
+
+        if let Ok(addr) = self.tcp().peer_addr() {
+            connected = connected.extra(RemoteAddr(addr));
+        }
+
+        if let Stream::Tls(tls) = self {
+            if tls.get_ref().1.get_alpn_protocol() == Some(ALPN_H2) {
+                connected = connected.negotiated_h2();
+            }
+        }
+
+        connected
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            Stream::Tls(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            Stream::Tls(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_flush(cx),
+            Stream::Tls(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            Stream::Tls(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::util::normalise;
+use crate::util::reverse;
+use log::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// A static table of host names and addresses, as typically found in
+/// `/etc/hosts`. See [hosts(5)].
+///
+/// Each line is a IP address, followed by the canonical host name, and then
+/// any aliases. The file is read on first use, and read again whenever its
+/// modification time changes. If the file can't be read, it is treated as
+/// empty.
+///
+/// # Example
+///
+/// ```rust
+/// use rustdns::clients::Hosts;
+///
+/// let hosts = Hosts::load();
+/// if let Some(ips) = hosts.lookup("localhost") {
+///     println!("localhost is {:?}", ips);
+/// }
+/// ```
+///
+/// [hosts(5)]: https://man7.org/linux/man-pages/man5/hosts.5.html
+pub struct Hosts {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The modification time of the file when it was last read.
+    modified: Option<SystemTime>,
+
+    /// Fully qualified (lowercase) name to addresses.
+    by_name: HashMap<String, Vec<IpAddr>>,
+
+    /// Reverse name (as returned by [`reverse`]) to fully qualified names.
+    /// The canonical name is first.
+    by_addr: HashMap<String, Vec<String>>,
+}
+
+impl State {
+    fn parse(s: &str) -> State {
+        let mut state = State::default();
+
+        for line in s.lines() {
+            let line = match line.find('#') {
+                Some(i) => &line[..i],
+                None => line,
+            };
+
+            let mut words = line.split_whitespace();
+            let ip = match words.next() {
+                Some(ip) => ip,
+                None => continue,
+            };
+
+            // Ignore any IPv6 scope id.
+            let ip = match ip.find('%') {
+                Some(i) => &ip[..i],
+                None => ip,
+            };
+
+            let ip: IpAddr = match ip.parse() {
+                Ok(ip) => ip,
+                Err(_) => {
+                    debug!("ignoring invalid hosts line '{}'", line);
+                    continue;
+                }
+            };
+
+            for name in words.map(normalise) {
+                let ips = state.by_name.entry(name.clone()).or_default();
+                if !ips.contains(&ip) {
+                    ips.push(ip);
+                }
+
+                let names = state.by_addr.entry(reverse(ip)).or_default();
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+
+        state
+    }
+}
+
+impl Hosts {
+    /// The default location of the hosts file.
+    pub const DEFAULT_PATH: &'static str = "/etc/hosts";
+
+    /// Uses the hosts file at [`Self::DEFAULT_PATH`].
+    pub fn load() -> Hosts {
+        Self::from_path(Self::DEFAULT_PATH)
+    }
+
+    /// Uses the hosts file at a alternative path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Hosts {
+        Hosts {
+            path: path.as_ref().to_path_buf(),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Calls `f` with the current state, reading the file first if it has
+    /// changed since it was last read.
+    fn with_state<T>(&self, f: impl FnOnce(&State) -> T) -> T {
+        let mut state = self.state.lock().unwrap();
+
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified != state.modified {
+            *state = match fs::read_to_string(&self.path) {
+                Ok(s) => State::parse(&s),
+                Err(e) => {
+                    debug!("failed to read {}: {}", self.path.display(), e);
+                    State::default()
+                }
+            };
+            state.modified = modified;
+        }
+
+        f(&state)
+    }
+
+    /// Returns the addresses for the name (or alias), or None if the name is
+    /// not in the file.
+    pub fn lookup(&self, name: &str) -> Option<Vec<IpAddr>> {
+        let name = normalise(name);
+        self.with_state(|state| state.by_name.get(&name).cloned())
+    }
+
+    /// Returns the fully qualified names for the address, with the canonical
+    /// name first, or None if the address is not in the file.
+    pub fn lookup_addr(&self, ip: IpAddr) -> Option<Vec<String>> {
+        self.lookup_ptr(&reverse(ip))
+    }
+
+    /// Returns the names for a reverse name, such as `1.0.0.127.in-addr.arpa.`.
+    pub(crate) fn lookup_ptr(&self, name: &str) -> Option<Vec<String>> {
+        let name = normalise(name);
+        self.with_state(|state| state.by_addr.get(&name).cloned())
+    }
+}
@@ -0,0 +1,262 @@
+//! A minimal implementation of Hybrid Public Key Encryption (HPKE) as defined
+//! by [rfc9180]. Only the base mode, with the DHKEM(X25519, HKDF-SHA256),
+//! HKDF-SHA256 and AES-128-GCM suite is supported, as that is what is
+//! required by Oblivious DoH.
+//!
+//! [rfc9180]: https://datatracker.ietf.org/doc/html/rfc9180
+
+use crate::bail;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::Aes128Gcm;
+use hkdf::Hkdf;
+use rand::RngCore;
+use sha2::Sha256;
+use std::convert::TryInto;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// DHKEM(X25519, HKDF-SHA256)
+pub(crate) const KEM_ID: u16 = 0x0020;
+
+/// HKDF-SHA256
+pub(crate) const KDF_ID: u16 = 0x0001;
+
+/// AES-128-GCM
+pub(crate) const AEAD_ID: u16 = 0x0001;
+
+/// Length of the AEAD key.
+pub(crate) const NK: usize = 16;
+
+/// Length of the AEAD nonce.
+pub(crate) const NN: usize = 12;
+
+/// Length of the KDF output.
+pub(crate) const NH: usize = 32;
+
+/// Length of the encapsulated key (a X25519 public key).
+pub(crate) const NENC: usize = 32;
+
+const MODE_BASE: u8 = 0x00;
+
+fn kem_suite_id() -> Vec<u8> {
+    let mut id = b"KEM".to_vec();
+    id.extend_from_slice(&KEM_ID.to_be_bytes());
+    id
+}
+
+fn hpke_suite_id() -> Vec<u8> {
+    let mut id = b"HPKE".to_vec();
+    id.extend_from_slice(&KEM_ID.to_be_bytes());
+    id.extend_from_slice(&KDF_ID.to_be_bytes());
+    id.extend_from_slice(&AEAD_ID.to_be_bytes());
+    id
+}
+
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> Vec<u8> {
+    let mut labeled_ikm = b"HPKE-v1".to_vec();
+    labeled_ikm.extend_from_slice(suite_id);
+    labeled_ikm.extend_from_slice(label);
+    labeled_ikm.extend_from_slice(ikm);
+
+    extract(salt, &labeled_ikm)
+}
+
+fn labeled_expand(suite_id: &[u8], prk: &[u8], label: &[u8], info: &[u8], len: usize) -> Vec<u8> {
+    let mut labeled_info = (len as u16).to_be_bytes().to_vec();
+    labeled_info.extend_from_slice(b"HPKE-v1");
+    labeled_info.extend_from_slice(suite_id);
+    labeled_info.extend_from_slice(label);
+    labeled_info.extend_from_slice(info);
+
+    expand(prk, &labeled_info, len)
+}
+
+/// HKDF-Extract with SHA256.
+pub(crate) fn extract(salt: &[u8], ikm: &[u8]) -> Vec<u8> {
+    let (prk, _) = Hkdf::<Sha256>::extract(Some(salt), ikm);
+    prk.to_vec()
+}
+
+/// HKDF-Expand with SHA256.
+pub(crate) fn expand(prk: &[u8], info: &[u8], len: usize) -> Vec<u8> {
+    let hkdf = Hkdf::<Sha256>::from_prk(prk).expect("prk is always NH bytes");
+
+    let mut okm = vec![0; len];
+    hkdf.expand(info, &mut okm)
+        .expect("len is always smaller than 255 * NH");
+    okm
+}
+
+/// Seals the plaintext with AES-128-GCM.
+pub(crate) fn seal(key: &[u8], nonce: &[u8], aad: &[u8], pt: &[u8]) -> Vec<u8> {
+    let cipher = Aes128Gcm::new_from_slice(key).expect("key is always NK bytes");
+    cipher
+        .encrypt(nonce.into(), Payload { msg: pt, aad })
+        .expect("plaintext is never too large")
+}
+
+/// Opens the ciphertext with AES-128-GCM.
+pub(crate) fn open(key: &[u8], nonce: &[u8], aad: &[u8], ct: &[u8]) -> std::io::Result<Vec<u8>> {
+    let cipher = Aes128Gcm::new_from_slice(key).expect("key is always NK bytes");
+    match cipher.decrypt(nonce.into(), Payload { msg: ct, aad }) {
+        Ok(pt) => Ok(pt),
+        Err(_) => bail!(InvalidData, "failed to decrypt message"),
+    }
+}
+
+/// Returns a new random X25519 private key.
+pub(crate) fn generate_secret() -> StaticSecret {
+    let mut bytes = [0; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    StaticSecret::from(bytes)
+}
+
+/// DHKEM's ExtractAndExpand.
+fn extract_and_expand(dh: &[u8], kem_context: &[u8]) -> Vec<u8> {
+    let suite_id = kem_suite_id();
+    let eae_prk = labeled_extract(&suite_id, b"", b"eae_prk", dh);
+    labeled_expand(&suite_id, &eae_prk, b"shared_secret", kem_context, NH)
+}
+
+/// The context shared between sender and receiver, after key setup.
+pub(crate) struct Context {
+    key: Vec<u8>,
+    base_nonce: Vec<u8>,
+    exporter_secret: Vec<u8>,
+    seq: u64,
+}
+
+impl Context {
+    fn new(shared_secret: &[u8], info: &[u8]) -> Context {
+        let suite_id = hpke_suite_id();
+
+        let psk_id_hash = labeled_extract(&suite_id, b"", b"psk_id_hash", b"");
+        let info_hash = labeled_extract(&suite_id, b"", b"info_hash", info);
+
+        let mut key_schedule_context = vec![MODE_BASE];
+        key_schedule_context.extend_from_slice(&psk_id_hash);
+        key_schedule_context.extend_from_slice(&info_hash);
+
+        let secret = labeled_extract(&suite_id, shared_secret, b"secret", b"");
+
+        Context {
+            key: labeled_expand(&suite_id, &secret, b"key", &key_schedule_context, NK),
+            base_nonce: labeled_expand(&suite_id, &secret, b"base_nonce", &key_schedule_context, NN),
+            exporter_secret: labeled_expand(&suite_id, &secret, b"exp", &key_schedule_context, NH),
+            seq: 0,
+        }
+    }
+
+    /// Sets up a sender context for the receiver's public key. Returns the
+    /// encapsulated key, which must be sent to the receiver.
+    pub fn sender(pk_r: &PublicKey, info: &[u8]) -> ([u8; NENC], Context) {
+        Self::sender_with_ephemeral(&generate_secret(), pk_r, info)
+    }
+
+    fn sender_with_ephemeral(
+        sk_e: &StaticSecret,
+        pk_r: &PublicKey,
+        info: &[u8],
+    ) -> ([u8; NENC], Context) {
+        let dh = sk_e.diffie_hellman(pk_r);
+        let enc = PublicKey::from(sk_e).to_bytes();
+
+        let mut kem_context = enc.to_vec();
+        kem_context.extend_from_slice(pk_r.as_bytes());
+
+        let shared_secret = extract_and_expand(dh.as_bytes(), &kem_context);
+        (enc, Self::new(&shared_secret, info))
+    }
+
+    /// Sets up a receiver context from the sender's encapsulated key.
+    pub fn receiver(enc: &[u8], sk_r: &StaticSecret, info: &[u8]) -> std::io::Result<Context> {
+        let enc: [u8; NENC] = match enc.try_into() {
+            Ok(enc) => enc,
+            Err(_) => bail!(InvalidData, "invalid encapsulated key length {}", enc.len()),
+        };
+
+        let dh = sk_r.diffie_hellman(&PublicKey::from(enc));
+
+        let mut kem_context = enc.to_vec();
+        kem_context.extend_from_slice(PublicKey::from(sk_r).as_bytes());
+
+        let shared_secret = extract_and_expand(dh.as_bytes(), &kem_context);
+        Ok(Self::new(&shared_secret, info))
+    }
+
+    fn next_nonce(&mut self) -> Vec<u8> {
+        let mut nonce = self.base_nonce.clone();
+        for (n, s) in nonce.iter_mut().rev().zip(self.seq.to_be_bytes().iter().rev()) {
+            *n ^= s;
+        }
+        self.seq += 1;
+        nonce
+    }
+
+    pub fn seal(&mut self, aad: &[u8], pt: &[u8]) -> Vec<u8> {
+        let nonce = self.next_nonce();
+        seal(&self.key, &nonce, aad, pt)
+    }
+
+    pub fn open(&mut self, aad: &[u8], ct: &[u8]) -> std::io::Result<Vec<u8>> {
+        let nonce = self.next_nonce();
+        open(&self.key, &nonce, aad, ct)
+    }
+
+    /// Derives a secret of `len` bytes from this context.
+    pub fn export(&self, exporter_context: &[u8], len: usize) -> Vec<u8> {
+        labeled_expand(
+            &hpke_suite_id(),
+            &self.exporter_secret,
+            b"sec",
+            exporter_context,
+            len,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Context;
+    use pretty_assertions::assert_eq;
+    use std::convert::TryInto;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    fn key(s: &str) -> StaticSecret {
+        let bytes: [u8; 32] = hex::decode(s).unwrap().try_into().unwrap();
+        StaticSecret::from(bytes)
+    }
+
+    /// Test vector A.1.1 from rfc9180.
+    #[test]
+    fn test_base_x25519_sha256_aes128gcm() {
+        let info = hex::decode("4f6465206f6e2061204772656369616e2055726e").unwrap();
+        let sk_e = key("52c4a758a802cd8b936eceea314432798d5baf2d7e9235dc084ab1b9cfa2f736");
+        let sk_r = key("4612c550263fc8ad58375df3f557aac531d26850903e55a9f23f21d8534e8ac8");
+
+        let (enc, mut sender) =
+            Context::sender_with_ephemeral(&sk_e, &PublicKey::from(&sk_r), &info);
+
+        assert_eq!(
+            hex::encode(enc),
+            "37fda3567bdbd628e88668c3c8d7e97d1d1253b6d4ea6d44c150f741f1bf4431"
+        );
+        assert_eq!(hex::encode(&sender.key), "4531685d41d65f03dc48f6b8302c05b0");
+        assert_eq!(hex::encode(&sender.base_nonce), "56d890e5accaaf011cff4b7d");
+        assert_eq!(
+            hex::encode(&sender.exporter_secret),
+            "45ff1c2e220db587171952c0592d5f5ebe103f1561a2614e38f2ffd47e99e3f8"
+        );
+
+        let pt = hex::decode("4265617574792069732074727574682c20747275746820626561757479").unwrap();
+        let aad = hex::decode("436f756e742d30").unwrap();
+        let ct = sender.seal(&aad, &pt);
+        assert_eq!(
+            hex::encode(&ct),
+            "f938558b5d72f1a23810b4be2ab4f84331acc02fc97babc53a52ae8218a355a96d8770ac83d07bea87e13c512a"
+        );
+
+        let mut receiver = Context::receiver(&enc, &sk_r, &info).unwrap();
+        assert_eq!(receiver.open(&aad, &ct).unwrap(), pt);
+        assert_eq!(receiver.export(b"", 32), sender.export(b"", 32));
+    }
+}
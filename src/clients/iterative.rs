@@ -0,0 +1,548 @@
+use crate::bail;
+use crate::clients::udp::Client as UdpClient;
+use crate::clients::Exchanger;
+use crate::util::normalise;
+use crate::Class;
+use crate::Message;
+use crate::Rcode;
+use crate::Record;
+use crate::Resource;
+use crate::Type;
+use log::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+#[cfg(feature = "tcp")]
+use crate::clients::tcp::Client as TcpClient;
+
+/// The most referrals that will be followed for one query.
+const MAX_REFERRALS: usize = 16;
+
+/// How deeply the lookups for name servers without glue may nest.
+const MAX_DEPTH: usize = 4;
+
+/// The root hints, from the IANA's [named.root].
+///
+/// [named.root]: https://www.internic.net/domain/named.root
+const ROOT_HINTS: &str = "
+.                        3600000      NS    A.ROOT-SERVERS.NET.
+A.ROOT-SERVERS.NET.      3600000      A     198.41.0.4
+A.ROOT-SERVERS.NET.      3600000      AAAA  2001:503:ba3e::2:30
+.                        3600000      NS    B.ROOT-SERVERS.NET.
+B.ROOT-SERVERS.NET.      3600000      A     170.247.170.2
+B.ROOT-SERVERS.NET.      3600000      AAAA  2801:1b8:10::b
+.                        3600000      NS    C.ROOT-SERVERS.NET.
+C.ROOT-SERVERS.NET.      3600000      A     192.33.4.12
+C.ROOT-SERVERS.NET.      3600000      AAAA  2001:500:2::c
+.                        3600000      NS    D.ROOT-SERVERS.NET.
+D.ROOT-SERVERS.NET.      3600000      A     199.7.91.13
+D.ROOT-SERVERS.NET.      3600000      AAAA  2001:500:2d::d
+.                        3600000      NS    E.ROOT-SERVERS.NET.
+E.ROOT-SERVERS.NET.      3600000      A     192.203.230.10
+E.ROOT-SERVERS.NET.      3600000      AAAA  2001:500:a8::e
+.                        3600000      NS    F.ROOT-SERVERS.NET.
+F.ROOT-SERVERS.NET.      3600000      A     192.5.5.241
+F.ROOT-SERVERS.NET.      3600000      AAAA  2001:500:2f::f
+.                        3600000      NS    G.ROOT-SERVERS.NET.
+G.ROOT-SERVERS.NET.      3600000      A     192.112.36.4
+G.ROOT-SERVERS.NET.      3600000      AAAA  2001:500:12::d0d
+.                        3600000      NS    H.ROOT-SERVERS.NET.
+H.ROOT-SERVERS.NET.      3600000      A     198.97.190.53
+H.ROOT-SERVERS.NET.      3600000      AAAA  2001:500:1::53
+.                        3600000      NS    I.ROOT-SERVERS.NET.
+I.ROOT-SERVERS.NET.      3600000      A     192.36.148.17
+I.ROOT-SERVERS.NET.      3600000      AAAA  2001:7fe::53
+.                        3600000      NS    J.ROOT-SERVERS.NET.
+J.ROOT-SERVERS.NET.      3600000      A     192.58.128.30
+J.ROOT-SERVERS.NET.      3600000      AAAA  2001:503:c27::2:30
+.                        3600000      NS    K.ROOT-SERVERS.NET.
+K.ROOT-SERVERS.NET.      3600000      A     193.0.14.129
+K.ROOT-SERVERS.NET.      3600000      AAAA  2001:7fd::1
+.                        3600000      NS    L.ROOT-SERVERS.NET.
+L.ROOT-SERVERS.NET.      3600000      A     199.7.83.42
+L.ROOT-SERVERS.NET.      3600000      AAAA  2001:500:9f::42
+.                        3600000      NS    M.ROOT-SERVERS.NET.
+M.ROOT-SERVERS.NET.      3600000      A     202.12.27.33
+M.ROOT-SERVERS.NET.      3600000      AAAA  2001:dc3::35
+";
+
+/// Sends a query to a server, and returns its response.
+type Transport = Box<dyn Fn(SocketAddr, &Message) -> Result<Message, crate::Error> + Send + Sync>;
+
+/// Resolves queries iteratively, starting at the root name servers, and
+/// following referrals down to the authoritative servers, without the need
+/// for any upstream recursive server. See [rfc1034 section 5.3.3].
+///
+/// The addresses of name servers are taken from the glue records included
+/// with referrals, or if there are none, looked up (again iteratively).
+/// Servers that don't respond, fail, or are lame (respond with a referral
+/// that doesn't get closer to the answer) are skipped. The delegations are
+/// cached for the TTL of their NS records, so later queries for names in
+/// the same zones start from the closest known delegation.
+///
+/// Only the referrals are followed. CNAMEs are left to the caller, so this
+/// is best used within a [`Resolver`](crate::clients::Resolver), which also
+/// caches the answers.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rustdns::clients::{Cache, Iterative, Resolver};
+///
+/// fn main() -> Result<(), rustdns::Error> {
+///     let resolver = Resolver::new_with_client(Iterative::new()).with_cache(Cache::default());
+///
+///     let ips = resolver.lookup("bramp.net")?;
+///     println!("{:?}", ips);
+///     Ok(())
+/// }
+/// ```
+///
+/// [rfc1034 section 5.3.3]: https://datatracker.ietf.org/doc/html/rfc1034#section-5.3.3
+pub struct Iterative {
+    roots: Vec<NameServer>,
+
+    /// How long to wait for each server.
+    timeout: Duration,
+
+    /// Overrides how queries are sent, otherwise UDP (then TCP if truncated).
+    transport: Option<Transport>,
+
+    /// Known delegations, keyed by the (lowercase) zone name.
+    delegations: Mutex<HashMap<String, Delegation>>,
+}
+
+#[derive(Clone, Debug)]
+struct NameServer {
+    /// The fully qualified (lowercase) name of the server.
+    name: String,
+
+    /// The server's addresses, which are empty if they are not yet known.
+    addrs: Vec<SocketAddr>,
+}
+
+struct Delegation {
+    nameservers: Vec<NameServer>,
+    expires: Instant,
+}
+
+/// The outcome of asking a name server.
+enum Step {
+    /// A final response, with the answer, or saying there is no answer.
+    Answer(Message),
+
+    /// A referral to a closer zone, its name servers, and how long they can
+    /// be cached for.
+    Referral(String, Vec<NameServer>, Duration),
+}
+
+impl Default for Iterative {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Iterative {
+    /// Creates a new Iterative resolver, using the built-in root hints.
+    pub fn new() -> Iterative {
+        Self::from_hints(ROOT_HINTS).expect("built-in root hints are valid")
+    }
+
+    /// Creates a new Iterative resolver, using the root hints in the same
+    /// format as [named.root]. Only the NS records for the root, and the A
+    /// and AAAA records for those name servers are used.
+    ///
+    /// [named.root]: https://www.internic.net/domain/named.root
+    pub fn from_hints(s: &str) -> Result<Iterative, crate::Error> {
+        let roots = parse_hints(s);
+        if roots.iter().all(|ns| ns.addrs.is_empty()) {
+            bail!(InvalidData, "no root name servers with addresses in hints");
+        }
+
+        Ok(Iterative {
+            roots,
+            timeout: Duration::from_secs(2),
+            transport: None,
+            delegations: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Creates a new Iterative resolver, using the root hints read from the
+    /// file, such as a copy of [named.root].
+    ///
+    /// [named.root]: https://www.internic.net/domain/named.root
+    pub fn from_hints_path<P: AsRef<Path>>(path: P) -> Result<Iterative, crate::Error> {
+        Self::from_hints(&fs::read_to_string(path)?)
+    }
+
+    /// Sets how long to wait for each server to respond. Defaults to 2
+    /// seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the function used to send a query to a server. This is mostly
+    /// useful for testing.
+    pub fn with_transport<F>(mut self, transport: F) -> Self
+    where
+        F: Fn(SocketAddr, &Message) -> Result<Message, crate::Error> + Send + Sync + 'static,
+    {
+        self.transport = Some(Box::new(transport));
+        self
+    }
+
+    /// Forgets all the cached delegations.
+    pub fn clear(&self) {
+        self.delegations.lock().unwrap().clear();
+    }
+
+    /// Sends the query to a single server.
+    fn send(&self, addr: SocketAddr, query: &Message) -> Result<Message, crate::Error> {
+        if let Some(transport) = &self.transport {
+            return transport(addr, query);
+        }
+
+        let response = UdpClient::new(addr)?
+            .with_read_timeout(Some(self.timeout))
+            .exchange(query)?;
+
+        #[cfg(feature = "tcp")]
+        if response.tc {
+            debug!("{} truncated the response, retrying with TCP", addr);
+            return TcpClient::new(addr)?
+                .with_read_timeout(Some(self.timeout))
+                .exchange(query);
+        }
+
+        Ok(response)
+    }
+
+    /// Returns the closest known delegation for the name, which is the root
+    /// if nothing closer is known.
+    fn closest(&self, name: &str) -> (String, Vec<NameServer>) {
+        let now = Instant::now();
+        let mut delegations = self.delegations.lock().unwrap();
+
+        let mut zone = name.to_lowercase();
+        loop {
+            match delegations.get(&zone) {
+                Some(delegation) if delegation.expires > now => {
+                    return (zone, delegation.nameservers.clone());
+                }
+                Some(_) => {
+                    delegations.remove(&zone);
+                }
+                None => (),
+            }
+
+            // Move up to the parent.
+            match zone.find('.') {
+                Some(i) if i + 1 < zone.len() => zone = zone[i + 1..].to_string(),
+                _ => break,
+            }
+        }
+
+        (".".to_string(), self.roots.clone())
+    }
+
+    /// Remembers the name servers for the zone.
+    fn remember(&self, zone: &str, nameservers: &[NameServer], ttl: Duration) {
+        self.delegations.lock().unwrap().insert(
+            zone.to_string(),
+            Delegation {
+                nameservers: nameservers.to_vec(),
+                expires: Instant::now() + ttl,
+            },
+        );
+    }
+
+    /// Remembers the addresses found for a name server without glue.
+    fn remember_addrs(&self, zone: &str, name: &str, addrs: &[SocketAddr]) {
+        let mut delegations = self.delegations.lock().unwrap();
+        if let Some(delegation) = delegations.get_mut(zone) {
+            for ns in &mut delegation.nameservers {
+                if ns.name == name {
+                    ns.addrs = addrs.to_vec();
+                }
+            }
+        }
+    }
+
+    /// Resolves the query, starting at the closest known delegation.
+    fn resolve(&self, query: &Message, depth: usize) -> Result<Message, crate::Error> {
+        let name = match query.questions.as_slice() {
+            [question] => question.name.clone(),
+            _ => bail!(
+                InvalidInput,
+                "only queries with one question can be resolved"
+            ),
+        };
+
+        // The servers being asked are not recursive.
+        let mut query = query.clone();
+        query.rd = false;
+
+        let (mut zone, mut nameservers) = self.closest(&name);
+
+        for _ in 0..MAX_REFERRALS {
+            match self.ask(&zone, &nameservers, &name, &query, depth)? {
+                Step::Answer(response) => return Ok(response),
+                Step::Referral(child, servers, ttl) => {
+                    debug!("{} referred {} to {}", zone, name, child);
+                    self.remember(&child, &servers, ttl);
+
+                    zone = child;
+                    nameservers = servers;
+                }
+            }
+        }
+
+        bail!(Other, "too many referrals resolving {}", name)
+    }
+
+    /// Asks each of the zone's name servers in turn, until one gives a useful
+    /// response.
+    fn ask(
+        &self,
+        zone: &str,
+        nameservers: &[NameServer],
+        name: &str,
+        query: &Message,
+        depth: usize,
+    ) -> Result<Step, crate::Error> {
+        let mut last = None;
+
+        for ns in nameservers {
+            let addrs = if ns.addrs.is_empty() {
+                match self.lookup_addrs(&ns.name, depth) {
+                    Ok(addrs) => {
+                        self.remember_addrs(zone, &ns.name, &addrs);
+                        addrs
+                    }
+                    Err(e) => {
+                        debug!("failed to find the address of {}: {}", ns.name, e);
+                        last = Some(e);
+                        continue;
+                    }
+                }
+            } else {
+                ns.addrs.clone()
+            };
+
+            for addr in addrs {
+                let response = match self.send(addr, query) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        debug!("{} ({}) failed: {}", ns.name, addr, e);
+                        last = Some(e);
+                        continue;
+                    }
+                };
+
+                match classify(zone, name, response) {
+                    Some(step) => return Ok(step),
+                    None => debug!("{} ({}) is lame for {}", ns.name, addr, zone),
+                }
+            }
+        }
+
+        match last {
+            Some(e) => Err(e),
+            None => bail!(Other, "no name server for {} gave a answer", zone),
+        }
+    }
+
+    /// Looks up the addresses of a name server that came without glue.
+    fn lookup_addrs(&self, name: &str, depth: usize) -> Result<Vec<SocketAddr>, crate::Error> {
+        if depth >= MAX_DEPTH {
+            bail!(Other, "too many name servers without glue, at {}", name);
+        }
+
+        let mut addrs = Vec::new();
+        for r#type in [Type::A, Type::AAAA] {
+            let mut query = Message::default();
+            query.add_question(name, r#type, Class::Internet);
+
+            let response = self.resolve(&query, depth + 1)?;
+            addrs.extend(response.answers.iter().filter_map(address));
+
+            if !addrs.is_empty() {
+                break;
+            }
+        }
+
+        if addrs.is_empty() {
+            bail!(NotFound, "name server {} has no addresses", name);
+        }
+
+        Ok(addrs)
+    }
+}
+
+impl Exchanger for Iterative {
+    fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+        let mut response = self.resolve(query, 0)?;
+
+        // Make the response look like it came from a recursive server.
+        response.id = query.id;
+        response.rd = query.rd;
+        response.ra = true;
+        Ok(response)
+    }
+}
+
+/// Decides what the response from a server for `zone` means, when resolving
+/// `name`. Returns None if the server is lame, or failed.
+fn classify(zone: &str, name: &str, response: Message) -> Option<Step> {
+    match response.rcode {
+        Rcode::NoError => (),
+        Rcode::NXDomain => return Some(Step::Answer(response)),
+        _ => return None,
+    }
+
+    if !response.answers.is_empty() {
+        return Some(Step::Answer(response));
+    }
+
+    let ns_records: Vec<&Record> = response
+        .authoritys
+        .iter()
+        .filter(|record| matches!(record.resource, Resource::NS(_)))
+        .collect();
+
+    if let Some(first) = ns_records.first() {
+        let child = normalise(&first.name);
+
+        // A referral must be closer to the name, otherwise it's lame.
+        if child != zone && is_subdomain(&child, zone) && is_subdomain(name, &child) {
+            let mut ttl = Duration::MAX;
+            let mut nameservers = Vec::new();
+
+            for record in ns_records.iter().filter(|r| normalise(&r.name) == child) {
+                if let Resource::NS(target) = &record.resource {
+                    let target = normalise(target);
+                    ttl = ttl.min(record.ttl);
+
+                    // Only trust glue for names within the zone being asked.
+                    let addrs = if is_subdomain(&target, zone) {
+                        response
+                            .additionals
+                            .iter()
+                            .filter(|r| normalise(&r.name) == target)
+                            .filter_map(address)
+                            .collect()
+                    } else {
+                        Vec::new()
+                    };
+
+                    nameservers.push(NameServer {
+                        name: target,
+                        addrs,
+                    });
+                }
+            }
+
+            return Some(Step::Referral(child, nameservers, ttl));
+        }
+
+        if response.aa {
+            return Some(Step::Answer(response));
+        }
+        return None;
+    }
+
+    // No records, which is only a answer (NODATA) if it came from the zone.
+    let has_soa = response
+        .authoritys
+        .iter()
+        .any(|record| matches!(record.resource, Resource::SOA(_)));
+
+    if response.aa || has_soa {
+        return Some(Step::Answer(response));
+    }
+
+    None
+}
+
+/// Returns the address in a A or AAAA record.
+fn address(record: &Record) -> Option<SocketAddr> {
+    match record.resource {
+        Resource::A(ip4) => Some(SocketAddr::new(IpAddr::V4(ip4), 53)),
+        Resource::AAAA(ip6) => Some(SocketAddr::new(IpAddr::V6(ip6), 53)),
+        _ => None,
+    }
+}
+
+/// Returns true if `name` is equal to, or below `zone`.
+fn is_subdomain(name: &str, zone: &str) -> bool {
+    if zone == "." {
+        return true;
+    }
+
+    let split = match name.len().checked_sub(zone.len()) {
+        Some(split) => split,
+        None => return false,
+    };
+
+    match (name.get(..split), name.get(split..)) {
+        (Some(prefix), Some(suffix)) => {
+            (prefix.is_empty() || prefix.ends_with('.')) && suffix.eq_ignore_ascii_case(zone)
+        }
+        _ => false,
+    }
+}
+
+/// Parses the root hints, returning the root name servers.
+fn parse_hints(s: &str) -> Vec<NameServer> {
+    let mut names = Vec::new();
+    let mut addrs: HashMap<String, Vec<SocketAddr>> = HashMap::new();
+
+    for line in s.lines() {
+        let line = match line.find(';') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+
+        let mut words = line.split_whitespace();
+        let owner = match words.next() {
+            Some(owner) => normalise(owner),
+            None => continue,
+        };
+
+        // Skip the optional TTL and class, to find the type.
+        let r#type =
+            words.find(|word| word.parse::<u32>().is_err() && !word.eq_ignore_ascii_case("IN"));
+
+        match (r#type.map(str::to_uppercase).as_deref(), words.next()) {
+            (Some("NS"), Some(target)) if owner == "." => {
+                let target = normalise(target);
+                if !names.contains(&target) {
+                    names.push(target);
+                }
+            }
+            (Some("A"), Some(ip)) | (Some("AAAA"), Some(ip)) => match ip.parse::<IpAddr>() {
+                Ok(ip) => addrs
+                    .entry(owner)
+                    .or_default()
+                    .push(SocketAddr::new(ip, 53)),
+                Err(_) => debug!("ignoring invalid root hint '{}'", line),
+            },
+            _ => (), // Ignore everything else
+        }
+    }
+
+    names
+        .into_iter()
+        .map(|name| NameServer {
+            addrs: addrs.remove(&name).unwrap_or_default(),
+            name,
+        })
+        .collect()
+}
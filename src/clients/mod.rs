@@ -1,20 +1,51 @@
 use crate::Message;
 
-#[cfg(feature = "doh")]
-pub mod doh;
+cfg_feature! {
+    #![feature = "doh"]
+
+    mod connector;
+    pub mod doh;
+}
+
+cfg_feature! {
+    #![feature = "odoh"]
+
+    mod hpke;
+    pub mod odoh;
+}
 
 #[cfg(feature = "json")]
 pub mod json;
 
+cfg_feature! {
+    #![feature = "tokio"]
+
+    mod blocking;
+    pub use self::blocking::Blocking;
+}
+
 #[cfg(feature = "tcp")]
 pub mod tcp;
 
+cfg_feature! {
+    #![any(feature = "tcp", feature = "udp")]
+
+    mod transfer;
+    pub use self::transfer::{Diff, Transfer};
+}
+
 cfg_feature! {
     #![feature = "udp"]
 
     pub mod udp;
+    mod hosts;
+    mod iterative;
+    mod resolv_conf;
     mod resolver;
-    pub use self::resolver::Resolver;
+    pub use self::hosts::Hosts;
+    pub use self::iterative::Iterative;
+    pub use self::resolv_conf::ResolvConf;
+    pub use self::resolver::{AddressFamily, Answer, Resolver};
 }
 
 cfg_feature! {
@@ -28,16 +59,36 @@ cfg_feature! {
 #[cfg(any(feature = "doh", feature = "json"))]
 mod mime;
 
+mod cache;
+mod race;
+mod selector;
 mod stats;
 
+pub use self::cache::{Cache, Cached};
+pub use self::race::Race;
+pub use self::selector::{Selector, ServerStats};
+
 /// Exchanger takes a query and returns a response.
 pub trait Exchanger {
     fn exchange(&self, query: &Message) -> Result<Message, crate::Error>;
 }
 
+impl<E: Exchanger + ?Sized> Exchanger for Box<E> {
+    fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+        (**self).exchange(query)
+    }
+}
+
 use async_trait::async_trait;
 
 #[async_trait]
 pub trait AsyncExchanger {
     async fn exchange(&self, query: &Message) -> Result<Message, crate::Error>;
 }
+
+#[async_trait]
+impl<E: AsyncExchanger + Send + Sync + ?Sized> AsyncExchanger for Box<E> {
+    async fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+        (**self).exchange(query).await
+    }
+}
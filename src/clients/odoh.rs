@@ -0,0 +1,636 @@
+use crate::bail;
+use crate::clients::connector::{Connector, RemoteAddr};
+use crate::clients::hpke;
+use crate::clients::mime::content_type_equal;
+use crate::clients::stats::StatsBuilder;
+use crate::clients::AsyncExchanger;
+use crate::io::SeekExt;
+use crate::Message;
+use async_trait::async_trait;
+use byteorder::{ReadBytesExt, BE};
+use http::header::*;
+use http::{Method, Request, StatusCode};
+use hyper::{Body, Client as HyperClient};
+use rand::RngCore;
+use std::convert::TryInto;
+use std::io;
+use std::io::Cursor;
+use std::io::Read;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use url::Url;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+pub const CLOUDFLARE: &str = "https://odoh.cloudflare-dns.com/dns-query";
+
+// For use in Content-type and Accept headers
+pub const CONTENT_TYPE_APPLICATION_OBLIVIOUS_DNS_MESSAGE: &str = "application/oblivious-dns-message";
+
+// Where the target's configs are found, relative to the target.
+const ODOH_CONFIGS_PATH: &str = "/.well-known/odohconfigs";
+
+// The param names the proxy uses to find the target.
+const TARGET_HOST_PARAM: &str = "targethost";
+const TARGET_PATH_PARAM: &str = "targetpath";
+
+/// The only version of [`Config`] defined by [rfc9230].
+///
+/// [rfc9230]: https://datatracker.ietf.org/doc/html/rfc9230#section-6
+const ODOH_VERSION: u16 = 0x0001;
+
+const MESSAGE_TYPE_QUERY: u8 = 0x01;
+const MESSAGE_TYPE_RESPONSE: u8 = 0x02;
+
+// Queries are padded to a multiple of this size, as recommended by rfc8467.
+const QUERY_PADDING_BLOCK_SIZE: usize = 128;
+
+/// The length of the nonce used to encrypt a response, max(Nn, Nk).
+const RESPONSE_NONCE_LEN: usize = if hpke::NN > hpke::NK {
+    hpke::NN
+} else {
+    hpke::NK
+};
+
+/// A target's public key configuration (`ObliviousDoHConfigContents`), as
+/// served from `/.well-known/odohconfigs`. See [rfc9230].
+///
+/// Only the DHKEM(X25519, HKDF-SHA256), HKDF-SHA256, AES-128-GCM suite is
+/// supported.
+///
+/// [rfc9230]: https://datatracker.ietf.org/doc/html/rfc9230#section-6
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Config {
+    pub kem_id: u16,
+    pub kdf_id: u16,
+    pub aead_id: u16,
+    pub public_key: Vec<u8>,
+}
+
+impl Config {
+    /// Decodes a `ObliviousDoHConfigs` structure, returning all the configs.
+    /// Configs with an unknown version are skipped.
+    pub fn from_slice(buf: &[u8]) -> io::Result<Vec<Config>> {
+        let mut cur = Cursor::new(buf);
+        let mut configs = Vec::new();
+
+        let len = cur.read_u16::<BE>()?;
+        if cur.remaining()? != u64::from(len) {
+            bail!(
+                InvalidData,
+                "invalid odohconfigs length {} expected {}",
+                len,
+                cur.remaining()?
+            );
+        }
+
+        while cur.remaining()? > 0 {
+            let version = cur.read_u16::<BE>()?;
+            let contents = read_vec(&mut cur)?;
+
+            if version != ODOH_VERSION {
+                continue;
+            }
+
+            let mut contents = Cursor::new(&contents[..]);
+            configs.push(Config {
+                kem_id: contents.read_u16::<BE>()?,
+                kdf_id: contents.read_u16::<BE>()?,
+                aead_id: contents.read_u16::<BE>()?,
+                public_key: read_vec(&mut contents)?,
+            });
+        }
+
+        Ok(configs)
+    }
+
+    /// Encodes the configs as a `ObliviousDoHConfigs` structure.
+    pub fn to_vec(configs: &[Config]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for config in configs {
+            buf.extend_from_slice(&ODOH_VERSION.to_be_bytes());
+            write_vec(&mut buf, &config.contents());
+        }
+
+        let mut result = Vec::with_capacity(buf.len() + 2);
+        write_vec(&mut result, &buf);
+        result
+    }
+
+    /// Returns true if this config uses a cipher suite we support.
+    pub fn is_supported(&self) -> bool {
+        self.kem_id == hpke::KEM_ID
+            && self.kdf_id == hpke::KDF_ID
+            && self.aead_id == hpke::AEAD_ID
+            && self.public_key.len() == hpke::NENC
+    }
+
+    /// The encoded `ObliviousDoHConfigContents`.
+    fn contents(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.kem_id.to_be_bytes());
+        buf.extend_from_slice(&self.kdf_id.to_be_bytes());
+        buf.extend_from_slice(&self.aead_id.to_be_bytes());
+        write_vec(&mut buf, &self.public_key);
+        buf
+    }
+
+    /// The identifier of this config's key, sent with each query.
+    fn key_id(&self) -> Vec<u8> {
+        hpke::expand(&hpke::extract(b"", &self.contents()), b"odoh key id", hpke::NH)
+    }
+
+    fn public_key(&self) -> io::Result<PublicKey> {
+        let key: [u8; hpke::NENC] = match self.public_key.as_slice().try_into() {
+            Ok(key) => key,
+            Err(_) => bail!(InvalidData, "unsupported odoh public key"),
+        };
+        Ok(PublicKey::from(key))
+    }
+}
+
+/// Reads a u16 length prefixed byte string.
+fn read_vec(cur: &mut Cursor<&[u8]>) -> io::Result<Vec<u8>> {
+    let len = cur.read_u16::<BE>()?;
+    let mut buf = vec![0; len.into()];
+    cur.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Writes a u16 length prefixed byte string.
+fn write_vec(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Encodes a `ObliviousDoHMessage`.
+fn encode_message(message_type: u8, key_id: &[u8], encrypted: &[u8]) -> Vec<u8> {
+    let mut buf = vec![message_type];
+    write_vec(&mut buf, key_id);
+    write_vec(&mut buf, encrypted);
+    buf
+}
+
+/// Decodes a `ObliviousDoHMessage`, returning the key id and encrypted message.
+fn decode_message(message_type: u8, buf: &[u8]) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut cur = Cursor::new(buf);
+    let got = cur.read_u8()?;
+    if got != message_type {
+        bail!(
+            InvalidData,
+            "unexpected odoh message type {} expected {}",
+            got,
+            message_type
+        );
+    }
+
+    let key_id = read_vec(&mut cur)?;
+    let encrypted = read_vec(&mut cur)?;
+
+    if cur.remaining()? > 0 {
+        bail!(InvalidData, "odoh message has trailing bytes");
+    }
+
+    Ok((key_id, encrypted))
+}
+
+/// Encodes a `ObliviousDoHMessagePlaintext`.
+fn encode_plaintext(dns_message: &[u8], padding: usize) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(dns_message.len() + padding + 4);
+    write_vec(&mut buf, dns_message);
+    write_vec(&mut buf, &vec![0; padding]);
+    buf
+}
+
+/// Decodes a `ObliviousDoHMessagePlaintext` returning the DNS message.
+fn decode_plaintext(buf: &[u8]) -> io::Result<Message> {
+    let mut cur = Cursor::new(buf);
+    let dns_message = read_vec(&mut cur)?;
+    let padding = read_vec(&mut cur)?;
+
+    if padding.iter().any(|b| *b != 0) {
+        bail!(InvalidData, "odoh message padding is not zero");
+    }
+
+    Message::from_slice(&dns_message)
+}
+
+/// The aad for a message of `message_type` with the `key_id`.
+fn aad(message_type: u8, key_id: &[u8]) -> Vec<u8> {
+    let mut aad = vec![message_type];
+    write_vec(&mut aad, key_id);
+    aad
+}
+
+/// Derives the response key and nonce, from the query's context.
+fn response_key(
+    context: &hpke::Context,
+    query_plaintext: &[u8],
+    response_nonce: &[u8],
+) -> (Vec<u8>, Vec<u8>) {
+    let secret = context.export(b"odoh response", hpke::NK);
+
+    let mut salt = query_plaintext.to_vec();
+    write_vec(&mut salt, response_nonce);
+
+    let prk = hpke::extract(&salt, &secret);
+    let key = hpke::expand(&prk, b"odoh key", hpke::NK);
+    let nonce = hpke::expand(&prk, b"odoh nonce", hpke::NN);
+
+    (key, nonce)
+}
+
+/// The target side of Oblivious DoH. Holds the private key, decrypts queries
+/// and encrypts their responses.
+///
+/// # Example
+///
+/// ```rust
+/// use rustdns::clients::odoh::Target;
+///
+/// let target = Target::new();
+///
+/// // Serve this from /.well-known/odohconfigs
+/// let configs = target.configs();
+/// ```
+pub struct Target {
+    secret: StaticSecret,
+    config: Config,
+}
+
+/// Holds the state needed to encrypt the response to a decrypted query.
+pub struct ResponseContext {
+    context: hpke::Context,
+    query_plaintext: Vec<u8>,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Target {
+    /// Creates a new Target with a random key.
+    pub fn new() -> Target {
+        Self::from_secret(hpke::generate_secret().to_bytes())
+    }
+
+    /// Creates a new Target with the given X25519 private key.
+    pub fn from_secret(secret: [u8; 32]) -> Target {
+        let secret = StaticSecret::from(secret);
+        let config = Config {
+            kem_id: hpke::KEM_ID,
+            kdf_id: hpke::KDF_ID,
+            aead_id: hpke::AEAD_ID,
+            public_key: PublicKey::from(&secret).as_bytes().to_vec(),
+        };
+
+        Target { secret, config }
+    }
+
+    /// Returns this target's config.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the encoded `ObliviousDoHConfigs`, suitable for serving from
+    /// `/.well-known/odohconfigs`.
+    pub fn configs(&self) -> Vec<u8> {
+        Config::to_vec(std::slice::from_ref(&self.config))
+    }
+
+    /// Decrypts a query sent by a client, returning the DNS [`Message`] and
+    /// a [`ResponseContext`] to encrypt the response with.
+    pub fn decrypt_query(&self, buf: &[u8]) -> io::Result<(Message, ResponseContext)> {
+        let (key_id, encrypted) = decode_message(MESSAGE_TYPE_QUERY, buf)?;
+        if key_id != self.config.key_id() {
+            bail!(InvalidData, "odoh query for unknown key id");
+        }
+        if encrypted.len() < hpke::NENC {
+            bail!(InvalidData, "odoh query too short");
+        }
+
+        let (enc, ct) = encrypted.split_at(hpke::NENC);
+        let mut context = hpke::Context::receiver(enc, &self.secret, b"odoh query")?;
+
+        let query_plaintext = context.open(&aad(MESSAGE_TYPE_QUERY, &key_id), ct)?;
+        let query = decode_plaintext(&query_plaintext)?;
+
+        Ok((
+            query,
+            ResponseContext {
+                context,
+                query_plaintext,
+            },
+        ))
+    }
+}
+
+impl ResponseContext {
+    /// Encrypts the response to the query this context was created from.
+    pub fn encrypt_response(&self, response: &Message) -> io::Result<Vec<u8>> {
+        let mut response_nonce = vec![0; RESPONSE_NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut response_nonce);
+
+        let (key, nonce) = response_key(&self.context, &self.query_plaintext, &response_nonce);
+
+        let plaintext = encode_plaintext(&response.to_vec()?, 0);
+        let ct = hpke::seal(
+            &key,
+            &nonce,
+            &aad(MESSAGE_TYPE_RESPONSE, &response_nonce),
+            &plaintext,
+        );
+
+        Ok(encode_message(MESSAGE_TYPE_RESPONSE, &response_nonce, &ct))
+    }
+}
+
+/// A Oblivious DNS over HTTPS (ODoH) Client (rfc9230).
+///
+/// Queries are encrypted to the target's public key, and sent via the proxy,
+/// so the proxy learns who is asking but not the question, while the target
+/// learns the question but not who is asking.
+///
+/// # Example
+///
+/// ```rust
+/// use rustdns::clients::AsyncExchanger;
+/// use rustdns::clients::odoh::Client;
+/// use rustdns::types::*;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), rustdns::Error> {
+///     let mut query = Message::default();
+///     query.add_question("bramp.net", Type::A, Class::Internet);
+///
+///     let client = Client::new(
+///         "https://odoh.cloudflare-dns.com/dns-query",
+///         "https://odoh-proxy.example.com/proxy",
+///     )?;
+///
+///     // The response can then be fetched with:
+///     // client.exchange(&query).await?
+///     Ok(())
+/// }
+/// ```
+///
+/// See <https://datatracker.ietf.org/doc/html/rfc9230>
+pub struct Client {
+    target: Url,
+    proxy: Url,
+
+    // Where to fetch the target's config from.
+    configs_url: Url,
+
+    // The target's config, fetched on first use.
+    config: Mutex<Option<Config>>,
+
+    headers: HeaderMap, // Extra headers added to every request to the proxy
+}
+
+impl Client {
+    /// Creates a new Client that sends queries for the `target` via the `proxy`.
+    pub fn new(target: &str, proxy: &str) -> Result<Self, crate::Error> {
+        let target: Url = target.parse()?;
+        let proxy: Url = proxy.parse()?;
+
+        let mut configs_url = target.clone();
+        configs_url.set_path(ODOH_CONFIGS_PATH);
+        configs_url.set_query(None);
+
+        Ok(Self {
+            target,
+            proxy,
+            configs_url,
+            config: Mutex::new(None),
+            headers: HeaderMap::new(),
+        })
+    }
+
+    /// Uses the given target config, instead of fetching it.
+    pub fn with_config(self, config: Config) -> Self {
+        *self.config.lock().unwrap() = Some(config);
+        self
+    }
+
+    /// Fetches the target's config from this url, instead of `/.well-known/odohconfigs`
+    /// on the target.
+    pub fn with_configs_url(mut self, url: Url) -> Self {
+        self.configs_url = url;
+        self
+    }
+
+    /// Adds a header to every request sent to the proxy.
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    fn http_client() -> HyperClient<Connector> {
+        HyperClient::builder()
+            .pool_idle_timeout(Duration::from_secs(30))
+            .build::<_, hyper::Body>(Connector::new(true, true, None))
+    }
+
+    /// Returns the target's config, fetching it if needed.
+    async fn config(&self) -> Result<Config, crate::Error> {
+        if let Some(config) = self.config.lock().unwrap().as_ref() {
+            return Ok(config.clone());
+        }
+
+        let uri: hyper::Uri = self.configs_url.as_str().parse()?;
+        let resp = Self::http_client().get(uri).await?;
+        if !resp.status().is_success() {
+            bail!(
+                InvalidInput,
+                "recevied unexpected HTTP status code fetching odohconfigs: {:}",
+                resp.status()
+            );
+        }
+
+        let body = hyper::body::to_bytes(resp.into_body()).await?;
+        let config = match Config::from_slice(&body)?
+            .into_iter()
+            .find(Config::is_supported)
+        {
+            Some(config) => config,
+            None => bail!(InvalidData, "target has no supported odoh configs"),
+        };
+
+        *self.config.lock().unwrap() = Some(config.clone());
+        Ok(config)
+    }
+
+    /// The proxy url, with the target's location added.
+    fn proxy_url(&self) -> Url {
+        let mut host = self.target.host_str().unwrap_or_default().to_string();
+        if let Some(port) = self.target.port() {
+            host = format!("{}:{}", host, port);
+        }
+
+        let mut url = self.proxy.clone();
+        url.query_pairs_mut()
+            .append_pair(TARGET_HOST_PARAM, &host)
+            .append_pair(TARGET_PATH_PARAM, self.target.path());
+        url
+    }
+}
+
+#[async_trait]
+impl AsyncExchanger for Client {
+    /// Encrypts the [`Message`], sends it via the proxy to the target, and
+    /// returns the decrypted result.
+    async fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+        let config = self.config().await?;
+        let key_id = config.key_id();
+
+        let mut query = query.clone();
+        query.id = 0;
+
+        let dns_message = query.to_vec()?;
+        let padding = (QUERY_PADDING_BLOCK_SIZE - dns_message.len() % QUERY_PADDING_BLOCK_SIZE)
+            % QUERY_PADDING_BLOCK_SIZE;
+        let query_plaintext = encode_plaintext(&dns_message, padding);
+
+        let (enc, mut context) = hpke::Context::sender(&config.public_key()?, b"odoh query");
+        let mut encrypted = enc.to_vec();
+        encrypted.extend(context.seal(&aad(MESSAGE_TYPE_QUERY, &key_id), &query_plaintext));
+
+        let body = encode_message(MESSAGE_TYPE_QUERY, &key_id, &encrypted);
+
+        let mut req = Request::builder().method(Method::POST);
+        if let Some(headers) = req.headers_mut() {
+            headers.extend(self.headers.clone());
+            headers.insert(
+                ACCEPT,
+                HeaderValue::from_static(CONTENT_TYPE_APPLICATION_OBLIVIOUS_DNS_MESSAGE),
+            );
+            headers.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static(CONTENT_TYPE_APPLICATION_OBLIVIOUS_DNS_MESSAGE),
+            );
+        }
+        let req = req.uri(self.proxy_url().as_str()).body(Body::from(body))?;
+
+        let stats = StatsBuilder::start(0);
+        let resp = Self::http_client().request(req).await?;
+
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            // The target may have rotated its key. Fetch it again next time.
+            *self.config.lock().unwrap() = None;
+        }
+
+        if !resp.status().is_success() {
+            bail!(
+                InvalidInput,
+                "recevied unexpected HTTP status code: {:}",
+                resp.status()
+            );
+        }
+
+        if let Some(content_type) = resp.headers().get(CONTENT_TYPE) {
+            if !content_type_equal(content_type, CONTENT_TYPE_APPLICATION_OBLIVIOUS_DNS_MESSAGE) {
+                bail!(
+                    InvalidData,
+                    "recevied invalid content-type: {:?} expected {}",
+                    content_type,
+                    CONTENT_TYPE_APPLICATION_OBLIVIOUS_DNS_MESSAGE,
+                );
+            }
+        }
+
+        let remote_addr = match resp.extensions().get::<RemoteAddr>() {
+            Some(RemoteAddr(addr)) => *addr,
+            None => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0), // Dummy address
+        };
+
+        let body = hyper::body::to_bytes(resp.into_body()).await?;
+
+        let (response_nonce, ct) = decode_message(MESSAGE_TYPE_RESPONSE, &body)?;
+        let (key, nonce) = response_key(&context, &query_plaintext, &response_nonce);
+        let plaintext = hpke::open(
+            &key,
+            &nonce,
+            &aad(MESSAGE_TYPE_RESPONSE, &response_nonce),
+            &ct,
+        )?;
+
+        let mut m = decode_plaintext(&plaintext)?;
+        m.stats = Some(stats.end(remote_addr, body.len()));
+
+        Ok(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_configs_round_trip() {
+        let target = Target::from_secret([7; 32]);
+        let configs = Config::from_slice(&target.configs()).unwrap();
+
+        assert_eq!(configs, vec![target.config().clone()]);
+        assert!(configs[0].is_supported());
+    }
+
+    #[test]
+    fn test_configs_skips_unknown_versions() {
+        let config = Target::from_secret([7; 32]).config().clone();
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&0xff00_u16.to_be_bytes());
+        write_vec(&mut buf, &[1, 2, 3]);
+        buf.extend_from_slice(&ODOH_VERSION.to_be_bytes());
+        write_vec(&mut buf, &config.contents());
+
+        let mut configs = Vec::new();
+        write_vec(&mut configs, &buf);
+
+        assert_eq!(Config::from_slice(&configs).unwrap(), vec![config]);
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let target = Target::new();
+        let config = target.config().clone();
+        let key_id = config.key_id();
+
+        let mut query = Message::default();
+        query.add_question("bramp.net", Type::A, Class::Internet);
+
+        let query_plaintext = encode_plaintext(&query.to_vec().unwrap(), 10);
+        let (enc, mut context) =
+            hpke::Context::sender(&config.public_key().unwrap(), b"odoh query");
+        let mut encrypted = enc.to_vec();
+        encrypted.extend(context.seal(&aad(MESSAGE_TYPE_QUERY, &key_id), &query_plaintext));
+
+        let (got, response_context) = target
+            .decrypt_query(&encode_message(MESSAGE_TYPE_QUERY, &key_id, &encrypted))
+            .unwrap();
+        assert_eq!(got, query);
+
+        let mut response = got.clone();
+        response.qr = QR::Response;
+
+        let body = response_context.encrypt_response(&response).unwrap();
+        let (response_nonce, ct) = decode_message(MESSAGE_TYPE_RESPONSE, &body).unwrap();
+        let (key, nonce) = response_key(&context, &query_plaintext, &response_nonce);
+        let plaintext = hpke::open(
+            &key,
+            &nonce,
+            &aad(MESSAGE_TYPE_RESPONSE, &response_nonce),
+            &ct,
+        )
+        .unwrap();
+
+        assert_eq!(decode_plaintext(&plaintext).unwrap(), response);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::bail;
+use crate::clients::Exchanger;
+use crate::Message;
+use crate::Rcode;
+use log::debug;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Races a query across multiple clients, returning the first valid answer.
+///
+/// The query is first sent with the first client. If no answer is received
+/// within the delay, or that client fails, the query is also sent with the
+/// next client, and so on, in a similar way to Happy Eyeballs ([rfc8305]).
+/// The first valid response (one that isn't an error, SERVFAIL, REFUSED,
+/// FORMERR or NOTIMP) is returned, and the remaining queries are cancelled.
+/// If every client fails, the last failure is returned.
+///
+/// The [`Stats`](crate::Stats) of the returned [`Message`] are those of the
+/// winning query, so record which server answered.
+///
+/// To race different transports, the clients may be boxed trait objects, for
+/// example UDP and DoH:
+///
+/// ```rust,no_run
+/// use rustdns::clients::{doh, udp, AsyncExchanger, Race};
+/// use rustdns::types::*;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), rustdns::Error> {
+///     let clients: Vec<Box<dyn AsyncExchanger + Send + Sync>> = vec![
+///         Box::new(udp::Client::new("8.8.8.8:53")?),
+///         Box::new(doh::Client::new(doh::GOOGLE, Default::default())?),
+///     ];
+///
+///     let mut query = Message::default();
+///     query.add_question("bramp.net", Type::A, Class::Internet);
+///
+///     let response = Race::new(clients).exchange(&query).await?;
+///     println!("{} won", response.stats.unwrap().server);
+///     Ok(())
+/// }
+/// ```
+///
+/// [rfc8305]: https://datatracker.ietf.org/doc/html/rfc8305
+pub struct Race<E> {
+    clients: Vec<Arc<E>>,
+
+    /// How long to wait before starting the next query.
+    delay: Duration,
+}
+
+/// Returns true if the result is an answer worth returning.
+fn is_valid(result: &Result<Message, crate::Error>) -> bool {
+    match result {
+        Ok(resp) => !matches!(
+            resp.rcode,
+            Rcode::ServFail | Rcode::Refused | Rcode::FormErr | Rcode::NotImp
+        ),
+        Err(_) => false,
+    }
+}
+
+impl<E> Race<E> {
+    /// Creates a new Race between the clients, in the order they should be
+    /// started.
+    pub fn new(clients: Vec<E>) -> Race<E> {
+        Race {
+            clients: clients.into_iter().map(Arc::new).collect(),
+            delay: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets the delay between starting each query. Defaults to 100ms.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+impl<E> Exchanger for Race<E>
+where
+    E: Exchanger + Send + Sync + 'static,
+{
+    /// Races the query across the clients. Each query is made on its own
+    /// thread. Queries already in flight can't be stopped, so once a answer
+    /// is found their results are ignored.
+    fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+        let (tx, rx) = mpsc::channel();
+
+        let mut waiting = self.clients.iter().enumerate();
+        let mut running = 0;
+        let mut last = None;
+
+        loop {
+            let result = if running == 0 {
+                None
+            } else if waiting.len() == 0 {
+                rx.recv().ok()
+            } else {
+                rx.recv_timeout(self.delay).ok()
+            };
+
+            match result {
+                Some((i, result)) => {
+                    running -= 1;
+                    if is_valid(&result) {
+                        return result;
+                    }
+
+                    debug!("racing client {} failed", i);
+                    last = Some(result);
+                }
+
+                // Nothing has finished, so fall through and start the next.
+                None if waiting.len() > 0 => (),
+
+                None => break,
+            }
+
+            if let Some((i, client)) = waiting.next() {
+                let client = client.clone();
+                let query = query.clone();
+                let tx = tx.clone();
+
+                thread::spawn(move || {
+                    // Fails if the race is already over, which is fine.
+                    let _ = tx.send((i, client.exchange(&query)));
+                });
+                running += 1;
+            }
+        }
+
+        match last {
+            Some(result) => result,
+            None => bail!(InvalidInput, "no clients to race"),
+        }
+    }
+}
+
+cfg_feature! {
+    #![feature = "tokio"]
+
+    use crate::clients::AsyncExchanger;
+    use async_trait::async_trait;
+    use futures_util::stream::{FuturesUnordered, StreamExt};
+    use tokio::time::timeout;
+
+    #[async_trait]
+    impl<E> AsyncExchanger for Race<E>
+    where
+        E: AsyncExchanger + Send + Sync,
+    {
+        /// Races the query across the clients. Once a answer is found, the
+        /// remaining queries are dropped, and thus cancelled.
+        async fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+            let mut waiting = self.clients.iter().enumerate();
+            let mut running = FuturesUnordered::new();
+            let mut last = None;
+
+            loop {
+                let result = if running.is_empty() {
+                    None
+                } else if waiting.len() == 0 {
+                    running.next().await
+                } else {
+                    timeout(self.delay, running.next()).await.unwrap_or(None)
+                };
+
+                match result {
+                    Some((i, result)) => {
+                        if is_valid(&result) {
+                            return result;
+                        }
+
+                        debug!("racing client {} failed", i);
+                        last = Some(result);
+                    }
+
+                    // Nothing has finished, so fall through and start the next.
+                    None if waiting.len() > 0 => (),
+
+                    None => break,
+                }
+
+                if let Some((i, client)) = waiting.next() {
+                    running.push(async move { (i, client.exchange(query).await) });
+                }
+            }
+
+            match last {
+                Some(result) => result,
+                None => bail!(InvalidInput, "no clients to race"),
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use crate::clients::udp::Client as UdpClient;
+use crate::clients::Exchanger;
+use crate::clients::Resolver;
+use crate::clients::Selector;
+use log::warn;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+#[cfg(feature = "tcp")]
+use crate::clients::tcp::Client as TcpClient;
+
+/// The most name servers that will be used, the same as glibc's `MAXNS`.
+const MAX_NAMESERVERS: usize = 3;
+
+/// The most search domains that will be used, the same as glibc.
+const MAX_SEARCH: usize = 6;
+
+/// The system resolver configuration, as typically found in
+/// `/etc/resolv.conf`. See [resolv.conf(5)].
+///
+/// Only the `nameserver`, `domain`, `search` and `options` keywords are
+/// understood, and of the options only `ndots`, `timeout`, `attempts`,
+/// `rotate`, `edns0` and `use-vc`. Everything else, including invalid lines,
+/// is ignored, in the same way as the system resolver.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rustdns::clients::ResolvConf;
+///
+/// fn main() -> Result<(), rustdns::Error> {
+///     let resolver = ResolvConf::load()?.build()?;
+///     let ips = resolver.lookup("bramp.net")?;
+///     Ok(())
+/// }
+/// ```
+///
+/// [resolv.conf(5)]: https://man7.org/linux/man-pages/man5/resolv.conf.5.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvConf {
+    /// The name servers to query, in order.
+    pub nameservers: Vec<SocketAddr>,
+
+    /// The domains appended to names when searching. Set by either the
+    /// `search` or `domain` keyword, whichever is last.
+    pub search: Vec<String>,
+
+    /// Names with fewer dots than this are tried with the search domains
+    /// first.
+    pub ndots: usize,
+
+    /// How long to wait for a response from a name server.
+    pub timeout: Duration,
+
+    /// How many times to try the name servers, before giving up.
+    pub attempts: usize,
+
+    /// Spread the queries across the name servers in turn, instead of
+    /// preferring the best.
+    pub rotate: bool,
+
+    /// Send EDNS(0) extensions with queries. Unlike the system resolver,
+    /// this is on by default, as truncated responses aren't retried over
+    /// TCP, so `options edns0` only confirms it.
+    pub edns0: bool,
+
+    /// Query the name servers with TCP instead of UDP.
+    pub use_vc: bool,
+}
+
+impl Default for ResolvConf {
+    /// The defaults used by the system resolver, when not configured, except
+    /// EDNS(0) is enabled.
+    fn default() -> Self {
+        ResolvConf {
+            nameservers: Vec::new(),
+            search: Vec::new(),
+            ndots: 1,
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+            rotate: false,
+            edns0: true,
+            use_vc: false,
+        }
+    }
+}
+
+impl ResolvConf {
+    /// The default location of the resolver configuration.
+    pub const DEFAULT_PATH: &'static str = "/etc/resolv.conf";
+
+    /// Reads the configuration from [`Self::DEFAULT_PATH`].
+    pub fn load() -> io::Result<ResolvConf> {
+        Self::from_path(Self::DEFAULT_PATH)
+    }
+
+    /// Reads the configuration from a alternative path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<ResolvConf> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parses the contents of a resolv.conf file.
+    pub fn parse(s: &str) -> ResolvConf {
+        let mut conf = ResolvConf::default();
+
+        for line in s.lines() {
+            // Everything after a ';' or '#' is a comment.
+            let line = match line.find(&[';', '#'][..]) {
+                Some(i) => &line[..i],
+                None => line,
+            };
+
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+
+            match keyword {
+                "nameserver" => match words.next().map(parse_nameserver) {
+                    Some(Some(addr)) => {
+                        if conf.nameservers.len() < MAX_NAMESERVERS {
+                            conf.nameservers.push(addr);
+                        }
+                    }
+                    _ => warn!("ignoring invalid resolv.conf line '{}'", line),
+                },
+
+                "domain" => conf.search = words.take(1).map(str::to_string).collect(),
+
+                "search" => {
+                    conf.search = words.take(MAX_SEARCH).map(str::to_string).collect();
+                }
+
+                "options" => {
+                    for option in words {
+                        conf.parse_option(option);
+                    }
+                }
+
+                _ => (), // Ignore unknown keywords
+            }
+        }
+
+        conf
+    }
+
+    fn parse_option(&mut self, option: &str) {
+        let (name, value) = match option.find(':') {
+            Some(i) => (&option[..i], Some(&option[i + 1..])),
+            None => (option, None),
+        };
+
+        // Values are clamped to the same limits as glibc.
+        let value = value.and_then(|v| v.parse::<usize>().ok());
+        match (name, value) {
+            ("ndots", Some(n)) => self.ndots = n.min(15),
+            ("timeout", Some(n)) => self.timeout = Duration::from_secs(n.clamp(1, 30) as u64),
+            ("attempts", Some(n)) => self.attempts = n.clamp(1, 5),
+            ("rotate", _) => self.rotate = true,
+            ("edns0", _) => self.edns0 = true,
+            ("use-vc", _) | ("usevc", _) => self.use_vc = true,
+            _ => (), // Ignore unknown options
+        }
+    }
+
+    /// Builds a [`Resolver`] using this configuration. If there are no name
+    /// servers, the local server (127.0.0.1) is used.
+    pub fn build(&self) -> Result<Resolver, crate::Error> {
+        let mut nameservers = self.nameservers.clone();
+        if nameservers.is_empty() {
+            nameservers.push(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53));
+        }
+
+        let mut clients = Vec::new();
+        for server in nameservers {
+            clients.push((server, self.client(server)?));
+        }
+
+        let client = Selector::new_with_clients(clients).with_rotate(self.rotate);
+        Ok(Resolver::new_with_client(client).with_conf(self.clone()))
+    }
+
+    fn client(&self, server: SocketAddr) -> Result<Box<dyn Exchanger + Send + Sync>, crate::Error> {
+        #[cfg(feature = "tcp")]
+        if self.use_vc {
+            let client = TcpClient::new(server)?.with_read_timeout(Some(self.timeout));
+            return Ok(Box::new(client));
+        }
+
+        let client = UdpClient::new(server)?.with_read_timeout(Some(self.timeout));
+        Ok(Box::new(client))
+    }
+}
+
+/// Parses a name server address, which may be IPv4 or IPv6, with an optional
+/// IPv6 scope id (which is ignored).
+fn parse_nameserver(addr: &str) -> Option<SocketAddr> {
+    let addr = match addr.find('%') {
+        Some(i) => &addr[..i],
+        None => addr,
+    };
+
+    addr.parse::<IpAddr>()
+        .ok()
+        .map(|ip| SocketAddr::new(ip, 53))
+}
@@ -1,30 +1,72 @@
 use crate::bail;
-use crate::clients::udp::Client as UdpClient;
+use crate::clients::Cache;
 use crate::clients::Exchanger;
+use crate::clients::Hosts;
+use crate::clients::ResolvConf;
+use crate::clients::Selector;
+use crate::clients::ServerStats;
 use crate::types::*;
+use crate::util::find_alias;
+use crate::util::reverse;
 use crate::Extension;
+use crate::LookupError;
 use crate::Message;
-use std::collections::HashSet;
+use crate::MX;
+use crate::SOA;
+use crate::SRV;
+use crate::TXT;
+use log::debug;
+use log::warn;
+use std::collections::HashMap;
+use std::io;
 use std::net::IpAddr;
-use std::net::SocketAddr;
-use std::net::ToSocketAddrs;
+use std::panic;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Work to be done with the client on a background thread.
+type Task<E> = Box<dyn FnOnce(&E) + Send>;
+
+/// The questions being refreshed in the background, and who is waiting for
+/// each response.
+type Refreshing = Mutex<HashMap<(String, Type, Class), Vec<mpsc::Sender<Message>>>>;
 
 // TODO https://docs.rs/hyper/0.14.9/src/hyper/client/connect/http.rs.html#32-35
 // https://docs.rs/hyper/0.14.9/src/hyper/client/client.rs.html#26-31
 // Lots of good example:
 //   https://docs.rs/tower/0.4.8/src/tower/limit/concurrency/service.rs.html#26-55
-pub struct Resolver<E = UdpClient> {
-    client: E,
-}
+pub struct Resolver<E = Selector<Box<dyn Exchanger + Send + Sync>>> {
+    client: Arc<E>,
+    conf: ResolvConf,
+
+    /// Consulted before the client, if set.
+    hosts: Option<Hosts>,
+
+    /// Responses from the client are cached here, if set.
+    cache: Option<Arc<Cache>>,
+
+    /// Refresh popular cached responses before they expire.
+    prefetch: bool,
+
+    /// How long to wait for the client, before serving a stale response.
+    stale_timeout: Option<Duration>,
+
+    /// Only one refresh of each question is sent at a time.
+    refreshing: Arc<Refreshing>,
 
-// TODO
+    /// Which addresses [`Resolver::lookup`] returns.
+    family: AddressFamily,
 
-//
-// Should track the RA bit from remove servers (to know if they support recursion)
-// Should track `batting stats`, distribution of delays, etc.
-// 1. Host name to host address translation. (name -> ips)
-// 2. Host address to host name translation. (ip -> name)
-// 3. General lookup function. (name, type -> records)
+    /// The most CNAME or DNAME records that will be followed for one query.
+    max_aliases: usize,
+
+    /// Runs a task on a background thread. Only set if the client can be
+    /// shared with other threads.
+    spawn: Option<Box<dyn Fn(Task<E>) + Send + Sync>>,
+}
 
 impl Default for Resolver {
     fn default() -> Self {
@@ -33,16 +75,38 @@ impl Default for Resolver {
 }
 
 impl Resolver {
-    /// Creates a new Resolver using the system's default DNS server.
-    pub fn new() -> Resolver<UdpClient> {
-        let servers = crate::clients::udp::GOOGLE
-            .iter()
-            .flat_map(|a| a.to_socket_addrs())
-            .flatten()
-            .collect::<Vec<SocketAddr>>();
+    /// Creates a new Resolver using the system's default DNS servers, as
+    /// configured in `/etc/resolv.conf`. If that can't be read or used, the
+    /// local server is used. Names in `/etc/hosts` are answered without
+    /// querying any server, and responses are cached.
+    ///
+    /// Each query is sent to whichever server has been performing best, see
+    /// [`Selector`].
+    pub fn new() -> Resolver {
+        let conf = ResolvConf::load().unwrap_or_default();
+        conf.build()
+            .or_else(|e| {
+                warn!("failed to use {}: {}", ResolvConf::DEFAULT_PATH, e);
+                ResolvConf::default().build()
+            })
+            .unwrap_or_else(|e| {
+                warn!("failed to use the local server: {}", e);
 
-        let client = UdpClient::new(&servers[..]).unwrap(); // TODO Fix this
-        Resolver::new_with_client(client)
+                // Every lookup will fail, as there are no servers.
+                Resolver::new_with_client(Selector::new_with_clients(Vec::new())).with_conf(conf)
+            })
+            .with_hosts(Hosts::load())
+            .with_cache(Cache::default())
+    }
+}
+
+impl<E> Resolver<Selector<E>>
+where
+    E: Exchanger,
+{
+    /// Returns the collected stats for each of the upstream servers.
+    pub fn server_stats(&self) -> Vec<ServerStats> {
+        self.client.stats()
     }
 }
 
@@ -50,14 +114,390 @@ impl<E> Resolver<E>
 where
     E: Exchanger,
 {
-    /// Creates a new Resolver using the system's default DNS server.
+    /// Creates a new Resolver that sends all queries to the client.
     pub fn new_with_client(client: E) -> Resolver<E> {
-        Resolver { client }
+        Resolver {
+            client: Arc::new(client),
+            conf: ResolvConf::default(),
+            hosts: None,
+            cache: None,
+            prefetch: false,
+            stale_timeout: None,
+            refreshing: Arc::default(),
+            family: AddressFamily::default(),
+            max_aliases: 8,
+            spawn: None,
+        }
+    }
+
+    /// Sets a hosts file, which is consulted for A, AAAA and PTR queries
+    /// before the client.
+    pub fn with_hosts(mut self, hosts: Hosts) -> Self {
+        self.hosts = Some(hosts);
+        self
+    }
+
+    /// Sets the configuration, such as the search domains and options. The
+    /// name servers in the configuration are ignored, as queries are always
+    /// sent to this Resolver's client.
+    pub fn with_conf(mut self, conf: ResolvConf) -> Self {
+        self.conf = conf;
+        self
+    }
+
+    /// Sets a cache, which is consulted before the client, and stores the
+    /// client's responses.
+    ///
+    /// If the cache is serving stale responses (see
+    /// [`Cache::with_serve_stale`]), they are returned when the client fails.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
     }
 
-    //pub fn new_with_client(Exchanger)
+    /// Returns the cache, if there is one.
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_deref()
+    }
+
+    /// Sets which addresses [`Resolver::lookup`] returns, and thus which
+    /// queries it sends. Defaults to both IPv4 and IPv6.
+    pub fn with_address_family(mut self, family: AddressFamily) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Sets the most CNAME or DNAME records that will be followed, before
+    /// giving up. Defaults to 8.
+    pub fn with_max_aliases(mut self, max_aliases: usize) -> Self {
+        self.max_aliases = max_aliases;
+        self
+    }
+
+    /// Returns the configuration.
+    pub fn conf(&self) -> &ResolvConf {
+        &self.conf
+    }
+
+    /// Sends the query to the client, trying up to `attempts` times. The
+    /// cache is checked first, and updated with the response. If the client
+    /// fails, a stale response is returned, if the cache has one.
+    fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return exchange_with_attempts(&*self.client, self.conf.attempts, query),
+        };
+
+        if let Some(response) = cache.get(query) {
+            if self.prefetch && cache.should_prefetch(query) {
+                debug!("prefetching {}", query.questions[0].name);
+                self.refresh(query, None);
+            }
+            return Ok(response);
+        }
+
+        let stale = cache.get_stale(query);
+
+        let result = match self.stale_timeout {
+            // Query in the background, so the stale response can be returned
+            // if the client takes too long. The cache is still updated once
+            // the client responds.
+            Some(timeout) if stale.is_some() && self.spawn.is_some() => {
+                let (tx, rx) = mpsc::channel();
+                self.refresh(query, Some(tx));
+
+                // Disconnected if the client failed.
+                rx.recv_timeout(timeout)
+                    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "client timed out").into())
+            }
+
+            _ => {
+                let result = exchange_with_attempts(&*self.client, self.conf.attempts, query);
+                if let Ok(response) = &result {
+                    cache.insert(query, response);
+                }
+                result
+            }
+        };
+
+        match (stale, &result) {
+            (Some(stale), Err(_)) => Ok(stale),
+            (Some(stale), Ok(response)) if is_failure(response) => Ok(stale),
+            _ => result,
+        }
+    }
+
+    /// Sends the query on a background thread, caching the response, and
+    /// then sending it to `tx` (if set). If the same question is already
+    /// being refreshed, `tx` waits for that response instead. Does nothing
+    /// if there is no way to run things in the background.
+    fn refresh(&self, query: &Message, tx: Option<mpsc::Sender<Message>>) {
+        let spawn = match &self.spawn {
+            Some(spawn) => spawn,
+            None => return,
+        };
+
+        let key = match query.questions.as_slice() {
+            [q] => (q.name.to_lowercase(), q.r#type, q.class),
+            _ => return,
+        };
+
+        {
+            let mut refreshing = self.refreshing.lock().unwrap();
+            if let Some(waiting) = refreshing.get_mut(&key) {
+                waiting.extend(tx);
+                return;
+            }
+            refreshing.insert(key.clone(), tx.into_iter().collect());
+        }
+
+        let cache = self.cache.clone();
+        let refreshing = self.refreshing.clone();
+        let attempts = self.conf.attempts;
+        let query = query.clone();
+
+        spawn(Box::new(move |client: &E| {
+            let result = exchange_with_attempts(client, attempts, &query);
+            if let (Some(cache), Ok(response)) = (&cache, &result) {
+                cache.insert(&query, response);
+            }
+
+            // On failure the senders are dropped, which the waiters see.
+            let waiting = refreshing.lock().unwrap().remove(&key);
+            if let (Some(waiting), Ok(response)) = (waiting, result) {
+                for tx in waiting {
+                    // Fails if the caller gave up waiting, which is fine.
+                    let _ = tx.send(response.clone());
+                }
+            }
+        }));
+    }
+
+    /// Returns the names to try for `name`, in order, after applying the
+    /// search list, in the same way as the system resolver.
+    ///
+    /// Absolute names (ending with a '.') are only tried as is. Names with
+    /// at least `ndots` dots are tried as is first, then with each search
+    /// domain. Otherwise they are tried with each search domain first.
+    fn candidates(&self, name: &str) -> Vec<String> {
+        if name.ends_with('.') || self.conf.search.is_empty() {
+            return vec![name.to_string()];
+        }
+
+        let mut candidates: Vec<String> = self
+            .conf
+            .search
+            .iter()
+            .map(|domain| format!("{}.{}", name, domain.trim_end_matches('.')))
+            .collect();
+
+        if name.matches('.').count() >= self.conf.ndots {
+            candidates.insert(0, name.to_string());
+        } else {
+            candidates.push(name.to_string());
+        }
+
+        candidates
+    }
+
+    /// Sends a single query for exactly this name, without the search list.
+    fn query_name(&self, name: &str, r#type: Type) -> Result<Message, crate::Error> {
+        let mut query = Message::default();
+        query.add_question(name, r#type, Class::Internet);
+        if self.conf.edns0 {
+            query.add_extension(Extension {
+                payload_size: 4096, // Allow for bigger responses.
+
+                ..Default::default()
+            });
+        }
+
+        self.exchange(&query)
+    }
+
+    /// Queries for the name and type, applying the search list. Each
+    /// candidate name is tried in turn until one doesn't return NXDOMAIN.
+    ///
+    /// Any CNAME or DNAME records are followed, querying for their targets
+    /// if the server didn't, see [`Answer::aliases`]. A error is returned if
+    /// the aliases loop, or there are more than [`Resolver::with_max_aliases`].
+    ///
+    /// The returned [`Answer`] says which name was found. If no name was
+    /// found, the NXDOMAIN response for the name as given is returned.
+    pub fn query(&self, name: &str, r#type: Type) -> Result<Answer, crate::Error> {
+        if let Some(answer) = self.query_hosts(name, r#type) {
+            return Ok(answer);
+        }
+
+        let mut not_found = None;
+
+        for candidate in self.candidates(name) {
+            let response = self.query_name(&candidate, r#type)?;
+            let found = response
+                .questions
+                .first()
+                .map_or(candidate.clone(), |q| q.name.clone());
+
+            let answer = self.follow(found, r#type, response)?;
+
+            // A alias to a name that doesn't exist, still exists.
+            if answer.response.rcode != Rcode::NXDomain || !answer.aliases.is_empty() {
+                return Ok(answer);
+            }
+
+            debug!("{} does not exist", answer.name);
+            if candidate == name || not_found.is_none() {
+                not_found = Some(answer);
+            }
+        }
+
+        Ok(not_found.expect("there is always one candidate"))
+    }
+
+    /// Follows any CNAME or DNAME records in the response for `name`. The
+    /// aliases within the response are followed first, and if the records
+    /// for the final name aren't there, that name is queried, and so on.
+    ///
+    /// The returned response has all the aliases, followed by the records
+    /// for the final name, in its answers, in the same way as a recursive
+    /// server would.
+    fn follow(
+        &self,
+        name: String,
+        r#type: Type,
+        mut response: Message,
+    ) -> Result<Answer, crate::Error> {
+        // Don't follow aliases when asking for them.
+        if matches!(r#type, Type::CNAME | Type::DNAME | Type::ANY) {
+            return Ok(Answer {
+                name,
+                aliases: Vec::new(),
+                response,
+            });
+        }
+
+        let questions = response.questions.clone();
+        let mut current = name.clone();
+        let mut aliases: Vec<String> = Vec::new();
+        let mut chain = Vec::new();
+        let mut queried = vec![name.to_lowercase()];
+
+        loop {
+            while let Some((records, next)) = find_alias(&response.answers, &current) {
+                if aliases.len() >= self.max_aliases {
+                    bail!(
+                        InvalidData,
+                        "more than {} aliases for {}",
+                        self.max_aliases,
+                        name
+                    );
+                }
+
+                if next.eq_ignore_ascii_case(&name)
+                    || aliases
+                        .iter()
+                        .any(|alias| alias.eq_ignore_ascii_case(&next))
+                {
+                    bail!(InvalidData, "alias loop for {} at {}", name, next);
+                }
+
+                debug!("{} is a alias for {}", current, next);
+                chain.extend(records);
+                aliases.push(next.clone());
+                current = next;
+            }
+
+            let found = response.answers.iter().any(|record| {
+                record.r#type() == r#type && record.name.eq_ignore_ascii_case(&current)
+            });
+
+            // Only NOERROR responses may be missing the records for the final
+            // name. A NXDOMAIN is for the final name.
+            if found
+                || response.rcode != Rcode::NoError
+                || queried.contains(&current.to_lowercase())
+            {
+                break;
+            }
+
+            queried.push(current.to_lowercase());
+            response = self.query_name(&current, r#type)?;
+        }
+
+        if !aliases.is_empty() {
+            let records = response.answers.into_iter().filter(|record| {
+                record.r#type() == r#type && record.name.eq_ignore_ascii_case(&current)
+            });
+
+            chain.extend(records);
+            response.answers = chain;
+            response.questions = questions;
+        }
+
+        Ok(Answer {
+            name,
+            aliases,
+            response,
+        })
+    }
+
+    /// Answers the query from the hosts file, if the name is in it.
+    fn query_hosts(&self, name: &str, r#type: Type) -> Option<Answer> {
+        let hosts = self.hosts.as_ref()?;
+
+        let resources: Vec<Resource> = match r#type {
+            Type::A | Type::AAAA => hosts
+                .lookup(name)?
+                .into_iter()
+                .filter_map(|ip| match ip {
+                    IpAddr::V4(ip4) if r#type == Type::A => Some(Resource::A(ip4)),
+                    IpAddr::V6(ip6) if r#type == Type::AAAA => Some(Resource::AAAA(ip6)),
+                    _ => None,
+                })
+                .collect(),
 
-    /// Resolves a name into one or more IP address.
+            Type::PTR => hosts
+                .lookup_ptr(name)?
+                .into_iter()
+                .map(Resource::PTR)
+                .collect(),
+
+            _ => return None,
+        };
+
+        let mut response = Message {
+            qr: QR::Response,
+            ..Default::default()
+        };
+        response.add_question(name, r#type, Class::Internet);
+
+        let name = response.questions[0].name.clone();
+        for resource in resources {
+            response.answers.push(Record {
+                name: name.clone(),
+                class: Class::Internet,
+                ttl: Duration::default(),
+                resource,
+            });
+        }
+
+        Some(Answer {
+            name,
+            aliases: Vec::new(),
+            response,
+        })
+    }
+
+    /// Resolves a name into one or more IP address. The search list is
+    /// applied to the name, see [`Resolver::query`].
+    ///
+    /// The A and AAAA queries are sent in parallel, and the IPv4 addresses
+    /// are returned before the IPv6 ones. If only one of the queries fails,
+    /// the addresses from the other are returned. Use
+    /// [`Resolver::with_address_family`] to only query for one.
+    ///
+    /// As the queries are sent from multiple threads, the client must be
+    /// [`Send`] and [`Sync`]. Use [`Resolver::query`] with clients that aren't.
     //
     /// See [rfc1035#section-7] and [rfc1034#section-5].
     ///
@@ -65,48 +505,473 @@ where
     /// [rfc1034#section-5]: https://datatracker.ietf.org/doc/html/
     // TODO Should this return a Iterator, or a Vector? Check other APIs.
     // https://docs.rs/tokio/1.6.1/tokio/net/fn.lookup_host.html yield a iterator
-    pub fn lookup(&self, name: &str) -> Result<Vec<IpAddr>, crate::Error> {
-        let mut results = HashSet::new();
+    pub fn lookup(&self, name: &str) -> Result<Vec<IpAddr>, crate::Error>
+    where
+        E: Send + Sync,
+    {
+        self.lookup_family(name, self.family)
+    }
 
-        // TODO Change this to make both DNS requests in parallel
-        // If we returned a iterator, perhaps we could start to return entries
-        // before they have all complete?
+    /// Resolves a name into the addresses of one family, or both.
+    fn lookup_family(&self, name: &str, family: AddressFamily) -> Result<Vec<IpAddr>, crate::Error>
+    where
+        E: Send + Sync,
+    {
+        if let Some(ips) = self.hosts.as_ref().and_then(|hosts| hosts.lookup(name)) {
+            return Ok(ips.into_iter().filter(|ip| family.allows(ip)).collect());
+        }
 
-        // Send two queries, a A and a AAAA.
-        for r#type in &[Type::A, Type::AAAA] {
-            let mut query = Message::default();
-            query.add_question(name, *r#type, Class::Internet);
-            query.add_extension(Extension {
-                payload_size: 4096, // Allow for bigger responses.
+        let types = match family {
+            AddressFamily::Any => vec![Type::A, Type::AAAA],
+            AddressFamily::Ipv4 => vec![Type::A],
+            AddressFamily::Ipv6 => vec![Type::AAAA],
+        };
 
-                ..Default::default()
-            });
+        // Each query applies the search list on its own, and thus finds the
+        // same name, unless the records change between queries.
+        let results = thread::scope(|s| {
+            let (first, rest) = types.split_first().expect("there is always one type");
+
+            let handles: Vec<_> = rest
+                .iter()
+                .map(|&r#type| (r#type, s.spawn(move || self.query(name, r#type))))
+                .collect();
+
+            let mut results = vec![(*first, self.query(name, *first))];
+            for (r#type, handle) in handles {
+                let result = handle.join().unwrap_or_else(|e| panic::resume_unwind(e));
+                results.push((r#type, result));
+            }
+            results
+        });
+
+        let mut ips = Vec::new();
+        let mut succeeded = false;
+        let mut error = None;
+
+        for (r#type, result) in results {
+            let answer = match result {
+                Ok(answer) if answer.response.rcode == Rcode::NoError => answer,
+                Ok(answer) => {
+                    let rcode = answer.response.rcode;
+                    debug!(
+                        "{} {} query failed with rcode: {}",
+                        answer.name, r#type, rcode
+                    );
 
-            let response = self.client.exchange(&query)?; // TODO Better error message
+                    let e = io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("query failed with rcode: {}", rcode),
+                    );
+                    error.get_or_insert(e.into());
+                    continue;
+                }
+                Err(e) => {
+                    debug!("{} {} query failed: {}", name, r#type, e);
+                    error.get_or_insert(e);
+                    continue;
+                }
+            };
 
-            println!(
+            debug!(
                 "{}: Trying {} and got {}",
-                name,
+                answer.name,
                 r#type,
-                response.answers.len()
+                answer.response.answers.len()
             );
+            succeeded = true;
 
-            match response.rcode {
-                Rcode::NoError => (), // Nothing
-                _ => bail!(InvalidInput, "query failed with rcode: {}", response.rcode),
-            };
-
-            for answer in response.answers {
+            for record in answer.response.answers {
                 // TODO Check the answer is for this question.
-                match answer.resource {
-                    Resource::A(ip4) => results.insert(IpAddr::V4(ip4)),
-                    Resource::AAAA(ip6) => results.insert(IpAddr::V6(ip6)),
-                    //Resource::A(ip4) => results.push(ip4),
-                    _ => false, // Ignore other types
+                let ip = match record.resource {
+                    Resource::A(ip4) => IpAddr::V4(ip4),
+                    Resource::AAAA(ip6) => IpAddr::V6(ip6),
+                    _ => continue, // Ignore other types
+                };
+
+                if !ips.contains(&ip) {
+                    ips.push(ip);
+                }
+            }
+        }
+
+        match error {
+            Some(e) if !succeeded => Err(e),
+            _ => Ok(ips),
+        }
+    }
+
+    /// Returns the records of this type for the name. The search list is
+    /// applied to the name, see [`Resolver::query`].
+    pub fn lookup_records(&self, name: &str, r#type: Type) -> Result<Vec<Record>, LookupError> {
+        let answer = self.query(name, r#type)?;
+
+        let name = answer.canonical_name().to_string();
+        match answer.response.rcode {
+            Rcode::NoError => (),
+            Rcode::NXDomain => return Err(LookupError::NxDomain(name)),
+            rcode => return Err(LookupError::ServerFailure(rcode)),
+        }
+
+        let records: Vec<Record> = answer
+            .response
+            .answers
+            .into_iter()
+            .filter(|record| r#type == Type::ANY || record.r#type() == r#type)
+            .collect();
+
+        if records.is_empty() {
+            return Err(LookupError::NoData(name, r#type));
+        }
+
+        Ok(records)
+    }
+
+    /// Returns the resources of this type for the name, converted with `f`.
+    fn lookup_resources<T>(
+        &self,
+        name: &str,
+        r#type: Type,
+        f: impl Fn(Resource) -> Option<T>,
+    ) -> Result<Vec<T>, LookupError> {
+        Ok(self
+            .lookup_records(name, r#type)?
+            .into_iter()
+            .filter_map(|record| f(record.resource))
+            .collect())
+    }
+
+    /// Returns the mail exchanges for the name, sorted by preference (the
+    /// most preferred first).
+    pub fn lookup_mx(&self, name: &str) -> Result<Vec<MX>, LookupError> {
+        let mut mxs = self.lookup_resources(name, Type::MX, |resource| match resource {
+            Resource::MX(mx) => Some(mx),
+            _ => None,
+        })?;
+
+        mxs.sort_by_key(|mx| mx.preference);
+        Ok(mxs)
+    }
+
+    /// Returns the text records for the name.
+    pub fn lookup_txt(&self, name: &str) -> Result<Vec<TXT>, LookupError> {
+        self.lookup_resources(name, Type::TXT, |resource| match resource {
+            Resource::TXT(txt) => Some(txt),
+            _ => None,
+        })
+    }
+
+    /// Returns the service records for the name, such as
+    /// `_sip._tcp.example.com`.
+    pub fn lookup_srv(&self, name: &str) -> Result<Vec<SRV>, LookupError> {
+        self.lookup_resources(name, Type::SRV, |resource| match resource {
+            Resource::SRV(srv) => Some(srv),
+            _ => None,
+        })
+    }
+
+    /// Returns the name servers for the name.
+    pub fn lookup_ns(&self, name: &str) -> Result<Vec<String>, LookupError> {
+        self.lookup_resources(name, Type::NS, |resource| match resource {
+            Resource::NS(ns) => Some(ns),
+            _ => None,
+        })
+    }
+
+    /// Returns the start of authority for the name.
+    pub fn lookup_soa(&self, name: &str) -> Result<SOA, LookupError> {
+        let soas = self.lookup_resources(name, Type::SOA, |resource| match resource {
+            Resource::SOA(soa) => Some(soa),
+            _ => None,
+        })?;
+
+        Ok(soas
+            .into_iter()
+            .next()
+            .expect("lookup_records never returns empty"))
+    }
+
+    /// Returns the names for the IP address, by querying for the PTR
+    /// records of its reverse name (see [`reverse`]).
+    pub fn reverse_lookup(&self, ip: IpAddr) -> Result<Vec<String>, LookupError> {
+        self.lookup_resources(&reverse(ip), Type::PTR, |resource| match resource {
+            Resource::PTR(ptr) => Some(ptr),
+            _ => None,
+        })
+    }
+}
+
+impl<E> Resolver<E>
+where
+    E: Exchanger + Send + Sync + 'static,
+{
+    /// Sets if popular cached responses should be refreshed, in the
+    /// background, shortly before they expire. See [`Cache::should_prefetch`].
+    pub fn with_prefetch(mut self, prefetch: bool) -> Self {
+        self.prefetch = prefetch;
+        self.with_spawn()
+    }
+
+    /// Sets how long to wait for the client, when there is a stale response
+    /// in the cache, before giving up and returning the stale response. The
+    /// query carries on in the background, and updates the cache once it
+    /// completes. Only one query for each question is in progress at a time.
+    /// rfc8767 suggests 1.8 seconds.
+    ///
+    /// The cache must be serving stale responses, see
+    /// [`Cache::with_serve_stale`]. Without this, a stale response is only
+    /// returned once the client has failed.
+    pub fn with_stale_timeout(mut self, timeout: Duration) -> Self {
+        self.stale_timeout = Some(timeout);
+        self.with_spawn()
+    }
+
+    /// Allows work to be done with the client on background threads.
+    fn with_spawn(mut self) -> Self {
+        let client = self.client.clone();
+        self.spawn = Some(Box::new(move |task: Task<E>| {
+            let client = client.clone();
+            thread::spawn(move || task(&client));
+        }));
+        self
+    }
+}
+
+/// Returns true if the response is a failure, and a stale response should be
+/// returned instead.
+fn is_failure(response: &Message) -> bool {
+    matches!(response.rcode, Rcode::ServFail | Rcode::Refused)
+}
+
+/// Sends the query with the client, trying up to `attempts` times.
+fn exchange_with_attempts<E: Exchanger>(
+    client: &E,
+    attempts: usize,
+    query: &Message,
+) -> Result<Message, crate::Error> {
+    let mut result = client.exchange(query);
+    for _ in 1..attempts {
+        if result.is_ok() {
+            break;
+        }
+        result = client.exchange(query);
+    }
+    result
+}
+
+/// The addresses returned by [`Resolver::lookup`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AddressFamily {
+    /// Both IPv4 and IPv6 addresses.
+    #[default]
+    Any,
+
+    /// Only IPv4 addresses, so only A queries are sent.
+    Ipv4,
+
+    /// Only IPv6 addresses, so only AAAA queries are sent.
+    Ipv6,
+}
+
+impl AddressFamily {
+    fn allows(&self, ip: &IpAddr) -> bool {
+        match self {
+            AddressFamily::Any => true,
+            AddressFamily::Ipv4 => ip.is_ipv4(),
+            AddressFamily::Ipv6 => ip.is_ipv6(),
+        }
+    }
+}
+
+/// The result of a [`Resolver::query`].
+#[derive(Clone, Debug)]
+pub struct Answer {
+    /// The fully qualified name that was found. This is the name as given,
+    /// or with one of the search domains appended.
+    pub name: String,
+
+    /// The names that `name` is a alias for (via CNAME or DNAME records), in
+    /// the order they were followed. The last is the canonical name. Empty if
+    /// `name` is not a alias.
+    pub aliases: Vec<String>,
+
+    /// The response for that name. If `name` is a alias, the answers contain
+    /// the alias records followed by the records for the canonical name.
+    pub response: Message,
+}
+
+impl Answer {
+    /// Returns the canonical name, that is the last alias, or the name if
+    /// there are no aliases.
+    pub fn canonical_name(&self) -> &str {
+        self.aliases.last().unwrap_or(&self.name)
+    }
+}
+
+cfg_feature! {
+    #![feature = "tokio"]
+
+    use futures_util::stream::{FuturesUnordered, StreamExt};
+    use std::collections::VecDeque;
+    use std::net::SocketAddr;
+    use tokio::net::TcpStream;
+    use tokio::task;
+    use tokio::time::{sleep_until, Instant};
+
+    /// How long to wait for the AAAA answer, once the A answer arrives.
+    const RESOLUTION_DELAY: Duration = Duration::from_millis(50);
+
+    /// How long to wait for a connection attempt, before starting the next.
+    const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+    impl<E> Resolver<E>
+    where
+        E: Exchanger + Send + Sync + 'static,
+    {
+        /// Resolves the host, and connects to it over TCP, using Happy
+        /// Eyeballs ([rfc8305]). Returns the first connection established,
+        /// and the address it was made to.
+        ///
+        /// The AAAA and A queries are sent in parallel, and connections are
+        /// attempted as soon as the addresses arrive, alternating between
+        /// IPv6 and IPv4 (IPv6 first). Each attempt is given 250ms before the
+        /// next is started, or less if it fails. Once one connects, the
+        /// others are dropped. If every attempt fails, the last error is
+        /// returned.
+        ///
+        /// The lookups are blocking, so they're run with
+        /// [`tokio::task::spawn_blocking`], which is why the resolver must be
+        /// in a [`Arc`].
+        ///
+        /// ```rust,no_run
+        /// use rustdns::clients::Resolver;
+        /// use std::sync::Arc;
+        ///
+        /// #[tokio::main]
+        /// async fn main() -> Result<(), rustdns::Error> {
+        ///     let resolver = Arc::new(Resolver::new());
+        ///
+        ///     let (stream, addr) = resolver.connect("bramp.net", 80).await?;
+        ///     println!("connected to {}", addr);
+        ///     Ok(())
+        /// }
+        /// ```
+        ///
+        /// [rfc8305]: https://datatracker.ietf.org/doc/html/rfc8305
+        pub async fn connect(
+            self: &Arc<Self>,
+            host: &str,
+            port: u16,
+        ) -> Result<(TcpStream, SocketAddr), crate::Error> {
+            if let Ok(ip) = host.parse::<IpAddr>() {
+                let addr = SocketAddr::new(ip, port);
+                return Ok((TcpStream::connect(addr).await?, addr));
+            }
+
+            let mut lookups = FuturesUnordered::new();
+            for family in [AddressFamily::Ipv6, AddressFamily::Ipv4] {
+                if self.family != AddressFamily::Any && self.family != family {
+                    continue;
+                }
+
+                let resolver = Arc::clone(self);
+                let host = host.to_string();
+                lookups.push(async move {
+                    let result =
+                        task::spawn_blocking(move || resolver.lookup_family(&host, family)).await;
+                    match result {
+                        Ok(result) => (family, result),
+                        Err(e) => panic::resume_unwind(e.into_panic()),
+                    }
+                });
+            }
+
+            let mut addrs = VecDeque::new();
+            let mut attempts = FuturesUnordered::new();
+            let mut next_attempt = Instant::now();
+            let mut waiting_for_ipv6 = None;
+            let mut error = None;
+
+            loop {
+                let now = Instant::now();
+                let start = match waiting_for_ipv6 {
+                    Some(deadline) if deadline > now => deadline,
+                    _ => next_attempt,
                 };
+
+                if start <= now {
+                    if let Some(addr) = addrs.pop_front() {
+                        debug!("{}: connecting to {}", host, addr);
+                        attempts.push(async move { (addr, TcpStream::connect(addr).await) });
+                        next_attempt = now + CONNECTION_ATTEMPT_DELAY;
+                        continue;
+                    }
+                }
+
+                if lookups.is_empty() && attempts.is_empty() && addrs.is_empty() {
+                    break;
+                }
+
+                tokio::select! {
+                    Some((family, result)) = lookups.next(), if !lookups.is_empty() => {
+                        match result {
+                            Ok(ips) => {
+                                let ips = ips.into_iter().map(|ip| SocketAddr::new(ip, port));
+                                addrs = interleave(addrs.drain(..).chain(ips));
+                            }
+                            Err(e) => {
+                                debug!("{}: {:?} lookup failed: {}", host, family, e);
+                                error.get_or_insert(e);
+                            }
+                        }
+
+                        // Give the AAAA answer a moment, if the A answer
+                        // arrived first, and nothing has been tried yet.
+                        waiting_for_ipv6 = if family == AddressFamily::Ipv4
+                            && !lookups.is_empty()
+                            && attempts.is_empty()
+                        {
+                            Some(Instant::now() + RESOLUTION_DELAY)
+                        } else {
+                            None
+                        };
+                    }
+
+                    Some((addr, result)) = attempts.next(), if !attempts.is_empty() => {
+                        match result {
+                            Ok(stream) => return Ok((stream, addr)),
+                            Err(e) => {
+                                debug!("{}: connecting to {} failed: {}", host, addr, e);
+                                error = Some(e.into());
+
+                                // Start the next attempt now.
+                                next_attempt = Instant::now();
+                            }
+                        }
+                    }
+
+                    _ = sleep_until(start), if !addrs.is_empty() => (),
+                }
+            }
+
+            match error {
+                Some(e) => Err(e),
+                None => bail!(NotFound, "no addresses found for {}", host),
             }
         }
+    }
+
+    /// Orders the addresses alternating between IPv6 and IPv4, starting with
+    /// IPv6, otherwise keeping their order.
+    fn interleave<I: Iterator<Item = SocketAddr>>(addrs: I) -> VecDeque<SocketAddr> {
+        let (mut ipv6, mut ipv4): (VecDeque<_>, VecDeque<_>) =
+            addrs.partition(|addr| addr.is_ipv6());
 
-        Ok(results.into_iter().collect())
+        let mut result = VecDeque::new();
+        while !ipv6.is_empty() || !ipv4.is_empty() {
+            result.extend(ipv6.pop_front());
+            result.extend(ipv4.pop_front());
+        }
+        result
     }
 }
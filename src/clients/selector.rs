@@ -0,0 +1,366 @@
+use crate::bail;
+use crate::clients::Exchanger;
+use crate::Message;
+use crate::Rcode;
+use log::debug;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+#[cfg(feature = "udp")]
+use crate::clients::udp::Client as UdpClient;
+#[cfg(feature = "udp")]
+use std::net::ToSocketAddrs;
+
+/// Weight given to the newest RTT sample, when updating the smoothed RTT.
+/// The same 3/10 weighting as BIND.
+const SRTT_WEIGHT: f64 = 0.3;
+
+/// Each time a server is not selected its smoothed RTT is multiplied by this,
+/// so that slow servers are eventually retried.
+const SRTT_DECAY: f64 = 0.98;
+
+/// Largest smoothed RTT that will be recorded.
+const MAX_SRTT: Duration = Duration::from_secs(10);
+
+/// Selects between multiple upstream servers, preferring the fastest healthy
+/// one, based on their past performance (their "batting stats").
+///
+/// For each server a smoothed round trip time (SRTT) is kept, which is
+/// updated after each query. Servers that are not selected have their SRTT
+/// slowly decayed, as is done in BIND and Unbound, so that a server that was
+/// once slow is eventually tried again. Any server that has not been used
+/// within the probe interval is also probed with the next query.
+///
+/// Servers that fail (with an error, or a SERVFAIL or REFUSED response)
+/// have their SRTT penalised, and after too many consecutive failures are
+/// skipped until they are next probed. Whenever a server fails the next best
+/// server is tried.
+///
+/// Finally the RA bit is tracked, so that recursive queries prefer servers
+/// that offer recursion.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rustdns::clients::Exchanger;
+/// use rustdns::clients::Selector;
+/// use rustdns::types::*;
+/// use std::net::SocketAddr;
+///
+/// fn main() -> Result<(), rustdns::Error> {
+///     let mut query = Message::default();
+///     query.add_question("bramp.net", Type::A, Class::Internet);
+///
+///     let servers = [
+///         SocketAddr::from(([8, 8, 8, 8], 53)),
+///         SocketAddr::from(([1, 1, 1, 1], 53)),
+///     ];
+///     let selector = Selector::new(&servers[..])?;
+///     let response = selector.exchange(&query)?;
+///
+///     for stats in selector.stats() {
+///         println!("{}: {:?}", stats.server, stats.srtt);
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct Selector<E> {
+    upstreams: Vec<Upstream<E>>,
+
+    /// How many consecutive failures before a server is considered unhealthy.
+    max_failures: u32,
+
+    /// How often a unused or unhealthy server is probed.
+    probe_interval: Duration,
+
+    /// Use the servers in turn, instead of picking the fastest.
+    rotate: bool,
+
+    /// The next server to use, when rotating.
+    next: AtomicUsize,
+}
+
+struct Upstream<E> {
+    client: E,
+    state: Mutex<UpstreamState>,
+}
+
+struct UpstreamState {
+    stats: ServerStats,
+
+    /// When this server was last selected, if ever.
+    last_selected: Option<Instant>,
+}
+
+/// The collected stats for one server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerStats {
+    /// The server these stats are for.
+    pub server: SocketAddr,
+
+    /// The smoothed round trip time. This starts at zero, so every server is
+    /// tried at least once.
+    pub srtt: Duration,
+
+    /// The round trip time of the last successful query.
+    pub last_rtt: Option<Duration>,
+
+    /// The total number of queries sent to this server.
+    pub queries: u64,
+
+    /// The total number of queries that failed.
+    pub failures: u64,
+
+    /// The number of failures since the last successful query.
+    pub consecutive_failures: u32,
+
+    /// The RA bit from the last response, or None if the server has not yet
+    /// responded.
+    pub recursion_available: Option<bool>,
+
+    /// When this server was last sent a query.
+    pub last_used: Option<SystemTime>,
+}
+
+impl ServerStats {
+    fn new(server: SocketAddr) -> ServerStats {
+        ServerStats {
+            server,
+            srtt: Duration::default(),
+            last_rtt: None,
+            queries: 0,
+            failures: 0,
+            consecutive_failures: 0,
+            recursion_available: None,
+            last_used: None,
+        }
+    }
+
+    fn record_success(&mut self, rtt: Duration, ra: bool) {
+        self.srtt = if self.last_rtt.is_none() {
+            // First successful query, so take the sample as is.
+            rtt
+        } else {
+            self.srtt
+                .mul_f64(1.0 - SRTT_WEIGHT)
+                .saturating_add(rtt.mul_f64(SRTT_WEIGHT))
+        }
+        .min(MAX_SRTT);
+
+        self.last_rtt = Some(rtt);
+        self.consecutive_failures = 0;
+        self.recursion_available = Some(ra);
+    }
+
+    fn record_failure(&mut self, elapsed: Duration) {
+        // Penalise the server, by at least doubling its SRTT.
+        self.srtt = (self.srtt * 2).max(elapsed).min(MAX_SRTT);
+
+        self.failures += 1;
+        self.consecutive_failures += 1;
+    }
+}
+
+#[cfg(feature = "udp")]
+impl Selector<UdpClient> {
+    /// Creates a new Selector, with a UDP client for each of the servers.
+    pub fn new<A: ToSocketAddrs>(servers: A) -> Result<Self, crate::Error> {
+        let mut clients = Vec::new();
+        for server in servers.to_socket_addrs()? {
+            clients.push((server, UdpClient::new(server)?));
+        }
+
+        Ok(Selector::new_with_clients(clients))
+    }
+}
+
+impl<E> Selector<E>
+where
+    E: Exchanger,
+{
+    /// Creates a new Selector, choosing between the given clients. Each
+    /// client should send queries to the server it is paired with.
+    pub fn new_with_clients(clients: Vec<(SocketAddr, E)>) -> Selector<E> {
+        let upstreams = clients
+            .into_iter()
+            .map(|(server, client)| Upstream {
+                client,
+                state: Mutex::new(UpstreamState {
+                    stats: ServerStats::new(server),
+                    last_selected: None,
+                }),
+            })
+            .collect();
+
+        Selector {
+            upstreams,
+            max_failures: 3,
+            probe_interval: Duration::from_secs(60),
+            rotate: false,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets how many consecutive failures before a server is skipped.
+    pub fn with_max_failures(mut self, max_failures: u32) -> Self {
+        self.max_failures = max_failures;
+        self
+    }
+
+    /// Sets how often a server that hasn't been used, or has been failing,
+    /// is probed.
+    pub fn with_probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+
+    /// Sets if the servers should be used in turn (round robin), instead of
+    /// picking the fastest. Unhealthy servers are still skipped.
+    pub fn with_rotate(mut self, rotate: bool) -> Self {
+        self.rotate = rotate;
+        self
+    }
+
+    /// Returns a snapshot of the collected stats for each server.
+    pub fn stats(&self) -> Vec<ServerStats> {
+        self.upstreams
+            .iter()
+            .map(|upstream| upstream.state.lock().unwrap().stats.clone())
+            .collect()
+    }
+
+    /// Returns the indexes of the upstreams, in the order they should be
+    /// tried for this query. This also decays the SRTT of every server not
+    /// picked first.
+    fn order(&self, query: &Message) -> Vec<usize> {
+        let now = Instant::now();
+
+        if self.rotate {
+            return self.rotation(now);
+        }
+
+        // Sort by (unhealthy, no recursion, srtt).
+        let mut ranked: Vec<_> = self
+            .upstreams
+            .iter()
+            .enumerate()
+            .map(|(i, upstream)| {
+                let state = upstream.state.lock().unwrap();
+                let stats = &state.stats;
+
+                let unhealthy = stats.consecutive_failures >= self.max_failures;
+                let no_recursion = query.rd && stats.recursion_available == Some(false);
+
+                (
+                    (unhealthy, no_recursion, stats.srtt),
+                    state.last_selected,
+                    i,
+                )
+            })
+            .collect();
+
+        ranked.sort();
+
+        // If any other server hasn't been selected within the probe interval,
+        // probe the one that has waited longest. Servers never selected have
+        // a zero SRTT, so will be tried anyway.
+        let probe = ranked
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter_map(|(n, (_, last, _))| match last {
+                Some(last) if now.duration_since(*last) >= self.probe_interval => Some((last, n)),
+                _ => None,
+            })
+            .min();
+
+        if let Some((_, n)) = probe {
+            let server = ranked.remove(n);
+            ranked.insert(0, server);
+        }
+
+        let order: Vec<usize> = ranked.into_iter().map(|(_, _, i)| i).collect();
+        for (n, i) in order.iter().enumerate() {
+            let mut state = self.upstreams[*i].state.lock().unwrap();
+            if n == 0 {
+                state.last_selected = Some(now);
+            } else {
+                state.stats.srtt = state.stats.srtt.mul_f64(SRTT_DECAY);
+            }
+        }
+
+        order
+    }
+
+    /// Returns the indexes of the upstreams, starting with the next in turn.
+    fn rotation(&self, now: Instant) -> Vec<usize> {
+        let len = self.upstreams.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % len.max(1);
+
+        let mut order: Vec<usize> = (0..len).map(|n| (start + n) % len).collect();
+
+        // A stable sort, so the rotation is kept amongst the healthy servers.
+        order.sort_by_key(|i| {
+            let state = self.upstreams[*i].state.lock().unwrap();
+            state.stats.consecutive_failures >= self.max_failures
+        });
+
+        if let Some(i) = order.first() {
+            self.upstreams[*i].state.lock().unwrap().last_selected = Some(now);
+        }
+
+        order
+    }
+}
+
+impl<E> Exchanger for Selector<E>
+where
+    E: Exchanger,
+{
+    /// Sends the query to the best server, failing over to the next best
+    /// server on failure.
+    fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+        let mut last = None;
+
+        for i in self.order(query) {
+            let upstream = &self.upstreams[i];
+
+            let start = Instant::now();
+            upstream.state.lock().unwrap().stats.last_used = Some(SystemTime::now());
+
+            let result = upstream.client.exchange(query);
+            let elapsed = start.elapsed();
+
+            let mut state = upstream.state.lock().unwrap();
+            let stats = &mut state.stats;
+            stats.queries += 1;
+
+            match &result {
+                Ok(resp) if !matches!(resp.rcode, Rcode::ServFail | Rcode::Refused) => {
+                    let rtt = resp.stats.as_ref().map_or(elapsed, |s| s.duration);
+                    stats.record_success(rtt, resp.ra);
+                    return result;
+                }
+                Ok(resp) => {
+                    debug!("{} responded with {}", stats.server, resp.rcode);
+                    stats.record_failure(elapsed);
+                }
+                Err(e) => {
+                    debug!("{} failed: {}", stats.server, e);
+                    stats.record_failure(elapsed);
+                }
+            }
+
+            last = Some(result);
+        }
+
+        match last {
+            Some(result) => result,
+            None => bail!(InvalidInput, "no servers to select from"),
+        }
+    }
+}
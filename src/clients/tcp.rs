@@ -1,12 +1,35 @@
+use crate::bail;
+use crate::clients::stats::StatsBuilder;
+use crate::clients::transfer;
+use crate::clients::transfer::Parser;
 use crate::clients::Exchanger;
+use crate::clients::Transfer;
+#[cfg(feature = "sig0")]
+use crate::sig0::PrivateKey;
+#[cfg(feature = "tsig")]
+use crate::tsig;
+#[cfg(feature = "tsig")]
+use crate::tsig::Key;
+use crate::ExtensionOption;
 use crate::Message;
-use crate::clients::stats::StatsBuilder;
+use crate::Question;
+use crate::Record;
+use crate::Resource;
+use log::debug;
+use std::collections::HashMap;
+use std::io;
 use std::io::Read;
 use std::io::Write;
+use std::net::Shutdown;
 use std::net::SocketAddr;
 use std::net::TcpStream;
 use std::net::ToSocketAddrs;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
 use std::time::Duration;
+use std::time::Instant;
 
 pub const GOOGLE_IPV4_PRIMARY: &str = "8.8.8.8:53";
 pub const GOOGLE_IPV4_SECONDARY: &str = "8.8.4.4:53";
@@ -22,6 +45,22 @@ pub const GOOGLE: [&str; 4] = [
 
 /// A TCP DNS Client.
 ///
+/// A single connection is kept open and reused between queries. Multiple
+/// queries (from multiple threads) are pipelined over that connection, and
+/// responses are matched to queries by their ID, as described in [rfc7766].
+///
+/// If a server advertises a idle timeout with the edns-tcp-keepalive option
+/// ([rfc7828]), the connection will not be reused once that timeout passes.
+///
+/// When a connection fails, it is reopened, and if that fails the next server
+/// is tried, until all servers have been tried. A query that times out, or gets
+/// a invalid response, fails on its own and leaves the connection open.
+///
+/// Queries, including zone transfers, can be signed with a TSIG key
+/// ([rfc8945]) with [`with_tsig`](Client::with_tsig), and then every
+/// response must be signed by the server. Or they can be signed with a
+/// private key using SIG(0) ([rfc2931]) with [`with_sig0`](Client::with_sig0).
+///
 /// # Example
 ///
 /// ```rust
@@ -43,6 +82,11 @@ pub const GOOGLE: [&str; 4] = [
 /// ```
 ///
 /// See <https://datatracker.ietf.org/doc/html/rfc1035#section-4.2.2>
+///
+/// [rfc7766]: https://datatracker.ietf.org/doc/html/rfc7766
+/// [rfc7828]: https://datatracker.ietf.org/doc/html/rfc7828
+/// [rfc2931]: https://datatracker.ietf.org/doc/html/rfc2931
+/// [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
 // TODO Document all the options.
 pub struct Client {
     servers: Vec<SocketAddr>,
@@ -50,6 +94,29 @@ pub struct Client {
     connect_timeout: Duration,
     read_timeout: Option<Duration>,
     write_timeout: Option<Duration>,
+
+    /// How long an idle connection is kept, if the server doesn't tell us.
+    idle_timeout: Duration,
+
+    /// The key queries are signed with.
+    #[cfg(feature = "tsig")]
+    tsig: Option<Key>,
+
+    /// The private key queries are signed with.
+    #[cfg(feature = "sig0")]
+    sig0: Option<PrivateKey>,
+
+    state: Mutex<State>,
+}
+
+/// The mutable state shared between all exchanges.
+#[derive(Default)]
+struct State {
+    /// Index into servers of the server to use.
+    next: usize,
+
+    /// The current open connection, if any.
+    conn: Option<Arc<Connection>>,
 }
 
 impl Default for Client {
@@ -59,6 +126,12 @@ impl Default for Client {
             connect_timeout: Duration::new(5, 0),
             read_timeout: Some(Duration::new(5, 0)),
             write_timeout: Some(Duration::new(5, 0)),
+            idle_timeout: Duration::new(10, 0),
+            #[cfg(feature = "tsig")]
+            tsig: None,
+            #[cfg(feature = "sig0")]
+            sig0: None,
+            state: Mutex::new(State::default()),
         }
     }
 }
@@ -67,46 +140,624 @@ impl Client {
     /// Creates a new Client bound to the specific servers.
     // TODO Document how it fails.
     pub fn new<A: ToSocketAddrs>(servers: A) -> Result<Self, crate::Error> {
-        let servers = servers.to_socket_addrs()?.collect();
         // TODO Check for zero servers.
-        Ok(Self {
-            servers,
+        let mut client = Self::default();
+        client.servers = servers.to_socket_addrs()?.collect();
+        Ok(client)
+    }
+
+    /// Sets how long to wait for a response. None waits forever.
+    pub fn with_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Sets how long a idle connection is kept open, when the server doesn't
+    /// advertise its own timeout.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Signs every query with the TSIG key, and verifies the responses were
+    /// signed with it, failing with [`Error::SignatureError`] if not. Replaces
+    /// any key set with [`with_sig0`](Client::with_sig0).
+    ///
+    /// [`Error::SignatureError`]: crate::Error::SignatureError
+    #[cfg(feature = "tsig")]
+    pub fn with_tsig(mut self, key: Key) -> Self {
+        self.tsig = Some(key);
+        #[cfg(feature = "sig0")]
+        {
+            self.sig0 = None;
+        }
+        self
+    }
+
+    /// Signs every query with the private key, using SIG(0). Responses aren't
+    /// verified. Replaces any key set with [`with_tsig`](Client::with_tsig),
+    /// as only one signature is used.
+    #[cfg(feature = "sig0")]
+    pub fn with_sig0(mut self, key: PrivateKey) -> Self {
+        self.sig0 = Some(key);
+        #[cfg(feature = "tsig")]
+        {
+            self.tsig = None;
+        }
+        self
+    }
+
+    /// Transfers the whole zone from the server with AXFR ([rfc5936]),
+    /// returning its records, starting with the zone's SOA record.
+    ///
+    /// The transfer is made over the shared connection, and the read timeout
+    /// applies to each message of the transfer, not the transfer as a whole.
+    /// A transfer that fails part way is started again from the next server.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use rustdns::clients::tcp::Client;
+    /// use rustdns::zones::Zone;
+    ///
+    /// fn main() -> Result<(), rustdns::Error> {
+    ///     let client = Client::new("192.0.2.53:53")?;
+    ///     let zone = Zone::new(client.axfr("example.com")?)?;
+    ///
+    ///     for record in zone.iter() {
+    ///         println!("{}", record);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// [rfc5936]: https://datatracker.ietf.org/doc/html/rfc5936
+    pub fn axfr(&self, zone: &str) -> Result<Vec<Record>, crate::Error> {
+        match self.transfer(&transfer::query(zone, None))? {
+            Transfer::Full(records) => Ok(records),
+            _ => unreachable!("a AXFR always returns the whole zone"),
+        }
+    }
+
+    /// Transfers the changes to the zone since the version with the `soa`
+    /// record, with IXFR ([rfc1995]). The server may instead return the
+    /// whole zone. See [`Transfer`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use rustdns::clients::tcp::Client;
+    ///
+    /// fn main() -> Result<(), rustdns::Error> {
+    ///     let client = Client::new("192.0.2.53:53")?;
+    ///     let mut records = client.axfr("example.com")?;
+    ///
+    ///     // Later, fetch and apply the changes.
+    ///     client.ixfr("example.com", &records[0])?.apply(&mut records)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// [rfc1995]: https://datatracker.ietf.org/doc/html/rfc1995
+    pub fn ixfr(&self, zone: &str, soa: &Record) -> Result<Transfer, crate::Error> {
+        if !matches!(soa.resource, Resource::SOA(_)) {
+            bail!(InvalidInput, "'{}' is not a SOA record", soa);
+        }
+        self.transfer(&transfer::query(zone, Some(soa)))
+    }
+
+    /// Makes the AXFR or IXFR query, trying each server in turn.
+    fn transfer(&self, query: &Message) -> Result<Transfer, crate::Error> {
+        let mut last_err = None;
+        for _ in 0..=self.servers.len() {
+            let (conn, reused) = self.connection()?;
+
+            #[cfg(feature = "tsig")]
+            let (query, mut verifier) = tsig::sign(self.tsig.as_ref(), query)?;
+
+            let mut parser = Parser::new(&query);
+            let result = conn.stream(&query, self.read_timeout, |resp, _buf| {
+                #[cfg(feature = "tsig")]
+                if let Some(verifier) = &mut verifier {
+                    if verifier.verify(_buf).is_err() {
+                        return false;
+                    }
+                }
+                parser.add(resp)
+            });
+
+            match result {
+                Ok(()) => {
+                    #[cfg(feature = "tsig")]
+                    if let Some(verifier) = &verifier {
+                        verifier.finish()?;
+                    }
+                    return parser.finish();
+                }
+                // The connection started closing after it was picked, so the
+                // query wasn't sent. Send it on a new connection.
+                Err(e) if e.kind() == io::ErrorKind::NotConnected && !conn.is_closed() => {
+                    last_err = Some(e);
+                }
+                Err(e) => {
+                    debug!("transfer from {} failed: {}", conn.server, e);
+                    self.reset(&conn, !reused);
+                    last_err = Some(e);
+                }
+            }
+        }
 
-            ..Default::default()
-        })
+        Err(last_err.unwrap().into())
+    }
+
+    /// Returns a open connection, opening a new one if needed. If a new
+    /// connection is needed, each server is tried in turn.
+    fn connection(&self) -> io::Result<(Arc<Connection>, bool)> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(conn) = &state.conn {
+            if conn.is_usable() {
+                return Ok((conn.clone(), true));
+            }
+            conn.drain();
+            state.conn = None;
+        }
+
+        let mut last_err = None;
+        for _ in 0..self.servers.len() {
+            let server = self.servers[state.next % self.servers.len()];
+
+            match Connection::open(server, self) {
+                Ok(conn) => {
+                    state.conn = Some(conn.clone());
+                    return Ok((conn, false));
+                }
+                Err(e) => {
+                    debug!("failed to connect to {}: {}", server, e);
+                    state.next = (state.next + 1) % self.servers.len();
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        match last_err {
+            Some(e) => Err(e),
+            None => bail!(InvalidInput, "no servers configured"),
+        }
+    }
+
+    /// Drops the failed connection, and if `failover` moves on to the next server.
+    fn reset(&self, failed: &Arc<Connection>, failover: bool) {
+        failed.close();
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(conn) = &state.conn {
+            if Arc::ptr_eq(conn, failed) {
+                state.conn = None;
+                if failover {
+                    state.next = (state.next + 1) % self.servers.len();
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        if let Some(conn) = self.state.lock().unwrap().conn.take() {
+            conn.close();
+        }
     }
 }
 
 impl Exchanger for Client {
     /// Sends the [`Message`] to the `server` via TCP and returns the result.
     fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
-        let mut stream = TcpStream::connect_timeout(&self.servers[0], self.connect_timeout)?;
+        let mut query = query.clone();
+        if let Some(extension) = &mut query.extension {
+            // Signal that we would like to keep the connection open.
+            if !extension
+                .options
+                .iter()
+                .any(|o| matches!(o, ExtensionOption::TcpKeepalive(_)))
+            {
+                extension.options.push(ExtensionOption::TcpKeepalive(None));
+            }
+        }
+
+        // Try each server once, plus once more in case the first connection
+        // we used was stale.
+        let mut last_err = None;
+        for _ in 0..=self.servers.len() {
+            let (conn, reused) = self.connection()?;
+
+            #[cfg(feature = "tsig")]
+            let (query, mut verifier) = tsig::sign(self.tsig.as_ref(), &query)?;
+
+            match conn.exchange(&query, self.read_timeout) {
+                Ok((resp, _buf)) => {
+                    #[cfg(feature = "tsig")]
+                    if let Some(verifier) = &mut verifier {
+                        verifier.verify(&_buf)?;
+                    }
+                    return Ok(resp);
+                }
+
+                // The connection started closing after it was picked, so the
+                // query wasn't sent. Send it on a new connection.
+                Err(e) if e.kind() == io::ErrorKind::NotConnected && !conn.is_closed() => {
+                    last_err = Some(e);
+                }
+
+                // The connection is still fine (for example this query timed out, or
+                // its response was invalid), so leave it open for the other queries.
+                Err(e) if !conn.is_closed() => return Err(e.into()),
+
+                Err(e) => {
+                    debug!("query to {} failed: {}", conn.server, e);
+
+                    // A connection we reused may have been closed by the server while idle,
+                    // so retry the same server, otherwise move onto the next.
+                    self.reset(&conn, !reused);
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        Err(last_err.unwrap().into())
+    }
+}
+
+/// A response, and the message as received, to verify its signature.
+type Reply = io::Result<(Message, Vec<u8>)>;
+
+/// A single TCP connection, which may have multiple outstanding queries.
+struct Connection {
+    server: SocketAddr,
+    stream: Mutex<TcpStream>,
+    inner: Mutex<Inner>,
+
+    /// The private key queries are signed with, once their ID is known.
+    #[cfg(feature = "sig0")]
+    sig0: Option<PrivateKey>,
+}
+
+struct Inner {
+    /// Outstanding queries, keyed by the ID sent on the wire.
+    pending: HashMap<u16, Pending>,
+
+    /// Set once the connection has failed or been closed.
+    closed: bool,
+
+    /// Set once the connection should no longer be used for new queries,
+    /// for example when the server asks for it to be closed. It's closed
+    /// once the outstanding queries finish.
+    draining: bool,
+
+    /// When the connection was last used, and how long it may be idle for.
+    last_used: Instant,
+    idle_timeout: Duration,
+}
+
+/// A outstanding query, waiting for its responses.
+struct Pending {
+    tx: mpsc::Sender<Reply>,
+
+    /// Set if more than one response is expected, such as for a zone
+    /// transfer, in which case the query stays pending until forgotten.
+    stream: bool,
+
+    /// The query's questions, which its responses must match.
+    questions: Vec<Question>,
+}
+
+impl Pending {
+    /// Is `resp` a response to this query. A response without questions
+    /// only has its ID to go by, otherwise its questions must match too, as
+    /// required by rfc7766 section 7.
+    fn matches(&self, resp: &Message) -> bool {
+        resp.questions.is_empty()
+            || (resp.questions.len() == self.questions.len()
+                && resp.questions.iter().zip(&self.questions).all(|(a, b)| {
+                    a.name.eq_ignore_ascii_case(&b.name)
+                        && a.r#type == b.r#type
+                        && a.class == b.class
+                }))
+    }
+}
+
+impl Connection {
+    fn open(server: SocketAddr, client: &Client) -> io::Result<Arc<Connection>> {
+        let stream = TcpStream::connect_timeout(&server, client.connect_timeout)?;
         stream.set_nodelay(true)?; // We send discrete packets, so we can send as soon as possible.
-        stream.set_read_timeout(self.read_timeout)?;
-        stream.set_write_timeout(self.write_timeout)?;
+        stream.set_write_timeout(client.write_timeout)?;
+
+        let reader = stream.try_clone()?;
+
+        let conn = Arc::new(Connection {
+            server,
+            stream: Mutex::new(stream),
+            inner: Mutex::new(Inner {
+                pending: HashMap::new(),
+                closed: false,
+                draining: false,
+                last_used: Instant::now(),
+                idle_timeout: client.idle_timeout,
+            }),
+            #[cfg(feature = "sig0")]
+            sig0: client.sig0.clone(),
+        });
+
+        let c = conn.clone();
+        thread::Builder::new()
+            .name(format!("rustdns-tcp-{}", server))
+            .spawn(move || c.read_loop(reader))?;
+
+        Ok(conn)
+    }
+
+    /// Can this connection still be used for new queries.
+    fn is_usable(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        !inner.closed
+            && !inner.draining
+            && (!inner.pending.is_empty() || inner.last_used.elapsed() < inner.idle_timeout)
+    }
+
+    /// Has this connection failed or been closed.
+    fn is_closed(&self) -> bool {
+        self.inner.lock().unwrap().closed
+    }
+
+    /// Stops this connection being used for new queries, and closes it once
+    /// the outstanding queries finish.
+    fn drain(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.draining = true;
+        let idle = inner.pending.is_empty();
+        drop(inner);
+
+        if idle {
+            self.close();
+        }
+    }
+
+    fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        // Wakes up the reader thread.
+        let _ = self.stream.lock().unwrap().shutdown(Shutdown::Both);
+    }
+
+    fn exchange(&self, query: &Message, timeout: Option<Duration>) -> Reply {
+        let (id, rx, stats) = self.send(query, false)?;
+        let reply = self.recv(&rx, timeout);
+        self.forget(id);
+
+        let (mut resp, buf) = reply?;
+        resp.id = query.id;
+        resp.stats = Some(stats.end(self.server, buf.len() + 2));
+
+        Ok((resp, buf))
+    }
+
+    /// Sends the query, and passes each response (and the message as
+    /// received) to `handle`, until it returns false.
+    fn stream<F>(&self, query: &Message, timeout: Option<Duration>, mut handle: F) -> io::Result<()>
+    where
+        F: FnMut(Message, &[u8]) -> bool,
+    {
+        let (id, rx, _) = self.send(query, true)?;
+
+        let result = loop {
+            match self.recv(&rx, timeout) {
+                Ok((mut resp, buf)) => {
+                    resp.id = query.id;
+                    if !handle(resp, &buf) {
+                        break Ok(());
+                    }
+                }
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.forget(id);
+        result
+    }
+
+    /// Sends the query, returning the ID it was sent with, and where its
+    /// responses will be received.
+    fn send(
+        &self,
+        query: &Message,
+        stream: bool,
+    ) -> io::Result<(u16, mpsc::Receiver<Reply>, StatsBuilder)> {
+        let mut query = query.clone();
+
+        let (tx, rx) = mpsc::channel();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.closed {
+                bail!(NotConnected, "connection to {} closed", self.server);
+            }
+            if inner.draining {
+                bail!(NotConnected, "connection to {} is closing", self.server);
+            }
+
+            // IDs must be unique across all outstanding queries on this connection.
+            while inner.pending.contains_key(&query.id) {
+                query.id = Message::random_id();
+            }
+            inner.pending.insert(
+                query.id,
+                Pending {
+                    tx,
+                    stream,
+                    questions: query.questions.clone(),
+                },
+            );
+            inner.last_used = Instant::now();
+        }
+
+        // The ID is signed, so the query can only be signed once it's chosen.
+        #[cfg(feature = "sig0")]
+        if let Some(key) = &self.sig0 {
+            if let Err(e) = key.sign(&mut query) {
+                self.forget(query.id);
+                bail!(InvalidInput, "unable to sign query: {}", e);
+            }
+        }
 
         let message = query.to_vec()?;
+        if message.len() > u16::MAX.into() {
+            self.forget(query.id);
+            bail!(InvalidInput, "message longer than {} bytes", u16::MAX);
+        }
+
+        // Two byte length prefix followed by the message, in a single write.
+        let mut buf = Vec::with_capacity(message.len() + 2);
+        buf.extend_from_slice(&(message.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&message);
+
+        let stats = StatsBuilder::start(buf.len());
+
+        if let Err(e) = self.stream.lock().unwrap().write_all(&buf) {
+            // A partial write leaves the stream in a unknown state.
+            self.close();
+            self.forget(query.id);
+            return Err(e);
+        }
 
-        let stats = StatsBuilder::start(message.len() + 2);
+        Ok((query.id, rx, stats))
+    }
+
+    /// Waits for the next response.
+    fn recv(&self, rx: &mpsc::Receiver<Reply>, timeout: Option<Duration>) -> Reply {
+        let reply = match timeout {
+            Some(timeout) => rx.recv_timeout(timeout).map_err(|e| match e {
+                mpsc::RecvTimeoutError::Timeout => io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("timed out waiting for {}", self.server),
+                ),
+                mpsc::RecvTimeoutError::Disconnected => closed_error(self.server),
+            }),
+            None => rx.recv().map_err(|_| closed_error(self.server)),
+        };
+        reply?
+    }
 
-        // Two byte length prefix followed by the message.
-        // TODO Move this into a single message!
-        stream.write_all(&(message.len() as u16).to_be_bytes())?;
-        stream.write_all(&message)?;
+    /// Removes the outstanding query, for example after a timeout.
+    fn forget(&self, id: u16) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pending.remove(&id);
+        inner.last_used = Instant::now();
+        let idle = inner.draining && inner.pending.is_empty();
+        drop(inner);
 
-        // Now receive a two byte length
+        if idle {
+            self.close();
+        }
+    }
+
+    /// Reads responses, and dispatches them to the waiting queries, until
+    /// the connection fails.
+    fn read_loop(&self, mut stream: TcpStream) {
+        let err = loop {
+            let buf = match Self::read_frame(&mut stream) {
+                Ok(buf) => buf,
+                Err(e) => break e,
+            };
+
+            // A invalid message within a valid frame only fails its own query,
+            // as the following messages can still be read.
+            match Message::from_slice(&buf) {
+                Ok(resp) => self.dispatch(resp, buf),
+                Err(e) => self.reject(&buf, e),
+            }
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.closed {
+            debug!("connection to {} failed: {}", self.server, err);
+        }
+        inner.closed = true;
+
+        // Tell everyone waiting that the connection failed.
+        for (_, pending) in inner.pending.drain() {
+            let _ = pending
+                .tx
+                .send(Err(io::Error::new(err.kind(), err.to_string())));
+        }
+    }
+
+    fn read_frame(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+        // Receive a two byte length
         let buf = &mut [0; 2];
         stream.read_exact(buf)?;
         let len = u16::from_be_bytes(*buf);
 
-        // and finally the message
+        // and then the message
         let mut buf = vec![0; len.into()];
-
         stream.read_exact(&mut buf)?;
 
-        let mut resp = Message::from_slice(&buf)?;
-        resp.stats = Some(stats.end(stream.peer_addr()?, (len + 2).into()));
+        Ok(buf)
+    }
+
+    /// Fails the query the invalid message `buf` was in response to, if its
+    /// ID can be read.
+    fn reject(&self, buf: &[u8], err: io::Error) {
+        if buf.len() < 2 {
+            debug!("{}: dropping invalid response: {}", self.server, err);
+            return;
+        }
 
-        Ok(resp)
+        let id = u16::from_be_bytes([buf[0], buf[1]]);
+        match self.inner.lock().unwrap().pending.remove(&id) {
+            Some(pending) => {
+                let _ = pending.tx.send(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid response from {}: {}", self.server, err),
+                )));
+            }
+            None => debug!(
+                "{}: dropping invalid response with unknown id {}: {}",
+                self.server, id, err
+            ),
+        }
     }
+
+    fn dispatch(&self, resp: Message, buf: Vec<u8>) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(timeout) = resp.extension.as_ref().and_then(|e| e.tcp_keepalive()) {
+            // A zero timeout means the server would like us to close the
+            // connection, so no more queries may be sent on it, see rfc7828
+            // section 3.3.2.
+            inner.idle_timeout = timeout;
+            if timeout.is_zero() {
+                inner.draining = true;
+            }
+        }
+
+        let id = resp.id;
+        match inner.pending.get(&id) {
+            Some(pending) if pending.matches(&resp) => {
+                let _ = pending.tx.send(Ok((resp, buf)));
+                if !pending.stream {
+                    inner.pending.remove(&id);
+                }
+            }
+            Some(_) => debug!(
+                "{}: dropping response with id {} for another question",
+                self.server, id
+            ),
+            None => debug!("{}: dropping response with unknown id {}", self.server, id),
+        }
+    }
+}
+
+fn closed_error(server: SocketAddr) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        format!("connection to {} closed", server),
+    )
 }
@@ -0,0 +1,341 @@
+use crate::bail;
+use crate::util::is_newer_serial;
+use crate::util::same_record;
+use crate::Class;
+use crate::Message;
+use crate::Rcode;
+use crate::Record;
+use crate::Resource;
+use crate::Type;
+use std::mem;
+
+/// The outcome of a incremental zone transfer (IXFR), as described in
+/// [rfc1995].
+///
+/// The server decides how to answer, sending the changes since the client's
+/// version of the zone if it has them, otherwise the whole zone.
+///
+/// [rfc1995]: https://datatracker.ietf.org/doc/html/rfc1995
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transfer {
+    /// The client's version of the zone is current. Holds the server's SOA.
+    UpToDate(Record),
+
+    /// The changes since the client's version, oldest first.
+    Incremental(Vec<Diff>),
+
+    /// The whole zone, starting with its SOA, in the same way as a AXFR.
+    Full(Vec<Record>),
+}
+
+/// The changes between two versions of a zone.
+///
+/// The SOA records are included in the changes, so `deleted` starts with
+/// the old SOA, and `added` with the new one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diff {
+    /// The serial of the version these changes apply to.
+    pub old_serial: u32,
+
+    /// The records removed from the old version.
+    pub deleted: Vec<Record>,
+
+    /// The serial of the version these changes make.
+    pub new_serial: u32,
+
+    /// The records added in the new version.
+    pub added: Vec<Record>,
+}
+
+impl Transfer {
+    /// Applies the transfer to the records of the zone, such as those from
+    /// a earlier [`axfr`](crate::clients::tcp::Client::axfr).
+    ///
+    /// Fails, leaving the records unchanged, if the changes don't apply to
+    /// the records. See [`Diff::apply`].
+    pub fn apply(&self, records: &mut Vec<Record>) -> Result<(), crate::Error> {
+        match self {
+            Transfer::UpToDate(_) => (),
+            Transfer::Incremental(diffs) => {
+                let mut updated = records.clone();
+                for diff in diffs {
+                    diff.apply(&mut updated)?;
+                }
+                *records = updated;
+            }
+            Transfer::Full(zone) => *records = zone.clone(),
+        }
+        Ok(())
+    }
+}
+
+impl Diff {
+    /// Applies the changes to the records of the zone.
+    ///
+    /// Records are matched by their name, class and data, but not their TTL.
+    /// Names, including those within the data, are compared ignoring case.
+    /// Fails, leaving the records unchanged, if the zone's SOA does not have
+    /// the old serial, or a deleted record is missing.
+    pub fn apply(&self, records: &mut Vec<Record>) -> Result<(), crate::Error> {
+        match records.iter().find_map(|r| match &r.resource {
+            Resource::SOA(soa) => Some(soa.serial),
+            _ => None,
+        }) {
+            Some(serial) if serial == self.old_serial => (),
+            Some(serial) => bail!(
+                InvalidData,
+                "changes apply to serial {}, not {}",
+                self.old_serial,
+                serial
+            ),
+            None => bail!(InvalidData, "zone has no SOA record"),
+        }
+
+        let mut updated = records.clone();
+        for record in &self.deleted {
+            match updated.iter().position(|r| same(r, record)) {
+                Some(i) => updated.remove(i),
+                None => bail!(
+                    InvalidData,
+                    "deleted record '{}' is not in the zone",
+                    record
+                ),
+            };
+        }
+
+        for record in &self.added {
+            // Replace any existing copy, which may have a different TTL.
+            updated.retain(|r| !same(r, record));
+
+            // Keep the SOA first.
+            if matches!(record.resource, Resource::SOA(_)) {
+                updated.insert(0, record.clone());
+            } else {
+                updated.push(record.clone());
+            }
+        }
+
+        *records = updated;
+        Ok(())
+    }
+}
+
+/// Returns true if the records are the same, ignoring their TTL.
+fn same(a: &Record, b: &Record) -> bool {
+    a.class == b.class && same_record(a, b)
+}
+
+/// Returns the serial of the SOA record, or None if it's not a SOA.
+pub(crate) fn serial(record: &Record) -> Option<u32> {
+    match &record.resource {
+        Resource::SOA(soa) => Some(soa.serial),
+        _ => None,
+    }
+}
+
+/// Returns the query to transfer the zone. For a IXFR `soa` is the SOA
+/// record of the client's version of the zone.
+pub(crate) fn query(zone: &str, soa: Option<&Record>) -> Message {
+    let mut query = Message {
+        rd: false,
+        ad: false,
+        ..Default::default()
+    };
+
+    match soa {
+        Some(soa) => {
+            query.add_question(zone, Type::IXFR, Class::Internet);
+            query.authoritys.push(soa.clone());
+        }
+        None => query.add_question(zone, Type::AXFR, Class::Internet),
+    }
+
+    query
+}
+
+/// Parses the responses to a AXFR or IXFR, as each message is received.
+pub(crate) struct Parser {
+    /// The (fully qualified) name of the zone.
+    zone: String,
+
+    /// For a IXFR, the serial of the client's version of the zone.
+    serial: Option<u32>,
+
+    state: State,
+}
+
+enum State {
+    /// Waiting for the first record.
+    Start,
+
+    /// Received the first record, the SOA of the server's version.
+    Soa(Record),
+
+    /// Receiving the whole zone.
+    Full(Vec<Record>),
+
+    /// Receiving changes, to the server's version `newest`. `adding` is set
+    /// once the deletions for `diff` have been received.
+    Incremental {
+        newest: u32,
+        diffs: Vec<Diff>,
+        diff: Diff,
+        adding: bool,
+    },
+
+    /// The transfer is complete.
+    Done(Transfer),
+
+    Failed(String),
+}
+
+impl Parser {
+    pub(crate) fn new(query: &Message) -> Parser {
+        Parser {
+            zone: query.questions[0].name.clone(),
+            serial: query.authoritys.first().and_then(serial),
+            state: State::Start,
+        }
+    }
+
+    /// Adds the message's records, returning true if more are expected.
+    pub(crate) fn add(&mut self, resp: Message) -> bool {
+        if resp.rcode != Rcode::NoError {
+            self.state = State::Failed(format!("transfer of {} failed: {}", self.zone, resp.rcode));
+            return false;
+        }
+
+        for record in resp.answers {
+            self.state = self.next(record);
+            if matches!(self.state, State::Done(_) | State::Failed(_)) {
+                return false;
+            }
+        }
+
+        // A IXFR answered with only the SOA of a version that isn't newer,
+        // is up to date. Otherwise the rest of the transfer is expected.
+        if let (State::Soa(soa), Some(ours)) = (&self.state, self.serial) {
+            if !serial(soa).is_some_and(|newest| is_newer_serial(newest, ours)) {
+                self.state = State::Done(Transfer::UpToDate(soa.clone()));
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Returns the state after the record.
+    fn next(&mut self, record: Record) -> State {
+        let soa = serial(&record);
+
+        match (mem::replace(&mut self.state, State::Start), soa) {
+            (State::Start, Some(_)) if record.name.eq_ignore_ascii_case(&self.zone) => {
+                State::Soa(record)
+            }
+            (State::Start, _) => State::Failed(format!(
+                "transfer of {} did not start with its SOA",
+                self.zone
+            )),
+
+            // A second SOA, with a different serial, starts the changes.
+            (State::Soa(first), Some(old_serial))
+                if self.serial.is_some() && soa != serial(&first) =>
+            {
+                State::Incremental {
+                    newest: serial(&first).unwrap(),
+                    diffs: Vec::new(),
+                    diff: Diff {
+                        old_serial,
+                        deleted: vec![record],
+                        ..Default::default()
+                    },
+                    adding: false,
+                }
+            }
+            // A zone with only a SOA.
+            (State::Soa(first), Some(_)) => State::Done(Transfer::Full(vec![first])),
+            (State::Soa(first), None) => State::Full(vec![first, record]),
+
+            (State::Full(records), Some(_)) => State::Done(Transfer::Full(records)),
+            (State::Full(mut records), None) => {
+                records.push(record);
+                State::Full(records)
+            }
+
+            (
+                State::Incremental {
+                    newest,
+                    diffs,
+                    mut diff,
+                    adding: false,
+                },
+                soa,
+            ) => {
+                match soa {
+                    Some(new_serial) => {
+                        diff.new_serial = new_serial;
+                        diff.added.push(record);
+                    }
+                    None => diff.deleted.push(record),
+                }
+                State::Incremental {
+                    newest,
+                    diffs,
+                    diff,
+                    adding: soa.is_some(),
+                }
+            }
+
+            (
+                State::Incremental {
+                    newest,
+                    mut diffs,
+                    mut diff,
+                    adding: true,
+                },
+                soa,
+            ) => match soa {
+                // The newest SOA again ends the transfer.
+                Some(serial) if serial == newest && diff.new_serial == newest => {
+                    diffs.push(diff);
+                    State::Done(Transfer::Incremental(diffs))
+                }
+                // Otherwise it's the old SOA of the next changes.
+                Some(old_serial) => {
+                    diffs.push(diff);
+                    State::Incremental {
+                        newest,
+                        diffs,
+                        diff: Diff {
+                            old_serial,
+                            deleted: vec![record],
+                            ..Default::default()
+                        },
+                        adding: false,
+                    }
+                }
+                None => {
+                    diff.added.push(record);
+                    State::Incremental {
+                        newest,
+                        diffs,
+                        diff,
+                        adding: true,
+                    }
+                }
+            },
+
+            // Anything after the end is ignored.
+            (state, _) => state,
+        }
+    }
+
+    /// Returns the completed transfer.
+    pub(crate) fn finish(self) -> Result<Transfer, crate::Error> {
+        match self.state {
+            State::Done(transfer) => Ok(transfer),
+            State::Failed(error) => bail!(InvalidData, "{}", error),
+            _ => bail!(UnexpectedEof, "transfer of {} ended early", self.zone),
+        }
+    }
+}
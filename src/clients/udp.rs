@@ -1,6 +1,24 @@
+use crate::bail;
+use crate::clients::stats::StatsBuilder;
+use crate::clients::transfer;
+use crate::clients::transfer::Parser;
 use crate::clients::Exchanger;
+use crate::clients::Transfer;
+#[cfg(feature = "sig0")]
+use crate::sig0;
+#[cfg(feature = "sig0")]
+use crate::sig0::PrivateKey;
+#[cfg(feature = "tsig")]
+use crate::tsig;
+#[cfg(feature = "tsig")]
+use crate::tsig::Key;
 use crate::Message;
-use crate::clients::stats::StatsBuilder;
+use crate::Notify;
+use crate::Opcode;
+use crate::Rcode;
+use crate::Record;
+use crate::Resource;
+use std::io;
 use std::net::SocketAddr;
 use std::net::ToSocketAddrs;
 use std::net::UdpSocket;
@@ -46,6 +64,14 @@ pub struct Client {
     servers: Vec<SocketAddr>,
 
     read_timeout: Option<Duration>,
+
+    /// The key queries are signed with.
+    #[cfg(feature = "tsig")]
+    tsig: Option<Key>,
+
+    /// The private key queries are signed with.
+    #[cfg(feature = "sig0")]
+    sig0: Option<PrivateKey>,
 }
 
 impl Default for Client {
@@ -53,6 +79,10 @@ impl Default for Client {
         Client {
             servers: Vec::default(),
             read_timeout: Some(Duration::new(5, 0)),
+            #[cfg(feature = "tsig")]
+            tsig: None,
+            #[cfg(feature = "sig0")]
+            sig0: None,
         }
     }
 }
@@ -71,6 +101,144 @@ impl Client {
             ..Default::default()
         })
     }
+
+    /// Sets how long to wait for a response. None waits forever.
+    pub fn with_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Signs every query with the TSIG key ([rfc8945]), and verifies the
+    /// responses were signed with it, failing with
+    /// [`Error::SignatureError`] if not. Replaces any key set with
+    /// [`with_sig0`](Client::with_sig0).
+    ///
+    /// [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
+    /// [`Error::SignatureError`]: crate::Error::SignatureError
+    #[cfg(feature = "tsig")]
+    pub fn with_tsig(mut self, key: Key) -> Self {
+        self.tsig = Some(key);
+        #[cfg(feature = "sig0")]
+        {
+            self.sig0 = None;
+        }
+        self
+    }
+
+    /// Signs every query with the private key, using SIG(0) ([rfc2931]).
+    /// Responses aren't verified. Replaces any key set with
+    /// [`with_tsig`](Client::with_tsig), as only one signature is used.
+    ///
+    /// [rfc2931]: https://datatracker.ietf.org/doc/html/rfc2931
+    #[cfg(feature = "sig0")]
+    pub fn with_sig0(mut self, key: PrivateKey) -> Self {
+        self.sig0 = Some(key);
+        #[cfg(feature = "tsig")]
+        {
+            self.tsig = None;
+        }
+        self
+    }
+
+    /// Transfers the changes to the zone since the version with the `soa`
+    /// record, with IXFR ([rfc1995]). The query is first made over UDP, and
+    /// if the changes don't fit in the response, it's repeated over TCP.
+    /// See [`tcp::Client::ixfr`](crate::clients::tcp::Client::ixfr).
+    ///
+    /// [rfc1995]: https://datatracker.ietf.org/doc/html/rfc1995
+    pub fn ixfr(&self, zone: &str, soa: &Record) -> Result<Transfer, crate::Error> {
+        if !matches!(soa.resource, Resource::SOA(_)) {
+            bail!(InvalidInput, "'{}' is not a SOA record", soa);
+        }
+
+        let query = transfer::query(zone, Some(soa));
+        let resp = Exchanger::exchange(self, &query)?;
+
+        // If the changes didn't fit, the response is truncated, or only has
+        // the server's newer SOA, and more records are expected.
+        let mut parser = Parser::new(&query);
+        if !resp.tc && !parser.add(resp) {
+            return parser.finish();
+        }
+
+        #[cfg(feature = "tcp")]
+        {
+            log::debug!("IXFR of {} did not fit in UDP, retrying over TCP", zone);
+            let client = crate::clients::tcp::Client::new(self.servers.as_slice())?
+                .with_read_timeout(self.read_timeout);
+
+            #[cfg(feature = "tsig")]
+            let client = match &self.tsig {
+                Some(key) => client.with_tsig(key.clone()),
+                None => client,
+            };
+
+            #[cfg(feature = "sig0")]
+            let client = match &self.sig0 {
+                Some(key) => client.with_sig0(key.clone()),
+                None => client,
+            };
+
+            client.ixfr(zone, soa)
+        }
+
+        #[cfg(not(feature = "tcp"))]
+        bail!(
+            Other,
+            "IXFR of {} did not fit in UDP, and TCP is disabled",
+            zone
+        )
+    }
+
+    /// Notifies the server that the zone has changed, with NOTIFY
+    /// ([rfc1996]), optionally including the zone's new SOA record. See
+    /// [`Notify`].
+    ///
+    /// If no response arrives within the read timeout, the NOTIFY is sent
+    /// again, up to `retries` more times. Fails if the server never
+    /// responds, or doesn't acknowledge the NOTIFY.
+    ///
+    /// [rfc1996]: https://datatracker.ietf.org/doc/html/rfc1996
+    pub fn notify(
+        &self,
+        zone: &str,
+        soa: Option<&Record>,
+        retries: usize,
+    ) -> Result<Message, crate::Error> {
+        let notify = Notify::new(zone);
+        let query = match soa {
+            Some(soa) => notify.with_soa(soa.clone()).build(),
+            None => notify.build(),
+        };
+
+        let mut attempt = 0;
+        let resp = loop {
+            match Exchanger::exchange(self, &query) {
+                Ok(resp) => break resp,
+                Err(crate::Error::IoError(e))
+                    if attempt < retries
+                        && matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                {
+                    log::debug!("NOTIFY of {} timed out, retrying", zone);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        if resp.opcode != Opcode::Notify || resp.rcode != Rcode::NoError {
+            bail!(
+                Other,
+                "NOTIFY of {} was not acknowledged: {}",
+                zone,
+                resp.rcode
+            );
+        }
+        Ok(resp)
+    }
 }
 
 impl Exchanger for Client {
@@ -86,6 +254,12 @@ impl Exchanger for Client {
         // from the server.
         socket.connect(self.servers.as_slice())?;
 
+        #[cfg(feature = "sig0")]
+        let query = &sig0::sign(self.sig0.as_ref(), query)?;
+
+        #[cfg(feature = "tsig")]
+        let (query, mut verifier) = tsig::sign(self.tsig.as_ref(), query)?;
+
         let req = query.to_vec()?;
 
         let stats = StatsBuilder::start(req.len());
@@ -96,8 +270,88 @@ impl Exchanger for Client {
         let len = socket.recv(&mut buf)?;
         let mut resp = Message::from_slice(&buf[0..len])?;
 
+        #[cfg(feature = "tsig")]
+        if let Some(verifier) = &mut verifier {
+            verifier.verify(&buf[0..len])?;
+        }
+
         resp.stats = Some(stats.end(socket.peer_addr()?, len));
 
         Ok(resp)
     }
 }
+
+cfg_feature! {
+    #![feature = "tokio"]
+
+    use crate::clients::AsyncExchanger;
+    use async_trait::async_trait;
+    use std::net::Ipv4Addr;
+    use std::net::Ipv6Addr;
+    use tokio::net::UdpSocket as AsyncUdpSocket;
+    use tokio::time::timeout;
+
+    impl Client {
+        /// Returns a socket connected to the first server that can be
+        /// connected to. It's bound to the unspecified address of the same
+        /// family as the server, so IPv6 servers can be reached.
+        async fn connect(&self) -> io::Result<AsyncUdpSocket> {
+            let mut last_err = None;
+            for server in &self.servers {
+                let local: SocketAddr = match server {
+                    SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                    SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+                };
+
+                let socket = AsyncUdpSocket::bind(local).await?;
+                match socket.connect(server).await {
+                    Ok(()) => return Ok(socket),
+                    Err(e) => last_err = Some(e),
+                }
+            }
+
+            match last_err {
+                Some(e) => Err(e),
+                None => bail!(InvalidInput, "no servers configured"),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AsyncExchanger for Client {
+        /// Sends the query [`Message`] to the `server` via UDP and returns the result.
+        async fn exchange(&self, query: &Message) -> Result<Message, crate::Error> {
+            let socket = self.connect().await?;
+
+            #[cfg(feature = "sig0")]
+            let query = &sig0::sign(self.sig0.as_ref(), query)?;
+
+            #[cfg(feature = "tsig")]
+            let (query, mut verifier) = tsig::sign(self.tsig.as_ref(), query)?;
+
+            let req = query.to_vec()?;
+
+            let stats = StatsBuilder::start(req.len());
+            socket.send(&req).await?;
+
+            let mut buf = [0; 4096];
+            let len = match self.read_timeout {
+                Some(read_timeout) => match timeout(read_timeout, socket.recv(&mut buf)).await {
+                    Ok(len) => len?,
+                    Err(_) => bail!(TimedOut, "timed out waiting for a response"),
+                },
+                None => socket.recv(&mut buf).await?,
+            };
+            let mut resp = Message::from_slice(&buf[0..len])?;
+
+            #[cfg(feature = "tsig")]
+            if let Some(verifier) = &mut verifier {
+                verifier.verify(&buf[0..len])?;
+            }
+
+            resp.stats = Some(stats.end(socket.peer_addr()?, len));
+
+            Ok(resp)
+        }
+    }
+}
@@ -2,10 +2,14 @@
 //! in `dig` style.
 // Refer to https://github.com/tigeli/bind-utils/blob/master/bin/dig/dig.c for reference.
 
-use crate::resource::TXT;
+use crate::resource::KEY;
 use crate::resource::MX;
+use crate::resource::SIG;
 use crate::resource::SOA;
 use crate::resource::SRV;
+use crate::resource::TSIG;
+use crate::resource::TXT;
+use crate::ExtensionOption;
 use crate::Message;
 use crate::Question;
 use crate::Record;
@@ -31,6 +35,9 @@ impl fmt::Display for Message {
                 version = e.version,
                 payload_size = e.payload_size,
             )?;
+            for option in &e.options {
+                option.fmt(f)?;
+            }
         }
 
         // Always display the question section, but optionally
@@ -121,6 +128,39 @@ impl Message {
     }
 }
 
+impl fmt::Display for ExtensionOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // ; TCP-KEEPALIVE: 30.0 secs
+            ExtensionOption::TcpKeepalive(None) => writeln!(f, "; TCP-KEEPALIVE"),
+            ExtensionOption::TcpKeepalive(Some(timeout)) => {
+                writeln!(f, "; TCP-KEEPALIVE: {:.1} secs", timeout.as_secs_f64())
+            }
+            // ; EDE: 3 (Stale Answer): (upstream unreachable)
+            ExtensionOption::ExtendedError {
+                info_code,
+                extra_text,
+            } => {
+                write!(f, "; EDE: {}", info_code)?;
+                if *info_code == ExtensionOption::STALE_ANSWER {
+                    write!(f, " (Stale Answer)")?;
+                }
+                if !extra_text.is_empty() {
+                    write!(f, ": ({})", extra_text)?;
+                }
+                writeln!(f)
+            }
+            ExtensionOption::Unknown(code, data) => {
+                write!(f, "; OPT={}:", code)?;
+                for b in data {
+                    write!(f, " {:02x}", b)?;
+                }
+                writeln!(f)
+            }
+        }
+    }
+}
+
 impl fmt::Display for Stats {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, ";; Query time: {} msec", self.duration.as_millis())?; // TODO Support usec as well
@@ -171,15 +211,20 @@ impl fmt::Display for Resource {
 
             Resource::NS(name) => name.fmt(f),
             Resource::CNAME(name) => name.fmt(f),
+            Resource::DNAME(name) => name.fmt(f),
             Resource::PTR(name) => name.fmt(f),
 
             Resource::SOA(soa) => soa.fmt(f),
             Resource::TXT(txts) | Resource::SPF(txts) => txts.fmt(f),
             Resource::MX(mx) => mx.fmt(f),
             Resource::SRV(srv) => srv.fmt(f),
+            Resource::SIG(sig) => sig.fmt(f),
+            Resource::KEY(key) => key.fmt(f),
+            Resource::TSIG(tsig) => tsig.fmt(f),
 
             Resource::OPT => write!(f, "OPT (TODO)"),
             Resource::ANY => write!(f, "*"),
+            Resource::Empty(_) => Ok(()),
         }
     }
 }
@@ -235,9 +280,71 @@ impl fmt::Display for SRV {
     }
 }
 
+impl fmt::Display for SIG {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // "Reserved 15 0 0 1633073100 1633072500 12345 update.example.com. 1A2B..."
+        write!(
+            f,
+            "{type_covered} {algorithm} {labels} {original_ttl} {expiration} {inception} {key_tag} {signer_name} {signature}",
+            type_covered = self.type_covered,
+            algorithm = self.algorithm,
+            labels = self.labels,
+            original_ttl = self.original_ttl.as_secs(),
+            expiration = self.expiration,
+            inception = self.inception,
+            key_tag = self.key_tag,
+            signer_name = self.signer_name,
+            signature = hex(&self.signature),
+        )
+    }
+}
+
+impl fmt::Display for KEY {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // "512 3 15 1A2B..."
+        write!(
+            f,
+            "{flags} {protocol} {algorithm} {public_key}",
+            flags = self.flags,
+            protocol = self.protocol,
+            algorithm = self.algorithm,
+            public_key = hex(&self.public_key),
+        )
+    }
+}
+
+impl fmt::Display for TSIG {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // "hmac-sha256. 1633072800 300 32 1A2B... 4321 NoError 0"
+        write!(
+            f,
+            "{algorithm} {time_signed} {fudge} {mac_len} {mac} {original_id} {error} {other_len}",
+            algorithm = self.algorithm,
+            time_signed = self.time_signed,
+            fudge = self.fudge.as_secs(),
+            mac_len = self.mac.len(),
+            mac = hex(&self.mac),
+            original_id = self.original_id,
+            error = self.error,
+            other_len = self.other.len(),
+        )?;
+
+        if !self.other.is_empty() {
+            write!(f, " {}", hex(&self.other))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the data as upper case hex.
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
 impl fmt::Display for TXT {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let output = self.0
+        let output = self
+            .0
             .iter()
             .map(|txt| {
                 match std::str::from_utf8(txt) {
@@ -258,11 +365,11 @@ impl fmt::Display for TXT {
 
 #[cfg(test)]
 mod tests {
-    use crate::TXT;
     use crate::Resource;
     use crate::MX;
     use crate::SOA;
     use crate::SRV;
+    use crate::TXT;
     use core::time::Duration;
     use pretty_assertions::assert_eq;
 
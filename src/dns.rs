@@ -1,13 +1,16 @@
 use crate::bail;
-use crate::io::{DNSReadExt, SeekExt};
+use crate::io::{CursorExt, DNSReadExt, SeekExt};
 use crate::types::Record;
 use crate::types::*;
 use byteorder::{ReadBytesExt, BE};
 use num_traits::FromPrimitive;
 use rand::Rng;
+use std::collections::HashMap;
 use std::io;
 use std::io::BufRead;
 use std::io::Cursor;
+use std::io::Read;
+use std::time::Duration;
 
 #[derive(Copy, Clone, PartialEq)]
 enum RecordSection {
@@ -220,6 +223,9 @@ impl Message {
 
     /// Encodes this DNS [`Message`] as a [`Vec<u8>`] ready to be sent, as defined by [rfc1035].
     ///
+    /// Fails if the [`rcode`](Message::rcode) doesn't fit in the header, for
+    /// example [`Rcode::BadSig`].
+    ///
     /// [rfc1035]: https://datatracker.ietf.org/doc/html/rfc1035
     pub fn to_vec(&self) -> io::Result<Vec<u8>> {
         let mut req = Vec::<u8>::with_capacity(512);
@@ -239,7 +245,16 @@ impl Message {
         b |= if self.z { 0b0100_0000 } else { 0 };
         b |= if self.ad { 0b0010_0000 } else { 0 };
         b |= if self.cd { 0b0001_0000 } else { 0 };
-        b |= (self.rcode as u8) & 0b0000_1111;
+        // Rcodes past 15, such as BadSig, only fit in the TSIG error field.
+        let rcode = self.rcode as u16;
+        if rcode > 0b0000_1111 {
+            bail!(
+                InvalidInput,
+                "{} does not fit in the header's rcode",
+                self.rcode
+            );
+        }
+        b |= rcode as u8;
 
         req.push(b);
 
@@ -250,36 +265,124 @@ impl Message {
         req.extend_from_slice(&(self.authoritys.len() as u16).to_be_bytes());
         req.extend_from_slice(&ar_count.to_be_bytes());
 
+        let mut names = Names::default();
         for question in &self.questions {
             // TODO use Question::as_vec()
-            Message::write_qname(&mut req, &question.name)?;
+            names.write(&mut req, &question.name)?;
 
             req.extend_from_slice(&(question.r#type as u16).to_be_bytes());
             req.extend_from_slice(&(question.class as u16).to_be_bytes());
         }
 
-        // TODO Implement answers, etc types.
-        assert!(self.answers.is_empty());
-        assert!(self.authoritys.is_empty());
-        assert!(self.additionals.is_empty());
+        for record in &self.answers {
+            record.write(&mut req, &mut names)?;
+        }
+        for record in &self.authoritys {
+            record.write(&mut req, &mut names)?;
+        }
+        // A TSIG or SIG(0) record must be the last record, so is written after
+        // the extension, see rfc8945 section 5.1 and rfc2931 section 3.
+        let (signatures, additionals): (Vec<&Record>, Vec<&Record>) =
+            self.additionals.iter().partition(|r| r.is_signature());
+        for record in additionals {
+            record.write(&mut req, &mut names)?;
+        }
 
         if let Some(e) = &self.extension {
             e.write(&mut req)?
         }
 
+        for record in signatures {
+            record.write(&mut req, &mut names)?;
+        }
+
         // TODO if the Vec<u8> is too long, truncate the request.
 
         Ok(req)
     }
 
-    /// Writes a Unicode domain name into the supplied [`Vec<u8>`].
-    ///
-    /// Used for writing out a encoded ASCII domain name into a DNS message. Will
-    /// returns the Unicode domain name, as well as the length of this qname (ignoring
-    /// any compressed pointers) in bytes.
+    /// Returns the message, in its wire format, without its last record, and
+    /// with one less additional record. This is the message as it was before
+    /// being signed with a TSIG or SIG(0) record, which is always last.
+    #[cfg(any(feature = "sig0", feature = "tsig"))]
+    pub(crate) fn without_last_record(buf: &[u8]) -> io::Result<Vec<u8>> {
+        let mut cur = Cursor::new(buf);
+        cur.set_position(4);
+        let qd_count = cur.read_u16::<BE>()?;
+        let rr_count = u32::from(cur.read_u16::<BE>()?)
+            + u32::from(cur.read_u16::<BE>()?)
+            + u32::from(cur.read_u16::<BE>()?);
+
+        for _ in 0..qd_count {
+            cur.read_qname()?;
+            cur.set_position(cur.position() + 4);
+        }
+
+        // Skip to the start of the last record.
+        let mut start = cur.position();
+        for _ in 0..rr_count {
+            start = cur.position();
+            cur.read_qname()?;
+            cur.set_position(cur.position() + 8);
+            let len = cur.read_u16::<BE>()?;
+            cur.set_position(cur.position() + u64::from(len));
+        }
+
+        let mut data = buf[..start as usize].to_vec();
+        let ar_count = u16::from_be_bytes([data[10], data[11]]).saturating_sub(1);
+        data[10..12].copy_from_slice(&ar_count.to_be_bytes());
+
+        Ok(data)
+    }
+
+    /// Returns the message's last record, if it's a signature, such as a
+    /// TSIG or SIG(0) record, as matched by `is_signature`. Fails if there
+    /// are other signatures, or the signature isn't the last record.
+    #[cfg(any(feature = "sig0", feature = "tsig"))]
+    pub(crate) fn last_signature<F>(
+        &self,
+        kind: &str,
+        is_signature: F,
+    ) -> io::Result<Option<&Record>>
+    where
+        F: Fn(&Record) -> bool,
+    {
+        let count = self
+            .answers
+            .iter()
+            .chain(&self.authoritys)
+            .chain(&self.additionals)
+            .filter(|r| is_signature(r))
+            .count();
+
+        match self.additionals.last() {
+            Some(record) if count == 1 && is_signature(record) => Ok(Some(record)),
+            _ if count == 0 => Ok(None),
+            _ => bail!(InvalidData, "{} record is not the last record", kind),
+        }
+    }
+
+    /// Writes a Unicode domain name into the supplied [`Vec<u8>`], without
+    /// compression.
     ///
-    // TODO Support compression.
-    fn write_qname(buf: &mut Vec<u8>, domain: &str) -> io::Result<()> {
+    /// Used for writing out a encoded ASCII domain name into a DNS message.
+    pub(crate) fn write_qname(buf: &mut Vec<u8>, domain: &str) -> io::Result<()> {
+        for label in Message::labels(domain)? {
+            // Write the length.
+            buf.push(label.len() as u8);
+
+            // Then the actual label.
+            buf.extend_from_slice(label.as_bytes());
+        }
+
+        buf.push(0);
+
+        Ok(())
+    }
+
+    /// Returns the ASCII labels of the Unicode domain name, failing if any
+    /// are empty or too long.
+    fn labels(domain: &str) -> io::Result<Vec<String>> {
         // Decode this label into the original unicode.
         // TODO Switch to using our own idna::Config. (but we can't use disallowed_by_std3_ascii_rules).
         let domain = match idna::domain_to_ascii(domain) {
@@ -289,22 +392,57 @@ impl Message {
             Ok(domain) => domain,
         };
 
-        if !domain.is_empty() && domain != "." {
-            for label in domain.split_terminator('.') {
-                if label.is_empty() {
-                    bail!(InvalidData, "empty label in domain name '{}'", domain);
-                }
+        if domain.is_empty() || domain == "." {
+            return Ok(Vec::new());
+        }
 
-                if label.len() > 63 {
-                    bail!(InvalidData, "label '{0}' longer than 63 characters", label);
-                }
+        let mut labels = Vec::new();
+        for label in domain.split_terminator('.') {
+            if label.is_empty() {
+                bail!(InvalidData, "empty label in domain name '{}'", domain);
+            }
+
+            if label.len() > 63 {
+                bail!(InvalidData, "label '{0}' longer than 63 characters", label);
+            }
 
-                // Write the length.
-                buf.push(label.len() as u8);
+            labels.push(label.to_string());
+        }
+
+        Ok(labels)
+    }
+}
 
-                // Then the actual label.
-                buf.extend_from_slice(label.as_bytes());
+/// Remembers where names were written in a message, so later names ending
+/// the same way can point back to them. See [rfc1035] section 4.1.4.
+///
+/// [rfc1035]: https://datatracker.ietf.org/doc/html/rfc1035
+#[derive(Default)]
+pub(crate) struct Names {
+    /// The offset of each name written so far, keyed by its ASCII labels.
+    offsets: HashMap<String, u16>,
+}
+
+impl Names {
+    /// Writes the Unicode domain name into the message being written in
+    /// `buf`, replacing the longest suffix already written with a pointer.
+    pub(crate) fn write(&mut self, buf: &mut Vec<u8>, domain: &str) -> io::Result<()> {
+        let labels = Message::labels(domain)?;
+
+        for i in 0..labels.len() {
+            let suffix = labels[i..].join(".");
+            if let Some(offset) = self.offsets.get(&suffix) {
+                buf.extend_from_slice(&(0xC000 | offset).to_be_bytes());
+                return Ok(());
             }
+
+            // Pointers only have 14 bits for the offset.
+            if buf.len() < 0x4000 {
+                self.offsets.insert(suffix, buf.len() as u16);
+            }
+
+            buf.push(labels[i].len() as u8);
+            buf.extend_from_slice(labels[i].as_bytes());
         }
 
         buf.push(0);
@@ -334,8 +472,18 @@ impl Extension {
 
         let _z = cur.read_u8()?;
 
-        // TODO implement this
         let rd_len = cur.read_u16::<BE>()?;
+        if cur.remaining()? < rd_len.into() {
+            bail!(InvalidData, "EDNS(0) extension longer than the message");
+        }
+
+        // Parse the options from a cursor limited to rd_len.
+        let pos = cur.position();
+        let end = pos as usize + rd_len as usize;
+        let mut options = cur.sub_cursor(0, end)?;
+        options.set_position(pos);
+
+        let options = ExtensionOption::parse_all(&mut options)?;
         cur.consume(rd_len.into());
 
         Ok(Extension {
@@ -343,6 +491,7 @@ impl Extension {
             extend_rcode,
             version,
             dnssec_ok,
+            options,
         })
     }
 
@@ -361,9 +510,104 @@ impl Extension {
         buf.push(b);
         buf.push(0);
 
-        // 16 bit RDLEN - TODO
-        buf.push(0);
-        buf.push(0);
+        let mut rdata = Vec::new();
+        for option in &self.options {
+            option.write(&mut rdata)?;
+        }
+
+        if rdata.len() > u16::MAX.into() {
+            bail!(
+                InvalidData,
+                "EDNS(0) options longer than {} bytes",
+                u16::MAX
+            );
+        }
+
+        buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&rdata);
+
+        Ok(())
+    }
+}
+
+impl ExtensionOption {
+    /// Parses all the options until the end of the cursor.
+    fn parse_all(cur: &mut Cursor<&[u8]>) -> io::Result<Vec<ExtensionOption>> {
+        let mut options = Vec::new();
+
+        while cur.remaining()? > 0 {
+            let code = cur.read_u16::<BE>()?;
+            let len = cur.read_u16::<BE>()?;
+
+            let mut data = vec![0; len.into()];
+            cur.read_exact(&mut data)?;
+
+            options.push(match code {
+                ExtensionOption::TCP_KEEPALIVE => match data.len() {
+                    0 => ExtensionOption::TcpKeepalive(None),
+                    2 => {
+                        let timeout = u16::from_be_bytes([data[0], data[1]]);
+                        ExtensionOption::TcpKeepalive(Some(Duration::from_millis(
+                            u64::from(timeout) * 100,
+                        )))
+                    }
+                    _ => bail!(
+                        InvalidData,
+                        "invalid edns-tcp-keepalive option length {}",
+                        data.len()
+                    ),
+                },
+
+                ExtensionOption::EXTENDED_ERROR => {
+                    if data.len() < 2 {
+                        bail!(
+                            InvalidData,
+                            "invalid extended error option length {}",
+                            data.len()
+                        );
+                    }
+
+                    // The text should not be NUL terminated, but some are.
+                    let text = String::from_utf8_lossy(&data[2..]);
+                    ExtensionOption::ExtendedError {
+                        info_code: u16::from_be_bytes([data[0], data[1]]),
+                        extra_text: text.trim_end_matches('\0').to_string(),
+                    }
+                }
+
+                _ => ExtensionOption::Unknown(code, data),
+            });
+        }
+
+        Ok(options)
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) -> io::Result<()> {
+        let data = match self {
+            ExtensionOption::TcpKeepalive(None) => Vec::new(),
+            ExtensionOption::TcpKeepalive(Some(timeout)) => {
+                // In units of 100 milliseconds.
+                let timeout = (timeout.as_millis() / 100).min(u16::MAX.into()) as u16;
+                timeout.to_be_bytes().to_vec()
+            }
+            ExtensionOption::ExtendedError {
+                info_code,
+                extra_text,
+            } => {
+                let mut data = info_code.to_be_bytes().to_vec();
+                data.extend_from_slice(extra_text.as_bytes());
+                data
+            }
+            ExtensionOption::Unknown(_, data) => data.clone(),
+        };
+
+        if data.len() > u16::MAX.into() {
+            bail!(InvalidData, "EDNS(0) option longer than {} bytes", u16::MAX);
+        }
+
+        buf.extend_from_slice(&self.code().to_be_bytes());
+        buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&data);
 
         Ok(())
     }
@@ -1,3 +1,4 @@
+use crate::Rcode;
 use crate::Type;
 use crate::from_str::FromStrError;
 use core::num::ParseIntError;
@@ -41,9 +42,18 @@ pub enum Error {
     #[error(transparent)]
     InvalidUri(#[from] http::uri::InvalidUri),
 
+    #[cfg(feature = "url")]
+    #[error(transparent)]
+    InvalidUrl(#[from] url::ParseError),
+
     #[error(transparent)]
     ParseError(#[from] ParseError),
 
+    /// A signed message failed verification, or the server reported a error
+    /// verifying our request, such as [`Rcode::BadSig`].
+    #[error("signature verification failed: {0}")]
+    SignatureError(Rcode),
+
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 }
@@ -73,3 +83,27 @@ pub enum ParseError {
     #[error("invalid rname email address: '{0}'")]
     InvalidRname(String),
 }
+
+/// The error returned by the typed lookups on [`Resolver`], such as
+/// [`Resolver::lookup_mx`], distinguishing why no records were found.
+///
+/// [`Resolver`]: crate::clients::Resolver
+/// [`Resolver::lookup_mx`]: crate::clients::Resolver::lookup_mx
+#[derive(Error, Debug)]
+pub enum LookupError {
+    /// The name does not exist (NXDOMAIN).
+    #[error("{0} does not exist")]
+    NxDomain(String),
+
+    /// The name exists, but has no records of this type (NODATA).
+    #[error("{0} has no {1} records")]
+    NoData(String, Type),
+
+    /// The server failed to answer, for example with SERVFAIL or REFUSED.
+    #[error("query failed with rcode: {0}")]
+    ServerFailure(Rcode),
+
+    /// The query could not be sent, or the response was invalid.
+    #[error(transparent)]
+    Error(#[from] Error),
+}
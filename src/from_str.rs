@@ -40,6 +40,7 @@ impl Resource {
             // Simple strings (domains)
             Type::NS => Resource::NS(s.to_string()),
             Type::CNAME => Resource::CNAME(s.to_string()),
+            Type::DNAME => Resource::DNAME(s.to_string()),
             Type::PTR => Resource::PTR(s.to_string()),
 
             // Complex types
@@ -50,7 +51,14 @@ impl Resource {
             Type::TXT => Resource::TXT(s.parse()?),
 
             // This should never appear in a answer record unless we have invalid data.
-            Type::Reserved | Type::OPT | Type::ANY => return Err(FromStrError::UnsupportedType),
+            Type::Reserved
+            | Type::OPT
+            | Type::SIG
+            | Type::KEY
+            | Type::TSIG
+            | Type::IXFR
+            | Type::AXFR
+            | Type::ANY => return Err(FromStrError::UnsupportedType),
         })
     }
 }
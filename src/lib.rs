@@ -85,8 +85,11 @@
 //! - `clients`: Enables the following clients:
 //!   - `doh`: DNS over HTTPS (DoH) client (rfc8484).
 //!   - `json`: DNS over HTTPS JSON client
+//!   - `odoh`: Oblivious DNS over HTTPS (ODoH) client (rfc9230).
 //!   - `tcp`: Enables the DNS over TCP client
 //!   - `udp`: Enables the DNS over UDP client
+//! - `sig0`: Public key transaction signatures (SIG(0)) for authenticating messages (rfc2931).
+//! - `tsig`: Transaction Signatures (TSIG) for authenticating messages (rfc8945).
 //! - `zones`: Enable a Zone File Parser
 //!
 //! # Usage (cli)
@@ -201,8 +204,16 @@ mod dns;
 mod errors;
 mod from_str;
 mod io;
+mod notify;
 pub mod resource;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "sig0")]
+pub mod sig0;
+#[cfg(feature = "tsig")]
+pub mod tsig;
 pub mod types;
+mod update;
 pub mod util;
 
 cfg_feature! {
@@ -230,10 +241,14 @@ pub use crate::types::*;
 #[doc(inline)]
 pub use crate::resource::*;
 
+pub use crate::notify::Notify;
+pub use crate::update::Update;
+
 #[doc(inline)]
 #[cfg(feature = "udp")]
  // TODO Allow this resolve to use any available client
 pub use crate::clients::Resolver;
 
 pub use crate::errors::Error;
+pub use crate::errors::LookupError;
 pub use crate::errors::ParseError;
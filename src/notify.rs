@@ -0,0 +1,58 @@
+use crate::Class;
+use crate::Message;
+use crate::Opcode;
+use crate::Record;
+use crate::Type;
+
+/// Builds a NOTIFY [`Message`], telling a secondary server that a zone has
+/// changed, as described in [rfc1996].
+///
+/// The zone is the question, and the zone's new SOA record may be included
+/// as the answer, as a hint of the new version. The secondary acknowledges
+/// the NOTIFY, and then checks the primary for the new version.
+///
+/// # Example
+///
+/// ```rust
+/// use rustdns::types::*;
+/// use rustdns::Notify;
+///
+/// let message = Notify::new("example.com").build();
+///
+/// assert_eq!(message.opcode, Opcode::Notify);
+/// assert_eq!(message.questions[0].r#type, Type::SOA);
+/// ```
+///
+/// [rfc1996]: https://datatracker.ietf.org/doc/html/rfc1996
+#[derive(Clone, Debug)]
+pub struct Notify {
+    message: Message,
+}
+
+impl Notify {
+    /// Creates a new Notify that the zone has changed.
+    pub fn new(zone: &str) -> Notify {
+        let mut message = Message {
+            opcode: Opcode::Notify,
+            aa: true,
+            rd: false,
+            ad: false,
+            ..Default::default()
+        };
+        message.add_question(zone, Type::SOA, Class::Internet);
+
+        Notify { message }
+    }
+
+    /// Includes the zone's new SOA record, so a secondary that already has
+    /// that version need not check the primary.
+    pub fn with_soa(mut self, soa: Record) -> Self {
+        self.message.answers = vec![soa];
+        self
+    }
+
+    /// Returns the NOTIFY message.
+    pub fn build(self) -> Message {
+        self.message
+    }
+}
@@ -1,8 +1,10 @@
 use crate::bail;
+use crate::dns::Names;
 use crate::io::{CursorExt, DNSReadExt, SeekExt};
 use crate::types::*;
 use crate::ParseError;
 use byteorder::{ReadBytesExt, BE};
+use num_traits::FromPrimitive;
 use std::io;
 use std::io::Cursor;
 use std::io::Read;
@@ -24,6 +26,11 @@ pub type NS = String;
 #[allow(clippy::upper_case_acronyms)]
 pub type CNAME = String;
 
+/// Delegation name (DNAME) record, for aliasing every name below one name
+/// to the same name below another.
+#[allow(clippy::upper_case_acronyms)]
+pub type DNAME = String;
+
 /// Pointer (PTR) record most commonly used for most common use is for
 /// implementing reverse DNS lookups.
 #[allow(clippy::upper_case_acronyms)]
@@ -66,20 +73,27 @@ impl Record {
         // TODO Consider changing these parse methods to some kind of common function
         // that accepts Cursor and Class.
         let resource = match r#type {
+            // UPDATE messages refer to whole RRsets with empty records.
+            _ if len == 0 && matches!(class, Class::Any | Class::None) => Resource::Empty(r#type),
+
             Type::A => Resource::A(parse_a(&mut record, class)?),
             Type::AAAA => Resource::AAAA(parse_aaaa(&mut record, class)?),
 
             Type::NS => Resource::NS(record.read_qname()?),
             Type::SOA => Resource::SOA(SOA::parse(&mut record)?),
             Type::CNAME => Resource::CNAME(record.read_qname()?),
+            Type::DNAME => Resource::DNAME(record.read_qname()?),
             Type::PTR => Resource::PTR(record.read_qname()?),
             Type::MX => Resource::MX(MX::parse(&mut record)?),
             Type::TXT => Resource::TXT(parse_txt(&mut record)?),
             Type::SPF => Resource::SPF(parse_txt(&mut record)?),
             Type::SRV => Resource::SRV(SRV::parse(&mut record)?),
+            Type::SIG => Resource::SIG(SIG::parse(&mut record)?),
+            Type::KEY => Resource::KEY(KEY::parse(&mut record)?),
+            Type::TSIG => Resource::TSIG(TSIG::parse(&mut record)?),
 
             // This should never appear in a answer record unless we have invalid data.
-            Type::Reserved | Type::OPT | Type::ANY => {
+            Type::Reserved | Type::OPT | Type::IXFR | Type::AXFR | Type::ANY => {
                 // TODO This could be a warning, instead of a full error.
                 bail!(InvalidData, "invalid record type '{}'", r#type);
             }
@@ -106,6 +120,79 @@ impl Record {
     }
 }
 
+impl Record {
+    /// Writes this record into the supplied [`Vec<u8>`], as defined by
+    /// [rfc1035 section 4.1.3].
+    ///
+    /// [rfc1035 section 4.1.3]: https://datatracker.ietf.org/doc/html/rfc1035#section-4.1.3
+    pub(crate) fn write(&self, buf: &mut Vec<u8>, names: &mut Names) -> io::Result<()> {
+        names.write(buf, &self.name)?;
+
+        buf.extend_from_slice(&(self.r#type() as u16).to_be_bytes());
+        buf.extend_from_slice(&(self.class as u16).to_be_bytes());
+
+        // TTLs are limited to 31 bits, see rfc2181 section 8.
+        let ttl = self.ttl.as_secs().min(i32::MAX as u64) as u32;
+        buf.extend_from_slice(&ttl.to_be_bytes());
+
+        // The RDATA is written in place, so names within it can be compressed,
+        // and then its length filled in.
+        let start = buf.len() + 2;
+        buf.extend_from_slice(&[0, 0]);
+        self.resource.write(buf, names)?;
+
+        let len = buf.len() - start;
+        if len > u16::MAX.into() {
+            bail!(
+                InvalidData,
+                "'{}' record longer than {} bytes",
+                self.r#type(),
+                u16::MAX
+            );
+        }
+
+        buf[start - 2..start].copy_from_slice(&(len as u16).to_be_bytes());
+
+        Ok(())
+    }
+}
+
+impl Resource {
+    /// Writes the RDATA of this resource into the supplied [`Vec<u8>`].
+    ///
+    /// Only the names in the types defined by rfc1035 are compressed, see
+    /// [rfc3597] section 4.
+    ///
+    /// [rfc3597]: https://datatracker.ietf.org/doc/html/rfc3597
+    pub(crate) fn write(&self, buf: &mut Vec<u8>, names: &mut Names) -> io::Result<()> {
+        match self {
+            Resource::A(ip4) => buf.extend_from_slice(&ip4.octets()),
+            Resource::AAAA(ip6) => buf.extend_from_slice(&ip6.octets()),
+
+            Resource::NS(name) | Resource::CNAME(name) | Resource::PTR(name) => {
+                names.write(buf, name)?
+            }
+            Resource::DNAME(name) => Message::write_qname(buf, name)?,
+
+            Resource::TXT(txt) | Resource::SPF(txt) => write_txt(buf, txt)?,
+            Resource::MX(mx) => mx.write(buf, names)?,
+            Resource::SOA(soa) => soa.write(buf, names)?,
+            Resource::SRV(srv) => srv.write(buf)?,
+            Resource::SIG(sig) => sig.write(buf)?,
+            Resource::KEY(key) => key.write(buf),
+            Resource::TSIG(tsig) => tsig.write(buf)?,
+
+            Resource::Empty(_) => (),
+
+            Resource::OPT | Resource::ANY => {
+                bail!(InvalidData, "invalid record type '{}'", self.r#type());
+            }
+        }
+
+        Ok(())
+    }
+}
+
 /// Mail EXchanger (MX) record specifies the mail server responsible
 /// for accepting email messages on behalf of a domain name.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
@@ -155,12 +242,110 @@ pub struct SRV {
     pub name: String,
 }
 
+/// Transaction Signature (TSIG) record, authenticating a message with a
+/// shared secret key. See [rfc8945] and [`tsig`](crate::tsig) for signing
+/// and verifying messages.
+///
+/// The record's name is the name of the key, its class is ANY, and its TTL
+/// is zero.
+///
+/// [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub struct TSIG {
+    /// The name of the MAC algorithm, for example "hmac-sha256.".
+    pub algorithm: String,
+
+    /// When the message was signed, in seconds since the UNIX epoch. Only the
+    /// lower 48 bits are sent.
+    pub time_signed: u64,
+
+    /// How far the time signed may be from the time the message is verified.
+    pub fudge: Duration,
+
+    /// The message authentication code.
+    pub mac: Vec<u8>,
+
+    /// The ID of the message when it was signed.
+    pub original_id: u16,
+
+    /// The error verifying the request, such as [`Rcode::BadSig`].
+    pub error: Rcode,
+
+    /// Other data, holding the server's time with a [`Rcode::BadTime`] error.
+    pub other: Vec<u8>,
+}
+
+/// Signature (SIG) record, which this crate uses for public key
+/// transaction signatures (SIG(0)), authenticating a message with a private
+/// key. See [rfc2535], [rfc2931] and [`sig0`](crate::sig0) for signing and
+/// verifying messages.
+///
+/// A SIG(0) record's name is the root, its class is ANY, its TTL is zero,
+/// and it covers no type.
+///
+/// [rfc2535]: https://datatracker.ietf.org/doc/html/rfc2535
+/// [rfc2931]: https://datatracker.ietf.org/doc/html/rfc2931
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub struct SIG {
+    /// The type of the records signed, or [`Type::Reserved`] for SIG(0).
+    pub type_covered: Type,
+
+    /// The number of the signing algorithm, such as 15 for Ed25519.
+    pub algorithm: u8,
+
+    /// The number of labels in the signed records' name, zero for SIG(0).
+    pub labels: u8,
+
+    /// The TTL of the signed records, zero for SIG(0).
+    pub original_ttl: Duration,
+
+    /// When the signature expires, in seconds since the UNIX epoch, using
+    /// serial number arithmetic.
+    pub expiration: u32,
+
+    /// When the signature becomes valid, in seconds since the UNIX epoch.
+    pub inception: u32,
+
+    /// The key tag of the signing [`KEY`], see [`KEY::key_tag`].
+    pub key_tag: u16,
+
+    /// The name of the signing [`KEY`] record.
+    pub signer_name: String,
+
+    /// The signature.
+    pub signature: Vec<u8>,
+}
+
+/// Public key (KEY) record, used to verify SIG(0) signatures. See [rfc2535]
+/// and [rfc3445].
+///
+/// [rfc2535]: https://datatracker.ietf.org/doc/html/rfc2535
+/// [rfc3445]: https://datatracker.ietf.org/doc/html/rfc3445
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub struct KEY {
+    /// The flags, such as 512 for a key that belongs to a host.
+    pub flags: u16,
+
+    /// The protocol, which must be 3 (DNSSEC).
+    pub protocol: u8,
+
+    /// The number of the algorithm, such as 15 for Ed25519.
+    pub algorithm: u8,
+
+    /// The public key, in the algorithm's format.
+    pub public_key: Vec<u8>,
+}
+
 fn parse_a(cur: &mut Cursor<&[u8]>, class: Class) -> io::Result<A> {
     let mut buf = [0_u8; 4];
     cur.read_exact(&mut buf)?;
 
     match class {
-        Class::Internet => Ok(A::new(buf[0], buf[1], buf[2], buf[3])),
+        // UPDATE messages delete records with the NONE class.
+        Class::Internet | Class::None => Ok(A::new(buf[0], buf[1], buf[2], buf[3])),
 
         _ => bail!(InvalidData, "unsupported A record class '{}'", class),
     }
@@ -171,7 +356,7 @@ fn parse_aaaa(cur: &mut Cursor<&[u8]>, class: Class) -> io::Result<AAAA> {
     cur.read_exact(&mut buf)?;
 
     match class {
-        Class::Internet => Ok(AAAA::from(buf)),
+        Class::Internet | Class::None => Ok(AAAA::from(buf)),
 
         _ => bail!(InvalidData, "unsupported AAAA record class '{}'", class),
     }
@@ -198,6 +383,19 @@ fn parse_txt(cur: &mut Cursor<&[u8]>) -> io::Result<TXT> {
     Ok(TXT(txts))
 }
 
+fn write_txt(buf: &mut Vec<u8>, txt: &TXT) -> io::Result<()> {
+    for row in &txt.0 {
+        if row.len() > u8::MAX.into() {
+            bail!(InvalidData, "text longer than {} bytes", u8::MAX);
+        }
+
+        buf.push(row.len() as u8);
+        buf.extend_from_slice(row);
+    }
+
+    Ok(())
+}
+
 impl SOA {
     pub(crate) fn parse(cur: &mut Cursor<&[u8]>) -> io::Result<SOA> {
         let mname = cur.read_qname()?;
@@ -221,6 +419,30 @@ impl SOA {
         })
     }
 
+    pub(crate) fn write(&self, buf: &mut Vec<u8>, names: &mut Names) -> io::Result<()> {
+        names.write(buf, &self.mname)?;
+
+        // The mailbox is written as is, so any dots stay part of the first label.
+        let (mailbox, domain) = match self.rname.split_once('@') {
+            Some(parts) => parts,
+            None => bail!(InvalidData, "invalid rname '{}'", self.rname),
+        };
+        if mailbox.is_empty() || mailbox.len() > 63 {
+            bail!(InvalidData, "invalid rname '{}'", self.rname);
+        }
+        buf.push(mailbox.len() as u8);
+        buf.extend_from_slice(mailbox.as_bytes());
+        names.write(buf, domain)?;
+
+        buf.extend_from_slice(&self.serial.to_be_bytes());
+        for duration in [self.refresh, self.retry, self.expire, self.minimum] {
+            let secs = duration.as_secs().min(u32::MAX.into()) as u32;
+            buf.extend_from_slice(&secs.to_be_bytes());
+        }
+
+        Ok(())
+    }
+
     /// Converts rnames to email address, for example, "admin.example.com" is
     /// converted to "admin@example.com", per the rules in
     /// https://datatracker.ietf.org/doc/html/rfc1035#section-8
@@ -266,6 +488,11 @@ impl SOA {
 }
 
 impl MX {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>, names: &mut Names) -> io::Result<()> {
+        buf.extend_from_slice(&self.preference.to_be_bytes());
+        names.write(buf, &self.exchange)
+    }
+
     pub(crate) fn parse(cur: &mut Cursor<&[u8]>) -> io::Result<MX> {
         let preference = cur.read_u16::<BE>()?;
         let exchange = cur.read_qname()?;
@@ -278,6 +505,13 @@ impl MX {
 }
 
 impl SRV {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) -> io::Result<()> {
+        buf.extend_from_slice(&self.priority.to_be_bytes());
+        buf.extend_from_slice(&self.weight.to_be_bytes());
+        buf.extend_from_slice(&self.port.to_be_bytes());
+        Message::write_qname(buf, &self.name)
+    }
+
     pub(crate) fn parse(cur: &mut Cursor<&[u8]>) -> io::Result<SRV> {
         let priority = cur.read_u16::<BE>()?;
         let weight = cur.read_u16::<BE>()?;
@@ -294,6 +528,155 @@ impl SRV {
     }
 }
 
+impl TSIG {
+    pub(crate) fn parse(cur: &mut Cursor<&[u8]>) -> io::Result<TSIG> {
+        let algorithm = cur.read_qname()?;
+        let time_signed = cur.read_u48::<BE>()?;
+        let fudge = cur.read_u16::<BE>()?;
+
+        let mut mac = vec![0; cur.read_u16::<BE>()?.into()];
+        cur.read_exact(&mut mac)?;
+
+        let original_id = cur.read_u16::<BE>()?;
+        let error = cur.read_u16::<BE>()?;
+        let error = match FromPrimitive::from_u16(error) {
+            Some(error) => error,
+            None => bail!(InvalidData, "invalid TSIG error({})", error),
+        };
+
+        let mut other = vec![0; cur.read_u16::<BE>()?.into()];
+        cur.read_exact(&mut other)?;
+
+        Ok(TSIG {
+            algorithm,
+            time_signed,
+            fudge: Duration::from_secs(fudge.into()),
+            mac,
+            original_id,
+            error,
+            other,
+        })
+    }
+
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) -> io::Result<()> {
+        if self.mac.len() > u16::MAX.into() || self.other.len() > u16::MAX.into() {
+            bail!(InvalidData, "TSIG data longer than {} bytes", u16::MAX);
+        }
+
+        // The algorithm name is never compressed, see rfc8945 section 4.2.
+        Message::write_qname(buf, &self.algorithm)?;
+        self.write_timers(buf);
+        buf.extend_from_slice(&(self.mac.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.mac);
+        buf.extend_from_slice(&self.original_id.to_be_bytes());
+        buf.extend_from_slice(&(self.error as u16).to_be_bytes());
+        buf.extend_from_slice(&(self.other.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.other);
+
+        Ok(())
+    }
+
+    /// Writes the time signed (48 bits) and fudge (16 bits).
+    pub(crate) fn write_timers(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.time_signed.to_be_bytes()[2..]);
+        let fudge = self.fudge.as_secs().min(u16::MAX.into()) as u16;
+        buf.extend_from_slice(&fudge.to_be_bytes());
+    }
+}
+
+impl SIG {
+    pub(crate) fn parse(cur: &mut Cursor<&[u8]>) -> io::Result<SIG> {
+        let type_covered = cur.read_type()?;
+        let algorithm = cur.read_u8()?;
+        let labels = cur.read_u8()?;
+        let original_ttl = cur.read_u32::<BE>()?;
+        let expiration = cur.read_u32::<BE>()?;
+        let inception = cur.read_u32::<BE>()?;
+        let key_tag = cur.read_u16::<BE>()?;
+        let signer_name = cur.read_qname()?;
+
+        let mut signature = Vec::new();
+        cur.read_to_end(&mut signature)?;
+
+        Ok(SIG {
+            type_covered,
+            algorithm,
+            labels,
+            original_ttl: Duration::from_secs(original_ttl.into()),
+            expiration,
+            inception,
+            key_tag,
+            signer_name,
+            signature,
+        })
+    }
+
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.write_fields(buf)?;
+        buf.extend_from_slice(&self.signature);
+        Ok(())
+    }
+
+    /// Writes every field before the signature, which start the signed data,
+    /// see rfc2535 section 4.1.8. The signer's name is never compressed.
+    pub(crate) fn write_fields(&self, buf: &mut Vec<u8>) -> io::Result<()> {
+        buf.extend_from_slice(&(self.type_covered as u16).to_be_bytes());
+        buf.push(self.algorithm);
+        buf.push(self.labels);
+        let ttl = self.original_ttl.as_secs().min(u32::MAX.into()) as u32;
+        buf.extend_from_slice(&ttl.to_be_bytes());
+        buf.extend_from_slice(&self.expiration.to_be_bytes());
+        buf.extend_from_slice(&self.inception.to_be_bytes());
+        buf.extend_from_slice(&self.key_tag.to_be_bytes());
+        Message::write_qname(buf, &self.signer_name)
+    }
+}
+
+impl KEY {
+    pub(crate) fn parse(cur: &mut Cursor<&[u8]>) -> io::Result<KEY> {
+        let flags = cur.read_u16::<BE>()?;
+        let protocol = cur.read_u8()?;
+        let algorithm = cur.read_u8()?;
+
+        let mut public_key = Vec::new();
+        cur.read_to_end(&mut public_key)?;
+
+        Ok(KEY {
+            flags,
+            protocol,
+            algorithm,
+            public_key,
+        })
+    }
+
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.flags.to_be_bytes());
+        buf.push(self.protocol);
+        buf.push(self.algorithm);
+        buf.extend_from_slice(&self.public_key);
+    }
+
+    /// Returns the key tag, which identifies the key in a [`SIG`] record,
+    /// see [rfc4034] appendix B.
+    ///
+    /// [rfc4034]: https://datatracker.ietf.org/doc/html/rfc4034#appendix-B
+    pub fn key_tag(&self) -> u16 {
+        let mut rdata = Vec::with_capacity(4 + self.public_key.len());
+        self.write(&mut rdata);
+
+        let mut ac: u32 = 0;
+        for (i, b) in rdata.iter().enumerate() {
+            ac += if i & 1 == 0 {
+                u32::from(*b) << 8
+            } else {
+                u32::from(*b)
+            };
+        }
+        ac += (ac >> 16) & 0xFFFF;
+        (ac & 0xFFFF) as u16
+    }
+}
+
 impl From<&str> for TXT {
     fn from(txt: &str) -> TXT {
         TXT(vec![txt.as_bytes().to_vec()])
@@ -0,0 +1,119 @@
+use crate::bail;
+use crate::server::Request;
+use crate::util::normalise;
+use std::net::IpAddr;
+
+/// A list of networks, and TSIG keys, allowed to make a request, such as a
+/// zone transfer. A request is allowed if it comes from one of the networks,
+/// or was signed with one of the keys.
+///
+/// The default Acl allows nothing.
+///
+/// # Example
+///
+/// ```rust
+/// use rustdns::server::Acl;
+///
+/// let acl = Acl::new(["192.0.2.0/24", "2001:db8::1"]).unwrap();
+///
+/// assert!(acl.allows("192.0.2.53".parse().unwrap()));
+/// assert!(acl.allows("2001:db8::1".parse().unwrap()));
+/// assert!(!acl.allows("198.51.100.1".parse().unwrap()));
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Acl {
+    /// The allowed networks, as a address and prefix length.
+    networks: Vec<(IpAddr, u8)>,
+
+    /// The names of the allowed TSIG keys, lowercase and fully qualified.
+    keys: Vec<String>,
+}
+
+impl Acl {
+    /// Creates a new Acl allowing the networks, each either a single address
+    /// ("192.0.2.1"), or a network in CIDR notation ("192.0.2.0/24").
+    pub fn new<I, S>(networks: I) -> Result<Acl, crate::Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut acl = Acl::default();
+        for network in networks {
+            acl.networks.push(parse_network(network.as_ref())?);
+        }
+        Ok(acl)
+    }
+
+    /// Returns a Acl that allows every address.
+    pub fn any() -> Acl {
+        Acl {
+            networks: vec![
+                (IpAddr::from([0, 0, 0, 0]), 0),
+                (IpAddr::from([0_u16; 8]), 0),
+            ],
+            keys: Vec::new(),
+        }
+    }
+
+    /// Also allows requests signed with the TSIG key, from any address.
+    pub fn with_key(mut self, name: &str) -> Self {
+        self.keys.push(normalise(name));
+        self
+    }
+
+    /// Returns true if the request comes from one of the networks, or was
+    /// signed with one of the keys.
+    pub fn allows_request(&self, request: &Request) -> bool {
+        let signed = match &request.key {
+            Some(key) => self.keys.iter().any(|k| k.eq_ignore_ascii_case(key)),
+            None => false,
+        };
+
+        signed || self.allows(request.src.ip())
+    }
+
+    /// Returns true if the address is within one of the networks.
+    pub fn allows(&self, addr: IpAddr) -> bool {
+        // IPv4 clients of a dual stack socket appear as IPv4-mapped addresses.
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+            IpAddr::V4(_) => addr,
+        };
+
+        self.networks
+            .iter()
+            .any(|(network, prefix)| match (network, addr) {
+                (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                    let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+                    u32::from(*network) & mask == u32::from(addr) & mask
+                }
+                (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                    let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+                    u128::from(*network) & mask == u128::from(addr) & mask
+                }
+                _ => false,
+            })
+    }
+}
+
+/// Parses a address, or a network in CIDR notation.
+fn parse_network(network: &str) -> Result<(IpAddr, u8), crate::Error> {
+    let (addr, prefix) = match network.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (network, None),
+    };
+
+    let addr: IpAddr = match addr.parse() {
+        Ok(addr) => addr,
+        Err(_) => bail!(InvalidInput, "invalid network '{}'", network),
+    };
+
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix.map(str::parse::<u8>) {
+        None => max,
+        Some(Ok(prefix)) if prefix <= max => prefix,
+        Some(_) => bail!(InvalidInput, "invalid prefix length in '{}'", network),
+    };
+
+    Ok((addr, prefix))
+}
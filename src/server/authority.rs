@@ -0,0 +1,604 @@
+use crate::bail;
+use crate::server::transfer;
+use crate::server::transfer::Messages;
+use crate::server::update;
+use crate::server::Acl;
+use crate::server::Handler;
+use crate::server::Protocol;
+use crate::server::Request;
+use crate::util::is_newer_serial;
+use crate::util::normalise;
+use crate::zones::File;
+use crate::zones::Lookup;
+use crate::zones::Zone;
+use crate::Class;
+use crate::Message;
+use crate::Opcode;
+use crate::Question;
+use crate::Rcode;
+use crate::Record;
+use crate::Resource;
+use crate::Type;
+use log::debug;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+/// The most CNAMEs that will be followed within the zone for one query.
+const MAX_CNAMES: usize = 8;
+
+/// Called with each NOTIFY received for the zone.
+type NotifyHook = dyn Fn(&Request) + Send + Sync;
+
+/// A [`Handler`] that answers authoritatively for a single [`Zone`].
+///
+/// Names that exist are answered with `aa` set. Names that don't exist are
+/// answered with NXDOMAIN, and names without records of the requested type
+/// with NODATA (NOERROR and no answers), both with the zone's SOA in the
+/// authority section, see [rfc2308]. CNAMEs (including those synthesized
+/// from DNAMEs) are followed while their target is within the zone. Queries
+/// for names at or below a delegation are answered with a referral, that is
+/// the NS records in the authority section, and any glue in the additional
+/// section. Wildcards are applied as described in [rfc4592].
+///
+/// Queries for names outside the zone are REFUSED, and opcodes other than
+/// QUERY, UPDATE and NOTIFY are NOTIMP.
+///
+/// # Zone transfers
+///
+/// Clients allowed by the transfer [`Acl`] (by default none) may transfer
+/// the zone. A AXFR ([rfc5936]) is only answered over TCP, with the whole
+/// zone split across as many messages as needed. A IXFR ([rfc1995]) is
+/// answered with the changes since the client's version, if they are still
+/// in the history kept by [`update`](Authority::update), otherwise with the
+/// whole zone. Over UDP, a IXFR whose answer doesn't fit in one message is
+/// answered with only the SOA, so the client retries over TCP.
+///
+/// # Dynamic updates
+///
+/// Clients allowed by the update [`Acl`] (by default none) may change the
+/// zone with a UPDATE message, as built by [`Update`](crate::Update). The
+/// prerequisites are checked and the changes applied as described in
+/// [rfc2136], either all of them or none, and the SOA serial incremented.
+/// Other clients are REFUSED.
+///
+/// # NOTIFY
+///
+/// When acting as a secondary, a NOTIFY ([rfc1996]) from a client allowed
+/// by the notify [`Acl`] is acknowledged, and the refresh callback set with
+/// [`with_notify`](Authority::with_notify) called, unless the NOTIFY
+/// includes a SOA whose serial is not newer than the zone's. Without a
+/// callback, NOTIFY is NOTIMP.
+///
+/// Any Acl may allow clients by the TSIG key they signed the request
+/// with, see [`Acl::with_key`], once the server is given the key to verify
+/// requests with.
+///
+/// [rfc1995]: https://datatracker.ietf.org/doc/html/rfc1995
+/// [rfc1996]: https://datatracker.ietf.org/doc/html/rfc1996
+/// [rfc2136]: https://datatracker.ietf.org/doc/html/rfc2136
+/// [rfc2308]: https://datatracker.ietf.org/doc/html/rfc2308
+/// [rfc4592]: https://datatracker.ietf.org/doc/html/rfc4592
+/// [rfc5936]: https://datatracker.ietf.org/doc/html/rfc5936
+pub struct Authority {
+    /// The name of the zone, which stays the same across updates.
+    origin: String,
+
+    state: RwLock<State>,
+
+    /// The clients allowed to transfer the zone.
+    transfer_acl: Acl,
+
+    /// The clients allowed to update the zone.
+    update_acl: Acl,
+
+    /// The clients allowed to notify of changes to the zone.
+    notify_acl: Acl,
+
+    /// Called when a NOTIFY is received, to refresh the zone.
+    on_notify: Option<Box<NotifyHook>>,
+
+    /// The most changes kept for IXFR.
+    max_history: usize,
+
+    /// The largest message sent during a transfer over TCP.
+    transfer_message_size: usize,
+}
+
+struct State {
+    zone: Arc<Zone>,
+
+    /// The changes between the recent versions of the zone, oldest first.
+    history: VecDeque<Change>,
+}
+
+/// The changes between two versions of the zone. As in a IXFR, `deleted`
+/// starts with the old SOA, and `added` with the new one.
+struct Change {
+    deleted: Vec<Record>,
+    added: Vec<Record>,
+}
+
+impl Authority {
+    /// Creates a new Authority serving the zone file, which must contain one
+    /// SOA record, for the apex of the zone.
+    pub fn new(file: File) -> Result<Authority, crate::Error> {
+        Ok(Self::from_zone(Zone::from_file(file)?))
+    }
+
+    /// Creates a new Authority serving the records, which must contain one
+    /// SOA record, for the apex of the zone.
+    pub fn from_records(records: Vec<Record>) -> Result<Authority, crate::Error> {
+        Ok(Self::from_zone(Zone::new(records)?))
+    }
+
+    fn from_zone(zone: Zone) -> Authority {
+        Authority {
+            origin: zone.origin().to_string(),
+            state: RwLock::new(State {
+                zone: Arc::new(zone),
+                history: VecDeque::new(),
+            }),
+            transfer_acl: Acl::default(),
+            update_acl: Acl::default(),
+            notify_acl: Acl::default(),
+            on_notify: None,
+            max_history: 16,
+            transfer_message_size: 16384,
+        }
+    }
+
+    /// Sets the clients allowed to transfer the zone (with AXFR or IXFR).
+    /// Defaults to none.
+    pub fn with_transfer_acl(mut self, acl: Acl) -> Self {
+        self.transfer_acl = acl;
+        self
+    }
+
+    /// Sets the clients allowed to update the zone (with UPDATE). Defaults to
+    /// none.
+    pub fn with_update_acl(mut self, acl: Acl) -> Self {
+        self.update_acl = acl;
+        self
+    }
+
+    /// Sets the clients allowed to notify of changes to the zone (with
+    /// NOTIFY), and the callback called when they do, which should start
+    /// refreshing the zone, for example from the primary with a IXFR, and
+    /// then [`update`](Authority::update) it.
+    ///
+    /// The callback is called on the server's thread, before the NOTIFY is
+    /// acknowledged, so should return quickly, for example by sending to a
+    /// channel.
+    pub fn with_notify<F>(mut self, acl: Acl, refresh: F) -> Self
+    where
+        F: Fn(&Request) + Send + Sync + 'static,
+    {
+        self.notify_acl = acl;
+        self.on_notify = Some(Box::new(refresh));
+        self
+    }
+
+    /// Sets how many updates are remembered, so clients with those versions
+    /// can be sent only the changes with a IXFR. Defaults to 16.
+    pub fn with_max_history(mut self, max: usize) -> Self {
+        self.max_history = max;
+        self
+    }
+
+    /// Sets the largest message sent during a transfer over TCP, including
+    /// its TSIG signature if the request was signed. Defaults to 16 KiB.
+    pub fn with_transfer_message_size(mut self, size: usize) -> Self {
+        self.transfer_message_size = size.min(u16::MAX.into());
+        self
+    }
+
+    /// Returns the name of the zone.
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// Returns the current version of the zone being served.
+    pub fn zone(&self) -> Arc<Zone> {
+        self.state.read().unwrap().zone.clone()
+    }
+
+    /// Replaces the zone being served with a newer version, remembering the
+    /// changes for IXFR.
+    ///
+    /// Fails if the zone has a different origin, or its SOA serial is not
+    /// newer than the current version.
+    pub fn update(&self, zone: Zone) -> Result<(), crate::Error> {
+        if zone.origin() != self.origin {
+            bail!(
+                InvalidInput,
+                "zone '{}' is not '{}'",
+                zone.origin(),
+                self.origin
+            );
+        }
+
+        let mut state = self.state.write().unwrap();
+        let (old, new) = (serial(state.zone.soa()), serial(zone.soa()));
+        if !is_newer_serial(new, old) {
+            bail!(
+                InvalidInput,
+                "serial {} of '{}' is not newer than {}",
+                new,
+                self.origin,
+                old
+            );
+        }
+
+        self.replace(&mut state, zone);
+        Ok(())
+    }
+
+    /// Replaces the zone with the newer version, remembering the changes.
+    fn replace(&self, state: &mut State, zone: Zone) {
+        if self.max_history > 0 {
+            let change = Change::new(&state.zone, &zone);
+            state.history.push_back(change);
+            while state.history.len() > self.max_history {
+                state.history.pop_front();
+            }
+        }
+
+        state.zone = Arc::new(zone);
+    }
+
+    /// Applies the UPDATE request to the zone.
+    fn dynamic_update(&self, request: &Request) -> Message {
+        let mut resp = request.response();
+
+        if !self.update_acl.allows_request(request) {
+            debug!("{}: update of {} refused", request.src, self.origin);
+            resp.rcode = Rcode::Refused;
+            return resp;
+        }
+
+        // Hold the lock throughout, so the prerequisites still hold when the
+        // changes are made.
+        let mut state = self.state.write().unwrap();
+        match update::apply(&state.zone, &request.message) {
+            Ok(Some(zone)) => self.replace(&mut state, zone),
+            Ok(None) => (),
+            Err(rcode) => resp.rcode = rcode,
+        }
+
+        resp
+    }
+
+    /// Acknowledges the NOTIFY request, calling the refresh callback if the
+    /// zone may have changed. See rfc1996 section 3.
+    fn notify(&self, request: &Request) -> Message {
+        let mut resp = request.response();
+
+        let refresh = match &self.on_notify {
+            Some(refresh) => refresh,
+            None => {
+                resp.rcode = Rcode::NotImp;
+                return resp;
+            }
+        };
+
+        let question = match request.message.questions.as_slice() {
+            [question] if question.r#type == Type::SOA => question,
+            _ => {
+                resp.rcode = Rcode::FormErr;
+                return resp;
+            }
+        };
+
+        if normalise(&question.name) != self.origin {
+            resp.rcode = Rcode::NotAuth;
+            return resp;
+        }
+
+        if !self.notify_acl.allows_request(request) {
+            debug!("{}: notify of {} refused", request.src, self.origin);
+            resp.rcode = Rcode::Refused;
+            return resp;
+        }
+
+        resp.aa = true;
+
+        // The SOA is only a hint, but there's no need to refresh if it's no
+        // newer than the zone.
+        let hint = request
+            .message
+            .answers
+            .iter()
+            .find_map(|r| match &r.resource {
+                Resource::SOA(soa) => Some(soa.serial),
+                _ => None,
+            });
+        if let Some(hint) = hint {
+            if !is_newer_serial(hint, serial(self.zone().soa())) {
+                debug!("{}: {} is up to date at {}", request.src, self.origin, hint);
+                return resp;
+            }
+        }
+
+        refresh(request);
+        resp
+    }
+
+    /// Answers the query for the name and type into the response.
+    fn answer(zone: &Zone, resp: &mut Message, name: &str, r#type: Type) {
+        let mut name = name.to_string();
+
+        for _ in 0..=MAX_CNAMES {
+            let target = match zone.lookup(&name, r#type) {
+                Lookup::Answer { records, .. } => {
+                    resp.answers.extend(records);
+                    return;
+                }
+                Lookup::Cname { record, .. } => {
+                    let target = match &record.resource {
+                        Resource::CNAME(target) => target.clone(),
+                        _ => return,
+                    };
+                    resp.answers.push(record);
+                    target
+                }
+                Lookup::Dname { dname, cname } => {
+                    let target = match &cname.resource {
+                        Resource::CNAME(target) => target.clone(),
+                        _ => return,
+                    };
+                    resp.answers.push(dname);
+                    resp.answers.push(cname);
+                    target
+                }
+                Lookup::Referral { ns, glue, .. } => {
+                    // CNAMEs followed so far are still authoritative.
+                    resp.aa = !resp.answers.is_empty();
+                    resp.authoritys = ns;
+                    resp.additionals = glue;
+                    return;
+                }
+                Lookup::NoData { .. } => {
+                    resp.authoritys.push(negative_soa(zone));
+                    return;
+                }
+                Lookup::NxDomain { .. } => {
+                    resp.rcode = Rcode::NXDomain;
+                    resp.authoritys.push(negative_soa(zone));
+                    return;
+                }
+                Lookup::OutOfZone => {
+                    // Only the first name can be outside the zone, targets
+                    // outside the zone are left for the resolver.
+                    if resp.answers.is_empty() {
+                        resp.aa = false;
+                        resp.rcode = Rcode::Refused;
+                    }
+                    return;
+                }
+            };
+
+            // Stop at loops, leaving the resolver to notice.
+            let normalised = normalise(&target);
+            if resp
+                .answers
+                .iter()
+                .any(|r| normalise(&r.name) == normalised)
+            {
+                return;
+            }
+            name = target;
+        }
+    }
+
+    /// Returns the records to answer the zone transfer with, or sets the
+    /// response's rcode if it's not allowed.
+    fn transfer(&self, request: &Request, resp: &mut Message) -> Option<Vec<Record>> {
+        let question = &request.message.questions[0];
+
+        if normalise(&question.name) != self.origin {
+            resp.rcode = Rcode::NotAuth;
+            return None;
+        }
+
+        if !self.transfer_acl.allows_request(request) {
+            debug!("{}: transfer of {} refused", request.src, self.origin);
+            resp.rcode = Rcode::Refused;
+            return None;
+        }
+
+        // The client's version, for a IXFR.
+        let client = match question.r#type {
+            Type::AXFR if request.protocol == Protocol::Udp => {
+                resp.rcode = Rcode::FormErr;
+                return None;
+            }
+            Type::AXFR => None,
+            _ => match request
+                .message
+                .authoritys
+                .iter()
+                .find_map(|r| match &r.resource {
+                    Resource::SOA(soa) => Some(soa.serial),
+                    _ => None,
+                }) {
+                Some(serial) => Some(serial),
+                None => {
+                    resp.rcode = Rcode::FormErr;
+                    return None;
+                }
+            },
+        };
+
+        let state = self.state.read().unwrap();
+        let soa = state.zone.soa().clone();
+
+        let mut records = vec![soa.clone()];
+        if let Some(client) = client {
+            if !is_newer_serial(serial(&soa), client) {
+                // Up to date.
+                return Some(records);
+            }
+
+            if let Some(i) = state
+                .history
+                .iter()
+                .position(|change| serial(&change.deleted[0]) == client)
+            {
+                for change in state.history.range(i..) {
+                    records.extend(change.deleted.iter().cloned());
+                    records.extend(change.added.iter().cloned());
+                }
+                records.push(soa);
+                return Some(records);
+            }
+        }
+
+        records.extend(state.zone.iter().skip(1).cloned());
+        records.push(soa);
+        Some(records)
+    }
+}
+
+impl Change {
+    /// Returns the changes from the old to the new version of the zone.
+    fn new(old: &Zone, new: &Zone) -> Change {
+        let before: HashSet<&Record> = old.iter().skip(1).collect();
+        let after: HashSet<&Record> = new.iter().skip(1).collect();
+
+        let mut deleted = vec![old.soa().clone()];
+        deleted.extend(old.iter().skip(1).filter(|r| !after.contains(r)).cloned());
+
+        let mut added = vec![new.soa().clone()];
+        added.extend(new.iter().skip(1).filter(|r| !before.contains(r)).cloned());
+
+        Change { deleted, added }
+    }
+}
+
+/// Returns the serial of the SOA record.
+pub(crate) fn serial(soa: &Record) -> u32 {
+    match &soa.resource {
+        Resource::SOA(soa) => soa.serial,
+        _ => 0,
+    }
+}
+
+/// Returns the SOA record to include with negative answers, which has
+/// the TTL of the SOA's minimum field, if lower. See rfc2308 section 3.
+fn negative_soa(zone: &Zone) -> Record {
+    let mut soa = zone.soa().clone();
+    if let Resource::SOA(data) = &soa.resource {
+        soa.ttl = soa.ttl.min(data.minimum);
+    }
+    soa
+}
+
+/// Returns the question of the request, or sets the response's rcode if it
+/// can't be answered.
+fn question<'a>(request: &'a Request, resp: &mut Message) -> Option<&'a Question> {
+    if request.message.opcode != Opcode::Query {
+        resp.rcode = Rcode::NotImp;
+        return None;
+    }
+
+    let question = match request.message.questions.as_slice() {
+        [question] => question,
+        _ => {
+            resp.rcode = Rcode::FormErr;
+            return None;
+        }
+    };
+
+    if !matches!(question.class, Class::Internet | Class::Any) {
+        resp.rcode = Rcode::Refused;
+        return None;
+    }
+
+    Some(question)
+}
+
+impl Handler for Authority {
+    fn handle(&self, request: &Request) -> Option<Message> {
+        if request.message.opcode == Opcode::Update {
+            return Some(self.dynamic_update(request));
+        }
+        if request.message.opcode == Opcode::Notify {
+            return Some(self.notify(request));
+        }
+
+        let mut resp = request.response();
+        let question = match question(request, &mut resp) {
+            Some(question) => question,
+            None => return Some(resp),
+        };
+
+        if !matches!(question.r#type, Type::AXFR | Type::IXFR) {
+            resp.aa = true;
+            Self::answer(&self.zone(), &mut resp, &question.name, question.r#type);
+            return Some(resp);
+        }
+
+        let records = match self.transfer(request, &mut resp) {
+            Some(records) => records,
+            None => return Some(resp),
+        };
+
+        // Only a answer that fits in one message can be sent this way.
+        let max_size: usize = match request.protocol {
+            Protocol::Udp => request
+                .message
+                .extension
+                .as_ref()
+                .map_or(512, |ext| ext.payload_size.max(512).into()),
+            Protocol::Tcp => u16::MAX.into(),
+        };
+        let max_size = max_size.saturating_sub(transfer::overhead(request));
+
+        resp.aa = true;
+        let soa = records[0].clone();
+        let mut messages = Messages::new(resp.clone(), records, max_size);
+        match (messages.next(), messages.next()) {
+            (Some(resp), None) => Some(resp),
+
+            // Tell the client to retry over TCP, see rfc1995 section 2.
+            _ if question.r#type == Type::IXFR => {
+                resp.answers = vec![soa];
+                Some(resp)
+            }
+            _ => {
+                resp.aa = false;
+                resp.rcode = Rcode::ServFail;
+                Some(resp)
+            }
+        }
+    }
+
+    fn handle_stream<'a>(&'a self, request: &'a Request) -> Box<dyn Iterator<Item = Message> + 'a> {
+        if request.message.opcode != Opcode::Query {
+            return Box::new(self.handle(request).into_iter());
+        }
+
+        let mut resp = request.response();
+        let question = match question(request, &mut resp) {
+            Some(question) => question,
+            None => return Box::new(Some(resp).into_iter()),
+        };
+
+        if !matches!(question.r#type, Type::AXFR | Type::IXFR) {
+            return Box::new(self.handle(request).into_iter());
+        }
+
+        match self.transfer(request, &mut resp) {
+            Some(records) => {
+                resp.aa = true;
+                let max_size = self
+                    .transfer_message_size
+                    .saturating_sub(transfer::overhead(request));
+                Box::new(Messages::new(resp, records, max_size))
+            }
+            None => Box::new(Some(resp).into_iter()),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::clients::Exchanger;
+use crate::server::Handler;
+use crate::server::Request;
+use crate::Extension;
+use crate::ExtensionOption;
+use crate::Message;
+use crate::Opcode;
+use crate::Rcode;
+use log::debug;
+
+/// A [`Handler`] that forwards each query to a upstream [`Exchanger`], and
+/// returns its response, turning the server into a forwarding proxy.
+///
+/// The query is sent upstream with a new random ID, which is rewritten back
+/// to the client's ID in the response. EDNS(0) options are passed through in
+/// both directions, except for those that only apply to a single hop (such
+/// as edns-tcp-keepalive), and the response only uses EDNS(0) if the request
+/// did. If the upstream fails, the client gets a SERVFAIL.
+///
+/// Any client can be used upstream. Async clients, such as DNS over HTTPS,
+/// can be wrapped with [`Blocking`](crate::clients::Blocking), and responses
+/// can be cached by wrapping the client with [`Cached`](crate::clients::Cached).
+///
+/// # Example
+///
+/// A local stub resolver, which forwards to Google over DNS over HTTPS:
+///
+/// ```rust,no_run
+/// use rustdns::clients::{doh, Blocking, Cache, Cached};
+/// use rustdns::server::{tcp, udp, Forwarder};
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// fn main() -> Result<(), rustdns::Error> {
+///     let client = Blocking::new(doh::Client::new(doh::GOOGLE, Default::default())?)?;
+///     let forwarder = Arc::new(Forwarder::new(Cached::new(client, Cache::default())));
+///
+///     let server = tcp::Server::bind("127.0.0.1:53", forwarder.clone())?;
+///     thread::spawn(move || server.serve());
+///
+///     udp::Server::bind("127.0.0.1:53", forwarder)?.serve()
+/// }
+/// ```
+pub struct Forwarder<E> {
+    client: E,
+}
+
+impl<E> Forwarder<E> {
+    /// Creates a new Forwarder sending queries to the client.
+    pub fn new(client: E) -> Forwarder<E> {
+        Forwarder { client }
+    }
+
+    /// Returns the upstream client.
+    pub fn client(&self) -> &E {
+        &self.client
+    }
+}
+
+impl<E> Handler for Forwarder<E>
+where
+    E: Exchanger,
+{
+    fn handle(&self, request: &Request) -> Option<Message> {
+        if request.message.opcode != Opcode::Query {
+            let mut resp = request.response();
+            resp.rcode = Rcode::NotImp;
+            return Some(resp);
+        }
+
+        let mut query = request.message.clone();
+        query.id = Message::random_id();
+        query.stats = None;
+        query.extension = query.extension.map(|ext| Extension {
+            // Leave the payload size to the upstream client.
+            payload_size: Extension::default().payload_size,
+            options: end_to_end(ext.options),
+            ..ext
+        });
+
+        let mut resp = match self.client.exchange(&query) {
+            Ok(resp) => resp,
+            Err(e) => {
+                debug!("{}: failed to forward query: {}", request.src, e);
+                let mut resp = request.response();
+                resp.rcode = Rcode::ServFail;
+                return Some(resp);
+            }
+        };
+
+        resp.id = request.message.id;
+        resp.questions = request.message.questions.clone();
+        resp.stats = None;
+        resp.extension = match (&request.message.extension, resp.extension) {
+            (Some(_), Some(ext)) => Some(Extension {
+                payload_size: Extension::default().payload_size,
+                options: end_to_end(ext.options),
+                ..ext
+            }),
+            (Some(_), None) => Some(Extension::default()),
+            (None, _) => None,
+        };
+
+        Some(resp)
+    }
+}
+
+/// Returns the options, without those that only apply to a single hop.
+fn end_to_end(options: Vec<ExtensionOption>) -> Vec<ExtensionOption> {
+    options
+        .into_iter()
+        .filter(|o| !matches!(o, ExtensionOption::TcpKeepalive(_)))
+        .collect()
+}
@@ -0,0 +1,198 @@
+//! DNS servers, which answer requests using a [`Handler`].
+//!
+//! The [`udp::Server`] and [`tcp::Server`] listen for requests and pass them
+//! to the handler. To serve the same handler over both, wrap it in a
+//! [`Arc`](std::sync::Arc) and give each server a clone.
+//!
+//! Two handlers are provided: [`Authority`] answers from a zone, and
+//! [`Forwarder`] forwards queries to another server.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use rustdns::server::{tcp, udp, Authority};
+//! use rustdns::zones::File;
+//! use std::str::FromStr;
+//! use std::sync::Arc;
+//! use std::thread;
+//!
+//! fn main() -> Result<(), rustdns::Error> {
+//!     let file = File::from_str(
+//!         "$ORIGIN example.com.
+//!          $TTL 3600
+//!          @    IN SOA ns admin 1 7200 3600 1209600 3600
+//!          @    IN NS  ns
+//!          ns   IN A   192.0.2.1
+//!          www  IN A   192.0.2.2",
+//!     )
+//!     .expect("invalid zone");
+//!
+//!     let authority = Arc::new(Authority::new(file)?);
+//!
+//!     let server = tcp::Server::bind("127.0.0.1:5353", authority.clone())?;
+//!     thread::spawn(move || server.serve());
+//!
+//!     udp::Server::bind("127.0.0.1:5353", authority)?.serve()
+//! }
+//! ```
+use crate::Message;
+use crate::Opcode;
+use crate::Rcode;
+use crate::QR;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+mod acl;
+pub mod tcp;
+pub mod udp;
+
+pub use self::acl::Acl;
+
+cfg_feature! {
+    #![feature = "zones"]
+
+    mod authority;
+    mod transfer;
+    mod update;
+    pub use self::authority::Authority;
+}
+
+cfg_feature! {
+    #![any(feature = "doh", feature = "json", feature = "tcp", feature = "udp")]
+
+    mod forwarder;
+    pub use self::forwarder::Forwarder;
+}
+
+/// The transport a [`Request`] was received over.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Protocol {
+    Udp,
+    Tcp,
+}
+
+/// A request received by a server.
+#[derive(Clone, Debug)]
+pub struct Request {
+    /// The request message.
+    pub message: Message,
+
+    /// The address the request came from.
+    pub src: SocketAddr,
+
+    /// The transport the request came over.
+    pub protocol: Protocol,
+
+    /// The name of the TSIG key the request was signed with, if it was
+    /// signed and verified by the server.
+    pub key: Option<String>,
+}
+
+impl Request {
+    /// Returns a empty response to this request, with the same ID, opcode,
+    /// and questions. If the request used EDNS(0), so does the response.
+    pub fn response(&self) -> Message {
+        Message {
+            id: self.message.id,
+            qr: QR::Response,
+            opcode: self.message.opcode,
+            rd: self.message.rd,
+            cd: self.message.cd,
+            ad: false,
+            questions: self.message.questions.clone(),
+            extension: self.message.extension.as_ref().map(|_| Default::default()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Handler answers the requests received by a server.
+pub trait Handler {
+    /// Returns the response to the request, or None to not respond.
+    fn handle(&self, request: &Request) -> Option<Message>;
+
+    /// Returns the responses to a request received over a stream, such as
+    /// TCP, where one request may be answered with many messages (for example
+    /// a zone transfer). Each message is sent as soon as it's returned.
+    ///
+    /// Defaults to the single response from [`handle`](Handler::handle).
+    fn handle_stream<'a>(&'a self, request: &'a Request) -> Box<dyn Iterator<Item = Message> + 'a> {
+        Box::new(self.handle(request).into_iter())
+    }
+}
+
+impl<H: Handler + ?Sized> Handler for Box<H> {
+    fn handle(&self, request: &Request) -> Option<Message> {
+        (**self).handle(request)
+    }
+
+    fn handle_stream<'a>(&'a self, request: &'a Request) -> Box<dyn Iterator<Item = Message> + 'a> {
+        (**self).handle_stream(request)
+    }
+}
+
+impl<H: Handler + ?Sized> Handler for Arc<H> {
+    fn handle(&self, request: &Request) -> Option<Message> {
+        (**self).handle(request)
+    }
+
+    fn handle_stream<'a>(&'a self, request: &'a Request) -> Box<dyn Iterator<Item = Message> + 'a> {
+        (**self).handle_stream(request)
+    }
+}
+
+/// Returns a FORMERR response for a request that couldn't be parsed, or None
+/// if it's too short to even have a header, or is itself a response.
+fn format_error(buf: &[u8]) -> Option<Message> {
+    if buf.len() < 12 || buf[2] & 0b1000_0000 != 0 {
+        return None;
+    }
+
+    Some(Message {
+        id: u16::from_be_bytes([buf[0], buf[1]]),
+        qr: QR::Response,
+        opcode: Opcode::Query,
+        rd: false,
+        ad: false,
+        rcode: Rcode::FormErr,
+        ..Default::default()
+    })
+}
+
+cfg_feature! {
+    #![feature = "tsig"]
+
+    use crate::tsig;
+    use crate::tsig::Key;
+    use crate::tsig::Signer;
+    use log::debug;
+
+    /// Verifies the request, in its wire format, if it's signed with TSIG,
+    /// and records the key it was signed with.
+    ///
+    /// Returns the signer for the responses, or if the request is invalid or
+    /// failed verification, the error response to send instead.
+    fn verify_tsig(
+        keys: &[Key],
+        buf: &[u8],
+        request: &mut Request,
+    ) -> Result<Option<Signer>, Option<Box<Message>>> {
+        let mut signer = match tsig::verify_request(keys, buf) {
+            Ok(Some(signer)) => signer,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                debug!("{}: invalid TSIG: {}", request.src, e);
+                return Err(format_error(buf).map(Box::new));
+            }
+        };
+
+        if signer.error() != Rcode::NoError {
+            debug!("{}: TSIG verification failed: {}", request.src, signer.error());
+            let mut resp = request.response();
+            return Err(signer.sign(&mut resp).ok().map(|_| Box::new(resp)));
+        }
+
+        request.key = signer.key().map(|key| key.name().to_string());
+        Ok(Some(signer))
+    }
+}
@@ -0,0 +1,244 @@
+use crate::bail;
+use crate::server::format_error;
+use crate::server::Handler;
+use crate::server::Protocol;
+use crate::server::Request;
+#[cfg(feature = "tsig")]
+use crate::tsig::Key;
+use crate::ExtensionOption;
+use crate::Message;
+use crate::Rcode;
+use log::debug;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// A TCP DNS Server.
+///
+/// Each connection is handled on its own thread, and may carry multiple
+/// requests, which are answered in order, each with the messages from
+/// [`Handler::handle_stream`]. Connections are closed once idle
+/// for the idle timeout. Clients that send the edns-tcp-keepalive option
+/// ([rfc7828]) are told this timeout.
+///
+/// Requests signed with TSIG ([rfc8945]) are verified, and every message of
+/// their responses signed, with the keys given to
+/// [`with_tsig_keys`](Server::with_tsig_keys).
+///
+/// See <https://datatracker.ietf.org/doc/html/rfc1035#section-4.2.2> and
+/// [rfc7766].
+///
+/// [rfc7766]: https://datatracker.ietf.org/doc/html/rfc7766
+/// [rfc7828]: https://datatracker.ietf.org/doc/html/rfc7828
+/// [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
+pub struct Server<H> {
+    listener: TcpListener,
+    handler: Arc<H>,
+    settings: Settings,
+}
+
+/// The settings shared by every connection.
+#[derive(Clone)]
+struct Settings {
+    /// How long a idle connection is kept open.
+    idle_timeout: Duration,
+
+    /// The keys requests may be signed with.
+    #[cfg(feature = "tsig")]
+    keys: Vec<Key>,
+}
+
+impl<H> Server<H>
+where
+    H: Handler + Send + Sync + 'static,
+{
+    /// Creates a new Server listening on the address.
+    pub fn bind<A: ToSocketAddrs>(addr: A, handler: H) -> Result<Self, crate::Error> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            handler: Arc::new(handler),
+            settings: Settings {
+                idle_timeout: Duration::new(10, 0),
+                #[cfg(feature = "tsig")]
+                keys: Vec::new(),
+            },
+        })
+    }
+
+    /// Sets how long a idle connection is kept open. Defaults to 10 seconds.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.idle_timeout = timeout;
+        self
+    }
+
+    /// Sets the TSIG keys requests may be signed with. Signed requests are
+    /// answered with a NOTAUTH error if their key isn't one of these.
+    #[cfg(feature = "tsig")]
+    pub fn with_tsig_keys(mut self, keys: Vec<Key>) -> Self {
+        self.settings.keys = keys;
+        self
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> Result<SocketAddr, crate::Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accepts connections and answers their requests, forever.
+    pub fn serve(&self) -> Result<(), crate::Error> {
+        loop {
+            let (stream, src) = match self.listener.accept() {
+                Ok(r) => r,
+                Err(e) => {
+                    debug!("failed to accept connection: {}", e);
+                    continue;
+                }
+            };
+
+            let handler = self.handler.clone();
+            let settings = self.settings.clone();
+            thread::Builder::new()
+                .name(format!("rustdns-server-{}", src))
+                .spawn(move || {
+                    if let Err(e) = serve_connection(&*handler, stream, src, &settings) {
+                        debug!("{}: connection failed: {}", src, e);
+                    }
+                })?;
+        }
+    }
+}
+
+/// Answers the requests on the connection, until it's closed or idle.
+fn serve_connection<H: Handler>(
+    handler: &H,
+    mut stream: TcpStream,
+    src: SocketAddr,
+    settings: &Settings,
+) -> io::Result<()> {
+    let idle_timeout = settings.idle_timeout;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(idle_timeout))?;
+
+    loop {
+        // Receive a two byte length
+        let buf = &mut [0; 2];
+        match stream.read_exact(buf) {
+            Ok(()) => (),
+            // The client closed the connection, or it was idle.
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::UnexpectedEof
+                        | io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(())
+            }
+            Err(e) => return Err(e),
+        }
+        let len = u16::from_be_bytes(*buf);
+
+        // and then the message
+        let mut buf = vec![0; len.into()];
+        stream.read_exact(&mut buf)?;
+
+        let message = match Message::from_slice(&buf) {
+            Ok(message) => message,
+            Err(e) => {
+                // The stream can't be trusted after a invalid message.
+                debug!("{}: invalid request: {}", src, e);
+                if let Some(resp) = format_error(&buf) {
+                    write_message(&mut stream, &resp)?;
+                }
+                return Ok(());
+            }
+        };
+
+        let keepalive = message.extension.as_ref().is_some_and(|ext| {
+            ext.options
+                .iter()
+                .any(|o| matches!(o, ExtensionOption::TcpKeepalive(_)))
+        });
+
+        #[allow(unused_mut)]
+        let mut request = Request {
+            message,
+            src,
+            protocol: Protocol::Tcp,
+            key: None,
+        };
+
+        #[cfg(feature = "tsig")]
+        let mut signer = match super::verify_tsig(&settings.keys, &buf, &mut request) {
+            Ok(signer) => signer,
+            Err(resp) => {
+                if let Some(resp) = resp {
+                    write_message(&mut stream, &resp)?;
+                }
+                continue;
+            }
+        };
+
+        // Some requests, such as zone transfers, are answered with many messages.
+        for mut resp in handler.handle_stream(&request) {
+            // A message that can't be encoded is replaced with a SERVFAIL,
+            // which ends the response, instead of dropping the connection.
+            let failed = match encode(&resp) {
+                Ok(_) => false,
+                Err(e) => {
+                    debug!("{}: failed to encode response: {}", src, e);
+                    resp = request.response();
+                    resp.rcode = Rcode::ServFail;
+                    true
+                }
+            };
+
+            if keepalive {
+                if let Some(ext) = &mut resp.extension {
+                    ext.options
+                        .push(ExtensionOption::TcpKeepalive(Some(idle_timeout)));
+                }
+            }
+
+            // Every message is signed, following the one before.
+            #[cfg(feature = "tsig")]
+            if let Some(signer) = &mut signer {
+                if let Err(e) = signer.sign(&mut resp) {
+                    bail!(InvalidData, "failed to sign response: {}", e);
+                }
+            }
+
+            write_message(&mut stream, &resp)?;
+            if failed {
+                break;
+            }
+        }
+    }
+}
+
+/// Writes the message with its two byte length prefix, in a single write.
+fn write_message(stream: &mut TcpStream, message: &Message) -> io::Result<()> {
+    stream.write_all(&encode(message)?)
+}
+
+/// Returns the message with its two byte length prefix.
+fn encode(message: &Message) -> io::Result<Vec<u8>> {
+    let message = message.to_vec()?;
+    if message.len() > u16::MAX.into() {
+        bail!(InvalidData, "message longer than {} bytes", u16::MAX);
+    }
+
+    let mut buf = Vec::with_capacity(message.len() + 2);
+    buf.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&message);
+
+    Ok(buf)
+}
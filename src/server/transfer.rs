@@ -0,0 +1,104 @@
+use crate::dns::Names;
+use crate::server::Protocol;
+use crate::server::Request;
+use crate::Message;
+use crate::Record;
+use crate::Resource;
+use std::vec;
+
+/// The size of the edns-tcp-keepalive option, which the TCP server may add
+/// to each message.
+const KEEPALIVE_SIZE: usize = 6;
+
+/// Returns how many bytes to leave free in each message of the transfer,
+/// for what the server adds to it after, so it stays within the limit.
+pub(crate) fn overhead(request: &Request) -> usize {
+    let keepalive = match (request.protocol, &request.message.extension) {
+        (Protocol::Tcp, Some(_)) => KEEPALIVE_SIZE,
+        _ => 0,
+    };
+
+    // Each message of a signed request's response is signed with the same
+    // key and algorithm, so its TSIG record is the same size as the request's.
+    let tsig = match (&request.key, request.message.additionals.last()) {
+        (Some(_), Some(record)) if matches!(record.resource, Resource::TSIG(_)) => {
+            let mut buf = Vec::new();
+            record
+                .write(&mut buf, &mut Names::default())
+                .map_or(0, |_| buf.len())
+        }
+        _ => 0,
+    };
+
+    keepalive + tsig
+}
+
+/// Splits the records of a zone transfer across as many messages as needed,
+/// as described in [rfc5936] section 2.2.
+///
+/// Each message is at most `max_size` bytes, unless a single record is
+/// longer, and then it's sent alone. Only the first message includes the
+/// question. See [`overhead`].
+///
+/// [rfc5936]: https://datatracker.ietf.org/doc/html/rfc5936
+pub(crate) struct Messages {
+    /// The response each message is built from.
+    resp: Message,
+
+    records: vec::IntoIter<Record>,
+
+    /// The record that didn't fit in the last message.
+    next: Option<Record>,
+
+    max_size: usize,
+    first: bool,
+}
+
+impl Messages {
+    pub(crate) fn new(resp: Message, records: Vec<Record>, max_size: usize) -> Messages {
+        Messages {
+            resp,
+            records: records.into_iter(),
+            next: None,
+            max_size,
+            first: true,
+        }
+    }
+}
+
+impl Iterator for Messages {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        let mut resp = self.resp.clone();
+        if !self.first {
+            resp.questions.clear();
+        }
+
+        // Names are compressed within each record, but not between them, so
+        // the size is a upper bound.
+        let mut size = resp.to_vec().map_or(0, |buf| buf.len());
+
+        while let Some(record) = self.next.take().or_else(|| self.records.next()) {
+            let mut buf = Vec::new();
+            let len = record
+                .write(&mut buf, &mut Names::default())
+                .map_or(0, |_| buf.len());
+
+            if !resp.answers.is_empty() && size + len > self.max_size {
+                self.next = Some(record);
+                break;
+            }
+
+            size += len;
+            resp.answers.push(record);
+        }
+
+        if resp.answers.is_empty() && !self.first {
+            return None;
+        }
+
+        self.first = false;
+        Some(resp)
+    }
+}
@@ -0,0 +1,149 @@
+use crate::server::format_error;
+use crate::server::Handler;
+use crate::server::Protocol;
+use crate::server::Request;
+#[cfg(feature = "tsig")]
+use crate::tsig::Key;
+use crate::Message;
+use crate::Rcode;
+use log::debug;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+
+/// The largest UDP response sent to clients that don't use EDNS(0).
+const MIN_PAYLOAD_SIZE: usize = 512;
+
+/// The largest UDP request or response.
+const MAX_PAYLOAD_SIZE: usize = 4096;
+
+/// A UDP DNS Server.
+///
+/// Requests are handled one at a time, in the order they're received.
+/// Responses larger than the client can accept (512 bytes, or the size
+/// advertised with EDNS(0)) are truncated, that is sent without any records
+/// and with the TC bit set, so the client retries over TCP.
+///
+/// Requests signed with TSIG ([rfc8945]) are verified, and their responses
+/// signed, with the keys given to [`with_tsig_keys`](Server::with_tsig_keys).
+///
+/// See <https://datatracker.ietf.org/doc/html/rfc1035#section-4.2.1>
+///
+/// [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
+pub struct Server<H> {
+    socket: UdpSocket,
+    handler: H,
+
+    /// The keys requests may be signed with.
+    #[cfg(feature = "tsig")]
+    keys: Vec<Key>,
+}
+
+impl<H: Handler> Server<H> {
+    /// Creates a new Server listening on the address.
+    pub fn bind<A: ToSocketAddrs>(addr: A, handler: H) -> Result<Self, crate::Error> {
+        Ok(Server {
+            socket: UdpSocket::bind(addr)?,
+            handler,
+            #[cfg(feature = "tsig")]
+            keys: Vec::new(),
+        })
+    }
+
+    /// Sets the TSIG keys requests may be signed with. Signed requests are
+    /// answered with a NOTAUTH error if their key isn't one of these.
+    #[cfg(feature = "tsig")]
+    pub fn with_tsig_keys(mut self, keys: Vec<Key>) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> Result<SocketAddr, crate::Error> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Receives and answers requests, forever.
+    pub fn serve(&self) -> Result<(), crate::Error> {
+        let mut buf = [0; MAX_PAYLOAD_SIZE];
+        loop {
+            let (len, src) = match self.socket.recv_from(&mut buf) {
+                Ok(r) => r,
+                Err(e) => {
+                    // Errors can be caused by earlier responses (for example
+                    // ICMP port unreachable), so keep going.
+                    debug!("failed to receive request: {}", e);
+                    continue;
+                }
+            };
+
+            if let Some(resp) = self.handle(&buf[..len], src) {
+                if let Err(e) = self.socket.send_to(&resp, src) {
+                    debug!("{}: failed to send response: {}", src, e);
+                }
+            }
+        }
+    }
+
+    /// Handles the request, returning the encoded response if any.
+    fn handle(&self, buf: &[u8], src: SocketAddr) -> Option<Vec<u8>> {
+        let message = match Message::from_slice(buf) {
+            Ok(message) => message,
+            Err(e) => {
+                debug!("{}: invalid request: {}", src, e);
+                return format_error(buf)?.to_vec().ok();
+            }
+        };
+
+        let max_size = match &message.extension {
+            Some(ext) => usize::from(ext.payload_size).clamp(MIN_PAYLOAD_SIZE, MAX_PAYLOAD_SIZE),
+            None => MIN_PAYLOAD_SIZE,
+        };
+
+        #[allow(unused_mut)]
+        let mut request = Request {
+            message,
+            src,
+            protocol: Protocol::Udp,
+            key: None,
+        };
+
+        #[cfg(feature = "tsig")]
+        let signer = match super::verify_tsig(&self.keys, buf, &mut request) {
+            Ok(signer) => signer,
+            Err(resp) => return resp?.to_vec().ok(),
+        };
+
+        let mut resp = self.handler.handle(&request)?;
+
+        // A response that can't be encoded is replaced with a SERVFAIL.
+        if let Err(e) = resp.to_vec() {
+            debug!("{}: failed to encode response: {}", src, e);
+            resp = request.response();
+            resp.rcode = Rcode::ServFail;
+        }
+
+        #[cfg(feature = "tsig")]
+        if let Some(signer) = &signer {
+            signer.clone().sign(&mut resp).ok()?;
+        }
+
+        let mut encoded = resp.to_vec().ok()?;
+
+        if encoded.len() > max_size {
+            resp.tc = true;
+            resp.answers.clear();
+            resp.authoritys.clear();
+            resp.additionals.clear();
+
+            #[cfg(feature = "tsig")]
+            if let Some(mut signer) = signer {
+                signer.sign(&mut resp).ok()?;
+            }
+
+            encoded = resp.to_vec().ok()?;
+        }
+
+        Some(encoded)
+    }
+}
@@ -0,0 +1,248 @@
+use crate::server::authority::serial;
+use crate::util::is_newer_serial;
+use crate::util::normalise;
+use crate::util::same_record;
+use crate::zones::Zone;
+use crate::Class;
+use crate::Message;
+use crate::Rcode;
+use crate::Record;
+use crate::Resource;
+use crate::Type;
+use std::time::Duration;
+
+/// Applies the dynamic update (UPDATE) message to the zone, as described in
+/// [rfc2136] section 3.
+///
+/// Returns the updated zone, with its SOA serial incremented (unless the
+/// update set a newer one), or None if nothing changed. If the zone section
+/// or prerequisites aren't met, or the message is invalid, returns the
+/// rcode to respond with, and nothing is changed.
+///
+/// [rfc2136]: https://datatracker.ietf.org/doc/html/rfc2136
+pub(crate) fn apply(zone: &Zone, message: &Message) -> Result<Option<Zone>, Rcode> {
+    let origin = zone.origin();
+
+    // The zone section, see section 3.1.
+    match message.questions.as_slice() {
+        [question] if question.r#type == Type::SOA => {
+            if normalise(&question.name) != origin || question.class != Class::Internet {
+                return Err(Rcode::NotAuth);
+            }
+        }
+        _ => return Err(Rcode::FormErr),
+    }
+
+    let mut records: Vec<Record> = zone.iter().cloned().collect();
+    check_prerequisites(&records, origin, &message.answers)?;
+    check_updates(origin, &message.authoritys)?;
+
+    let serial = serial(&records[0]);
+    let mut changed = false;
+    for update in &message.authoritys {
+        changed |= apply_update(&mut records, origin, update);
+    }
+
+    if !changed {
+        return Ok(None);
+    }
+
+    // Bump the serial, unless the update already did.
+    if let Resource::SOA(soa) = &mut records[0].resource {
+        if soa.serial == serial {
+            soa.serial = serial.wrapping_add(1);
+        }
+    }
+
+    match Zone::new(records) {
+        Ok(zone) => Ok(Some(zone)),
+        Err(_) => Err(Rcode::ServFail),
+    }
+}
+
+/// Checks the prerequisites against the records, see section 3.2.
+fn check_prerequisites(records: &[Record], origin: &str, prereqs: &[Record]) -> Result<(), Rcode> {
+    let exists = |name: &str, r#type: Type| {
+        records
+            .iter()
+            .any(|r| r.name == name && (r#type == Type::ANY || r.r#type() == r#type))
+    };
+
+    // The RRsets that must exist with exactly these records.
+    let mut rrsets = Vec::new();
+
+    for prereq in prereqs {
+        if prereq.ttl != Duration::ZERO {
+            return Err(Rcode::FormErr);
+        }
+
+        let name = normalise(&prereq.name);
+        if !in_zone(&name, origin) {
+            return Err(Rcode::NotZone);
+        }
+
+        match (prereq.class, &prereq.resource) {
+            (Class::Any, Resource::Empty(Type::ANY)) if !exists(&name, Type::ANY) => {
+                return Err(Rcode::NXDomain)
+            }
+            (Class::Any, Resource::Empty(r#type)) if !exists(&name, *r#type) => {
+                return Err(Rcode::NXRRSet)
+            }
+            (Class::Any, Resource::Empty(_)) => (),
+
+            (Class::None, Resource::Empty(Type::ANY)) if exists(&name, Type::ANY) => {
+                return Err(Rcode::YXDomain)
+            }
+            (Class::None, Resource::Empty(r#type)) if exists(&name, *r#type) => {
+                return Err(Rcode::YXRRSet)
+            }
+            (Class::None, Resource::Empty(_)) => (),
+
+            (Class::Internet, resource) if is_data(resource) => rrsets.push(prereq),
+            _ => return Err(Rcode::FormErr),
+        }
+    }
+
+    for prereq in &rrsets {
+        let in_rrset = |r: &&Record| {
+            normalise(&r.name) == normalise(&prereq.name) && r.r#type() == prereq.r#type()
+        };
+
+        let expected: Vec<&Record> = rrsets.iter().copied().filter(in_rrset).collect();
+        let actual: Vec<&Record> = records.iter().filter(in_rrset).collect();
+
+        if !expected
+            .iter()
+            .all(|e| actual.iter().any(|a| same_record(a, e)))
+            || !actual
+                .iter()
+                .all(|a| expected.iter().any(|e| same_record(a, e)))
+        {
+            return Err(Rcode::NXRRSet);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the updates are valid before any are applied, see section 3.4.1.
+fn check_updates(origin: &str, updates: &[Record]) -> Result<(), Rcode> {
+    for update in updates {
+        if !in_zone(&normalise(&update.name), origin) {
+            return Err(Rcode::NotZone);
+        }
+
+        let valid = match (update.class, &update.resource) {
+            (Class::Internet, resource) => is_data(resource),
+            (Class::Any, Resource::Empty(r#type)) => {
+                update.ttl == Duration::ZERO && !matches!(r#type, Type::AXFR | Type::IXFR)
+            }
+            (Class::None, resource) => update.ttl == Duration::ZERO && is_data(resource),
+            _ => false,
+        };
+
+        if !valid {
+            return Err(Rcode::FormErr);
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies a single update to the records, returning true if they changed,
+/// see section 3.4.2.
+fn apply_update(records: &mut Vec<Record>, origin: &str, update: &Record) -> bool {
+    let name = normalise(&update.name);
+    let r#type = update.r#type();
+    let apex = name == origin;
+
+    match update.class {
+        Class::Internet => {
+            if r#type == Type::SOA {
+                // Only a newer SOA, at the apex, replaces the current one.
+                let newer = matches!(&update.resource, Resource::SOA(soa)
+                    if is_newer_serial(soa.serial, serial(&records[0])));
+                if apex && newer {
+                    records[0] = Record {
+                        name,
+                        ..update.clone()
+                    };
+                }
+                return apex && newer;
+            }
+
+            // A CNAME can't be added to a name with other records, nor
+            // other records to a alias, but a CNAME replaces a CNAME.
+            let mut at_name = records.iter().filter(|r| r.name == name);
+            if at_name.any(|r| (r.r#type() == Type::CNAME) != (r#type == Type::CNAME)) {
+                return false;
+            }
+            if r#type == Type::CNAME {
+                records.retain(|r| {
+                    r.name != name || r.r#type() != Type::CNAME || same_record(r, update)
+                });
+            }
+
+            match records.iter_mut().find(|r| same_record(r, update)) {
+                Some(existing) if existing.ttl == update.ttl => false,
+                Some(existing) => {
+                    existing.ttl = update.ttl;
+                    true
+                }
+                None => {
+                    records.push(Record {
+                        name,
+                        ..update.clone()
+                    });
+                    true
+                }
+            }
+        }
+
+        // Deletes the RRset, or every RRset for ANY, but never the SOA or
+        // NS records at the apex.
+        Class::Any => {
+            let len = records.len();
+            records.retain(|r| {
+                r.name != name
+                    || (r#type != Type::ANY && r.r#type() != r#type)
+                    || (apex && matches!(r.r#type(), Type::SOA | Type::NS))
+            });
+            records.len() != len
+        }
+
+        // Deletes the record, unless it's the SOA, or the last NS at the apex.
+        Class::None => {
+            if r#type == Type::SOA {
+                return false;
+            }
+            if apex && r#type == Type::NS {
+                let ns = records
+                    .iter()
+                    .filter(|r| r.name == name && r.r#type() == Type::NS);
+                if ns.count() <= 1 {
+                    return false;
+                }
+            }
+
+            let len = records.len();
+            records.retain(|r| !same_record(r, update));
+            records.len() != len
+        }
+
+        _ => false,
+    }
+}
+
+/// Returns true if the resource is a record with data, that can be in a zone.
+fn is_data(resource: &Resource) -> bool {
+    !matches!(
+        resource,
+        Resource::Empty(_) | Resource::ANY | Resource::OPT | Resource::TSIG(_)
+    )
+}
+
+/// Returns true if the (normalised) name is within the zone.
+fn in_zone(name: &str, origin: &str) -> bool {
+    origin == "." || name == origin || name.ends_with(&format!(".{}", origin))
+}
@@ -0,0 +1,410 @@
+//! Public key transaction signatures (SIG(0)), authenticating messages with
+//! a private key, as described in [rfc2931].
+//!
+//! A client signs its request with a [`PrivateKey`], appending a SIG record
+//! to the message. A server checks the request with [`verify`], against the
+//! KEY record holding the client's public key, usually found in the zone
+//! being updated.
+//!
+//! # Example
+//!
+//! ```rust
+//! use rustdns::sig0::{verify, Algorithm, PrivateKey};
+//! use rustdns::types::*;
+//! use std::time::Duration;
+//!
+//! let key = PrivateKey::new("update.example.com", Algorithm::Ed25519, &[7; 32]).unwrap();
+//!
+//! // The client signs the request.
+//! let mut query = Message::default();
+//! query.add_question("example.com", Type::SOA, Class::Internet);
+//! key.sign(&mut query).unwrap();
+//!
+//! // The server verifies it against the KEY record.
+//! let record = Record::new(
+//!     key.name(),
+//!     Class::Internet,
+//!     Duration::from_secs(3600),
+//!     Resource::KEY(key.key()),
+//! );
+//! verify(&query.to_vec().unwrap(), &record).unwrap();
+//! ```
+//!
+//! [rfc2931]: https://datatracker.ietf.org/doc/html/rfc2931
+use crate::bail;
+use crate::util::normalise;
+use crate::util::unix_seconds;
+use crate::Class;
+use crate::Message;
+use crate::Rcode;
+use crate::Record;
+use crate::Resource;
+use crate::Type;
+use crate::KEY;
+use crate::SIG;
+use p256::ecdsa::signature::{Signer, Verifier};
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// The KEY flags of a key that belongs to a host (or other end entity), not
+/// a zone, see rfc2535 section 3.1.2.
+const HOST_FLAGS: u16 = 0x0200;
+
+/// The KEY protocol for DNSSEC, see rfc2535 section 3.1.3.
+const DNSSEC_PROTOCOL: u8 = 3;
+
+/// The signing algorithm used by a [`PrivateKey`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Algorithm {
+    /// ECDSA with the P-256 curve and SHA-256, see [rfc6605].
+    ///
+    /// [rfc6605]: https://datatracker.ietf.org/doc/html/rfc6605
+    EcdsaP256Sha256,
+
+    /// Ed25519, see [rfc8080].
+    ///
+    /// [rfc8080]: https://datatracker.ietf.org/doc/html/rfc8080
+    Ed25519,
+}
+
+impl Algorithm {
+    /// Returns the number of the algorithm, as used in the KEY and SIG records.
+    pub fn number(&self) -> u8 {
+        match self {
+            Algorithm::EcdsaP256Sha256 => 13,
+            Algorithm::Ed25519 => 15,
+        }
+    }
+
+    fn from_number(number: u8) -> Option<Algorithm> {
+        match number {
+            13 => Some(Algorithm::EcdsaP256Sha256),
+            15 => Some(Algorithm::Ed25519),
+            _ => None,
+        }
+    }
+
+    /// Checks the signature of the data with the public key, returning the
+    /// error if the key or signature is wrong.
+    fn verify(&self, public_key: &[u8], data: &[u8], signature: &[u8]) -> Result<(), Rcode> {
+        match self {
+            Algorithm::EcdsaP256Sha256 => {
+                // The KEY holds the uncompressed point, without its SEC1 tag.
+                let mut point = vec![0x04];
+                point.extend_from_slice(public_key);
+                let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)
+                    .map_err(|_| Rcode::BadKey)?;
+                let signature =
+                    p256::ecdsa::Signature::from_slice(signature).map_err(|_| Rcode::BadSig)?;
+                key.verify(data, &signature).map_err(|_| Rcode::BadSig)
+            }
+            Algorithm::Ed25519 => {
+                let key = public_key.try_into().map_err(|_| Rcode::BadKey)?;
+                let key =
+                    ed25519_dalek::VerifyingKey::from_bytes(key).map_err(|_| Rcode::BadKey)?;
+                let signature =
+                    ed25519_dalek::Signature::from_slice(signature).map_err(|_| Rcode::BadSig)?;
+                key.verify_strict(data, &signature)
+                    .map_err(|_| Rcode::BadSig)
+            }
+        }
+    }
+}
+
+/// Parses the algorithm's mnemonic, for example "ED25519", or its number.
+impl FromStr for Algorithm {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let algorithm = match s.to_ascii_uppercase().as_str() {
+            "ECDSAP256SHA256" => Some(Algorithm::EcdsaP256Sha256),
+            "ED25519" => Some(Algorithm::Ed25519),
+            number => number.parse().ok().and_then(Algorithm::from_number),
+        };
+
+        match algorithm {
+            Some(algorithm) => Ok(algorithm),
+            None => bail!(InvalidData, "unsupported SIG(0) algorithm '{}'", s),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Algorithm::EcdsaP256Sha256 => write!(f, "ECDSAP256SHA256"),
+            Algorithm::Ed25519 => write!(f, "ED25519"),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Secret {
+    EcdsaP256Sha256(p256::ecdsa::SigningKey),
+    Ed25519(ed25519_dalek::SigningKey),
+}
+
+/// A named private key, for signing messages with SIG(0).
+#[derive(Clone)]
+pub struct PrivateKey {
+    /// The name of the key, lowercase and fully qualified. This is the name
+    /// of the KEY record holding its public key.
+    name: String,
+
+    secret: Secret,
+
+    /// How long before and after it was signed the signature is valid for.
+    fudge: Duration,
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The secret is deliberately left out.
+        f.debug_struct("PrivateKey")
+            .field("name", &self.name)
+            .field("algorithm", &self.algorithm())
+            .field("fudge", &self.fudge)
+            .finish()
+    }
+}
+
+impl PrivateKey {
+    /// Creates a key named `name`, from the algorithm's private key, which
+    /// for both Ed25519 and ECDSA P-256 is 32 bytes. Fails if the private key
+    /// is invalid.
+    pub fn new(
+        name: &str,
+        algorithm: Algorithm,
+        secret: &[u8],
+    ) -> Result<PrivateKey, crate::Error> {
+        let secret = match algorithm {
+            Algorithm::EcdsaP256Sha256 => match p256::ecdsa::SigningKey::from_slice(secret) {
+                Ok(key) => Secret::EcdsaP256Sha256(key),
+                Err(_) => bail!(InvalidData, "invalid ECDSA P-256 private key"),
+            },
+            Algorithm::Ed25519 => match secret.try_into() {
+                Ok(secret) => Secret::Ed25519(ed25519_dalek::SigningKey::from_bytes(secret)),
+                Err(_) => bail!(InvalidData, "invalid Ed25519 private key"),
+            },
+        };
+
+        let name = normalise(name);
+
+        Ok(PrivateKey {
+            name,
+            secret,
+            fudge: Duration::from_secs(300),
+        })
+    }
+
+    /// Sets how long before and after signing the signature is valid for,
+    /// allowing for clocks that differ. Defaults to 5 minutes.
+    pub fn with_fudge(mut self, fudge: Duration) -> Self {
+        self.fudge = fudge;
+        self
+    }
+
+    /// Parses a private key file, as written by BIND's `dnssec-keygen -T KEY`,
+    /// for the key named `name`. For example:
+    ///
+    /// ```text
+    /// Private-key-format: v1.3
+    /// Algorithm: 15 (ED25519)
+    /// PrivateKey: BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc=
+    /// ```
+    pub fn parse(name: &str, s: &str) -> Result<PrivateKey, crate::Error> {
+        let mut algorithm = None;
+        let mut secret = None;
+
+        for line in s.lines() {
+            match line.split_once(':') {
+                Some(("Algorithm", value)) => {
+                    // The number may be followed by the mnemonic, "15 (ED25519)".
+                    let number = value.split_whitespace().next().unwrap_or_default();
+                    algorithm = Some(number.parse::<Algorithm>()?);
+                }
+                Some(("PrivateKey", value)) => match base64::decode(value.trim()) {
+                    Ok(value) => secret = Some(value),
+                    Err(e) => bail!(InvalidData, "invalid private key for '{}': {}", name, e),
+                },
+                _ => (),
+            }
+        }
+
+        match (algorithm, secret) {
+            (Some(algorithm), Some(secret)) => PrivateKey::new(name, algorithm, &secret),
+            _ => bail!(InvalidData, "no private key found for '{}'", name),
+        }
+    }
+
+    /// Reads a private key file, see [`PrivateKey::parse`].
+    pub fn from_file<P: AsRef<Path>>(name: &str, path: P) -> Result<PrivateKey, crate::Error> {
+        PrivateKey::parse(name, &fs::read_to_string(path)?)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self.secret {
+            Secret::EcdsaP256Sha256(_) => Algorithm::EcdsaP256Sha256,
+            Secret::Ed25519(_) => Algorithm::Ed25519,
+        }
+    }
+
+    /// Returns the public key, as the KEY record to publish under the key's
+    /// [`name`](PrivateKey::name), so servers can verify its signatures.
+    pub fn key(&self) -> KEY {
+        let public_key = match &self.secret {
+            // The uncompressed point, without its SEC1 tag, see rfc6605 section 4.
+            Secret::EcdsaP256Sha256(key) => {
+                key.verifying_key().to_encoded_point(false).as_bytes()[1..].to_vec()
+            }
+            Secret::Ed25519(key) => key.verifying_key().to_bytes().to_vec(),
+        };
+
+        KEY {
+            flags: HOST_FLAGS,
+            protocol: DNSSEC_PROTOCOL,
+            algorithm: self.algorithm().number(),
+            public_key,
+        }
+    }
+
+    /// Signs the message, appending a SIG record to its additionals, which is
+    /// always written as the last record. Any existing SIG(0) record is
+    /// replaced. The message must not be changed once signed.
+    pub fn sign(&self, message: &mut Message) -> Result<(), crate::Error> {
+        self.sign_at(message, SystemTime::now())
+    }
+
+    /// Signs the message as if the time was `now`.
+    pub fn sign_at(&self, message: &mut Message, now: SystemTime) -> Result<(), crate::Error> {
+        message.additionals.retain(|r| !is_sig0(r));
+
+        // Times are 32 bit serial numbers, so wrap around.
+        let now = unix_seconds(now);
+        let fudge = self.fudge.as_secs();
+        let mut sig = SIG {
+            type_covered: Type::Reserved,
+            algorithm: self.algorithm().number(),
+            labels: 0,
+            original_ttl: Duration::ZERO,
+            expiration: now.wrapping_add(fudge) as u32,
+            inception: now.wrapping_sub(fudge) as u32,
+            key_tag: self.key().key_tag(),
+            signer_name: self.name.clone(),
+            signature: Vec::new(),
+        };
+
+        // The SIG's fields, followed by the unsigned message, see rfc2931 section 3.1.
+        let mut data = Vec::with_capacity(512);
+        sig.write_fields(&mut data)?;
+        data.extend_from_slice(&message.to_vec()?);
+
+        sig.signature = match &self.secret {
+            Secret::EcdsaP256Sha256(key) => {
+                let signature: p256::ecdsa::Signature = key.sign(&data);
+                signature.to_bytes().to_vec()
+            }
+            Secret::Ed25519(key) => key.sign(&data).to_bytes().to_vec(),
+        };
+
+        message.additionals.push(Record {
+            name: ".".to_string(),
+            class: Class::Any,
+            ttl: Duration::ZERO,
+            resource: Resource::SIG(sig),
+        });
+        Ok(())
+    }
+}
+
+/// Returns the query signed with the key, if any.
+#[cfg(feature = "udp")]
+pub(crate) fn sign(key: Option<&PrivateKey>, query: &Message) -> Result<Message, crate::Error> {
+    let mut query = query.clone();
+    if let Some(key) = key {
+        key.sign(&mut query)?;
+    }
+    Ok(query)
+}
+
+/// Verifies the message, in its wire format, was signed with SIG(0) by the
+/// private key of the KEY record, returning the message.
+///
+/// Fails with [`Error::SignatureError`] if the signature is wrong
+/// ([`Rcode::BadSig`]), isn't from this key ([`Rcode::BadKey`]), or has
+/// expired ([`Rcode::BadTime`]). Fails with a IO error if the message isn't
+/// signed with SIG(0).
+///
+/// [`Error::SignatureError`]: crate::Error::SignatureError
+pub fn verify(buf: &[u8], key: &Record) -> Result<Message, crate::Error> {
+    let public = match &key.resource {
+        Resource::KEY(public) => public,
+        _ => bail!(InvalidInput, "'{}' is not a KEY record", key.name),
+    };
+
+    let message = Message::from_slice(buf)?;
+    let sig = match find(&message)? {
+        Some(sig) => sig,
+        None => bail!(InvalidData, "message is not signed with SIG(0)"),
+    };
+
+    let algorithm = match Algorithm::from_number(sig.algorithm) {
+        Some(algorithm)
+            if normalise(&sig.signer_name) == normalise(&key.name)
+                && public.protocol == DNSSEC_PROTOCOL
+                && public.algorithm == sig.algorithm
+                && public.key_tag() == sig.key_tag =>
+        {
+            algorithm
+        }
+        _ => return Err(crate::Error::SignatureError(Rcode::BadKey)),
+    };
+
+    // The signer's name is signed in its canonical (lowercase) form.
+    let mut fields = sig.clone();
+    fields.signer_name = sig.signer_name.to_ascii_lowercase();
+
+    let mut data = Vec::with_capacity(buf.len());
+    fields.write_fields(&mut data)?;
+    data.extend_from_slice(&Message::without_last_record(buf)?);
+
+    algorithm
+        .verify(&public.public_key, &data, &sig.signature)
+        .map_err(crate::Error::SignatureError)?;
+
+    // Times are compared with serial number arithmetic, see rfc4034 section 3.1.5.
+    let now = unix_seconds(SystemTime::now()) as u32;
+    let started = now.wrapping_sub(sig.inception) as i32 >= 0;
+    let expired = (sig.expiration.wrapping_sub(now) as i32) < 0;
+    if !started || expired {
+        return Err(crate::Error::SignatureError(Rcode::BadTime));
+    }
+
+    Ok(message)
+}
+
+/// Returns the message's SIG(0), if signed. Fails if the SIG(0) record isn't
+/// the last record, or there are more than one.
+fn find(message: &Message) -> Result<Option<&SIG>, crate::Error> {
+    match message.last_signature("SIG(0)", is_sig0)? {
+        Some(Record {
+            resource: Resource::SIG(sig),
+            ..
+        }) => Ok(Some(sig)),
+        _ => Ok(None),
+    }
+}
+
+/// Returns true if the record is a SIG(0), which covers no type.
+fn is_sig0(record: &Record) -> bool {
+    matches!(&record.resource, Resource::SIG(sig) if sig.type_covered == Type::Reserved)
+}
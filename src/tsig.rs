@@ -0,0 +1,651 @@
+//! Transaction Signatures (TSIG), authenticating messages with a shared
+//! secret key, as described in [rfc8945].
+//!
+//! A client signs its request with a [`Key`], and uses the returned
+//! [`Verifier`] to check the responses were signed by a server holding the
+//! same key. A server checks requests with [`verify_request`], and signs its
+//! responses with the returned [`Signer`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use rustdns::tsig::{verify_request, Algorithm, Key};
+//! use rustdns::types::*;
+//!
+//! let key = Key::new("transfer-key", Algorithm::HmacSha256, b"secret".to_vec());
+//!
+//! // The client signs the request.
+//! let mut query = Message::default();
+//! query.add_question("example.com", Type::SOA, Class::Internet);
+//! let mut verifier = key.sign(&mut query).unwrap();
+//!
+//! // The server verifies it, and signs the response.
+//! let keys = [key];
+//! let buf = query.to_vec().unwrap();
+//! let mut signer = verify_request(&keys, &buf).unwrap().expect("signed request");
+//! assert_eq!(signer.error(), Rcode::NoError);
+//!
+//! let mut resp = Message::from_slice(&buf).unwrap();
+//! resp.additionals.clear();
+//! resp.qr = QR::Response;
+//! signer.sign(&mut resp).unwrap();
+//!
+//! // And the client verifies the response.
+//! verifier.verify(&resp.to_vec().unwrap()).unwrap();
+//! ```
+//!
+//! [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
+use crate::bail;
+use crate::util::normalise;
+use crate::util::unix_seconds;
+use crate::Class;
+use crate::Message;
+use crate::Rcode;
+use crate::Record;
+use crate::Resource;
+use crate::Type;
+use crate::TSIG;
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use sha2::{Sha256, Sha384, Sha512};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// The most unsigned messages allowed between signed ones, in a response
+/// with many messages, see rfc8945 section 5.3.1.
+const MAX_UNSIGNED: usize = 99;
+
+/// The MAC algorithm used by a [`Key`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Algorithm {
+    HmacSha256,
+    HmacSha384,
+    HmacSha512,
+}
+
+impl Algorithm {
+    /// Returns the name of the algorithm, as used in the TSIG record.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::HmacSha256 => "hmac-sha256.",
+            Algorithm::HmacSha384 => "hmac-sha384.",
+            Algorithm::HmacSha512 => "hmac-sha512.",
+        }
+    }
+
+    /// Returns the length of a untruncated MAC.
+    fn len(&self) -> usize {
+        match self {
+            Algorithm::HmacSha256 => 32,
+            Algorithm::HmacSha384 => 48,
+            Algorithm::HmacSha512 => 64,
+        }
+    }
+
+    fn mac(&self, secret: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::HmacSha256 => hmac::<Hmac<Sha256>>(secret, data)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            Algorithm::HmacSha384 => hmac::<Hmac<Sha384>>(secret, data)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            Algorithm::HmacSha512 => hmac::<Hmac<Sha512>>(secret, data)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+        }
+    }
+
+    /// Checks the (possibly truncated) MAC in constant time, returning the
+    /// TSIG error if it's wrong.
+    fn verify(&self, secret: &[u8], data: &[u8], mac: &[u8]) -> Result<(), Rcode> {
+        // Truncated MACs must keep at least half, and at least 10 bytes, see
+        // rfc8945 section 5.2.2.1.
+        if mac.len() > self.len() || mac.len() < (self.len() / 2).max(10) {
+            return Err(Rcode::BadTrunc);
+        }
+
+        let valid = match self {
+            Algorithm::HmacSha256 => hmac::<Hmac<Sha256>>(secret, data).verify_truncated_left(mac),
+            Algorithm::HmacSha384 => hmac::<Hmac<Sha384>>(secret, data).verify_truncated_left(mac),
+            Algorithm::HmacSha512 => hmac::<Hmac<Sha512>>(secret, data).verify_truncated_left(mac),
+        };
+
+        valid.map_err(|_| Rcode::BadSig)
+    }
+}
+
+/// Parses the algorithm's name, with or without the trailing dot, for
+/// example "hmac-sha256".
+impl FromStr for Algorithm {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_end_matches('.').to_ascii_lowercase().as_str() {
+            "hmac-sha256" => Ok(Algorithm::HmacSha256),
+            "hmac-sha384" => Ok(Algorithm::HmacSha384),
+            "hmac-sha512" => Ok(Algorithm::HmacSha512),
+            _ => bail!(InvalidData, "unsupported TSIG algorithm '{}'", s),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name().trim_end_matches('.'))
+    }
+}
+
+/// Returns the HMAC of the data, ready to finalize or verify.
+fn hmac<M: Mac + KeyInit>(secret: &[u8], data: &[u8]) -> M {
+    let mut mac = <M as KeyInit>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac
+}
+
+/// A shared secret key, used to sign and verify messages.
+///
+/// # Example
+///
+/// ```rust
+/// use rustdns::tsig::{Algorithm, Key};
+///
+/// let keys = Key::parse(r#"
+///     key "transfer-key" {
+///         algorithm hmac-sha256;
+///         secret "c2VjcmV0";
+///     };
+/// "#).unwrap();
+///
+/// assert_eq!(keys[0].name(), "transfer-key.");
+/// assert_eq!(keys[0].algorithm(), Algorithm::HmacSha256);
+/// ```
+#[derive(Clone, Eq, PartialEq)]
+pub struct Key {
+    /// The name of the key, lowercase and fully qualified.
+    name: String,
+    algorithm: Algorithm,
+    secret: Vec<u8>,
+
+    /// How far the time signed may be from the time a message is verified.
+    fudge: Duration,
+}
+
+impl Key {
+    /// Creates a new Key with the name, and secret.
+    pub fn new(name: &str, algorithm: Algorithm, secret: Vec<u8>) -> Key {
+        let name = normalise(name);
+
+        Key {
+            name,
+            algorithm,
+            secret,
+            fudge: Duration::from_secs(300),
+        }
+    }
+
+    /// Sets how far the time a message was signed may be from the time it's
+    /// verified. Defaults to 300 seconds, as recommended by rfc8945.
+    pub fn with_fudge(mut self, fudge: Duration) -> Self {
+        self.fudge = fudge;
+        self
+    }
+
+    /// Parses the keys in a BIND style key file, such as those generated by
+    /// `tsig-keygen`, each of the form:
+    ///
+    /// ```text
+    /// key "name" {
+    ///     algorithm hmac-sha256;
+    ///     secret "base64 encoded secret";
+    /// };
+    /// ```
+    ///
+    /// Comments may start with `#` or `//`, or be enclosed in `/* */`.
+    pub fn parse(s: &str) -> Result<Vec<Key>, crate::Error> {
+        lazy_static! {
+            // A comment, or a quoted string (which may contain "//").
+            static ref COMMENT: Regex =
+                Regex::new(r#"(?s)("[^"]*")|#[^\n]*|//[^\n]*|/\*.*?\*/"#).unwrap();
+            static ref KEY: Regex =
+                Regex::new(r#"(?s)\bkey\s+"?([^"\s{]+)"?\s*\{(.*?)\}\s*;"#).unwrap();
+            static ref ALGORITHM: Regex =
+                Regex::new(r#"\balgorithm\s+"?([^"\s;]+)"?\s*;"#).unwrap();
+            static ref SECRET: Regex = Regex::new(r#"\bsecret\s+"([^"]*)"\s*;"#).unwrap();
+        }
+
+        // Remove the comments, keeping any quoted strings.
+        let s = COMMENT.replace_all(s, "$1");
+
+        let mut keys = Vec::new();
+        for caps in KEY.captures_iter(&s) {
+            let name = &caps[1];
+
+            let algorithm = match ALGORITHM.captures(&caps[2]) {
+                Some(algorithm) => algorithm[1].parse()?,
+                None => bail!(InvalidData, "key '{}' has no algorithm", name),
+            };
+
+            let secret = match SECRET.captures(&caps[2]) {
+                Some(secret) => match base64::decode(secret[1].replace(char::is_whitespace, "")) {
+                    Ok(secret) => secret,
+                    Err(e) => bail!(InvalidData, "key '{}' has a invalid secret: {}", name, e),
+                },
+                None => bail!(InvalidData, "key '{}' has no secret", name),
+            };
+
+            keys.push(Key::new(name, algorithm, secret));
+        }
+
+        if keys.is_empty() {
+            bail!(InvalidData, "no keys found");
+        }
+
+        Ok(keys)
+    }
+
+    /// Reads the keys from a BIND style key file. See [`Key::parse`].
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Vec<Key>, crate::Error> {
+        Key::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Returns the name of the key, lowercase and fully qualified.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Signs the request, appending a TSIG record to its additionals, and
+    /// returns the [`Verifier`] for its responses.
+    ///
+    /// The message must not be changed after it's signed, other than its ID.
+    pub fn sign(&self, message: &mut Message) -> Result<Verifier, crate::Error> {
+        self.sign_at(message, SystemTime::now())
+    }
+
+    /// Signs the request as if at `time`. See [`Key::sign`].
+    pub fn sign_at(
+        &self,
+        message: &mut Message,
+        time: SystemTime,
+    ) -> Result<Verifier, crate::Error> {
+        message.additionals.retain(|r| r.r#type() != Type::TSIG);
+
+        let mut tsig = TSIG {
+            algorithm: self.algorithm.name().to_string(),
+            time_signed: unix_seconds(time),
+            fudge: self.fudge,
+            mac: Vec::new(),
+            original_id: message.id,
+            error: Rcode::NoError,
+            other: Vec::new(),
+        };
+
+        let mut data = message.to_vec()?;
+        write_variables(&mut data, &self.name, &tsig)?;
+        tsig.mac = self.algorithm.mac(&self.secret, &data);
+
+        let verifier = Verifier {
+            key: self.clone(),
+            mac: tsig.mac.clone(),
+            unsigned: Vec::new(),
+            count: 0,
+            signed: false,
+            failed: None,
+        };
+
+        message.additionals.push(record(&self.name, tsig));
+        Ok(verifier)
+    }
+}
+
+/// Debug doesn't include the secret.
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Key")
+            .field("name", &self.name)
+            .field("algorithm", &self.algorithm)
+            .field("fudge", &self.fudge)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Verifies the responses to a signed request, returned by [`Key::sign`].
+///
+/// A response with many messages, such as a zone transfer, may only sign
+/// some of them, but must sign the first and last, and at least every 100th.
+/// Call [`finish`](Verifier::finish) after the last message to check it
+/// was signed.
+#[derive(Debug)]
+pub struct Verifier {
+    key: Key,
+
+    /// The MAC of the request, or the last signed response.
+    mac: Vec<u8>,
+
+    /// The unsigned messages since the last signed one, and how many.
+    unsigned: Vec<u8>,
+    count: usize,
+
+    /// Set once a signed response is verified.
+    signed: bool,
+
+    /// The error from the first response that failed verification.
+    failed: Option<Rcode>,
+}
+
+impl Verifier {
+    /// Verifies the next response, in its wire format, returning the parsed
+    /// message. Fails with [`Error::SignatureError`] holding the TSIG error
+    /// if the response isn't correctly signed by the key, or the server
+    /// returned a error, such as [`Rcode::BadTime`].
+    ///
+    /// [`Error::SignatureError`]: crate::Error::SignatureError
+    pub fn verify(&mut self, buf: &[u8]) -> Result<Message, crate::Error> {
+        let result = self.check(buf);
+        if let Err(e) = &result {
+            self.failed.get_or_insert(match e {
+                crate::Error::SignatureError(error) => *error,
+                _ => Rcode::FormErr,
+            });
+        }
+        result
+    }
+
+    fn check(&mut self, buf: &[u8]) -> Result<Message, crate::Error> {
+        let message = Message::from_slice(buf)?;
+
+        let tsig = match find(&message)? {
+            Some(tsig) => tsig,
+
+            // Only later messages may be unsigned.
+            None if !self.signed || self.count >= MAX_UNSIGNED => {
+                return Err(crate::Error::SignatureError(Rcode::BadSig))
+            }
+            None => {
+                self.unsigned.extend_from_slice(buf);
+                self.count += 1;
+                return Ok(message);
+            }
+        };
+
+        let (name, tsig) = tsig;
+        if !name.eq_ignore_ascii_case(&self.key.name)
+            || tsig.algorithm.parse::<Algorithm>().ok() != Some(self.key.algorithm)
+        {
+            return Err(crate::Error::SignatureError(Rcode::BadKey));
+        }
+        if tsig.error != Rcode::NoError {
+            return Err(crate::Error::SignatureError(tsig.error));
+        }
+
+        let mut data = prior(&self.mac);
+        if !self.signed {
+            data.extend_from_slice(&unsigned(buf, tsig.original_id)?);
+            write_variables(&mut data, &self.key.name, tsig)?;
+        } else {
+            data.append(&mut self.unsigned);
+            data.extend_from_slice(&unsigned(buf, tsig.original_id)?);
+            tsig.write_timers(&mut data);
+        }
+
+        self.key
+            .algorithm
+            .verify(&self.key.secret, &data, &tsig.mac)
+            .map_err(crate::Error::SignatureError)?;
+        check_time(tsig, SystemTime::now()).map_err(crate::Error::SignatureError)?;
+
+        self.mac = tsig.mac.clone();
+        self.unsigned.clear();
+        self.count = 0;
+        self.signed = true;
+
+        Ok(message)
+    }
+
+    /// Checks every response was verified, and the last was signed.
+    pub fn finish(&self) -> Result<(), crate::Error> {
+        if let Some(error) = self.failed {
+            return Err(crate::Error::SignatureError(error));
+        }
+        if !self.signed || self.count > 0 {
+            return Err(crate::Error::SignatureError(Rcode::BadSig));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the query signed with the key, if any, and the verifier for its
+/// responses.
+#[cfg(any(feature = "tcp", feature = "udp"))]
+pub(crate) fn sign(
+    key: Option<&Key>,
+    query: &Message,
+) -> Result<(Message, Option<Verifier>), crate::Error> {
+    let mut query = query.clone();
+    let verifier = match key {
+        Some(key) => Some(key.sign(&mut query)?),
+        None => None,
+    };
+    Ok((query, verifier))
+}
+
+/// Verifies the request, in its wire format, against the keys.
+///
+/// Returns None if the request isn't signed, otherwise the [`Signer`] to sign
+/// the responses with. If the request failed verification the signer's
+/// [`error`](Signer::error) is set, such as [`Rcode::BadSig`], and the
+/// request should be answered with just that error. Fails if the TSIG record
+/// is invalid, which should be answered with a FORMERR.
+pub fn verify_request(keys: &[Key], buf: &[u8]) -> Result<Option<Signer>, crate::Error> {
+    let message = Message::from_slice(buf)?;
+    let (name, tsig) = match find(&message)? {
+        Some(tsig) => tsig,
+        None => return Ok(None),
+    };
+
+    let algorithm = tsig.algorithm.parse::<Algorithm>().ok();
+    let key = keys
+        .iter()
+        .find(|key| name.eq_ignore_ascii_case(&key.name) && Some(key.algorithm) == algorithm);
+
+    let mut signer = Signer {
+        key: key.cloned(),
+        name: name.to_string(),
+        algorithm: tsig.algorithm.clone(),
+        fudge: tsig.fudge,
+        mac: tsig.mac.clone(),
+        time_signed: tsig.time_signed,
+        error: Rcode::NoError,
+        first: true,
+    };
+
+    let key = match key {
+        Some(key) => key,
+        None => {
+            signer.error = Rcode::BadKey;
+            return Ok(Some(signer));
+        }
+    };
+
+    let mut data = unsigned(buf, tsig.original_id)?;
+    write_variables(&mut data, &key.name, tsig)?;
+
+    let result = key
+        .algorithm
+        .verify(&key.secret, &data, &tsig.mac)
+        .and_then(|_| check_time(tsig, SystemTime::now()));
+
+    if let Err(error) = result {
+        signer.error = error;
+        if error != Rcode::BadTime {
+            // The MAC can't be trusted, so isn't included in the response.
+            signer.mac.clear();
+        }
+    }
+
+    Ok(Some(signer))
+}
+
+/// Signs the responses to a request, returned by [`verify_request`].
+#[derive(Clone, Debug)]
+pub struct Signer {
+    /// The key the request was signed with, if it's known.
+    key: Option<Key>,
+
+    /// The key name and algorithm from the request.
+    name: String,
+    algorithm: String,
+    fudge: Duration,
+
+    /// The MAC of the request, or the last response.
+    mac: Vec<u8>,
+
+    /// When the request was signed.
+    time_signed: u64,
+
+    error: Rcode,
+    first: bool,
+}
+
+impl Signer {
+    /// Returns the key the request was signed with, if it's known.
+    pub fn key(&self) -> Option<&Key> {
+        self.key.as_ref()
+    }
+
+    /// Returns the error verifying the request, or [`Rcode::NoError`].
+    pub fn error(&self) -> Rcode {
+        self.error
+    }
+
+    /// Signs the next response, appending a TSIG record to its additionals.
+    /// Every message of a response with many messages must be signed in
+    /// order.
+    ///
+    /// If the request failed verification, the response's rcode is set to
+    /// NOTAUTH, and the TSIG record holds the error. Responses with a
+    /// [`Rcode::BadKey`] or [`Rcode::BadSig`] error aren't signed.
+    pub fn sign(&mut self, message: &mut Message) -> Result<(), crate::Error> {
+        message.additionals.retain(|r| r.r#type() != Type::TSIG);
+
+        let now = unix_seconds(SystemTime::now());
+        let mut tsig = TSIG {
+            algorithm: self.algorithm.clone(),
+            time_signed: now,
+            fudge: self.fudge,
+            mac: Vec::new(),
+            original_id: message.id,
+            error: self.error,
+            other: Vec::new(),
+        };
+
+        if self.error != Rcode::NoError {
+            message.rcode = Rcode::NotAuth;
+        }
+
+        let key = match (&self.key, self.error) {
+            (Some(key), Rcode::NoError | Rcode::BadTime) => key,
+            _ => {
+                message.additionals.push(record(&self.name, tsig));
+                return Ok(());
+            }
+        };
+
+        // The client is told our time, and can tell the response is fresh
+        // from its request's time, see rfc8945 section 5.2.3.
+        if self.error == Rcode::BadTime {
+            tsig.time_signed = self.time_signed;
+            tsig.other = now.to_be_bytes()[2..].to_vec();
+        }
+
+        let mut data = prior(&self.mac);
+        data.extend_from_slice(&message.to_vec()?);
+        if self.first {
+            write_variables(&mut data, &self.name, &tsig)?;
+        } else {
+            tsig.write_timers(&mut data);
+        }
+        tsig.mac = key.algorithm.mac(&key.secret, &data);
+
+        self.mac = tsig.mac.clone();
+        self.first = false;
+
+        message.additionals.push(record(&self.name, tsig));
+        Ok(())
+    }
+}
+
+/// Returns the key name and TSIG of the message, if signed. Fails if the
+/// TSIG record isn't the last record, or there are more than one.
+fn find(message: &Message) -> Result<Option<(&str, &TSIG)>, crate::Error> {
+    match message.last_signature("TSIG", |r| r.r#type() == Type::TSIG)? {
+        Some(Record {
+            name,
+            resource: Resource::TSIG(tsig),
+            ..
+        }) => Ok(Some((name, tsig))),
+        _ => Ok(None),
+    }
+}
+
+/// Returns the message without its TSIG record, which must be last, and
+/// with its original ID, as it was when signed.
+fn unsigned(buf: &[u8], original_id: u16) -> io::Result<Vec<u8>> {
+    let mut data = Message::without_last_record(buf)?;
+    data[0..2].copy_from_slice(&original_id.to_be_bytes());
+    Ok(data)
+}
+
+/// Writes the TSIG variables included in the MAC, see rfc8945 section 4.3.3.
+fn write_variables(buf: &mut Vec<u8>, name: &str, tsig: &TSIG) -> io::Result<()> {
+    Message::write_qname(buf, &name.to_ascii_lowercase())?;
+    buf.extend_from_slice(&(Class::Any as u16).to_be_bytes());
+    buf.extend_from_slice(&0_u32.to_be_bytes()); // TTL
+
+    Message::write_qname(buf, &tsig.algorithm.to_ascii_lowercase())?;
+    tsig.write_timers(buf);
+
+    buf.extend_from_slice(&(tsig.error as u16).to_be_bytes());
+    buf.extend_from_slice(&(tsig.other.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&tsig.other);
+
+    Ok(())
+}
+
+/// Returns the prior MAC, with its length, that starts the data of the next MAC.
+fn prior(mac: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(512);
+    data.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+    data.extend_from_slice(mac);
+    data
+}
+
+/// Checks the message was signed within the fudge of now.
+fn check_time(tsig: &TSIG, now: SystemTime) -> Result<(), Rcode> {
+    if unix_seconds(now).abs_diff(tsig.time_signed) > tsig.fudge.as_secs() {
+        return Err(Rcode::BadTime);
+    }
+    Ok(())
+}
+
+fn record(name: &str, tsig: TSIG) -> Record {
+    Record {
+        name: name.to_string(),
+        class: Class::Any,
+        ttl: Duration::ZERO,
+        resource: Resource::TSIG(tsig),
+    }
+}
@@ -163,6 +163,16 @@ impl Record {
     pub fn r#type(&self) -> Type {
         self.resource.r#type()
     }
+
+    /// Returns true if this is a TSIG or SIG(0) record, which signs the whole
+    /// message, so must be its last record.
+    pub(crate) fn is_signature(&self) -> bool {
+        match &self.resource {
+            Resource::TSIG(_) => true,
+            Resource::SIG(sig) => sig.type_covered == Type::Reserved,
+            _ => false,
+        }
+    }
 }
 
 /// EDNS(0) extension record as defined in [rfc2671] and [rfc6891].
@@ -187,6 +197,9 @@ pub struct Extension {
     ///
     /// [rfc3225]: https://datatracker.ietf.org/doc/html/rfc3225
     pub dnssec_ok: bool,
+
+    /// The options carried within the extension.
+    pub options: Vec<ExtensionOption>,
 }
 
 impl Default for Extension {
@@ -196,10 +209,83 @@ impl Default for Extension {
             extend_rcode: 0,
             version: 0,
             dnssec_ok: false,
+            options: Vec::default(),
         }
     }
 }
 
+/// A option carried within a EDNS(0) [`Extension`]. See [rfc6891] and the
+/// list of [DNS EDNS0 Option Codes].
+///
+/// [rfc6891]: https://datatracker.ietf.org/doc/html/rfc6891#section-6.1.2
+/// [DNS EDNS0 Option Codes]: https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-11
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ExtensionOption {
+    /// The edns-tcp-keepalive option, see [rfc7828]. Clients send this without
+    /// a timeout, and servers respond with the idle timeout they will allow,
+    /// in units of 100 milliseconds.
+    ///
+    /// [rfc7828]: https://datatracker.ietf.org/doc/html/rfc7828
+    TcpKeepalive(Option<Duration>),
+
+    /// A Extended DNS Error, see [rfc8914]. Gives additional information
+    /// about the cause of a error, or a answer, such as it being stale.
+    ///
+    /// [rfc8914]: https://datatracker.ietf.org/doc/html/rfc8914
+    ExtendedError {
+        /// The reason for the error, such as [`ExtensionOption::STALE_ANSWER`].
+        info_code: u16,
+
+        /// Optional human readable text, for debugging.
+        extra_text: String,
+    },
+
+    /// Any option we don't (yet) understand, stored as the option code and raw data.
+    Unknown(u16, Vec<u8>),
+}
+
+impl ExtensionOption {
+    /// The option code for edns-tcp-keepalive.
+    pub const TCP_KEEPALIVE: u16 = 11;
+
+    /// The option code for a Extended DNS Error.
+    pub const EXTENDED_ERROR: u16 = 15;
+
+    /// The Extended DNS Error info code for a answer served from stale data.
+    pub const STALE_ANSWER: u16 = 3;
+
+    /// Returns this option's code.
+    pub fn code(&self) -> u16 {
+        match self {
+            ExtensionOption::TcpKeepalive(_) => Self::TCP_KEEPALIVE,
+            ExtensionOption::ExtendedError { .. } => Self::EXTENDED_ERROR,
+            ExtensionOption::Unknown(code, _) => *code,
+        }
+    }
+}
+
+impl Extension {
+    /// Returns the timeout from the edns-tcp-keepalive option, if present.
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        self.options.iter().find_map(|option| match option {
+            ExtensionOption::TcpKeepalive(timeout) => *timeout,
+            _ => None,
+        })
+    }
+
+    /// Returns the info codes of any Extended DNS Errors.
+    pub fn extended_errors(&self) -> Vec<u16> {
+        self.options
+            .iter()
+            .filter_map(|option| match option {
+                ExtensionOption::ExtendedError { info_code, .. } => Some(*info_code),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
 /// Stats related to the specific query, optionally filed in by the client
 /// and does not change the query behaviour.
 #[derive(Clone, Debug, PartialEq)]
@@ -360,6 +446,29 @@ pub enum Rcode {
     /// [rfc8490]: https://datatracker.ietf.org/doc/html/rfc8490
     DSOTYPENI = 11,
     // 12-15 Unassigned
+
+    // The following only fit in the TSIG error field, or a extended rcode.
+    /// TSIG Signature Failure, see [rfc8945]. Shares its value with BADVERS
+    /// (Bad OPT Version) from [rfc6891].
+    ///
+    /// [rfc6891]: https://datatracker.ietf.org/doc/html/rfc6891
+    /// [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
+    BadSig = 16,
+
+    /// Key not recognized. See [rfc8945].
+    ///
+    /// [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
+    BadKey = 17,
+
+    /// Signature out of time window. See [rfc8945].
+    ///
+    /// [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
+    BadTime = 18,
+
+    /// Bad Truncation. See [rfc8945].
+    ///
+    /// [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
+    BadTrunc = 22,
 }
 
 /// Defaults to [`Rcode::NoError`].
@@ -371,13 +480,11 @@ impl Default for Rcode {
 /*
 pub enum ExtendedRcode {
     Rcode,
-    BADVERS_or_BADSIG = 16  //  Bad OPT Version [RFC6891] or TSIG Signature Failure  [RFC8945]
-    BADKEY = 17  //   Key not recognized  [RFC8945]
-    BADTIME = 18  //  Signature out of time window    [RFC8945]
+    // BADSIG/BADVERS = 16, BADKEY = 17, and BADTIME = 18 are in Rcode.
     BADMODE = 19  //  Bad TKEY Mode   [RFC2930]
     BADNAME = 20  //  Duplicate key name  [RFC2930]
     BADALG = 21  //   Algorithm not supported [RFC2930]
-    BADTRUNC = 22  //     Bad Truncation  [RFC8945]
+    // BADTRUNC = 22 is in Rcode.
     BADCOOKIE = 23  //    Bad/missing Server Cookie   [RFC7873]
     // 24-3840  Unassigned
     // 3841-4095     Reserved for Private Use        [RFC6895]
@@ -411,12 +518,30 @@ pub enum Type {
     /// Text strings.
     TXT = 16,
 
+    /// Signature, used for public key transaction signatures (SIG(0)). See
+    /// [rfc2535] and [rfc2931].
+    ///
+    /// [rfc2535]: https://datatracker.ietf.org/doc/html/rfc2535
+    /// [rfc2931]: https://datatracker.ietf.org/doc/html/rfc2931
+    SIG = 24,
+
+    /// Public key, for verifying SIG(0) signatures. See [rfc2535] and [rfc3445].
+    ///
+    /// [rfc2535]: https://datatracker.ietf.org/doc/html/rfc2535
+    /// [rfc3445]: https://datatracker.ietf.org/doc/html/rfc3445
+    KEY = 25,
+
     /// IPv6 Address.
     AAAA = 28,
 
     /// Server Selection
     SRV = 33,
 
+    /// Delegation name, which aliases a entire subtree of names. See [rfc6672].
+    ///
+    /// [rfc6672]: https://datatracker.ietf.org/doc/html/rfc6672
+    DNAME = 39,
+
     /// EDNS(0) Opt type. See [rfc3225] and [rfc6891].
     ///
     /// [rfc3225]: https://datatracker.ietf.org/doc/html/rfc3225
@@ -430,6 +555,24 @@ pub enum Type {
     /// [rfc7208]: https://datatracker.ietf.org/doc/html/rfc7208
     SPF = 99,
 
+    /// Transaction Signature, authenticating a message with a shared key.
+    /// See [rfc8945]. Only valid as the last additional record.
+    ///
+    /// [rfc8945]: https://datatracker.ietf.org/doc/html/rfc8945
+    TSIG = 250,
+
+    /// Incremental zone transfer. See [rfc1995].
+    /// Only valid as a Question Type.
+    ///
+    /// [rfc1995]: https://datatracker.ietf.org/doc/html/rfc1995
+    IXFR = 251,
+
+    /// Full zone transfer. See [rfc5936].
+    /// Only valid as a Question Type.
+    ///
+    /// [rfc5936]: https://datatracker.ietf.org/doc/html/rfc5936
+    AXFR = 252,
+
     /// Any record type.
     /// Only valid as a Question Type.
     ANY = 255,
@@ -500,6 +643,7 @@ pub enum Resource {
     AAAA(AAAA),
 
     CNAME(CNAME),
+    DNAME(DNAME),
     NS(NS),
     PTR(PTR),
 
@@ -512,9 +656,25 @@ pub enum Resource {
     SOA(SOA),
     SRV(SRV),
 
+    /// A Signature, see [`SIG`].
+    SIG(SIG),
+
+    /// A Public Key, see [`KEY`].
+    KEY(KEY),
+
+    /// A Transaction Signature, see [`TSIG`].
+    TSIG(TSIG),
+
     OPT,
 
     ANY, // Not a valid Record Type, but is a Type
+
+    /// A record of the type without any data, which refers to the whole
+    /// RRset (or with [`Type::ANY`] every RRset) in UPDATE messages. Only
+    /// valid with [`Class::Any`] or [`Class::None`], see [rfc2136] section 2.4.
+    ///
+    /// [rfc2136]: https://datatracker.ietf.org/doc/html/rfc2136
+    Empty(Type),
 }
 
 impl Resource {
@@ -525,6 +685,7 @@ impl Resource {
             Resource::A(_) => Type::A,
             Resource::AAAA(_) => Type::AAAA,
             Resource::CNAME(_) => Type::CNAME,
+            Resource::DNAME(_) => Type::DNAME,
             Resource::NS(_) => Type::NS,
             Resource::PTR(_) => Type::PTR,
             Resource::TXT(_) => Type::TXT,
@@ -532,8 +693,12 @@ impl Resource {
             Resource::SOA(_) => Type::SOA,
             Resource::SRV(_) => Type::SRV,
             Resource::SPF(_) => Type::SPF,
+            Resource::SIG(_) => Type::SIG,
+            Resource::KEY(_) => Type::KEY,
+            Resource::TSIG(_) => Type::TSIG,
             Resource::OPT => Type::OPT,
             Resource::ANY => Type::ANY,
+            Resource::Empty(r#type) => *r#type,
         }
     }
 }
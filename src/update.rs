@@ -0,0 +1,143 @@
+use crate::Class;
+use crate::Message;
+use crate::Opcode;
+use crate::Record;
+use crate::Resource;
+use crate::Type;
+use std::time::Duration;
+
+/// Builds a dynamic update (UPDATE) [`Message`], as described in [rfc2136].
+///
+/// A update names the zone to change, any prerequisites that must hold
+/// before the server makes the change, and then the records to add or
+/// delete. The server applies every change, or none of them. In the message
+/// the zone is the question, the prerequisites are the answers, and the
+/// changes are the authoritys.
+///
+/// # Example
+///
+/// ```rust
+/// use rustdns::types::*;
+/// use rustdns::{Record, Resource, Update};
+/// use std::time::Duration;
+///
+/// let www = Record {
+///     name: "www.example.com.".to_string(),
+///     class: Class::Internet,
+///     ttl: Duration::new(3600, 0),
+///     resource: Resource::A("192.0.2.2".parse().unwrap()),
+/// };
+///
+/// // Replace the addresses of www, as long as it isn't a alias.
+/// let message = Update::new("example.com")
+///     .require_absent("www.example.com", Type::CNAME)
+///     .delete_rrset("www.example.com", Type::A)
+///     .add(www)
+///     .build();
+///
+/// assert_eq!(message.opcode, Opcode::Update);
+/// assert_eq!(message.authoritys.len(), 2);
+/// ```
+///
+/// [rfc2136]: https://datatracker.ietf.org/doc/html/rfc2136
+#[derive(Clone, Debug)]
+pub struct Update {
+    message: Message,
+}
+
+impl Update {
+    /// Creates a new Update to the zone.
+    pub fn new(zone: &str) -> Update {
+        let mut message = Message {
+            opcode: Opcode::Update,
+            rd: false,
+            ad: false,
+            ..Default::default()
+        };
+        message.add_question(zone, Type::SOA, Class::Internet);
+
+        Update { message }
+    }
+
+    /// Requires the RRset with the name and type to exist, with any data.
+    pub fn require_exists(mut self, name: &str, r#type: Type) -> Self {
+        self.message.answers.push(empty(name, Class::Any, r#type));
+        self
+    }
+
+    /// Requires the RRset with the record's name and type to exist, and be
+    /// exactly the records given by calls to `require`. The TTL is ignored.
+    pub fn require(mut self, record: Record) -> Self {
+        self.message.answers.push(Record {
+            class: Class::Internet,
+            ttl: Duration::ZERO,
+            ..record
+        });
+        self
+    }
+
+    /// Requires the RRset with the name and type to not exist.
+    pub fn require_absent(mut self, name: &str, r#type: Type) -> Self {
+        self.message.answers.push(empty(name, Class::None, r#type));
+        self
+    }
+
+    /// Requires the name to have at least one record.
+    pub fn require_name_in_use(self, name: &str) -> Self {
+        self.require_exists(name, Type::ANY)
+    }
+
+    /// Requires the name to have no records.
+    pub fn require_name_not_in_use(self, name: &str) -> Self {
+        self.require_absent(name, Type::ANY)
+    }
+
+    /// Adds the record to its RRset.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, record: Record) -> Self {
+        self.message.authoritys.push(Record {
+            class: Class::Internet,
+            ..record
+        });
+        self
+    }
+
+    /// Deletes the RRset with the name and type.
+    pub fn delete_rrset(mut self, name: &str, r#type: Type) -> Self {
+        self.message
+            .authoritys
+            .push(empty(name, Class::Any, r#type));
+        self
+    }
+
+    /// Deletes every RRset with the name.
+    pub fn delete_name(self, name: &str) -> Self {
+        self.delete_rrset(name, Type::ANY)
+    }
+
+    /// Deletes the record (matched by its name, type and data) from its
+    /// RRset.
+    pub fn delete(mut self, record: Record) -> Self {
+        self.message.authoritys.push(Record {
+            class: Class::None,
+            ttl: Duration::ZERO,
+            ..record
+        });
+        self
+    }
+
+    /// Returns the UPDATE message.
+    pub fn build(self) -> Message {
+        self.message
+    }
+}
+
+/// Returns a record without data, referring to the whole RRset.
+fn empty(name: &str, class: Class, r#type: Type) -> Record {
+    Record {
+        name: name.to_string(),
+        class,
+        ttl: Duration::ZERO,
+        resource: Resource::Empty(r#type),
+    }
+}
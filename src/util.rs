@@ -1,7 +1,31 @@
+#[cfg(any(
+    feature = "tcp",
+    feature = "udp",
+    all(feature = "server", feature = "zones")
+))]
+use crate::dns::Names;
+#[cfg(any(
+    feature = "doh",
+    feature = "json",
+    feature = "tcp",
+    feature = "udp",
+    all(feature = "server", feature = "zones")
+))]
+use crate::Record;
+#[cfg(any(
+    feature = "doh",
+    feature = "json",
+    feature = "tcp",
+    feature = "udp",
+    all(feature = "server", feature = "zones")
+))]
+use crate::Resource;
 use std::fmt::Write;
 use std::net::IpAddr;
 use std::net::IpAddr::V4;
 use std::net::IpAddr::V6;
+#[cfg(any(feature = "sig0", feature = "tsig"))]
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[cfg(test)]
 use pretty_assertions::assert_eq;
@@ -50,6 +74,119 @@ pub fn reverse(ip: IpAddr) -> String {
     }
 }
 
+/// Returns true if SOA serial `a` is newer than `b`, using the serial number
+/// arithmetic of [rfc1982], which allows serials to wrap around.
+///
+/// # Example
+///
+/// ```rust
+/// use rustdns::util::is_newer_serial;
+///
+/// assert!(is_newer_serial(2021010102, 2021010101));
+/// assert!(is_newer_serial(1, u32::MAX));
+/// ```
+///
+/// [rfc1982]: https://datatracker.ietf.org/doc/html/rfc1982
+pub fn is_newer_serial(a: u32, b: u32) -> bool {
+    a != b && a.wrapping_sub(b) < 1 << 31
+}
+
+/// Returns the alias records for `name`, and the name they point to. A DNAME
+/// record for a ancestor of `name` is preferred, and returned along with the
+/// CNAME synthesized from it, as described in rfc6672.
+#[cfg(any(feature = "doh", feature = "json", feature = "tcp", feature = "udp"))]
+pub(crate) fn find_alias(answers: &[Record], name: &str) -> Option<(Vec<Record>, String)> {
+    for record in answers {
+        if let Resource::DNAME(target) = &record.resource {
+            if let Some(prefix) = strip_ancestor(name, &record.name) {
+                let next = format!("{}{}", prefix, target);
+                let cname = Record {
+                    name: name.to_string(),
+                    class: record.class,
+                    ttl: record.ttl,
+                    resource: Resource::CNAME(next.clone()),
+                };
+                return Some((vec![record.clone(), cname], next));
+            }
+        }
+    }
+
+    answers.iter().find_map(|record| match &record.resource {
+        Resource::CNAME(target) if record.name.eq_ignore_ascii_case(name) => {
+            Some((vec![record.clone()], target.clone()))
+        }
+        _ => None,
+    })
+}
+
+/// Returns the labels of `name` before `ancestor`, including the trailing
+/// '.', if `ancestor` is a proper ancestor of `name`.
+#[cfg(any(feature = "doh", feature = "json", feature = "tcp", feature = "udp"))]
+fn strip_ancestor<'a>(name: &'a str, ancestor: &str) -> Option<&'a str> {
+    let split = name.len().checked_sub(ancestor.len())?;
+    if split == 0 || !name.is_char_boundary(split) {
+        return None;
+    }
+
+    let (prefix, suffix) = name.split_at(split);
+    if prefix.ends_with('.') && suffix.eq_ignore_ascii_case(ancestor) {
+        Some(prefix)
+    } else {
+        None
+    }
+}
+
+/// Returns the name in lowercase, and fully qualified.
+#[cfg(any(
+    feature = "server",
+    feature = "sig0",
+    feature = "tcp",
+    feature = "tsig",
+    feature = "udp",
+    feature = "zones"
+))]
+pub(crate) fn normalise(name: &str) -> String {
+    let mut name = name.to_lowercase();
+    if !name.ends_with('.') {
+        name.push('.');
+    }
+    name
+}
+
+/// Returns true if the records have the same name, type and data, ignoring
+/// their class and TTL. Names within the data are compared in the same way as
+/// the wire format, which ignores case and any trailing dot.
+#[cfg(any(
+    feature = "tcp",
+    feature = "udp",
+    all(feature = "server", feature = "zones")
+))]
+pub(crate) fn same_record(a: &Record, b: &Record) -> bool {
+    normalise(&a.name) == normalise(&b.name)
+        && a.r#type() == b.r#type()
+        && (a.resource == b.resource
+            || matches!((rdata(&a.resource), rdata(&b.resource)), (Some(a), Some(b)) if a == b))
+}
+
+/// Returns the resource in wire format, or None if it can't be written.
+#[cfg(any(
+    feature = "tcp",
+    feature = "udp",
+    all(feature = "server", feature = "zones")
+))]
+fn rdata(resource: &Resource) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+    resource.write(&mut buf, &mut Names::default()).ok()?;
+    Some(buf)
+}
+
+/// Returns the time in seconds since the UNIX epoch.
+#[cfg(any(feature = "sig0", feature = "tsig"))]
+pub(crate) fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
 #[test]
 fn test_reverse() {
     let tests: Vec<(IpAddr, &str)> = vec![
@@ -65,3 +202,14 @@ fn test_reverse() {
         assert_eq!(reverse(test.0), test.1);
     }
 }
+
+#[test]
+fn test_is_newer_serial() {
+    assert!(is_newer_serial(2, 1));
+    assert!(!is_newer_serial(1, 2));
+    assert!(!is_newer_serial(1, 1));
+
+    // Serials wrap around.
+    assert!(is_newer_serial(0, u32::MAX));
+    assert!(!is_newer_serial(u32::MAX, 0));
+}
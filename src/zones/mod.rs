@@ -14,6 +14,9 @@ mod parser;
 mod parser_tests;
 mod preprocessor;
 mod process;
+mod zone;
+
+pub use self::zone::{Lookup, Zone};
 
 /// A Zone File. This is the unprocessed version of the zone file
 /// where domains such as "@" have not yet been resolved, and fields
@@ -117,6 +117,15 @@ impl ZoneParser {
         ))
     }
 
+    #[alias(resource)]
+    fn resource_dname(input: Node) -> Result<Resource> {
+        assert_eq!(input.as_rule(), Rule::resource_dname);
+
+        Ok(match_nodes!(input.into_children();
+            [domain(name)] => Resource::DNAME(name.to_string()),
+        ))
+    }
+
     #[alias(resource)]
     fn resource_ns(input: Node) -> Result<Resource> {
         assert_eq!(input.as_rule(), Rule::resource_ns);
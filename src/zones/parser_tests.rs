@@ -34,6 +34,15 @@ mod tests {
                     resource: Resource::A("26.3.0.103".parse().unwrap()),
                 },
             ),
+            (
+                "*.example.com.  A  26.3.0.103",
+                Record {
+                    name: Some("*.example.com.".to_string()),
+                    ttl: None,
+                    class: None,
+                    resource: Resource::A("26.3.0.103".parse().unwrap()),
+                },
+            ),
             (
                 "A       1       A       26.3.0.103",
                 Record {
@@ -166,6 +175,15 @@ mod tests {
                     resource: Resource::CNAME("example.com".to_string()),
                 },
             ),
+            (
+                "DNAME example.net",
+                Record {
+                    name: None,
+                    ttl: None,
+                    class: None,
+                    resource: Resource::DNAME("example.net".to_string()),
+                },
+            ),
             (
                 "NS      VAXA",
                 Record {
@@ -78,6 +78,73 @@ impl File {
         Ok(results)
     }
 
+    /// Creates a File holding the records, the inverse of [`File::into_records`].
+    /// All names are written as absolute names, so the origin is only
+    /// recorded for reference. This is useful to keep a zone received by a
+    /// zone transfer.
+    pub fn from_records(origin: &str, records: Vec<Record>) -> File {
+        let entries = records
+            .into_iter()
+            .map(|record| {
+                Entry::Record(crate::zones::Record {
+                    name: Some(Self::absolute(&record.name)),
+                    ttl: Some(record.ttl),
+                    class: Some(record.class),
+                    resource: Self::absolute_resource(record.resource),
+                })
+            })
+            .collect();
+
+        File::new(Some(Self::absolute(origin)), entries)
+    }
+
+    /// Returns the name with a dot on the end.
+    fn absolute(name: &str) -> String {
+        if name.ends_with('.') {
+            name.to_string()
+        } else {
+            name.to_owned() + "."
+        }
+    }
+
+    /// The inverse of [`File::resolve_resource`], making every name absolute.
+    fn absolute_resource(resource: Resource) -> Resource {
+        match resource {
+            Resource::A(_)
+            | Resource::AAAA(_)
+            | Resource::TXT(_)
+            | Resource::SPF(_)
+            | Resource::KEY(_)
+            | Resource::TSIG(_)
+            | Resource::OPT
+            | Resource::ANY
+            | Resource::Empty(_) => resource,
+
+            Resource::CNAME(domain) => Resource::CNAME(Self::absolute(&domain)),
+            Resource::DNAME(domain) => Resource::DNAME(Self::absolute(&domain)),
+            Resource::NS(domain) => Resource::NS(Self::absolute(&domain)),
+            Resource::PTR(domain) => Resource::PTR(Self::absolute(&domain)),
+            Resource::MX(mx) => Resource::MX(MX {
+                exchange: Self::absolute(&mx.exchange),
+                ..mx
+            }),
+            Resource::SOA(soa) => Resource::SOA(SOA {
+                mname: Self::absolute(&soa.mname),
+                // Zone files hold the rname as a domain, not a email address.
+                rname: Self::absolute(&SOA::email_to_rname(&soa.rname).unwrap_or(soa.rname)),
+                ..soa
+            }),
+            Resource::SRV(srv) => Resource::SRV(SRV {
+                name: Self::absolute(&srv.name),
+                ..srv
+            }),
+            Resource::SIG(sig) => Resource::SIG(SIG {
+                signer_name: Self::absolute(&sig.signer_name),
+                ..sig
+            }),
+        }
+    }
+
     fn resolve_name(name: &str, origin: Option<&str>) -> String {
         // Absolute domain name
         if let Some(name) = name.strip_suffix('.') {
@@ -104,11 +171,15 @@ impl File {
             | Resource::AAAA(_)
             | Resource::TXT(_)
             | Resource::SPF(_)
+            | Resource::KEY(_)
+            | Resource::TSIG(_)
             | Resource::OPT
-            | Resource::ANY => resource.clone(),
+            | Resource::ANY
+            | Resource::Empty(_) => resource.clone(),
 
             // The rest need some kind of tweaking
             Resource::CNAME(domain) => Resource::CNAME(Self::resolve_name(domain, origin)),
+            Resource::DNAME(domain) => Resource::DNAME(Self::resolve_name(domain, origin)),
             Resource::NS(domain) => Resource::NS(Self::resolve_name(domain, origin)),
             Resource::PTR(domain) => Resource::PTR(Self::resolve_name(domain, origin)),
             Resource::MX(mx) => Resource::MX(MX {
@@ -130,6 +201,10 @@ impl File {
                 port: srv.port,
                 name: Self::resolve_name(&srv.name, origin),
             }),
+            Resource::SIG(sig) => Resource::SIG(SIG {
+                signer_name: Self::resolve_name(&sig.signer_name, origin),
+                ..sig.clone()
+            }),
         }
     }
 }
@@ -0,0 +1,613 @@
+use crate::bail;
+use crate::util::normalise;
+use crate::zones::File;
+use crate::Record;
+use crate::Resource;
+use crate::Type;
+use std::collections::BTreeMap;
+
+/// A zone held in memory as a tree of names, for answering lookups the way
+/// a authoritative server does.
+///
+/// Unlike a flat list of records, the tree knows which names exist, even
+/// those without records of their own (empty non-terminals, such as
+/// `b.example.com` when only `a.b.example.com` has records), where the zone
+/// is cut by a delegation, and which names are hidden (occluded) below a
+/// delegation or DNAME. Lookups apply wildcards as described in [rfc4592].
+///
+/// All names are stored in lowercase, and fully qualified.
+///
+/// # Example
+///
+/// ```rust
+/// use rustdns::zones::{File, Lookup, Zone};
+/// use rustdns::Type;
+/// use std::str::FromStr;
+///
+/// let file = File::from_str(
+///     "$ORIGIN example.com.
+///      $TTL 3600
+///      @         IN SOA ns admin 1 7200 3600 1209600 3600
+///      *         IN A   192.0.2.1
+///      a.b       IN A   192.0.2.2",
+/// )
+/// .unwrap();
+/// let zone = Zone::from_file(file).unwrap();
+///
+/// // Synthesized from the wildcard.
+/// assert!(matches!(
+///     zone.lookup("www.example.com", Type::A),
+///     Lookup::Answer { wildcard: true, .. }
+/// ));
+///
+/// // A empty non-terminal exists, so the wildcard doesn't apply.
+/// assert_eq!(zone.lookup("b.example.com", Type::A), Lookup::NoData { wildcard: false });
+/// ```
+///
+/// [rfc4592]: https://datatracker.ietf.org/doc/html/rfc4592
+#[derive(Clone, Debug)]
+pub struct Zone {
+    /// The name of the zone.
+    origin: String,
+
+    /// The node for the origin, the apex of the zone.
+    apex: Node,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    records: Vec<Record>,
+
+    /// The nodes below this one, keyed by their (lowercase) label.
+    children: BTreeMap<String, Node>,
+}
+
+/// The outcome of looking up a name and type in a [`Zone`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Lookup {
+    /// The records of the type for the name. If `wildcard` is true they
+    /// were synthesized from a wildcard, and have been renamed to the name.
+    Answer {
+        records: Vec<Record>,
+        wildcard: bool,
+    },
+
+    /// The name is a alias, for which the lookup should be repeated with
+    /// the CNAME's target.
+    Cname { record: Record, wildcard: bool },
+
+    /// The name is below a DNAME, and is thus a alias, for which the lookup
+    /// should be repeated with the target of the synthesized CNAME. See
+    /// [rfc6672].
+    ///
+    /// [rfc6672]: https://datatracker.ietf.org/doc/html/rfc6672
+    Dname { dname: Record, cname: Record },
+
+    /// The name is at or below a zone cut, and is thus served by another
+    /// zone. The NS records of that zone, and the addresses of those name
+    /// servers known in this zone (the glue).
+    Referral {
+        cut: String,
+        ns: Vec<Record>,
+        glue: Vec<Record>,
+    },
+
+    /// The name exists, but has no records of the type.
+    NoData { wildcard: bool },
+
+    /// The name does not exist. The closest encloser is the closest
+    /// ancestor of the name that does exist.
+    NxDomain { closest_encloser: String },
+
+    /// The name is not within this zone.
+    OutOfZone,
+}
+
+impl Zone {
+    /// Creates a new Zone from the records, which must contain one SOA
+    /// record, for the apex of the zone.
+    ///
+    /// Fails if any record is outside the zone, or a name has a CNAME
+    /// record and any other record (see [rfc2181 section 10.1]), or more than
+    /// one DNAME record.
+    ///
+    /// [rfc2181 section 10.1]: https://datatracker.ietf.org/doc/html/rfc2181#section-10.1
+    pub fn new(records: Vec<Record>) -> Result<Zone, crate::Error> {
+        let mut soas = records
+            .iter()
+            .filter(|r| matches!(r.resource, Resource::SOA(_)));
+
+        let origin = match (soas.next(), soas.next()) {
+            (Some(soa), None) => normalise(&soa.name),
+            (None, _) => bail!(InvalidData, "zone has no SOA record"),
+            (Some(_), Some(_)) => bail!(InvalidData, "zone has multiple SOA records"),
+        };
+
+        let mut zone = Zone {
+            origin,
+            apex: Node::default(),
+        };
+
+        // Insert the SOA first, so it's the first record of the zone.
+        let (soa, rest): (Vec<Record>, Vec<Record>) = records
+            .into_iter()
+            .partition(|r| matches!(r.resource, Resource::SOA(_)));
+
+        for mut record in soa.into_iter().chain(rest) {
+            record.name = normalise(&record.name);
+
+            let labels = match zone.labels(&record.name) {
+                Some(labels) => labels,
+                None => bail!(
+                    InvalidData,
+                    "record '{}' is outside the zone '{}'",
+                    record.name,
+                    zone.origin
+                ),
+            };
+
+            let mut node = &mut zone.apex;
+            for label in labels {
+                node = node.children.entry(label.to_string()).or_default();
+            }
+
+            let r#type = record.r#type();
+            let conflict = node.records.iter().find(|r| {
+                matches!(
+                    (r#type, r.r#type()),
+                    (Type::CNAME, _) | (_, Type::CNAME) | (Type::DNAME, Type::DNAME)
+                )
+            });
+            if let Some(existing) = conflict {
+                bail!(
+                    InvalidData,
+                    "'{}' has conflicting {} and {} records",
+                    record.name,
+                    existing.r#type(),
+                    r#type
+                );
+            }
+
+            node.records.push(record);
+        }
+
+        Ok(zone)
+    }
+
+    /// Creates a new Zone from the zone file. See [`Zone::new`].
+    pub fn from_file(file: File) -> Result<Zone, crate::Error> {
+        match file.into_records() {
+            Ok(records) => Self::new(records),
+            Err(()) => bail!(InvalidData, "invalid zone file"),
+        }
+    }
+
+    /// Returns the name of the zone.
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// Returns the zone's SOA record.
+    pub fn soa(&self) -> &Record {
+        // Zone::new checks there is one, and puts it first.
+        &self.apex.records[0]
+    }
+
+    /// Returns the records with exactly this name, ignoring delegations,
+    /// wildcards, and so on. None if the name does not exist.
+    pub fn records(&self, name: &str) -> Option<&[Record]> {
+        self.find(&normalise(name))
+            .map(|node| node.records.as_slice())
+    }
+
+    /// Returns every record in the zone, starting with the SOA, and then
+    /// each name in turn, parents before their children.
+    pub fn iter(&self) -> impl Iterator<Item = &Record> {
+        let mut nodes = vec![&self.apex];
+        let mut records = Vec::new();
+
+        while let Some(node) = nodes.pop() {
+            records.extend(&node.records);
+            nodes.extend(node.children.values().rev());
+        }
+
+        records.into_iter()
+    }
+
+    /// Looks up the records of the type (or every type for [`Type::ANY`])
+    /// for the name.
+    pub fn lookup(&self, name: &str, r#type: Type) -> Lookup {
+        let name = normalise(name);
+        let labels = match self.labels(&name) {
+            Some(labels) => labels,
+            None => return Lookup::OutOfZone,
+        };
+
+        let mut node = &self.apex;
+        let mut current = self.origin.clone();
+
+        for label in labels {
+            // A DNAME redirects every name below it.
+            if let Some(dname) = node.find(Type::DNAME) {
+                return synthesize(dname, &name);
+            }
+
+            node = match node.children.get(label) {
+                Some(child) => child,
+                None => {
+                    // The wildcard for the closest encloser, if any, applies.
+                    return match node.children.get("*") {
+                        Some(wildcard) => wildcard.answer(&name, r#type, true),
+                        None => Lookup::NxDomain {
+                            closest_encloser: current,
+                        },
+                    };
+                }
+            };
+            current = join(label, &current);
+
+            // Below the apex, NS records cut the zone.
+            if node.find(Type::NS).is_some() {
+                return Lookup::Referral {
+                    ns: node.all(Type::NS),
+                    glue: self.glue(node),
+                    cut: current,
+                };
+            }
+        }
+
+        node.answer(&name, r#type, false)
+    }
+
+    /// Returns the labels of the name below the origin, from the origin
+    /// down, or None if the name is outside the zone.
+    fn labels<'a>(&self, name: &'a str) -> Option<Vec<&'a str>> {
+        let prefix = if self.origin == "." {
+            name.strip_suffix('.')?
+        } else if name == self.origin {
+            ""
+        } else {
+            name.strip_suffix(&self.origin)?.strip_suffix('.')?
+        };
+
+        if prefix.is_empty() {
+            return Some(Vec::new());
+        }
+        Some(prefix.rsplit('.').collect())
+    }
+
+    /// Returns the node with exactly this (normalised) name.
+    fn find(&self, name: &str) -> Option<&Node> {
+        let mut node = &self.apex;
+        for label in self.labels(name)? {
+            node = node.children.get(label)?;
+        }
+        Some(node)
+    }
+
+    /// Returns the A and AAAA records within this zone, for the delegation's
+    /// name servers.
+    fn glue(&self, cut: &Node) -> Vec<Record> {
+        let mut glue = Vec::new();
+        for record in &cut.records {
+            if let Resource::NS(target) = &record.resource {
+                if let Some(node) = self.find(&normalise(target)) {
+                    glue.extend(node.all(Type::A));
+                    glue.extend(node.all(Type::AAAA));
+                }
+            }
+        }
+        glue
+    }
+}
+
+impl Node {
+    /// Returns the first record of the type.
+    fn find(&self, r#type: Type) -> Option<&Record> {
+        self.records.iter().find(|r| r.r#type() == r#type)
+    }
+
+    /// Returns all the records of the type.
+    fn all(&self, r#type: Type) -> Vec<Record> {
+        self.records
+            .iter()
+            .filter(|r| r.r#type() == r#type)
+            .cloned()
+            .collect()
+    }
+
+    /// Answers the lookup from this node's records, renaming the records to
+    /// `name` (which only changes them if this is a wildcard).
+    fn answer(&self, name: &str, r#type: Type, wildcard: bool) -> Lookup {
+        let rename = |record: &Record| Record {
+            name: name.to_string(),
+            ..record.clone()
+        };
+
+        let records: Vec<Record> = self
+            .records
+            .iter()
+            .filter(|r| r#type == Type::ANY || r.r#type() == r#type)
+            .map(rename)
+            .collect();
+
+        if !records.is_empty() {
+            return Lookup::Answer { records, wildcard };
+        }
+
+        match self.find(Type::CNAME) {
+            Some(cname) => Lookup::Cname {
+                record: rename(cname),
+                wildcard,
+            },
+            None => Lookup::NoData { wildcard },
+        }
+    }
+}
+
+/// Returns the DNAME, and the CNAME synthesized from it for the name.
+fn synthesize(dname: &Record, name: &str) -> Lookup {
+    let target = match &dname.resource {
+        Resource::DNAME(target) => normalise(target),
+        _ => unreachable!("only called with DNAME records"),
+    };
+
+    // The name is always below the DNAME's owner.
+    let prefix = &name[..name.len() - dname.name.len()];
+
+    Lookup::Dname {
+        cname: Record {
+            name: name.to_string(),
+            class: dname.class,
+            ttl: dname.ttl,
+            resource: Resource::CNAME(format!("{}{}", prefix, target.trim_start_matches('.'))),
+        },
+        dname: dname.clone(),
+    }
+}
+
+/// Returns the label prepended to the name.
+fn join(label: &str, name: &str) -> String {
+    if name == "." {
+        format!("{}.", label)
+    } else {
+        format!("{}.{}", label, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Class;
+    use core::time::Duration;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@           IN  SOA     ns1 admin 1 7200 3600 1209600 300
+@           IN  NS      ns1
+ns1         IN  A       192.0.2.1
+*           IN  A       192.0.2.2
+*           IN  MX      10 mail
+a.b         IN  A       192.0.2.3
+*.c         IN  CNAME   www
+sub         IN  NS      ns.sub
+ns.sub      IN  A       192.0.2.53
+old         IN  DNAME   new.example.net.
+";
+
+    fn zone() -> Zone {
+        Zone::from_file(File::from_str(ZONE).unwrap()).unwrap()
+    }
+
+    fn a(name: &str, ip: &str) -> Record {
+        Record {
+            name: name.to_string(),
+            class: Class::Internet,
+            ttl: Duration::new(3600, 0),
+            resource: Resource::A(ip.parse().unwrap()),
+        }
+    }
+
+    fn names(zone: &Zone) -> Vec<(&str, Type)> {
+        zone.iter().map(|r| (r.name.as_str(), r.r#type())).collect()
+    }
+
+    #[test]
+    fn test_exact() {
+        let zone = zone();
+        assert_eq!(zone.origin(), "example.com.");
+        assert_eq!(
+            zone.lookup("A.B.Example.COM", Type::A),
+            Lookup::Answer {
+                records: vec![a("a.b.example.com.", "192.0.2.3")],
+                wildcard: false
+            }
+        );
+        assert_eq!(
+            zone.lookup("ns1.example.com.", Type::AAAA),
+            Lookup::NoData { wildcard: false }
+        );
+
+        // The apex NS records are not a delegation.
+        assert!(matches!(
+            zone.lookup("example.com", Type::NS),
+            Lookup::Answer { .. }
+        ));
+    }
+
+    #[test]
+    fn test_wildcard() {
+        let zone = zone();
+
+        // Synthesized, and renamed to the name asked for.
+        assert_eq!(
+            zone.lookup("www.example.com", Type::A),
+            Lookup::Answer {
+                records: vec![a("www.example.com.", "192.0.2.2")],
+                wildcard: true
+            }
+        );
+
+        // The wildcard applies to any depth below the closest encloser, but
+        // only to names that don't exist.
+        assert_eq!(
+            zone.lookup("x.www.example.com", Type::A),
+            Lookup::Answer {
+                records: vec![a("x.www.example.com.", "192.0.2.2")],
+                wildcard: true
+            }
+        );
+        assert_eq!(
+            zone.lookup("ns1.example.com", Type::MX),
+            Lookup::NoData { wildcard: false }
+        );
+        assert_eq!(
+            zone.lookup("www.example.com", Type::TXT),
+            Lookup::NoData { wildcard: true }
+        );
+
+        // The wildcard itself can be asked for.
+        assert!(matches!(
+            zone.lookup("*.example.com", Type::A),
+            Lookup::Answer {
+                wildcard: false,
+                ..
+            }
+        ));
+
+        match zone.lookup("x.c.example.com", Type::A) {
+            Lookup::Cname { record, wildcard } => {
+                assert_eq!(record.name, "x.c.example.com.");
+                assert_eq!(
+                    record.resource,
+                    Resource::CNAME("www.example.com".to_string())
+                );
+                assert!(wildcard);
+            }
+            lookup => panic!("unexpected {:?}", lookup),
+        }
+    }
+
+    #[test]
+    fn test_empty_non_terminal() {
+        let zone = zone();
+
+        // b.example.com exists without records, so the wildcard doesn't
+        // apply to it, and names below it don't exist.
+        assert_eq!(
+            zone.lookup("b.example.com", Type::A),
+            Lookup::NoData { wildcard: false }
+        );
+        assert_eq!(zone.records("b.example.com"), Some(&[][..]));
+        assert_eq!(
+            zone.lookup("x.b.example.com", Type::A),
+            Lookup::NxDomain {
+                closest_encloser: "b.example.com.".to_string()
+            }
+        );
+
+        // c.example.com only exists to hold its wildcard.
+        assert_eq!(
+            zone.lookup("c.example.com", Type::A),
+            Lookup::NoData { wildcard: false }
+        );
+
+        assert_eq!(zone.records("x.b.example.com"), None);
+    }
+
+    #[test]
+    fn test_referral() {
+        let zone = zone();
+
+        for name in [
+            "sub.example.com",
+            "ns.sub.example.com",
+            "x.y.sub.example.com",
+        ] {
+            match zone.lookup(name, Type::A) {
+                Lookup::Referral { cut, ns, glue } => {
+                    assert_eq!(cut, "sub.example.com.", "{}", name);
+                    assert_eq!(ns.len(), 1, "{}", name);
+                    assert_eq!(glue, vec![a("ns.sub.example.com.", "192.0.2.53")]);
+                }
+                lookup => panic!("{}: unexpected {:?}", name, lookup),
+            }
+        }
+    }
+
+    #[test]
+    fn test_dname() {
+        let zone = zone();
+
+        match zone.lookup("www.old.example.com", Type::A) {
+            Lookup::Dname { dname, cname } => {
+                assert_eq!(dname.name, "old.example.com.");
+                assert_eq!(cname.name, "www.old.example.com.");
+                assert_eq!(
+                    cname.resource,
+                    Resource::CNAME("www.new.example.net.".to_string())
+                );
+            }
+            lookup => panic!("unexpected {:?}", lookup),
+        }
+
+        // The DNAME's owner is not itself redirected.
+        assert!(matches!(
+            zone.lookup("old.example.com", Type::DNAME),
+            Lookup::Answer { .. }
+        ));
+    }
+
+    #[test]
+    fn test_out_of_zone() {
+        let zone = zone();
+        assert_eq!(zone.lookup("www.example.net", Type::A), Lookup::OutOfZone);
+        assert_eq!(zone.lookup("com", Type::A), Lookup::OutOfZone);
+        assert_eq!(zone.lookup("badexample.com", Type::A), Lookup::OutOfZone);
+    }
+
+    #[test]
+    fn test_iter() {
+        let zone = zone();
+        assert_eq!(
+            names(&zone),
+            vec![
+                ("example.com.", Type::SOA),
+                ("example.com.", Type::NS),
+                ("*.example.com.", Type::A),
+                ("*.example.com.", Type::MX),
+                ("a.b.example.com.", Type::A),
+                ("*.c.example.com.", Type::CNAME),
+                ("ns1.example.com.", Type::A),
+                ("old.example.com.", Type::DNAME),
+                ("sub.example.com.", Type::NS),
+                ("ns.sub.example.com.", Type::A),
+            ]
+        );
+        assert_eq!(zone.soa().r#type(), Type::SOA);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let soa = zone().soa().clone();
+
+        assert!(Zone::new(vec![a("www.example.com", "192.0.2.1")]).is_err());
+        assert!(Zone::new(vec![soa.clone(), soa.clone()]).is_err());
+        assert!(Zone::new(vec![soa.clone(), a("www.example.net", "192.0.2.1")]).is_err());
+
+        let cname = Record {
+            resource: Resource::CNAME("ns1".to_string()),
+            ..a("www.example.com", "192.0.2.1")
+        };
+        assert!(Zone::new(vec![
+            soa.clone(),
+            a("www.example.com", "192.0.2.1"),
+            cname.clone()
+        ])
+        .is_err());
+        assert!(Zone::new(vec![soa, cname]).is_ok());
+    }
+}
@@ -0,0 +1,618 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(feature = "udp")]
+mod tests {
+    use super::common::{a, cname, ns, soa};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::Exchanger;
+    use rustdns::clients::Resolver;
+    use rustdns::clients::{Cache, Cached};
+    use rustdns::types::*;
+    use rustdns::Message;
+    use rustdns::Record;
+    use rustdns::Resource;
+    use rustdns::SOA;
+    use std::io;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    /// A mock client that answers every A query with a single record, unless
+    /// the name starts with "nx", in which case it returns NXDOMAIN. Negative
+    /// responses include a SOA. Counts the queries it receives, and can be
+    /// made to fail, or be slow.
+    struct MockClient {
+        ttl: Duration,
+        soa: bool,
+        queries: AtomicUsize,
+        fail: AtomicBool,
+        delay: Mutex<Duration>,
+    }
+
+    impl MockClient {
+        fn new(ttl: u64) -> MockClient {
+            MockClient {
+                ttl: Duration::from_secs(ttl),
+                soa: true,
+                queries: AtomicUsize::new(0),
+                fail: AtomicBool::new(false),
+                delay: Mutex::new(Duration::default()),
+            }
+        }
+
+        /// Returns a MockClient that lives forever, for use on other threads.
+        fn leak(ttl: u64) -> &'static MockClient {
+            Box::leak(Box::new(MockClient::new(ttl)))
+        }
+
+        fn set_fail(&self, fail: bool) {
+            self.fail.store(fail, Ordering::SeqCst);
+        }
+
+        fn set_delay(&self, delay: Duration) {
+            *self.delay.lock().unwrap() = delay;
+        }
+
+        /// Waits up to a second for the number of queries to reach `n`.
+        fn wait_for(&self, n: usize) -> usize {
+            let start = Instant::now();
+            while self.queries() < n && start.elapsed() < Duration::from_secs(1) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            self.queries()
+        }
+
+        fn queries(&self) -> usize {
+            self.queries.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Exchanger for &MockClient {
+        fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+            thread::sleep(*self.delay.lock().unwrap());
+            self.queries.fetch_add(1, Ordering::SeqCst);
+
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "mock failure").into());
+            }
+
+            let question = &query.questions[0];
+            let mut resp = query.clone();
+            resp.qr = QR::Response;
+
+            if question.name.starts_with("nx") {
+                resp.rcode = Rcode::NXDomain;
+            } else if question.r#type == Type::A {
+                resp.answers.push(Record {
+                    name: question.name.clone(),
+                    class: Class::Internet,
+                    ttl: self.ttl,
+                    resource: Resource::A("192.0.2.1".parse().unwrap()),
+                });
+            }
+
+            if resp.answers.is_empty() && self.soa {
+                resp.authoritys.push(Record {
+                    name: "example.com.".to_string(),
+                    class: Class::Internet,
+                    ttl: Duration::from_secs(3600),
+                    resource: Resource::SOA(SOA {
+                        mname: "ns.example.com.".to_string(),
+                        rname: "admin@example.com".to_string(),
+                        serial: 1,
+                        refresh: Duration::from_secs(3600),
+                        retry: Duration::from_secs(600),
+                        expire: Duration::from_secs(86400),
+                        minimum: Duration::from_secs(300),
+                    }),
+                });
+            }
+
+            Ok(resp)
+        }
+    }
+
+    fn query(name: &str, r#type: Type) -> Message {
+        let mut query = Message::default();
+        query.add_question(name, r#type, Class::Internet);
+        query
+    }
+
+    #[test]
+    fn test_hit() {
+        let mock = MockClient::new(60);
+        let client = Cached::new(&mock, Cache::default());
+
+        let first = client.exchange(&query("example.com", Type::A)).unwrap();
+        let mut q = query("example.com", Type::A);
+        q.id = 1234;
+        let second = client.exchange(&q).unwrap();
+
+        assert_eq!(
+            mock.queries(),
+            1,
+            "second query should be answered from the cache"
+        );
+        assert_eq!(second.id, 1234);
+        assert_eq!(second.stats, None);
+        assert_eq!(second.answers, first.answers);
+        assert_eq!(client.cache().len(), 1);
+
+        // A different type is a different key.
+        client.exchange(&query("example.com", Type::AAAA)).unwrap();
+        assert_eq!(mock.queries(), 2);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mock = MockClient::new(60);
+        let client = Cached::new(&mock, Cache::default());
+
+        client.exchange(&query("example.com", Type::A)).unwrap();
+        // add_question lowercases, so set the name directly.
+        let mut q = query("example.com", Type::A);
+        q.questions[0].name = "EXAMPLE.com.".to_string();
+        let resp = client.exchange(&q).unwrap();
+
+        assert_eq!(mock.queries(), 1);
+        assert_eq!(resp.questions[0].name, "EXAMPLE.com.");
+    }
+
+    #[test]
+    fn test_ttl_decrements() {
+        let mock = MockClient::new(60);
+        let client = Cached::new(&mock, Cache::default());
+
+        client.exchange(&query("example.com", Type::A)).unwrap();
+        thread::sleep(Duration::from_millis(1100));
+        let resp = client.exchange(&query("example.com", Type::A)).unwrap();
+
+        assert_eq!(mock.queries(), 1);
+        assert_eq!(resp.answers[0].ttl, Duration::from_secs(59));
+    }
+
+    #[test]
+    fn test_expires() {
+        let mock = MockClient::new(1);
+        let client = Cached::new(&mock, Cache::default());
+
+        client.exchange(&query("example.com", Type::A)).unwrap();
+        thread::sleep(Duration::from_millis(1100));
+        client.exchange(&query("example.com", Type::A)).unwrap();
+
+        assert_eq!(mock.queries(), 2);
+    }
+
+    #[test]
+    fn test_negative() {
+        let mock = MockClient::new(60);
+        let client = Cached::new(&mock, Cache::default());
+
+        client.exchange(&query("nx.example.com", Type::A)).unwrap();
+        let resp = client.exchange(&query("nx.example.com", Type::A)).unwrap();
+
+        assert_eq!(mock.queries(), 1);
+        assert_eq!(resp.rcode, Rcode::NXDomain);
+
+        // Cached for the smaller of the SOA's TTL and minimum.
+        let ttl = resp.authoritys[0].ttl;
+        assert!(
+            ttl <= Duration::from_secs(300),
+            "ttl {:?} exceeds the minimum",
+            ttl
+        );
+        assert!(ttl > Duration::from_secs(290), "ttl {:?}", ttl);
+
+        // NODATA is also cached.
+        client.exchange(&query("example.com", Type::MX)).unwrap();
+        client.exchange(&query("example.com", Type::MX)).unwrap();
+        assert_eq!(mock.queries(), 2);
+    }
+
+    #[test]
+    fn test_negative_types() {
+        let mock = MockClient::new(60);
+        let client = Cached::new(&mock, Cache::default());
+
+        // A NXDOMAIN means the name has no records of any type.
+        client.exchange(&query("nx.example.com", Type::A)).unwrap();
+        let resp = client.exchange(&query("nx.example.com", Type::MX)).unwrap();
+
+        assert_eq!(mock.queries(), 1);
+        assert_eq!(resp.rcode, Rcode::NXDomain);
+        assert_eq!(resp.questions, query("nx.example.com", Type::MX).questions);
+
+        // But a NODATA is only for the type queried.
+        client.exchange(&query("example.com", Type::MX)).unwrap();
+        client.exchange(&query("example.com", Type::A)).unwrap();
+        assert_eq!(mock.queries(), 3);
+    }
+
+    #[test]
+    fn test_negative_without_soa() {
+        let mut mock = MockClient::new(60);
+        mock.soa = false;
+        let client = Cached::new(&mock, Cache::default());
+
+        client.exchange(&query("nx.example.com", Type::A)).unwrap();
+        client.exchange(&query("nx.example.com", Type::A)).unwrap();
+        client.exchange(&query("example.com", Type::MX)).unwrap();
+        client.exchange(&query("example.com", Type::MX)).unwrap();
+
+        assert_eq!(mock.queries(), 4);
+        assert!(client.cache().is_empty());
+    }
+
+    #[test]
+    fn test_lru() {
+        let mock = MockClient::new(60);
+        let client = Cached::new(&mock, Cache::new(2));
+
+        client.exchange(&query("a.example.com", Type::A)).unwrap();
+        client.exchange(&query("b.example.com", Type::A)).unwrap();
+        client.exchange(&query("a.example.com", Type::A)).unwrap(); // a is now most recent
+        client.exchange(&query("c.example.com", Type::A)).unwrap(); // evicts b
+        assert_eq!(mock.queries(), 3);
+        assert_eq!(client.cache().len(), 2);
+
+        client.exchange(&query("a.example.com", Type::A)).unwrap();
+        client.exchange(&query("c.example.com", Type::A)).unwrap();
+        assert_eq!(mock.queries(), 3);
+
+        client.exchange(&query("b.example.com", Type::A)).unwrap();
+        assert_eq!(mock.queries(), 4);
+    }
+
+    #[test]
+    fn test_clamps() {
+        let mock = MockClient::new(60);
+        let cache = Cache::default().with_max_ttl(Duration::from_secs(10));
+        let client = Cached::new(&mock, cache);
+
+        let resp = client.exchange(&query("example.com", Type::A)).unwrap();
+        assert_eq!(
+            resp.answers[0].ttl,
+            Duration::from_secs(60),
+            "response is not modified"
+        );
+
+        let resp = client.exchange(&query("example.com", Type::A)).unwrap();
+        assert!(resp.answers[0].ttl <= Duration::from_secs(10));
+
+        let mock = MockClient::new(0);
+        let cache = Cache::default().with_min_ttl(Duration::from_secs(30));
+        let client = Cached::new(&mock, cache);
+
+        client.exchange(&query("example.com", Type::A)).unwrap();
+        let resp = client.exchange(&query("example.com", Type::A)).unwrap();
+        assert_eq!(
+            mock.queries(),
+            1,
+            "zero TTL should be cached for the minimum"
+        );
+        assert!(resp.answers[0].ttl > Duration::from_secs(25));
+    }
+
+    /// Returns the response to the query, with the records.
+    fn response(query: &Message, answers: Vec<Record>, authoritys: Vec<Record>) -> Message {
+        Message {
+            qr: QR::Response,
+            answers,
+            authoritys,
+            ..query.clone()
+        }
+    }
+
+    #[test]
+    fn test_rrsets() {
+        let cache = Cache::default();
+
+        let q = query("www.example.com", Type::A);
+        let mut resp = response(
+            &q,
+            vec![
+                cname("www.example.com.", "web.example.com."),
+                a("web.example.com.", "192.0.2.1"),
+                a("web.example.com.", "192.0.2.2"),
+            ],
+            vec![ns("example.com.", "ns1.example.com.")],
+        );
+        resp.additionals.push(a("ns1.example.com.", "192.0.2.53"));
+        cache.insert(&q, &resp);
+        assert_eq!(cache.len(), 2);
+
+        // The whole chain is answered from the cached RRsets.
+        let cached = cache.get(&q).expect("www is cached");
+        assert_eq!(cached.answers, resp.answers);
+
+        // As is the CNAME's target on its own.
+        let cached = cache.get(&query("web.example.com", Type::A)).unwrap();
+        assert_eq!(cached.answers, resp.answers[1..]);
+
+        // But not the authority and additional records.
+        assert!(cache.get(&query("ns1.example.com", Type::A)).is_none());
+        assert!(cache.get(&query("example.com", Type::NS)).is_none());
+    }
+
+    #[test]
+    fn test_unrelated() {
+        let cache = Cache::default();
+
+        let q = query("www.bank.com", Type::A);
+        let resp = response(&q, vec![a("www.bank.com.", "192.0.2.1")], vec![]);
+        cache.insert(&q, &resp);
+
+        // A response for another name, with records for www.bank.com in
+        // every section.
+        let q = query("evil.example", Type::A);
+        let mut evil = response(
+            &q,
+            vec![
+                a("evil.example.", "198.51.100.1"),
+                a("www.bank.com.", "198.51.100.2"),
+            ],
+            vec![ns("bank.com.", "ns.evil.example.")],
+        );
+        evil.additionals.push(a("www.bank.com.", "198.51.100.3"));
+        evil.additionals.push(a("ns.evil.example.", "198.51.100.4"));
+        cache.insert(&q, &evil);
+
+        let cached = cache.get(&query("www.bank.com", Type::A)).unwrap();
+        assert_eq!(cached.answers, resp.answers);
+        assert!(cache.get(&query("bank.com", Type::NS)).is_none());
+        assert!(cache.get(&query("ns.evil.example", Type::A)).is_none());
+
+        // Nor do they clear a cached NXDOMAIN.
+        let q = query("nx.bank.com", Type::A);
+        let nx = Message {
+            rcode: Rcode::NXDomain,
+            ..response(&q, vec![], vec![soa(1)])
+        };
+        cache.insert(&q, &nx);
+        let q = query("evil.example", Type::A);
+        let mut evil = response(&q, vec![a("evil.example.", "198.51.100.1")], vec![]);
+        evil.additionals.push(a("nx.bank.com.", "198.51.100.5"));
+        cache.insert(&q, &evil);
+
+        let cached = cache.get(&query("nx.bank.com", Type::A)).unwrap();
+        assert_eq!(cached.rcode, Rcode::NXDomain);
+    }
+
+    #[test]
+    fn test_negative_cname() {
+        let cache = Cache::default();
+
+        // The NXDOMAIN is for the CNAME's target, not the name queried.
+        let q = query("www.example.com", Type::A);
+        let resp = Message {
+            rcode: Rcode::NXDomain,
+            ..response(
+                &q,
+                vec![cname("www.example.com.", "nx.example.com.")],
+                vec![soa(1)],
+            )
+        };
+        cache.insert(&q, &resp);
+
+        let cached = cache.get(&q).expect("www is cached");
+        assert_eq!(cached.rcode, Rcode::NXDomain);
+        assert_eq!(cached.answers, resp.answers);
+
+        let cached = cache.get(&query("nx.example.com", Type::MX)).unwrap();
+        assert_eq!(cached.rcode, Rcode::NXDomain);
+        assert!(cached.answers.is_empty());
+
+        // Only the CNAME exists for www.
+        let cached = cache.get(&query("www.example.com", Type::CNAME)).unwrap();
+        assert_eq!(cached.rcode, Rcode::NoError);
+        assert_eq!(cached.answers, resp.answers);
+    }
+
+    #[test]
+    fn test_resolver() {
+        let mock = MockClient::new(60);
+        let resolver = Resolver::new_with_client(&mock).with_cache(Cache::default());
+
+        resolver.query("example.com", Type::A).unwrap();
+        resolver.query("example.com", Type::A).unwrap();
+
+        assert_eq!(mock.queries(), 1);
+        assert_eq!(resolver.cache().unwrap().len(), 1);
+    }
+
+    /// Returns true if the response is marked as stale.
+    fn is_stale(response: &Message) -> bool {
+        response
+            .extension
+            .as_ref()
+            .is_some_and(|e| e.extended_errors() == vec![ExtensionOption::STALE_ANSWER])
+    }
+
+    /// Returns a query with a EDNS(0) extension, so stale responses are marked.
+    fn edns_query(name: &str, r#type: Type) -> Message {
+        let mut query = query(name, r#type);
+        query.add_extension(Extension::default());
+        query
+    }
+
+    #[test]
+    fn test_serve_stale() {
+        let mock = MockClient::new(1);
+        let cache = Cache::default().with_serve_stale(Duration::from_secs(60));
+        let client = Cached::new(&mock, cache);
+
+        let resp = client
+            .exchange(&edns_query("example.com", Type::A))
+            .unwrap();
+        assert!(!is_stale(&resp));
+
+        thread::sleep(Duration::from_millis(1100));
+        assert_eq!(
+            client.cache().get(&edns_query("example.com", Type::A)),
+            None
+        );
+
+        // Upstream works, so the expired response is replaced.
+        let resp = client
+            .exchange(&edns_query("example.com", Type::A))
+            .unwrap();
+        assert_eq!(mock.queries(), 2);
+        assert!(!is_stale(&resp));
+
+        // Upstream fails, so the stale response is returned.
+        thread::sleep(Duration::from_millis(1100));
+        mock.set_fail(true);
+        let resp = client
+            .exchange(&edns_query("example.com", Type::A))
+            .unwrap();
+        assert_eq!(mock.queries(), 3);
+        assert!(is_stale(&resp), "{:?}", resp.extension);
+        assert_eq!(resp.answers[0].ttl, Duration::from_secs(30));
+
+        // Without a OPT record in the query, there is none in the response.
+        let resp = client.exchange(&query("example.com", Type::A)).unwrap();
+        assert_eq!(resp.extension, None);
+        assert_eq!(resp.answers[0].ttl, Duration::from_secs(30));
+
+        // Not cached, so the failure is returned.
+        assert!(client.exchange(&query("other.com", Type::A)).is_err());
+    }
+
+    #[test]
+    fn test_serve_stale_disabled() {
+        let mock = MockClient::new(1);
+        let client = Cached::new(&mock, Cache::default());
+
+        client.exchange(&query("example.com", Type::A)).unwrap();
+        thread::sleep(Duration::from_millis(1100));
+
+        mock.set_fail(true);
+        assert!(client.exchange(&query("example.com", Type::A)).is_err());
+        assert!(
+            client.cache().is_empty(),
+            "expired response should be removed"
+        );
+    }
+
+    #[test]
+    fn test_extended_error() {
+        let mut m = query("example.com", Type::A);
+        m.add_extension(Extension {
+            options: vec![ExtensionOption::ExtendedError {
+                info_code: ExtensionOption::STALE_ANSWER,
+                extra_text: "upstream unreachable".to_string(),
+            }],
+            ..Default::default()
+        });
+
+        let parsed = Message::from_slice(&m.to_vec().unwrap()).unwrap();
+        assert_eq!(parsed.extension, m.extension);
+        assert!(parsed
+            .to_string()
+            .contains("; EDE: 3 (Stale Answer): (upstream unreachable)"));
+    }
+
+    #[test]
+    fn test_resolver_serve_stale() {
+        let mock = MockClient::new(1);
+        let cache = Cache::default().with_serve_stale(Duration::from_secs(60));
+        let resolver = Resolver::new_with_client(&mock).with_cache(cache);
+
+        resolver.query("example.com", Type::A).unwrap();
+        thread::sleep(Duration::from_millis(1100));
+
+        mock.set_fail(true);
+        let answer = resolver.query("example.com", Type::A).unwrap();
+        assert!(is_stale(&answer.response));
+        assert_eq!(mock.queries(), 1 + resolver.conf().attempts);
+    }
+
+    #[test]
+    fn test_resolver_stale_timeout() {
+        let mock = MockClient::leak(1);
+        let cache = Cache::default().with_serve_stale(Duration::from_secs(60));
+        let resolver = Resolver::new_with_client(mock)
+            .with_cache(cache)
+            .with_stale_timeout(Duration::from_millis(100));
+
+        resolver.query("example.com", Type::A).unwrap();
+        thread::sleep(Duration::from_millis(1100));
+
+        // The client is too slow, so the stale response is returned.
+        mock.set_delay(Duration::from_millis(500));
+        let start = Instant::now();
+        let answer = resolver.query("example.com", Type::A).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(400));
+        assert!(is_stale(&answer.response));
+
+        // But the query carries on, and refreshes the cache.
+        assert_eq!(mock.wait_for(2), 2);
+        thread::sleep(Duration::from_millis(50));
+        let answer = resolver.query("example.com", Type::A).unwrap();
+        assert!(!is_stale(&answer.response));
+        assert_eq!(mock.queries(), 2);
+    }
+
+    #[test]
+    fn test_resolver_stale_refreshes_once() {
+        let mock = MockClient::leak(1);
+        let cache = Cache::default().with_serve_stale(Duration::from_secs(60));
+        let resolver = Arc::new(
+            Resolver::new_with_client(mock)
+                .with_cache(cache)
+                .with_stale_timeout(Duration::from_millis(100)),
+        );
+
+        resolver.query("example.com", Type::A).unwrap();
+        thread::sleep(Duration::from_millis(1100));
+
+        // Many queries for the stale response, while the client is slow.
+        mock.set_delay(Duration::from_millis(500));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let resolver = resolver.clone();
+                thread::spawn(move || resolver.query("example.com", Type::A).unwrap())
+            })
+            .collect();
+
+        for handle in handles {
+            assert!(is_stale(&handle.join().unwrap().response));
+        }
+
+        // Only one refresh was sent.
+        thread::sleep(Duration::from_millis(600));
+        assert_eq!(mock.queries(), 2);
+    }
+
+    #[test]
+    fn test_resolver_prefetch() {
+        let mock = MockClient::leak(2);
+        let resolver = Resolver::new_with_client(mock)
+            .with_cache(Cache::default())
+            .with_prefetch(true);
+
+        // Make the response popular.
+        resolver.query("example.com", Type::A).unwrap();
+        resolver.query("example.com", Type::A).unwrap();
+        assert_eq!(mock.queries(), 1);
+
+        // Not yet close enough to expiring.
+        thread::sleep(Duration::from_millis(1000));
+        resolver.query("example.com", Type::A).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(mock.queries(), 1);
+
+        // Within the last 10% of the TTL, so prefetched.
+        thread::sleep(Duration::from_millis(850));
+        resolver.query("example.com", Type::A).unwrap();
+        assert_eq!(mock.wait_for(2), 2);
+
+        // The original would have expired, but the prefetched one hasn't.
+        thread::sleep(Duration::from_millis(200));
+        resolver.query("example.com", Type::A).unwrap();
+        assert_eq!(mock.queries(), 2);
+    }
+}
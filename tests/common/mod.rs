@@ -0,0 +1,153 @@
+//! Helpers shared between the integration tests.
+//!
+//! Each test binary only uses some of these.
+#![allow(dead_code)]
+
+#[cfg(all(feature = "server", feature = "zones"))]
+use rustdns::server::Authority;
+#[cfg(feature = "server")]
+use rustdns::server::{Protocol, Request};
+#[cfg(all(feature = "server", feature = "zones"))]
+use rustdns::zones::File;
+use rustdns::Class;
+#[cfg(any(feature = "sig0", feature = "tsig"))]
+use rustdns::Error;
+use rustdns::Message;
+#[cfg(any(feature = "sig0", feature = "tsig"))]
+use rustdns::Rcode;
+use rustdns::Record;
+use rustdns::Resource;
+use rustdns::Type;
+use rustdns::QR;
+use rustdns::SIG;
+use rustdns::SOA;
+use rustdns::TSIG;
+#[cfg(any(feature = "sig0", feature = "tsig"))]
+use std::fmt::Debug;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+#[cfg(all(feature = "server", feature = "zones"))]
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Returns a Internet class record, with a one hour TTL.
+pub fn record(name: &str, resource: Resource) -> Record {
+    Record::new(name, Class::Internet, Duration::from_secs(3600), resource)
+}
+
+/// Returns a A record for the address.
+pub fn a(name: &str, ip: &str) -> Record {
+    record(name, Resource::A(ip.parse().unwrap()))
+}
+
+/// Returns a CNAME record pointing at the target.
+pub fn cname(name: &str, target: &str) -> Record {
+    record(name, Resource::CNAME(target.to_string()))
+}
+
+/// Returns a NS record delegating to the target.
+pub fn ns(name: &str, target: &str) -> Record {
+    record(name, Resource::NS(target.to_string()))
+}
+
+/// Returns the SOA record of the example.com zone, with the serial.
+pub fn soa(serial: u32) -> Record {
+    record(
+        "example.com.",
+        Resource::SOA(SOA {
+            mname: "ns1.example.com.".to_string(),
+            rname: "admin@example.com.".to_string(),
+            serial,
+            refresh: Duration::from_secs(7200),
+            retry: Duration::from_secs(3600),
+            expire: Duration::from_secs(1209600),
+            minimum: Duration::from_secs(300),
+        }),
+    )
+}
+
+/// Returns a query for the SOA record of the example.com zone.
+pub fn soa_query() -> Message {
+    let mut query = Message::default();
+    query.add_question("example.com", Type::SOA, Class::Internet);
+    query
+}
+
+/// Returns a response echoing the request, in its wire format, without the
+/// request's signature.
+pub fn response(request: &[u8]) -> Message {
+    let mut resp = Message::from_slice(request).unwrap();
+    resp.qr = QR::Response;
+    resp.additionals.clear();
+    resp
+}
+
+/// Returns the TSIG record of the message.
+pub fn tsig(message: &Message) -> &TSIG {
+    match &message.additionals.last().unwrap().resource {
+        Resource::TSIG(tsig) => tsig,
+        resource => panic!("expected a TSIG record, got {}", resource),
+    }
+}
+
+/// Returns the SIG record of the message.
+pub fn sig(message: &Message) -> &SIG {
+    match &message.additionals.last().unwrap().resource {
+        Resource::SIG(sig) => sig,
+        resource => panic!("expected a SIG record, got {}", resource),
+    }
+}
+
+/// Returns the rcode of the signature error the result failed with.
+#[cfg(any(feature = "sig0", feature = "tsig"))]
+pub fn signature_error<T: Debug>(result: Result<T, Error>) -> Rcode {
+    match result {
+        Err(Error::SignatureError(rcode)) => rcode,
+        result => panic!("expected a signature error, got {:?}", result),
+    }
+}
+
+/// Returns a authority serving the zone file.
+#[cfg(all(feature = "server", feature = "zones"))]
+pub fn authority(zone: &str) -> Authority {
+    let file = File::from_str(zone).expect("failed to parse zone");
+    Authority::new(file).expect("failed to load zone")
+}
+
+/// Returns a request for the message, unsigned, from a local client.
+#[cfg(feature = "server")]
+pub fn request(message: Message, protocol: Protocol) -> Request {
+    Request {
+        message,
+        src: "127.0.0.1:1234".parse().unwrap(),
+        protocol,
+        key: None,
+    }
+}
+
+/// Reads the next message over TCP, returning its bytes without the two byte
+/// length prefix, or None if the connection was closed.
+pub fn read_frame(stream: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut len = [0; 2];
+    stream.read_exact(&mut len).ok()?;
+    let mut buf = vec![0; u16::from_be_bytes(len).into()];
+    stream.read_exact(&mut buf).ok()?;
+    Some(buf)
+}
+
+/// Writes the bytes of a message over TCP, after their two byte length prefix.
+pub fn write_frame(stream: &mut TcpStream, buf: &[u8]) {
+    stream.write_all(&(buf.len() as u16).to_be_bytes()).unwrap();
+    stream.write_all(buf).unwrap();
+}
+
+/// Reads the next message over TCP, or None if the connection was closed or
+/// the message is invalid.
+pub fn read_message(stream: &mut TcpStream) -> Option<Message> {
+    Message::from_slice(&read_frame(stream)?).ok()
+}
+
+/// Writes the message over TCP.
+pub fn write_message(stream: &mut TcpStream, message: &Message) {
+    write_frame(stream, &message.to_vec().unwrap());
+}
@@ -0,0 +1,187 @@
+#[cfg(test)]
+#[cfg(feature = "udp")]
+#[cfg(feature = "tokio")]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::AddressFamily;
+    use rustdns::clients::Exchanger;
+    use rustdns::clients::Resolver;
+    use rustdns::types::*;
+    use rustdns::Record;
+    use rustdns::Resource;
+    use std::io;
+    use std::net::{Ipv4Addr, Ipv6Addr, TcpListener};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// A mock client that answers every A query with 127.0.0.1, and every
+    /// AAAA query with ::1, after a delay.
+    struct MockClient {
+        a_delay: Duration,
+        aaaa_delay: Duration,
+
+        /// The types of the queries received.
+        queried: Arc<Mutex<Vec<Type>>>,
+    }
+
+    impl MockClient {
+        fn new(a_delay_ms: u64, aaaa_delay_ms: u64) -> MockClient {
+            MockClient {
+                a_delay: Duration::from_millis(a_delay_ms),
+                aaaa_delay: Duration::from_millis(aaaa_delay_ms),
+                queried: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+    }
+
+    impl Exchanger for MockClient {
+        fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+            let question = &query.questions[0];
+            self.queried.lock().unwrap().push(question.r#type);
+
+            let (delay, resource) = match question.r#type {
+                Type::A => (self.a_delay, Resource::A(Ipv4Addr::LOCALHOST)),
+                Type::AAAA => (self.aaaa_delay, Resource::AAAA(Ipv6Addr::LOCALHOST)),
+                _ => return Err(io::Error::other("unexpected type").into()),
+            };
+            thread::sleep(delay);
+
+            let mut resp = query.clone();
+            resp.qr = QR::Response;
+            resp.answers.push(Record {
+                name: question.name.clone(),
+                class: Class::Internet,
+                ttl: Duration::new(10, 0),
+                resource,
+            });
+            Ok(resp)
+        }
+    }
+
+    /// Listens on the same port of both 127.0.0.1 and ::1.
+    fn listen_both() -> (TcpListener, TcpListener) {
+        loop {
+            let ipv4 = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = ipv4.local_addr().unwrap().port();
+            if let Ok(ipv6) = TcpListener::bind(("::1", port)) {
+                return (ipv4, ipv6);
+            }
+        }
+    }
+
+    fn resolver(client: MockClient) -> Arc<Resolver<MockClient>> {
+        Arc::new(Resolver::new_with_client(client))
+    }
+
+    #[tokio::test]
+    async fn test_connect_prefers_ipv6() {
+        let (ipv4, ipv6) = listen_both();
+        let port = ipv4.local_addr().unwrap().port();
+
+        let resolver = resolver(MockClient::new(0, 0));
+        let (stream, addr) = resolver.connect("example.com", port).await.unwrap();
+
+        assert_eq!(addr, ipv6.local_addr().unwrap());
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+
+    #[tokio::test]
+    async fn test_connect_falls_back_to_ipv4() {
+        let ipv4 = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = ipv4.local_addr().unwrap().port();
+        if TcpListener::bind(("::1", port)).is_err() {
+            return; // The IPv6 port is in use, so the test can't be run.
+        }
+
+        let resolver = resolver(MockClient::new(0, 0));
+
+        // Nothing listens on ::1, so that attempt is refused, and the IPv4
+        // attempt starts without waiting for the attempt delay.
+        let start = Instant::now();
+        let (_, addr) = resolver.connect("example.com", port).await.unwrap();
+
+        assert_eq!(addr, ipv4.local_addr().unwrap());
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_connect_slow_aaaa() {
+        let (ipv4, _ipv6) = listen_both();
+        let port = ipv4.local_addr().unwrap().port();
+
+        // After the A answer, the AAAA answer is only waited for briefly.
+        let resolver = resolver(MockClient::new(0, 1000));
+
+        let start = Instant::now();
+        let (_, addr) = resolver.connect("example.com", port).await.unwrap();
+
+        assert_eq!(addr, ipv4.local_addr().unwrap());
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_connect_slow_a() {
+        let (_ipv4, ipv6) = listen_both();
+        let port = ipv6.local_addr().unwrap().port();
+
+        // The AAAA answer is used without waiting for the A answer.
+        let resolver = resolver(MockClient::new(1000, 0));
+
+        let start = Instant::now();
+        let (_, addr) = resolver.connect("example.com", port).await.unwrap();
+
+        assert_eq!(addr, ipv6.local_addr().unwrap());
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_connect_address_family() {
+        let (ipv4, _ipv6) = listen_both();
+        let port = ipv4.local_addr().unwrap().port();
+
+        let client = MockClient::new(0, 0);
+        let queried = client.queried.clone();
+
+        let resolver =
+            Arc::new(Resolver::new_with_client(client).with_address_family(AddressFamily::Ipv4));
+        let (_, addr) = resolver.connect("example.com", port).await.unwrap();
+
+        assert_eq!(addr, ipv4.local_addr().unwrap());
+        assert_eq!(*queried.lock().unwrap(), vec![Type::A]);
+    }
+
+    #[tokio::test]
+    async fn test_connect_ip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let want = listener.local_addr().unwrap();
+
+        let client = MockClient::new(0, 0);
+        let queried = client.queried.clone();
+
+        let resolver = resolver(client);
+        let (_, addr) = resolver.connect("127.0.0.1", want.port()).await.unwrap();
+
+        assert_eq!(addr, want);
+        assert_eq!(*queried.lock().unwrap(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_connect_refused() {
+        // Find a port with nothing listening.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let resolver = resolver(MockClient::new(0, 0));
+        let err = resolver.connect("example.com", port).await.unwrap_err();
+
+        assert!(
+            matches!(&err, rustdns::Error::IoError(e) if e.kind() == io::ErrorKind::ConnectionRefused),
+            "{:?}",
+            err
+        );
+    }
+}
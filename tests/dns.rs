@@ -57,5 +57,24 @@ fn test_from_slice(case: TestCase) {
 
     assert_eq!(got, want, "{}: Formatted string doesn't match", case.name);
 
-    // TODO Test writing the result back out.
+    // Writing the message back out, should parse to the same message.
+    let output = match m.to_vec() {
+        Err(e) => panic!("{}: Unable to write: {}", case.name, e),
+        Ok(o) => o,
+    };
+    let got = match Message::from_slice(&output) {
+        Err(e) => panic!("{}: Unable to parse written message: {}", case.name, e),
+        Ok(p) => p,
+    };
+
+    assert_eq!(got, m, "{}: Written message doesn't match", case.name);
+
+    // Names are compressed at least as well as the original.
+    assert!(
+        output.len() <= input.len(),
+        "{}: Written message is longer ({} > {} bytes)",
+        case.name,
+        output.len(),
+        input.len()
+    );
 }
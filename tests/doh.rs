@@ -0,0 +1,200 @@
+#[cfg(test)]
+#[cfg(feature = "doh")]
+mod tests {
+    use http::header::{HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+    use http::{Method, Request, Response, StatusCode};
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Server};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::doh::{Client, HttpVersion};
+    use rustdns::clients::AsyncExchanger;
+    use rustdns::types::*;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const TOKEN: &str = "Bearer secret";
+
+    /// A minimal HTTP/1.1 only DoH server, that answers every query with
+    /// an empty response. Requests without the expected Authorization header
+    /// are rejected.
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let error = |status| {
+            Ok(Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .unwrap())
+        };
+
+        if req.headers().get(AUTHORIZATION) != Some(&HeaderValue::from_static(TOKEN)) {
+            return error(StatusCode::UNAUTHORIZED);
+        }
+
+        let body = match *req.method() {
+            Method::GET => {
+                let query = req.uri().query().unwrap_or_default();
+                let dns = query.strip_prefix("dns=").unwrap_or_default();
+                base64::decode_config(dns, base64::URL_SAFE_NO_PAD).unwrap_or_default()
+            }
+            Method::POST => hyper::body::to_bytes(req.into_body())
+                .await
+                .unwrap()
+                .to_vec(),
+            _ => return error(StatusCode::METHOD_NOT_ALLOWED),
+        };
+
+        let mut m = match Message::from_slice(&body) {
+            Ok(m) => m,
+            Err(_) => return error(StatusCode::BAD_REQUEST),
+        };
+        m.qr = QR::Response;
+        m.ra = true;
+
+        Ok(Response::builder()
+            .header(CONTENT_TYPE, "application/dns-message")
+            .body(Body::from(m.to_vec().unwrap()))
+            .unwrap())
+    }
+
+    async fn start_server() -> SocketAddr {
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap())
+            .http1_only(true)
+            .serve(make_svc);
+        let addr = server.local_addr();
+
+        tokio::spawn(server);
+        addr
+    }
+
+    /// A minimal HTTP CONNECT proxy, which only proxies a single connection.
+    async fn start_proxy() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (client, _) = listener.accept().await.unwrap();
+            let mut client = BufReader::new(client);
+
+            let mut request = String::new();
+            client.read_line(&mut request).await.unwrap();
+
+            // Skip the remaining headers
+            loop {
+                let mut line = String::new();
+                client.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+            }
+
+            // "CONNECT 127.0.0.1:1234 HTTP/1.1"
+            let target = request.split_whitespace().nth(1).unwrap().to_string();
+            let mut server = TcpStream::connect(target).await.unwrap();
+
+            client
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await
+                .unwrap();
+
+            tokio::io::copy_bidirectional(&mut client, &mut server)
+                .await
+                .ok();
+        });
+
+        addr
+    }
+
+    fn query() -> Message {
+        let mut query = Message::default();
+        query.add_question("bramp.net", Type::A, Class::Internet);
+        query
+    }
+
+    #[tokio::test]
+    async fn test_http1() {
+        let addr = start_server().await;
+        let url = format!("http://{}/dns-query", addr);
+
+        for method in [Method::GET, Method::POST] {
+            let client = Client::new(url.as_str(), method.clone())
+                .unwrap()
+                .without_proxy()
+                .with_http_version(HttpVersion::Http1)
+                .with_header(AUTHORIZATION, HeaderValue::from_static(TOKEN));
+
+            let resp = client.exchange(&query()).await.expect("exchange failed");
+
+            assert_eq!(resp.qr, QR::Response, "{} response", method);
+            assert_eq!(resp.questions, query().questions, "{} response", method);
+            assert_eq!(resp.stats.unwrap().server, addr, "{} response", method);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_header() {
+        let addr = start_server().await;
+        let url = format!("http://{}/dns-query", addr);
+
+        let client = Client::new(url.as_str(), Method::POST)
+            .unwrap()
+            .without_proxy()
+            .with_header(
+                HeaderName::from_static("x-unrelated"),
+                HeaderValue::from_static("value"),
+            );
+
+        assert!(client.exchange(&query()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        let addr = start_server().await;
+        let proxy = start_proxy().await;
+        let url = format!("http://{}/dns-query", addr);
+
+        let client = Client::new(url.as_str(), Method::POST)
+            .unwrap()
+            .with_proxy(format!("http://{}", proxy).parse().unwrap())
+            .with_no_proxy(&[])
+            .with_header(AUTHORIZATION, HeaderValue::from_static(TOKEN));
+
+        let resp = client.exchange(&query()).await.expect("exchange failed");
+
+        assert_eq!(resp.questions, query().questions);
+        assert_eq!(resp.stats.unwrap().server, proxy);
+    }
+
+    #[tokio::test]
+    async fn test_no_proxy() {
+        let addr = start_server().await;
+        let url = format!("http://{}/dns-query", addr);
+
+        // A proxy that isn't listening, so the exchange fails if it's used.
+        let proxy = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        for no_proxy in [&["127.0.0.1"][..], &["*"], &["example.com", "127.0.0.1"]] {
+            let client = Client::new(url.as_str(), Method::GET)
+                .unwrap()
+                .with_proxy(format!("http://{}", proxy).parse().unwrap())
+                .with_no_proxy(no_proxy)
+                .with_header(AUTHORIZATION, HeaderValue::from_static(TOKEN));
+
+            let resp = client.exchange(&query()).await.expect("exchange failed");
+
+            assert_eq!(resp.stats.unwrap().server, addr, "no_proxy {:?}", no_proxy);
+        }
+
+        let client = Client::new(url.as_str(), Method::GET)
+            .unwrap()
+            .with_proxy(format!("http://{}", proxy).parse().unwrap())
+            .with_no_proxy(&["0.0.1", "example.com"]);
+
+        assert!(client.exchange(&query()).await.is_err());
+    }
+}
@@ -0,0 +1,211 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(feature = "server")]
+#[cfg(feature = "udp")]
+mod tests {
+    use super::common::{a, request};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::{udp, Cache, Cached, Exchanger};
+    use rustdns::server::{Forwarder, Handler, Protocol};
+    use rustdns::types::*;
+    use std::io;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// A upstream that records the queries it receives, and answers them
+    /// with a single A record.
+    #[derive(Default)]
+    struct Upstream {
+        queries: Mutex<Vec<Message>>,
+
+        /// Fail every exchange.
+        fail: bool,
+    }
+
+    impl Upstream {
+        fn queries(&self) -> Vec<Message> {
+            self.queries.lock().unwrap().clone()
+        }
+    }
+
+    impl Exchanger for Upstream {
+        fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+            self.queries.lock().unwrap().push(query.clone());
+            if self.fail {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "mock failure").into());
+            }
+
+            let mut resp = query.clone();
+            resp.qr = QR::Response;
+            resp.ra = true;
+            resp.answers.push(a(&query.questions[0].name, "192.0.2.1"));
+
+            resp.extension = Some(Extension {
+                options: vec![
+                    ExtensionOption::TcpKeepalive(Some(Duration::new(30, 0))),
+                    ExtensionOption::Unknown(65001, vec![4, 5, 6]),
+                ],
+                ..Default::default()
+            });
+
+            Ok(resp)
+        }
+    }
+
+    fn query(edns: bool) -> Message {
+        let mut message = Message {
+            id: 0x1234,
+            ..Default::default()
+        };
+        message.add_question("www.example.com", Type::A, Class::Internet);
+        if edns {
+            message.add_extension(Extension {
+                payload_size: 1232,
+                options: vec![
+                    ExtensionOption::TcpKeepalive(None),
+                    ExtensionOption::Unknown(65001, vec![1, 2, 3]),
+                ],
+                ..Default::default()
+            });
+        }
+        message
+    }
+
+    #[test]
+    fn test_forward() {
+        let forwarder = Forwarder::new(Upstream::default());
+
+        let resp = forwarder
+            .handle(&request(query(true), Protocol::Udp))
+            .unwrap();
+        assert_eq!(resp.id, 0x1234);
+        assert_eq!(resp.qr, QR::Response);
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.answers, vec![a("www.example.com.", "192.0.2.1")]);
+
+        // Only the end to end options are passed on, in both directions.
+        assert_eq!(
+            resp.extension.unwrap().options,
+            vec![ExtensionOption::Unknown(65001, vec![4, 5, 6])]
+        );
+
+        let queries = forwarder.client().queries();
+        assert_eq!(queries.len(), 1);
+        assert_eq!(queries[0].questions, query(true).questions);
+        assert_eq!(
+            queries[0].extension.as_ref().unwrap().options,
+            vec![ExtensionOption::Unknown(65001, vec![1, 2, 3])]
+        );
+    }
+
+    #[test]
+    fn test_forward_without_edns() {
+        let forwarder = Forwarder::new(Upstream::default());
+
+        let resp = forwarder
+            .handle(&request(query(false), Protocol::Udp))
+            .unwrap();
+        assert_eq!(resp.id, 0x1234);
+        assert_eq!(resp.answers.len(), 1);
+        assert_eq!(resp.extension, None);
+        assert_eq!(forwarder.client().queries()[0].extension, None);
+    }
+
+    #[test]
+    fn test_servfail() {
+        let forwarder = Forwarder::new(Upstream {
+            fail: true,
+            ..Default::default()
+        });
+
+        let resp = forwarder
+            .handle(&request(query(true), Protocol::Udp))
+            .unwrap();
+        assert_eq!(resp.id, 0x1234);
+        assert_eq!(resp.rcode, Rcode::ServFail);
+        assert_eq!(resp.answers, vec![]);
+        assert!(resp.extension.is_some());
+    }
+
+    #[test]
+    fn test_not_query() {
+        let forwarder = Forwarder::new(Upstream::default());
+
+        let mut message = query(false);
+        message.opcode = Opcode::Status;
+        let resp = forwarder.handle(&request(message, Protocol::Udp)).unwrap();
+        assert_eq!(resp.rcode, Rcode::NotImp);
+        assert_eq!(forwarder.client().queries().len(), 0);
+    }
+
+    #[test]
+    fn test_cached() {
+        let forwarder = Forwarder::new(Cached::new(Upstream::default(), Cache::default()));
+
+        for id in [1, 2] {
+            let mut message = query(true);
+            message.id = id;
+
+            let resp = forwarder.handle(&request(message, Protocol::Udp)).unwrap();
+            assert_eq!(resp.id, id);
+            assert_eq!(resp.answers, vec![a("www.example.com.", "192.0.2.1")]);
+        }
+
+        assert_eq!(forwarder.client().cache().len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "zones")]
+    fn test_udp() {
+        use super::common::authority;
+        use std::thread;
+
+        let authority = authority(
+            "$ORIGIN example.com.
+             $TTL 3600
+             @    IN SOA ns admin 1 7200 3600 1209600 300
+             www  IN A   192.0.2.2",
+        );
+        let server = rustdns::server::udp::Server::bind("127.0.0.1:0", authority).unwrap();
+        let upstream = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        let forwarder = Forwarder::new(udp::Client::new(upstream).unwrap());
+        let server = rustdns::server::udp::Server::bind("127.0.0.1:0", forwarder).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        let message = query(false);
+        let resp = udp::Client::new(addr).unwrap().exchange(&message).unwrap();
+        assert_eq!(resp.id, message.id);
+        assert_eq!(resp.aa, true);
+        assert_eq!(resp.answers, vec![a("www.example.com.", "192.0.2.2")]);
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn test_blocking() {
+        use async_trait::async_trait;
+        use rustdns::clients::{AsyncExchanger, Blocking};
+
+        struct AsyncUpstream(Upstream);
+
+        #[async_trait]
+        impl AsyncExchanger for AsyncUpstream {
+            async fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                self.0.exchange(query)
+            }
+        }
+
+        let client = Blocking::new(AsyncUpstream(Upstream::default())).unwrap();
+        let forwarder = Forwarder::new(client);
+
+        let resp = forwarder
+            .handle(&request(query(false), Protocol::Udp))
+            .unwrap();
+        assert_eq!(resp.id, 0x1234);
+        assert_eq!(resp.answers, vec![a("www.example.com.", "192.0.2.1")]);
+    }
+}
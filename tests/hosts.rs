@@ -0,0 +1,101 @@
+#[cfg(test)]
+#[cfg(feature = "udp")]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::Hosts;
+    use std::fs::File;
+    use std::io::Write;
+    use std::net::IpAddr;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    fn fixture() -> Hosts {
+        Hosts::from_path(format!("{}/tests/hosts/hosts", env!("CARGO_MANIFEST_DIR")))
+    }
+
+    fn ips(ips: &[&str]) -> Option<Vec<IpAddr>> {
+        Some(ips.iter().map(|ip| ip.parse().unwrap()).collect())
+    }
+
+    fn names(names: &[&str]) -> Option<Vec<String>> {
+        Some(names.iter().map(|name| name.to_string()).collect())
+    }
+
+    #[test]
+    fn test_lookup() {
+        let hosts = fixture();
+
+        assert_eq!(hosts.lookup("localhost"), ips(&["127.0.0.1", "::1"]));
+        assert_eq!(hosts.lookup("localhost."), ips(&["127.0.0.1", "::1"]));
+        assert_eq!(hosts.lookup("linklocal"), ips(&["fe80::1"]));
+        assert_eq!(
+            hosts.lookup("server.example.com"),
+            ips(&["192.168.1.10", "192.168.1.12"])
+        );
+        assert_eq!(hosts.lookup("ignored.example.com"), None);
+        assert_eq!(hosts.lookup("missing.example.com"), None);
+    }
+
+    #[test]
+    fn test_aliases() {
+        let hosts = fixture();
+
+        assert_eq!(hosts.lookup("server"), ips(&["192.168.1.10"]));
+        assert_eq!(hosts.lookup("ip6-loopback"), ips(&["::1"]));
+
+        // Names are case insensitive.
+        assert_eq!(hosts.lookup("PRINTER"), ips(&["192.168.1.11"]));
+        assert_eq!(hosts.lookup("printer.example.com"), ips(&["192.168.1.11"]));
+    }
+
+    #[test]
+    fn test_lookup_addr() {
+        let hosts = fixture();
+
+        assert_eq!(
+            hosts.lookup_addr("192.168.1.10".parse().unwrap()),
+            names(&["server.example.com.", "server."])
+        );
+        assert_eq!(
+            hosts.lookup_addr("::1".parse().unwrap()),
+            names(&["localhost.", "ip6-localhost.", "ip6-loopback."])
+        );
+        assert_eq!(hosts.lookup_addr("10.0.0.1".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let hosts = Hosts::from_path("/does/not/exist/hosts");
+        assert_eq!(hosts.lookup("localhost"), None);
+    }
+
+    fn write(path: &PathBuf, contents: &str, modified: SystemTime) {
+        let mut file = File::create(path).unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file.set_modified(modified).unwrap();
+    }
+
+    #[test]
+    fn test_reload() {
+        let path = std::env::temp_dir().join(format!("rustdns-hosts-{}", std::process::id()));
+        let now = SystemTime::now();
+
+        write(&path, "10.0.0.1 a.example.com\n", now);
+        let hosts = Hosts::from_path(&path);
+        assert_eq!(hosts.lookup("a.example.com"), ips(&["10.0.0.1"]));
+
+        // Changed, but with the same modification time, so not reloaded.
+        write(&path, "10.0.0.2 a.example.com\n", now);
+        assert_eq!(hosts.lookup("a.example.com"), ips(&["10.0.0.1"]));
+
+        write(
+            &path,
+            "10.0.0.2 a.example.com\n",
+            now + Duration::from_secs(1),
+        );
+        assert_eq!(hosts.lookup("a.example.com"), ips(&["10.0.0.2"]));
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(hosts.lookup("a.example.com"), None);
+    }
+}
@@ -0,0 +1,457 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(feature = "udp")]
+mod tests {
+    use super::common::{a, ns, record};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::Exchanger;
+    use rustdns::clients::Iterative;
+    use rustdns::clients::Resolver;
+    use rustdns::types::*;
+    use rustdns::Message;
+    use rustdns::Record;
+    use rustdns::Resource;
+    use rustdns::SOA;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io;
+    use std::net::IpAddr;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    const HINTS: &str = "
+; The root of the mock hierarchy.
+.                        3600000      NS    A.ROOT-SERVERS.NET.
+A.ROOT-SERVERS.NET.      3600000      A     10.0.0.1
+";
+
+    /// A in-process authoritative server for a single zone.
+    struct Server {
+        zone: String,
+        records: Vec<Record>,
+
+        /// A lame server refers every query back to the root.
+        lame: bool,
+    }
+
+    impl Server {
+        fn new(zone: &str, records: Vec<Record>) -> Server {
+            Server {
+                zone: zone.to_string(),
+                records,
+                lame: false,
+            }
+        }
+
+        fn lame(zone: &str) -> Server {
+            Server {
+                zone: zone.to_string(),
+                records: Vec::new(),
+                lame: true,
+            }
+        }
+
+        fn answer(&self, query: &Message) -> Message {
+            let question = &query.questions[0];
+
+            let mut resp = query.clone();
+            resp.qr = QR::Response;
+
+            if self.lame {
+                resp.authoritys = vec![ns(".", "a.root-servers.net.")];
+                return resp;
+            }
+
+            // Refer queries for names within a child zone.
+            let child = self.records.iter().find(|r| {
+                matches!(r.resource, Resource::NS(_))
+                    && r.name != self.zone
+                    && (question.name == r.name || question.name.ends_with(&format!(".{}", r.name)))
+            });
+            if let Some(child) = child {
+                resp.authoritys = self
+                    .records
+                    .iter()
+                    .filter(|r| r.name == child.name && matches!(r.resource, Resource::NS(_)))
+                    .cloned()
+                    .collect();
+
+                resp.additionals = self
+                    .records
+                    .iter()
+                    .filter(|r| matches!(r.resource, Resource::A(_)))
+                    .filter(|r| {
+                        resp.authoritys
+                            .iter()
+                            .any(|ns| ns.resource == Resource::NS(r.name.clone()))
+                    })
+                    .cloned()
+                    .collect();
+                return resp;
+            }
+
+            resp.aa = true;
+            resp.answers = self
+                .records
+                .iter()
+                .filter(|r| r.name == question.name && r.r#type() == question.r#type)
+                .cloned()
+                .collect();
+
+            if resp.answers.is_empty() {
+                if !self.records.iter().any(|r| r.name == question.name) {
+                    resp.rcode = Rcode::NXDomain;
+                }
+                resp.authoritys = vec![soa(&self.zone)];
+            }
+
+            resp
+        }
+    }
+
+    /// The mock hierarchy of servers, keyed by their address, which records
+    /// every address that was sent a query.
+    struct Network {
+        servers: HashMap<IpAddr, Server>,
+        queried: Mutex<Vec<IpAddr>>,
+    }
+
+    impl Network {
+        fn new() -> Arc<Network> {
+            let mut servers = HashMap::new();
+
+            servers.insert(
+                ip("10.0.0.1"),
+                Server::new(
+                    ".",
+                    vec![
+                        ns("com.", "ns.com."),
+                        a("ns.com.", "10.0.1.1"),
+                        ns("net.", "ns.net."),
+                        a("ns.net.", "10.0.2.1"),
+                    ],
+                ),
+            );
+
+            servers.insert(
+                ip("10.0.1.1"),
+                Server::new(
+                    "com.",
+                    vec![
+                        ns("example.com.", "ns.example.com."),
+                        a("ns.example.com.", "10.0.3.1"),
+                        // Out of bailiwick, so this glue must be ignored.
+                        ns("glueless.com.", "ns.example.net."),
+                        a("ns.example.net.", "10.9.9.9"),
+                        ns("lame.com.", "ns1.lame.com."),
+                        a("ns1.lame.com.", "10.0.6.1"),
+                        ns("lame.com.", "ns2.lame.com."),
+                        a("ns2.lame.com.", "10.0.6.2"),
+                    ],
+                ),
+            );
+
+            servers.insert(
+                ip("10.0.2.1"),
+                Server::new(
+                    "net.",
+                    vec![
+                        ns("example.net.", "ns.example.net."),
+                        a("ns.example.net.", "10.0.4.1"),
+                    ],
+                ),
+            );
+
+            servers.insert(
+                ip("10.0.3.1"),
+                Server::new(
+                    "example.com.",
+                    vec![
+                        ns("example.com.", "ns.example.com."),
+                        a("ns.example.com.", "10.0.3.1"),
+                        a("www.example.com.", "192.0.2.1"),
+                        a("mail.example.com.", "192.0.2.10"),
+                    ],
+                ),
+            );
+
+            servers.insert(
+                ip("10.0.4.1"),
+                Server::new(
+                    "example.net.",
+                    vec![
+                        ns("example.net.", "ns.example.net."),
+                        a("ns.example.net.", "10.0.5.1"),
+                    ],
+                ),
+            );
+
+            servers.insert(
+                ip("10.0.5.1"),
+                Server::new("glueless.com.", vec![a("www.glueless.com.", "192.0.2.2")]),
+            );
+
+            servers.insert(ip("10.0.6.1"), Server::lame("lame.com."));
+            servers.insert(
+                ip("10.0.6.2"),
+                Server::new("lame.com.", vec![a("www.lame.com.", "192.0.2.3")]),
+            );
+
+            Arc::new(Network {
+                servers,
+                queried: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn exchange(&self, addr: SocketAddr, query: &Message) -> Result<Message, rustdns::Error> {
+            assert_eq!(
+                query.rd, false,
+                "iterative queries should not ask for recursion"
+            );
+
+            self.queried.lock().unwrap().push(addr.ip());
+
+            match self.servers.get(&addr.ip()) {
+                Some(server) => Ok(server.answer(query)),
+                None => Err(io::Error::new(io::ErrorKind::TimedOut, "timed out").into()),
+            }
+        }
+
+        /// Returns the addresses that were queried, and clears the list.
+        fn take(&self) -> Vec<IpAddr> {
+            self.queried.lock().unwrap().drain(..).collect()
+        }
+    }
+
+    fn iterative(network: &Arc<Network>) -> Iterative {
+        let network = network.clone();
+        Iterative::from_hints(HINTS)
+            .unwrap()
+            .with_transport(move |addr, query| network.exchange(addr, query))
+    }
+
+    fn query(name: &str, r#type: Type) -> Message {
+        let mut query = Message::default();
+        query.add_question(name, r#type, Class::Internet);
+        query
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn soa(zone: &str) -> Record {
+        record(
+            zone,
+            Resource::SOA(SOA {
+                mname: format!("ns.{}", zone),
+                rname: format!("admin@{}", zone),
+                serial: 1,
+                refresh: Duration::new(3600, 0),
+                retry: Duration::new(600, 0),
+                expire: Duration::new(86400, 0),
+                minimum: Duration::new(60, 0),
+            }),
+        )
+    }
+
+    fn answers(response: &Message) -> Vec<Resource> {
+        response
+            .answers
+            .iter()
+            .map(|r| r.resource.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let network = Network::new();
+        let client = iterative(&network);
+
+        let mut q = query("www.example.com", Type::A);
+        q.id = 1234;
+        let response = client.exchange(&q).unwrap();
+
+        assert_eq!(response.id, 1234);
+        assert_eq!(response.rd, true);
+        assert_eq!(response.ra, true);
+        assert_eq!(response.rcode, Rcode::NoError);
+        assert_eq!(
+            answers(&response),
+            vec![Resource::A("192.0.2.1".parse().unwrap())]
+        );
+        assert_eq!(
+            network.take(),
+            vec![ip("10.0.0.1"), ip("10.0.1.1"), ip("10.0.3.1")]
+        );
+    }
+
+    #[test]
+    fn test_delegation_cache() {
+        let network = Network::new();
+        let client = iterative(&network);
+
+        client.exchange(&query("www.example.com", Type::A)).unwrap();
+        network.take();
+
+        // The zone's servers are already known.
+        let response = client
+            .exchange(&query("mail.example.com", Type::A))
+            .unwrap();
+        assert_eq!(
+            answers(&response),
+            vec![Resource::A("192.0.2.10".parse().unwrap())]
+        );
+        assert_eq!(network.take(), vec![ip("10.0.3.1")]);
+
+        // As are the servers for the parent zone.
+        let response = client.exchange(&query("other.com", Type::A)).unwrap();
+        assert_eq!(response.rcode, Rcode::NXDomain);
+        assert_eq!(network.take(), vec![ip("10.0.1.1")]);
+
+        client.clear();
+        client
+            .exchange(&query("mail.example.com", Type::A))
+            .unwrap();
+        assert_eq!(
+            network.take(),
+            vec![ip("10.0.0.1"), ip("10.0.1.1"), ip("10.0.3.1")]
+        );
+    }
+
+    #[test]
+    fn test_glueless() {
+        let network = Network::new();
+        let client = iterative(&network);
+
+        let response = client
+            .exchange(&query("www.glueless.com", Type::A))
+            .unwrap();
+        assert_eq!(
+            answers(&response),
+            vec![Resource::A("192.0.2.2".parse().unwrap())]
+        );
+
+        // The out of bailiwick glue (10.9.9.9) is never used, instead the
+        // name server's address is resolved from the root.
+        assert_eq!(
+            network.take(),
+            vec![
+                ip("10.0.0.1"),
+                ip("10.0.1.1"),
+                ip("10.0.0.1"),
+                ip("10.0.2.1"),
+                ip("10.0.4.1"),
+                ip("10.0.5.1"),
+            ]
+        );
+
+        // The resolved address is remembered.
+        client
+            .exchange(&query("www.glueless.com", Type::A))
+            .unwrap();
+        assert_eq!(network.take(), vec![ip("10.0.5.1")]);
+    }
+
+    #[test]
+    fn test_lame_delegation() {
+        let network = Network::new();
+        let client = iterative(&network);
+
+        let response = client.exchange(&query("www.lame.com", Type::A)).unwrap();
+        assert_eq!(
+            answers(&response),
+            vec![Resource::A("192.0.2.3".parse().unwrap())]
+        );
+        assert_eq!(
+            network.take(),
+            vec![
+                ip("10.0.0.1"),
+                ip("10.0.1.1"),
+                ip("10.0.6.1"),
+                ip("10.0.6.2")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nxdomain_and_nodata() {
+        let network = Network::new();
+        let client = iterative(&network);
+
+        let response = client
+            .exchange(&query("missing.example.com", Type::A))
+            .unwrap();
+        assert_eq!(response.rcode, Rcode::NXDomain);
+        assert_eq!(response.answers, vec![]);
+        assert_eq!(response.authoritys, vec![soa("example.com.")]);
+
+        let response = client
+            .exchange(&query("www.example.com", Type::AAAA))
+            .unwrap();
+        assert_eq!(response.rcode, Rcode::NoError);
+        assert_eq!(response.answers, vec![]);
+        assert_eq!(response.authoritys, vec![soa("example.com.")]);
+
+        // Names directly under the root.
+        let response = client.exchange(&query("missing", Type::A)).unwrap();
+        assert_eq!(response.rcode, Rcode::NXDomain);
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let network = Network::new();
+        let hints = ". NS a.root. \n a.root. A 10.255.0.1";
+        let client = Iterative::from_hints(hints)
+            .unwrap()
+            .with_transport(move |addr, query| network.exchange(addr, query));
+
+        match client.exchange(&query("www.example.com", Type::A)) {
+            Err(rustdns::Error::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            result => panic!("expected a timeout, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_hints() {
+        assert!(Iterative::from_hints("").is_err());
+        assert!(Iterative::from_hints("; only a comment").is_err());
+        assert!(
+            Iterative::from_hints(". 3600 IN NS a.root.").is_err(),
+            "no glue"
+        );
+        assert!(Iterative::from_hints(". IN NS a.root.\na.root. IN AAAA ::1").is_ok());
+
+        assert!(Iterative::from_hints_path("/does/not/exist/named.root").is_err());
+
+        let path = std::env::temp_dir().join(format!("rustdns-named-{}.root", std::process::id()));
+        fs::write(&path, HINTS).unwrap();
+
+        let network = Network::new();
+        let client = Iterative::from_hints_path(&path).unwrap().with_transport({
+            let network = network.clone();
+            move |addr, query| network.exchange(addr, query)
+        });
+        client.exchange(&query("www.example.com", Type::A)).unwrap();
+        assert_eq!(network.take()[0], ip("10.0.0.1"));
+        fs::remove_file(&path).unwrap();
+
+        // The built-in hints should be usable.
+        let _ = Iterative::new();
+    }
+
+    #[test]
+    fn test_resolver() {
+        let network = Network::new();
+        let resolver = Resolver::new_with_client(iterative(&network));
+
+        assert_eq!(
+            resolver.lookup("www.example.com").unwrap(),
+            vec![ip("192.0.2.1")]
+        );
+        assert!(resolver.lookup("missing.example.com").is_err());
+    }
+}
@@ -0,0 +1,191 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(feature = "server")]
+#[cfg(feature = "zones")]
+#[cfg(feature = "udp")]
+mod tests {
+    use super::common::{self, request, soa};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::udp;
+    use rustdns::server::{Acl, Authority, Handler, Protocol, Request};
+    use rustdns::types::*;
+    use rustdns::Notify;
+    use std::collections::HashSet;
+    use std::net::UdpSocket;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@           IN  SOA     ns1 admin 5 7200 3600 1209600 300
+@           IN  NS      ns1
+ns1         IN  A       192.0.2.1
+www         IN  A       192.0.2.10
+";
+
+    fn zone() -> Authority {
+        common::authority(ZONE)
+    }
+
+    /// Returns a secondary that counts the NOTIFYs that trigger a refresh.
+    fn secondary() -> (Authority, Arc<AtomicUsize>) {
+        let refreshes = Arc::new(AtomicUsize::new(0));
+        let counter = refreshes.clone();
+        let authority = zone().with_notify(Acl::new(["127.0.0.0/8"]).unwrap(), move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        (authority, refreshes)
+    }
+
+    /// Sends the message (as it would be over the wire) to the authority.
+    fn send(authority: &Authority, message: Message, src: &str) -> Message {
+        let message = Message::from_slice(&message.to_vec().unwrap()).unwrap();
+
+        let request = Request {
+            src: src.parse().unwrap(),
+            ..request(message, Protocol::Udp)
+        };
+        authority.handle(&request).expect("no response")
+    }
+
+    #[test]
+    fn test_build() {
+        let message = Notify::new("example.com").with_soa(soa(6)).build();
+
+        assert_eq!(message.qr, QR::Query);
+        assert_eq!(message.opcode, Opcode::Notify);
+        assert_eq!(message.aa, true);
+        assert_eq!(message.rd, false);
+        assert_eq!(
+            message.questions,
+            vec![Question {
+                name: "example.com.".to_string(),
+                r#type: Type::SOA,
+                class: Class::Internet,
+            }]
+        );
+        assert_eq!(message.answers, vec![soa(6)]);
+
+        // It survives the round trip.
+        let parsed = Message::from_slice(&message.to_vec().unwrap()).unwrap();
+        assert_eq!(parsed.opcode, Opcode::Notify);
+        assert_eq!(parsed.answers, vec![soa(6)]);
+    }
+
+    #[test]
+    fn test_notify() {
+        let (authority, refreshes) = secondary();
+
+        let notify = Notify::new("example.com").build();
+        let resp = send(&authority, notify.clone(), "127.0.0.1:1234");
+        assert_eq!(resp.id, notify.id);
+        assert_eq!(resp.qr, QR::Response);
+        assert_eq!(resp.opcode, Opcode::Notify);
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.aa, true);
+        assert_eq!(resp.questions, notify.questions);
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+
+        // A newer SOA hint triggers a refresh, an older or the same doesn't.
+        for (serial, refreshed) in [(6, true), (5, false), (4, false)] {
+            let before = refreshes.load(Ordering::SeqCst);
+            let notify = Notify::new("example.com").with_soa(soa(serial)).build();
+            let resp = send(&authority, notify, "127.0.0.1:1234");
+            assert_eq!(resp.rcode, Rcode::NoError, "serial {}", serial);
+            assert_eq!(
+                refreshes.load(Ordering::SeqCst),
+                before + refreshed as usize,
+                "serial {}",
+                serial
+            );
+        }
+    }
+
+    #[test]
+    fn test_notify_errors() {
+        let (authority, refreshes) = secondary();
+
+        let notify = Notify::new("example.com").build();
+        let resp = send(&authority, notify, "192.0.2.1:1234");
+        assert_eq!(resp.rcode, Rcode::Refused);
+
+        let notify = Notify::new("example.net").build();
+        let resp = send(&authority, notify, "127.0.0.1:1234");
+        assert_eq!(resp.rcode, Rcode::NotAuth);
+
+        let mut notify = Notify::new("example.com").build();
+        notify.questions[0].r#type = Type::A;
+        let resp = send(&authority, notify, "127.0.0.1:1234");
+        assert_eq!(resp.rcode, Rcode::FormErr);
+
+        assert_eq!(refreshes.load(Ordering::SeqCst), 0);
+
+        // Without a refresh callback NOTIFY isn't implemented.
+        let notify = Notify::new("example.com").build();
+        let resp = send(&zone(), notify, "127.0.0.1:1234");
+        assert_eq!(resp.rcode, Rcode::NotImp);
+    }
+
+    #[test]
+    fn test_server() {
+        let (tx, rx) = mpsc::channel();
+        let tx = std::sync::Mutex::new(tx);
+        let authority = zone().with_notify(Acl::new(["127.0.0.0/8"]).unwrap(), move |request| {
+            tx.lock().unwrap().send(request.message.clone()).unwrap();
+        });
+
+        let server =
+            rustdns::server::udp::Server::bind("127.0.0.1:0", Arc::new(authority)).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        let client = udp::Client::new(addr).unwrap();
+        let resp = client.notify("example.com", Some(&soa(6)), 0).unwrap();
+        assert_eq!(resp.opcode, Opcode::Notify);
+        assert_eq!(resp.rcode, Rcode::NoError);
+
+        let notify = rx.recv_timeout(Duration::new(5, 0)).unwrap();
+        assert_eq!(notify.answers, vec![soa(6)]);
+
+        // Not acknowledged, as the server isn't authoritative.
+        assert!(client.notify("example.net", None, 0).is_err());
+    }
+
+    #[test]
+    fn test_retries() {
+        // A server that ignores each NOTIFY the first time it's sent.
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut seen = HashSet::new();
+            let mut buf = [0; 4096];
+            loop {
+                let (len, src) = socket.recv_from(&mut buf).unwrap();
+                let mut resp = Message::from_slice(&buf[..len]).unwrap();
+                tx.send(resp.id).unwrap();
+                if !seen.insert(resp.id) {
+                    resp.qr = QR::Response;
+                    socket.send_to(&resp.to_vec().unwrap(), src).unwrap();
+                }
+            }
+        });
+
+        let client = udp::Client::new(addr)
+            .unwrap()
+            .with_read_timeout(Some(Duration::from_millis(100)));
+        assert!(client.notify("example.com", None, 0).is_err());
+
+        let resp = client.notify("example.com", None, 1).unwrap();
+        assert_eq!(resp.opcode, Opcode::Notify);
+
+        // The first NOTIFY, and the second sent twice with the same ID.
+        let ids: Vec<u16> = rx.try_iter().collect();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids[1], ids[2]);
+    }
+}
@@ -0,0 +1,138 @@
+#[cfg(test)]
+#[cfg(feature = "odoh")]
+mod tests {
+    use http::header::CONTENT_TYPE;
+    use http::{Method, Request, Response, StatusCode};
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Client as HyperClient, Server};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::odoh::{Client, Target, CONTENT_TYPE_APPLICATION_OBLIVIOUS_DNS_MESSAGE};
+    use rustdns::clients::AsyncExchanger;
+    use rustdns::types::*;
+    use std::collections::HashMap;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    fn error(status: StatusCode) -> Result<Response<Body>, Infallible> {
+        Ok(Response::builder()
+            .status(status)
+            .body(Body::empty())
+            .unwrap())
+    }
+
+    /// A target stand-in, which serves its config, and answers every query
+    /// with an empty response.
+    async fn target(target: Arc<Target>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        match (req.method(), req.uri().path()) {
+            (&Method::GET, "/.well-known/odohconfigs") => {
+                Ok(Response::new(Body::from(target.configs())))
+            }
+
+            (&Method::POST, "/dns-query") => {
+                let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                let (query, context) = match target.decrypt_query(&body) {
+                    Ok(result) => result,
+                    Err(_) => return error(StatusCode::BAD_REQUEST),
+                };
+
+                let mut response = query;
+                response.qr = QR::Response;
+                response.ra = true;
+
+                Ok(Response::builder()
+                    .header(CONTENT_TYPE, CONTENT_TYPE_APPLICATION_OBLIVIOUS_DNS_MESSAGE)
+                    .body(Body::from(context.encrypt_response(&response).unwrap()))
+                    .unwrap())
+            }
+
+            _ => error(StatusCode::NOT_FOUND),
+        }
+    }
+
+    /// A proxy stand-in, which forwards the request body to the target.
+    async fn proxy(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let params: HashMap<String, String> =
+            url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                .into_owned()
+                .collect();
+
+        let url = format!("http://{}{}", params["targethost"], params["targetpath"]);
+
+        let forward = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header(CONTENT_TYPE, CONTENT_TYPE_APPLICATION_OBLIVIOUS_DNS_MESSAGE)
+            .body(req.into_body())
+            .unwrap();
+
+        match HyperClient::new().request(forward).await {
+            Ok(resp) => Ok(resp),
+            Err(_) => error(StatusCode::BAD_GATEWAY),
+        }
+    }
+
+    async fn start_target(t: Arc<Target>) -> SocketAddr {
+        let make_svc = make_service_fn(move |_conn| {
+            let t = t.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| target(t.clone(), req))) }
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+        let addr = server.local_addr();
+
+        tokio::spawn(server);
+        addr
+    }
+
+    async fn start_proxy() -> SocketAddr {
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(proxy)) });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+        let addr = server.local_addr();
+
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_exchange() {
+        let target_addr = start_target(Arc::new(Target::new())).await;
+        let proxy_addr = start_proxy().await;
+
+        let client = Client::new(
+            &format!("http://{}/dns-query", target_addr),
+            &format!("http://{}/proxy", proxy_addr),
+        )
+        .unwrap();
+
+        let mut query = Message::default();
+        query.add_question("bramp.net", Type::A, Class::Internet);
+
+        // Twice, to check the fetched config is reused.
+        for _ in 0..2 {
+            let resp = client.exchange(&query).await.expect("exchange failed");
+
+            assert_eq!(resp.qr, QR::Response);
+            assert_eq!(resp.questions, query.questions);
+            assert_eq!(resp.stats.unwrap().server, proxy_addr);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wrong_key() {
+        let target_addr = start_target(Arc::new(Target::new())).await;
+        let proxy_addr = start_proxy().await;
+
+        // Use a config for a different key than the target's.
+        let client = Client::new(
+            &format!("http://{}/dns-query", target_addr),
+            &format!("http://{}/proxy", proxy_addr),
+        )
+        .unwrap()
+        .with_config(Target::new().config().clone());
+
+        let mut query = Message::default();
+        query.add_question("bramp.net", Type::A, Class::Internet);
+
+        assert!(client.exchange(&query).await.is_err());
+    }
+}
@@ -0,0 +1,193 @@
+#[cfg(test)]
+#[cfg(feature = "tokio")]
+mod tests {
+    use async_trait::async_trait;
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::{AsyncExchanger, Exchanger, Race};
+    use rustdns::types::*;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant, SystemTime};
+
+    /// A mock client, that answers after a delay, or fails.
+    struct MockClient {
+        server: SocketAddr,
+        delay: Duration,
+        rcode: Rcode,
+
+        /// Number of queries started and completed.
+        started: Arc<AtomicUsize>,
+        completed: Arc<AtomicUsize>,
+    }
+
+    impl MockClient {
+        fn new(port: u16, delay_ms: u64, rcode: Rcode) -> MockClient {
+            MockClient {
+                server: SocketAddr::from(([127, 0, 0, 1], port)),
+                delay: Duration::from_millis(delay_ms),
+                rcode,
+                started: Arc::new(AtomicUsize::new(0)),
+                completed: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn response(&self, query: &Message) -> Message {
+            self.completed.fetch_add(1, Ordering::SeqCst);
+
+            let mut resp = query.clone();
+            resp.qr = QR::Response;
+            resp.rcode = self.rcode;
+            resp.stats = Some(Stats {
+                start: SystemTime::now(),
+                duration: self.delay,
+                server: self.server,
+                request_size: 0,
+                response_size: 0,
+            });
+            resp
+        }
+    }
+
+    impl Exchanger for MockClient {
+        fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+            self.started.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(self.delay);
+            Ok(self.response(query))
+        }
+    }
+
+    #[async_trait]
+    impl AsyncExchanger for MockClient {
+        async fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+            self.started.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            Ok(self.response(query))
+        }
+    }
+
+    fn query() -> Message {
+        let mut query = Message::default();
+        query.add_question("bramp.net", Type::A, Class::Internet);
+        query
+    }
+
+    fn winner(resp: &Message) -> u16 {
+        resp.stats.as_ref().unwrap().server.port()
+    }
+
+    #[test]
+    fn test_first_wins() {
+        let second = MockClient::new(2, 10, Rcode::NoError);
+        let started = second.started.clone();
+
+        let race = Race::new(vec![MockClient::new(1, 10, Rcode::NoError), second])
+            .with_delay(Duration::from_millis(200));
+
+        let resp = Exchanger::exchange(&race, &query()).expect("exchange failed");
+        assert_eq!(winner(&resp), 1);
+
+        // The second query was never needed.
+        assert_eq!(started.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_staggered() {
+        let race = Race::new(vec![
+            MockClient::new(1, 1000, Rcode::NoError),
+            MockClient::new(2, 10, Rcode::NoError),
+        ])
+        .with_delay(Duration::from_millis(50));
+
+        let start = Instant::now();
+        let resp = Exchanger::exchange(&race, &query()).expect("exchange failed");
+
+        assert_eq!(winner(&resp), 2);
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_failure_starts_next() {
+        let race = Race::new(vec![
+            MockClient::new(1, 0, Rcode::ServFail),
+            MockClient::new(2, 0, Rcode::NXDomain),
+        ])
+        .with_delay(Duration::from_secs(10));
+
+        let start = Instant::now();
+        let resp = Exchanger::exchange(&race, &query()).expect("exchange failed");
+
+        // NXDOMAIN is a valid answer.
+        assert_eq!(winner(&resp), 2);
+        assert_eq!(resp.rcode, Rcode::NXDomain);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_all_fail() {
+        let race = Race::new(vec![
+            MockClient::new(1, 0, Rcode::ServFail),
+            MockClient::new(2, 0, Rcode::Refused),
+        ]);
+
+        let resp = Exchanger::exchange(&race, &query()).expect("exchange failed");
+        assert_eq!(resp.rcode, Rcode::Refused);
+    }
+
+    #[test]
+    fn test_empty() {
+        let race: Race<MockClient> = Race::new(Vec::new());
+        assert!(Exchanger::exchange(&race, &query()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_async_staggered() {
+        let first = MockClient::new(1, 300, Rcode::NoError);
+        let (started, completed) = (first.started.clone(), first.completed.clone());
+
+        let race = Race::new(vec![first, MockClient::new(2, 10, Rcode::NoError)])
+            .with_delay(Duration::from_millis(50));
+
+        let resp = AsyncExchanger::exchange(&race, &query())
+            .await
+            .expect("exchange failed");
+        assert_eq!(winner(&resp), 2);
+
+        // The slow query was started, but then cancelled.
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+        assert_eq!(completed.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_async_failure_starts_next() {
+        let race = Race::new(vec![
+            MockClient::new(1, 0, Rcode::ServFail),
+            MockClient::new(2, 0, Rcode::NoError),
+        ])
+        .with_delay(Duration::from_secs(10));
+
+        let start = Instant::now();
+        let resp = AsyncExchanger::exchange(&race, &query())
+            .await
+            .expect("exchange failed");
+
+        assert_eq!(winner(&resp), 2);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_async_boxed() {
+        let clients: Vec<Box<dyn AsyncExchanger + Send + Sync>> = vec![
+            Box::new(MockClient::new(1, 0, Rcode::Refused)),
+            Box::new(MockClient::new(2, 0, Rcode::NoError)),
+        ];
+
+        let resp = Race::new(clients)
+            .exchange(&query())
+            .await
+            .expect("exchange failed");
+        assert_eq!(winner(&resp), 2);
+    }
+}
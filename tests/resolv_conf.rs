@@ -0,0 +1,102 @@
+#[cfg(test)]
+#[cfg(feature = "udp")]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::ResolvConf;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    fn load(name: &str) -> ResolvConf {
+        let path = format!("{}/tests/resolv_conf/{}", env!("CARGO_MANIFEST_DIR"), name);
+        ResolvConf::from_path(path).expect("failed to read fixture")
+    }
+
+    fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+        addrs.iter().map(|a| a.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_simple() {
+        assert_eq!(
+            load("simple.conf"),
+            ResolvConf {
+                nameservers: addrs(&["192.168.1.1:53", "[2001:db8::1]:53"]),
+                search: vec!["example.com".to_string()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_kubernetes() {
+        assert_eq!(
+            load("kubernetes.conf"),
+            ResolvConf {
+                nameservers: addrs(&["10.96.0.10:53"]),
+                search: vec![
+                    "default.svc.cluster.local".to_string(),
+                    "svc.cluster.local".to_string(),
+                    "cluster.local".to_string(),
+                ],
+                ndots: 5,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(
+            load("options.conf"),
+            ResolvConf {
+                // Invalid lines are skipped, and only the first three are used.
+                nameservers: addrs(&["10.0.0.1:53", "[fe80::1]:53", "10.0.0.2:53"]),
+                search: vec!["example.com".to_string()],
+                ndots: 15,
+                timeout: Duration::from_secs(1),
+                attempts: 5,
+                rotate: true,
+                edns0: true,
+                use_vc: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_last_search_wins() {
+        let conf = ResolvConf::parse("search a.com b.com\ndomain c.com\n");
+        assert_eq!(conf.search, vec!["c.com".to_string()]);
+
+        let conf = ResolvConf::parse("domain c.com\nsearch a.com b.com\n");
+        assert_eq!(conf.search, vec!["a.com".to_string(), "b.com".to_string()]);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(ResolvConf::parse(""), ResolvConf::default());
+    }
+
+    #[test]
+    fn test_missing() {
+        assert!(ResolvConf::from_path("/does/not/exist/resolv.conf").is_err());
+    }
+
+    #[test]
+    fn test_build() {
+        let resolver = load("kubernetes.conf").build().expect("failed to build");
+
+        assert_eq!(resolver.conf().ndots, 5);
+        assert!(
+            resolver.conf().edns0,
+            "EDNS(0) is on without 'options edns0'"
+        );
+        assert_eq!(
+            resolver
+                .server_stats()
+                .iter()
+                .map(|s| s.server)
+                .collect::<Vec<_>>(),
+            addrs(&["10.96.0.10:53"])
+        );
+    }
+}
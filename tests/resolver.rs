@@ -1,41 +1,632 @@
+mod common;
+
 #[cfg(test)]
 #[cfg(feature = "udp")]
 mod tests {
+    use super::common::{a, cname, record};
     use pretty_assertions::assert_eq;
+    use rustdns::clients::AddressFamily;
     use rustdns::clients::Exchanger;
+    use rustdns::clients::Hosts;
+    use rustdns::clients::ResolvConf;
     use rustdns::clients::Resolver;
     use rustdns::types::*;
+    use rustdns::LookupError;
     use rustdns::Message;
     use rustdns::Record;
     use rustdns::Resource;
+    use rustdns::{MX, SOA, SRV, TXT};
+    use std::io;
     use std::net::IpAddr;
+    use std::sync::Mutex;
     use std::time::Duration;
 
-    struct MockClient {}
+    /// A mock client that answers from a fixed set of records, and records
+    /// every name it was asked about.
+    struct MockClient {
+        records: Vec<Record>,
+        queried: Mutex<Vec<String>>,
+    }
+
+    impl MockClient {
+        fn new(records: &[(&str, &str)]) -> MockClient {
+            MockClient {
+                records: records
+                    .iter()
+                    .map(|(name, ip)| Record {
+                        name: name.to_string(),
+                        class: Class::Internet,
+                        ttl: Duration::new(10, 0),
+                        resource: match ip.parse().unwrap() {
+                            IpAddr::V4(ip) => Resource::A(ip),
+                            IpAddr::V6(ip) => Resource::AAAA(ip),
+                        },
+                    })
+                    .collect(),
+                queried: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn from_records(records: Vec<Record>) -> MockClient {
+            MockClient {
+                records,
+                queried: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn queried(&self) -> Vec<String> {
+            self.queried.lock().unwrap().clone()
+        }
+    }
+
+    impl Exchanger for &MockClient {
+        fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+            let question = &query.questions[0];
+            self.queried.lock().unwrap().push(question.name.clone());
+
+            let mut resp = query.clone();
+            resp.qr = QR::Response;
+
+            // Like a authoritative server, only the first alias is returned,
+            // without following it.
+            let mut exists = false;
+            for record in &self.records {
+                let is_ancestor = question.name.ends_with(&format!(".{}", record.name));
+                match record.resource {
+                    Resource::DNAME(_) if is_ancestor => {
+                        resp.answers = vec![record.clone()];
+                        exists = true;
+                        break;
+                    }
+                    Resource::CNAME(_) if record.name == question.name => {
+                        resp.answers = vec![record.clone()];
+                        exists = true;
+                        break;
+                    }
+                    _ if record.name == question.name => {
+                        exists = true;
+                        if record.r#type() == question.r#type {
+                            resp.answers.push(record.clone());
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            if !exists {
+                resp.rcode = Rcode::NXDomain;
+            }
+
+            Ok(resp)
+        }
+    }
+
+    fn conf(search: &[&str], ndots: usize) -> ResolvConf {
+        ResolvConf {
+            search: search.iter().map(|s| s.to_string()).collect(),
+            ndots,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let client = MockClient::new(&[("web.svc.cluster.local.", "10.0.0.1")]);
+        let resolver = Resolver::new_with_client(&client)
+            .with_conf(conf(&["default.svc.cluster.local", "svc.cluster.local"], 5));
+
+        let answer = resolver.query("web", Type::A).expect("query failed");
+        assert_eq!(answer.name, "web.svc.cluster.local.");
+        assert_eq!(answer.response.rcode, Rcode::NoError);
+        assert_eq!(
+            client.queried(),
+            vec!["web.default.svc.cluster.local.", "web.svc.cluster.local."]
+        );
+    }
+
+    #[test]
+    fn test_search_ndots() {
+        let client = MockClient::new(&[("a.bramp.net.", "127.0.0.1")]);
+        let resolver = Resolver::new_with_client(&client).with_conf(conf(&["example.com"], 1));
+
+        // Enough dots, so the name is tried as is first.
+        let answer = resolver
+            .query("a.bramp.net", Type::A)
+            .expect("query failed");
+        assert_eq!(answer.name, "a.bramp.net.");
+        assert_eq!(client.queried(), vec!["a.bramp.net."]);
+    }
+
+    #[test]
+    fn test_search_as_is_last() {
+        let client = MockClient::new(&[("a.bramp.net.", "127.0.0.1")]);
+        let resolver = Resolver::new_with_client(&client).with_conf(conf(&["example.com"], 5));
+
+        let answer = resolver
+            .query("a.bramp.net", Type::A)
+            .expect("query failed");
+        assert_eq!(answer.name, "a.bramp.net.");
+        assert_eq!(
+            client.queried(),
+            vec!["a.bramp.net.example.com.", "a.bramp.net."]
+        );
+    }
+
+    #[test]
+    fn test_search_absolute() {
+        let client = MockClient::new(&[("web.example.com.", "127.0.0.1")]);
+        let resolver = Resolver::new_with_client(&client).with_conf(conf(&["example.com"], 5));
+
+        let answer = resolver.query("web.", Type::A).expect("query failed");
+        assert_eq!(answer.name, "web.");
+        assert_eq!(answer.response.rcode, Rcode::NXDomain);
+        assert_eq!(client.queried(), vec!["web."]);
+    }
+
+    #[test]
+    fn test_search_not_found() {
+        let client = MockClient::new(&[]);
+        let resolver = Resolver::new_with_client(&client).with_conf(conf(&["a.com", "b.com"], 1));
+
+        // The NXDOMAIN for the name as given is returned.
+        let answer = resolver.query("web", Type::A).expect("query failed");
+        assert_eq!(answer.name, "web.");
+        assert_eq!(answer.response.rcode, Rcode::NXDomain);
+        assert_eq!(client.queried(), vec!["web.a.com.", "web.b.com.", "web."]);
+    }
+
+    #[test]
+    fn test_search_nodata_stops() {
+        // The name exists in the first domain, but without a A record.
+        let client = MockClient::new(&[("web.a.com.", "::1"), ("web.b.com.", "127.0.0.1")]);
+        let resolver = Resolver::new_with_client(&client).with_conf(conf(&["a.com", "b.com"], 1));
+
+        let answer = resolver.query("web", Type::A).expect("query failed");
+        assert_eq!(answer.name, "web.a.com.");
+        assert!(answer.response.answers.is_empty());
+    }
+
+    #[test]
+    fn test_lookup_search() {
+        let client = MockClient::new(&[("web.b.com.", "127.0.0.1"), ("web.b.com.", "::1")]);
+        let resolver = Resolver::new_with_client(&client).with_conf(conf(&["a.com", "b.com"], 1));
+
+        let mut got = resolver.lookup("web").expect("lookup failed");
+        got.sort();
+
+        let want: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+        assert_eq!(got, want);
+
+        // The A and AAAA queries both apply the search list, in parallel.
+        let mut queried = client.queried();
+        queried.sort();
+        assert_eq!(
+            queried,
+            vec!["web.a.com.", "web.a.com.", "web.b.com.", "web.b.com."]
+        );
+    }
+
+    #[test]
+    fn test_lookup_order() {
+        let client = MockClient::new(&[("web.", "::1"), ("web.", "127.0.0.1")]);
+        let resolver = Resolver::new_with_client(&client);
+
+        let got = resolver.lookup("web").expect("lookup failed");
+        let want: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_lookup_address_family() {
+        let client = MockClient::new(&[("web.", "127.0.0.1"), ("web.", "::1")]);
+
+        let resolver = Resolver::new_with_client(&client).with_address_family(AddressFamily::Ipv4);
+        let got = resolver.lookup("web").expect("lookup failed");
+        assert_eq!(got, vec!["127.0.0.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(client.queried(), vec!["web."]);
+
+        let resolver = Resolver::new_with_client(&client).with_address_family(AddressFamily::Ipv6);
+        let got = resolver.lookup("web").expect("lookup failed");
+        assert_eq!(got, vec!["::1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(client.queried(), vec!["web.", "web."]);
+
+        // The hosts file is also filtered.
+        let resolver = Resolver::new_with_client(&client)
+            .with_hosts(hosts())
+            .with_address_family(AddressFamily::Ipv6);
+        let got = resolver.lookup("localhost").expect("lookup failed");
+        assert_eq!(got, vec!["::1".parse::<IpAddr>().unwrap()]);
+    }
 
-    impl Exchanger for MockClient {
-        /// Sends the query [`Message`] to the `server` via UDP and returns the result.
-        fn exchange(&self, _query: &Message) -> Result<Message, rustdns::Error> {
-            //let mut records = HashMap::new();
+    /// Fails every AAAA query, and answers A queries with 127.0.0.1.
+    struct NoAaaaClient;
 
-            // TODO FINISH!
-            let _a = Record {
-                name: "a.bramp.net".to_string(),
+    impl Exchanger for NoAaaaClient {
+        fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+            let question = &query.questions[0];
+            if question.r#type == Type::AAAA {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out").into());
+            }
+
+            let mut resp = query.clone();
+            resp.qr = QR::Response;
+            resp.answers.push(Record {
+                name: question.name.clone(),
                 class: Class::Internet,
                 ttl: Duration::new(10, 0),
                 resource: Resource::A("127.0.0.1".parse().unwrap()),
-            };
+            });
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_lookup_partial() {
+        let resolver = Resolver::new_with_client(NoAaaaClient);
+
+        // The AAAA query fails, but the A query's results are returned.
+        let got = resolver.lookup("web").expect("lookup failed");
+        assert_eq!(got, vec!["127.0.0.1".parse::<IpAddr>().unwrap()]);
 
-            /*
-                    records.insert("aaaa.bramp.net", Resource::A("127.0.0.1".parse()));
-                    records.insert("aaaaa.bramp.net", Resource::A("127.0.0.1".parse()));
-                    records.insert("aaaaa.bramp.net", Resource::AAAA("::1".parse()));
-            */
+        // Unless that's the only query.
+        let resolver =
+            Resolver::new_with_client(NoAaaaClient).with_address_family(AddressFamily::Ipv6);
+        assert!(resolver.lookup("web").is_err());
+    }
+
+    #[test]
+    fn test_lookup_not_found() {
+        let client = MockClient::new(&[]);
+        let resolver = Resolver::new_with_client(&client);
+
+        assert!(resolver.lookup("web").is_err());
+    }
+
+    fn records() -> Vec<Record> {
+        vec![
+            record(
+                "example.com.",
+                Resource::MX(MX {
+                    preference: 20,
+                    exchange: "mx2.example.com.".to_string(),
+                }),
+            ),
+            record(
+                "example.com.",
+                Resource::MX(MX {
+                    preference: 10,
+                    exchange: "mx1.example.com.".to_string(),
+                }),
+            ),
+            record(
+                "example.com.",
+                Resource::TXT(TXT(vec![b"v=spf1 -all".to_vec()])),
+            ),
+            record("example.com.", Resource::NS("ns1.example.com.".to_string())),
+            record(
+                "example.com.",
+                Resource::SOA(SOA {
+                    mname: "ns1.example.com.".to_string(),
+                    rname: "admin@example.com".to_string(),
+                    serial: 2021010101,
+                    refresh: Duration::from_secs(3600),
+                    retry: Duration::from_secs(600),
+                    expire: Duration::from_secs(86400),
+                    minimum: Duration::from_secs(300),
+                }),
+            ),
+            record(
+                "_sip._tcp.example.com.",
+                Resource::SRV(SRV {
+                    priority: 10,
+                    weight: 5,
+                    port: 5060,
+                    name: "sip.example.com.".to_string(),
+                }),
+            ),
+            record(
+                "1.2.0.192.in-addr.arpa.",
+                Resource::PTR("www.example.com.".to_string()),
+            ),
+        ]
+    }
 
-            panic!()
+    #[test]
+    fn test_lookup_records() {
+        let client = MockClient::from_records(records());
+        let resolver = Resolver::new_with_client(&client);
+
+        let mxs = resolver.lookup_mx("example.com").expect("lookup failed");
+        assert_eq!(
+            mxs.iter()
+                .map(|mx| mx.exchange.as_str())
+                .collect::<Vec<_>>(),
+            vec!["mx1.example.com.", "mx2.example.com."],
+            "sorted by preference"
+        );
+
+        let txts = resolver.lookup_txt("example.com").expect("lookup failed");
+        assert_eq!(txts, vec![TXT(vec![b"v=spf1 -all".to_vec()])]);
+
+        let nss = resolver.lookup_ns("example.com").expect("lookup failed");
+        assert_eq!(nss, vec!["ns1.example.com."]);
+
+        let soa = resolver.lookup_soa("example.com").expect("lookup failed");
+        assert_eq!(soa.serial, 2021010101);
+
+        let srvs = resolver
+            .lookup_srv("_sip._tcp.example.com")
+            .expect("lookup failed");
+        assert_eq!(srvs[0].port, 5060);
+
+        let names = resolver
+            .reverse_lookup("192.0.2.1".parse().unwrap())
+            .expect("lookup failed");
+        assert_eq!(names, vec!["www.example.com."]);
+
+        let records = resolver
+            .lookup_records("example.com", Type::NS)
+            .expect("lookup failed");
+        assert_eq!(
+            records,
+            vec![record(
+                "example.com.",
+                Resource::NS("ns1.example.com.".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_lookup_errors() {
+        let client = MockClient::from_records(records());
+        let resolver = Resolver::new_with_client(&client);
+
+        match resolver.lookup_mx("missing.example.com") {
+            Err(LookupError::NxDomain(name)) => assert_eq!(name, "missing.example.com."),
+            result => panic!("expected NXDOMAIN, got {:?}", result),
+        }
+
+        match resolver.lookup_srv("example.com") {
+            Err(LookupError::NoData(name, r#type)) => {
+                assert_eq!(name, "example.com.");
+                assert_eq!(r#type, Type::SRV);
+            }
+            result => panic!("expected NODATA, got {:?}", result),
+        }
+
+        let resolver = Resolver::new_with_client(ServFailClient);
+        match resolver.lookup_txt("example.com") {
+            Err(LookupError::ServerFailure(Rcode::ServFail)) => (),
+            result => panic!("expected SERVFAIL, got {:?}", result),
+        }
+
+        let resolver = Resolver::new_with_client(NoAaaaClient);
+        match resolver.lookup_records("example.com", Type::AAAA) {
+            Err(LookupError::Error(_)) => (),
+            result => panic!("expected a error, got {:?}", result),
         }
     }
 
+    #[test]
+    fn test_cname() {
+        let client = MockClient::from_records(vec![
+            cname("www.example.com.", "web.example.com."),
+            cname("web.example.com.", "web.example.net."),
+            a("web.example.net.", "192.0.2.1"),
+        ]);
+        let resolver = Resolver::new_with_client(&client);
+
+        let answer = resolver
+            .query("www.example.com", Type::A)
+            .expect("query failed");
+        assert_eq!(answer.name, "www.example.com.");
+        assert_eq!(answer.aliases, vec!["web.example.com.", "web.example.net."]);
+        assert_eq!(answer.canonical_name(), "web.example.net.");
+        assert_eq!(
+            answer.response.answers,
+            vec![
+                cname("www.example.com.", "web.example.com."),
+                cname("web.example.com.", "web.example.net."),
+                a("web.example.net.", "192.0.2.1"),
+            ]
+        );
+        assert_eq!(answer.response.questions[0].name, "www.example.com.");
+        assert_eq!(
+            client.queried(),
+            vec!["www.example.com.", "web.example.com.", "web.example.net."]
+        );
+
+        let got = resolver.lookup("www.example.com").expect("lookup failed");
+        assert_eq!(got, vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+
+        // Asking for the CNAME itself doesn't follow it.
+        let answer = resolver
+            .query("www.example.com", Type::CNAME)
+            .expect("query failed");
+        assert!(answer.aliases.is_empty());
+        assert_eq!(
+            answer.response.answers,
+            vec![cname("www.example.com.", "web.example.com.")]
+        );
+    }
+
+    /// Returns the same answers to every query, like a recursive server that
+    /// has already followed the aliases.
+    struct FixedClient(Vec<Record>, Mutex<usize>);
+
+    impl Exchanger for &FixedClient {
+        fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+            *self.1.lock().unwrap() += 1;
+
+            let mut resp = query.clone();
+            resp.qr = QR::Response;
+            resp.answers = self.0.clone();
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_cname_in_response() {
+        let client = FixedClient(
+            vec![
+                cname("www.example.com.", "web.example.com."),
+                a("web.example.com.", "192.0.2.1"),
+            ],
+            Mutex::new(0),
+        );
+        let resolver = Resolver::new_with_client(&client);
+
+        let answer = resolver
+            .query("www.example.com", Type::A)
+            .expect("query failed");
+        assert_eq!(answer.aliases, vec!["web.example.com."]);
+        assert_eq!(answer.response.answers.len(), 2);
+        assert_eq!(
+            *client.1.lock().unwrap(),
+            1,
+            "the chain was already followed"
+        );
+    }
+
+    #[test]
+    fn test_cname_loop() {
+        let client = MockClient::from_records(vec![
+            cname("cname-loop1.bramp.net.", "cname-loop2.bramp.net."),
+            cname("cname-loop2.bramp.net.", "cname-loop1.bramp.net."),
+        ]);
+        let resolver = Resolver::new_with_client(&client);
+
+        let err = resolver
+            .query("cname-loop1.bramp.net", Type::A)
+            .expect_err("query should fail");
+        assert!(err.to_string().contains("loop"), "{}", err);
+        assert_eq!(client.queried().len(), 2);
+    }
+
+    #[test]
+    fn test_cname_max_aliases() {
+        let client = MockClient::from_records(vec![
+            cname("a.example.com.", "b.example.com."),
+            cname("b.example.com.", "c.example.com."),
+            cname("c.example.com.", "d.example.com."),
+            a("d.example.com.", "192.0.2.1"),
+        ]);
+
+        let resolver = Resolver::new_with_client(&client).with_max_aliases(3);
+        assert!(resolver.query("a.example.com", Type::A).is_ok());
+
+        let resolver = Resolver::new_with_client(&client).with_max_aliases(2);
+        assert!(resolver.query("a.example.com", Type::A).is_err());
+    }
+
+    #[test]
+    fn test_cname_nxdomain() {
+        let client = MockClient::from_records(vec![cname("web.a.com.", "missing.example.com.")]);
+        let resolver = Resolver::new_with_client(&client).with_conf(conf(&["a.com", "b.com"], 1));
+
+        // The alias exists, so the search stops, even though its target doesn't.
+        let answer = resolver.query("web", Type::A).expect("query failed");
+        assert_eq!(answer.name, "web.a.com.");
+        assert_eq!(answer.canonical_name(), "missing.example.com.");
+        assert_eq!(answer.response.rcode, Rcode::NXDomain);
+
+        match resolver.lookup_records("web", Type::A) {
+            Err(LookupError::NxDomain(name)) => assert_eq!(name, "missing.example.com."),
+            result => panic!("expected NXDOMAIN, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_dname() {
+        let client = MockClient::from_records(vec![
+            record("example.com.", Resource::DNAME("example.net.".to_string())),
+            a("www.example.net.", "192.0.2.1"),
+        ]);
+        let resolver = Resolver::new_with_client(&client);
+
+        let answer = resolver
+            .query("www.example.com", Type::A)
+            .expect("query failed");
+        assert_eq!(answer.aliases, vec!["www.example.net."]);
+        assert_eq!(
+            answer.response.answers,
+            vec![
+                record("example.com.", Resource::DNAME("example.net.".to_string())),
+                cname("www.example.com.", "www.example.net."),
+                a("www.example.net.", "192.0.2.1"),
+            ]
+        );
+    }
+
+    /// Responds to every query with SERVFAIL.
+    struct ServFailClient;
+
+    impl Exchanger for ServFailClient {
+        fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+            let mut resp = query.clone();
+            resp.qr = QR::Response;
+            resp.rcode = Rcode::ServFail;
+            Ok(resp)
+        }
+    }
+
+    fn hosts() -> Hosts {
+        Hosts::from_path(format!("{}/tests/hosts/hosts", env!("CARGO_MANIFEST_DIR")))
+    }
+
+    #[test]
+    fn test_hosts() {
+        let client = MockClient::new(&[("web.example.com.", "10.0.0.1")]);
+        let resolver = Resolver::new_with_client(&client).with_hosts(hosts());
+
+        let mut got = resolver.lookup("localhost").expect("lookup failed");
+        got.sort();
+        let want: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+        assert_eq!(got, want);
+
+        let answer = resolver.query("server", Type::A).expect("query failed");
+        assert_eq!(answer.name, "server.");
+        assert_eq!(
+            answer.response.answers[0].resource,
+            Resource::A("192.168.1.10".parse().unwrap())
+        );
+
+        // A name in the file, but without a AAAA record.
+        let answer = resolver.query("server", Type::AAAA).expect("query failed");
+        assert_eq!(answer.response.rcode, Rcode::NoError);
+        assert!(answer.response.answers.is_empty());
+
+        // The hosts file is used without querying the client.
+        assert!(client.queried().is_empty());
+
+        // Other names go to the client.
+        let got = resolver.lookup("web.example.com").expect("lookup failed");
+        assert_eq!(got, vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn test_hosts_ptr() {
+        let client = MockClient::new(&[]);
+        let resolver = Resolver::new_with_client(&client).with_hosts(hosts());
+
+        let answer = resolver
+            .query("11.1.168.192.in-addr.arpa", Type::PTR)
+            .expect("query failed");
+        assert_eq!(
+            answer
+                .response
+                .answers
+                .iter()
+                .map(|r| r.resource.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Resource::PTR("printer.example.com.".to_string()),
+                Resource::PTR("printer.".to_string()),
+            ]
+        );
+        assert!(client.queried().is_empty());
+    }
+
     // This test may be flakly, if it is running in an environment that doesn't
     // have both IPv4 and IPv6, and has DNS queries that can fail.
     // TODO Mock out the client.
@@ -82,4 +673,4 @@ mod tests {
             assert_eq!(got, want, "when resolving {}", test.name);
         }
     }
-}
\ No newline at end of file
+}
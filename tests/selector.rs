@@ -0,0 +1,186 @@
+#[cfg(test)]
+#[cfg(feature = "udp")]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::Exchanger;
+    use rustdns::clients::Selector;
+    use rustdns::types::*;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    /// A mock client, that answers after a fixed delay, or fails.
+    struct MockClient {
+        server: SocketAddr,
+        rtt: Duration,
+        fail: bool,
+        ra: bool,
+        count: Arc<AtomicUsize>,
+    }
+
+    impl Exchanger for MockClient {
+        fn exchange(&self, query: &Message) -> Result<Message, rustdns::Error> {
+            self.count.fetch_add(1, Ordering::SeqCst);
+
+            let mut resp = query.clone();
+            resp.qr = QR::Response;
+            resp.ra = self.ra;
+            if self.fail {
+                resp.rcode = Rcode::ServFail;
+            }
+
+            // Report the RTT via the Stats, instead of actually waiting.
+            resp.stats = Some(Stats {
+                start: SystemTime::now(),
+                duration: self.rtt,
+                server: self.server,
+                request_size: 0,
+                response_size: 0,
+            });
+
+            Ok(resp)
+        }
+    }
+
+    fn server(n: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 5300 + n))
+    }
+
+    /// Returns a selector across servers with the given (rtt in ms, fail, ra),
+    /// and the count of queries each server received.
+    fn selector(servers: &[(u64, bool, bool)]) -> (Selector<MockClient>, Vec<Arc<AtomicUsize>>) {
+        let mut clients = Vec::new();
+        let mut counts = Vec::new();
+
+        for (i, (rtt, fail, ra)) in servers.iter().enumerate() {
+            let count = Arc::new(AtomicUsize::new(0));
+            let client = MockClient {
+                server: server(i as u16),
+                rtt: Duration::from_millis(*rtt),
+                fail: *fail,
+                ra: *ra,
+                count: count.clone(),
+            };
+
+            clients.push((server(i as u16), client));
+            counts.push(count);
+        }
+
+        (Selector::new_with_clients(clients), counts)
+    }
+
+    fn query() -> Message {
+        let mut query = Message::default();
+        query.add_question("bramp.net", Type::A, Class::Internet);
+        query
+    }
+
+    fn counts(counts: &[Arc<AtomicUsize>]) -> Vec<usize> {
+        counts.iter().map(|c| c.load(Ordering::SeqCst)).collect()
+    }
+
+    #[test]
+    fn test_prefers_fastest() {
+        let (selector, c) = selector(&[(100, false, true), (10, false, true), (50, false, true)]);
+
+        for _ in 0..10 {
+            selector.exchange(&query()).expect("exchange failed");
+        }
+
+        // Each server is tried once, then the fastest is used.
+        assert_eq!(counts(&c), vec![1, 8, 1]);
+
+        let stats = selector.stats();
+        assert_eq!(stats[1].server, server(1));
+        assert!(stats[1].srtt <= Duration::from_millis(10));
+        assert_eq!(stats[1].last_rtt, Some(Duration::from_millis(10)));
+        assert_eq!(stats[1].queries, 8);
+        assert_eq!(stats[1].recursion_available, Some(true));
+        assert!(
+            stats[0].srtt < Duration::from_millis(100),
+            "srtt was not decayed"
+        );
+    }
+
+    #[test]
+    fn test_decay_retries_slow_server() {
+        let (selector, c) = selector(&[(10, false, true), (12, false, true)]);
+
+        for _ in 0..20 {
+            selector.exchange(&query()).expect("exchange failed");
+        }
+
+        // The slower server decays until it is tried again.
+        assert!(counts(&c)[1] > 1, "slow server was never retried");
+        assert!(counts(&c)[0] > counts(&c)[1]);
+    }
+
+    #[test]
+    fn test_failover() {
+        let (selector, c) = selector(&[(10, true, true), (50, false, true)]);
+
+        for _ in 0..5 {
+            let resp = selector.exchange(&query()).expect("exchange failed");
+            assert_eq!(resp.rcode, Rcode::NoError);
+            assert_eq!(resp.stats.unwrap().server, server(1));
+        }
+
+        // The failing server is skipped once it is unhealthy.
+        assert_eq!(counts(&c), vec![3, 5]);
+
+        let stats = selector.stats();
+        assert_eq!(stats[0].failures, 3);
+        assert_eq!(stats[0].consecutive_failures, 3);
+    }
+
+    #[test]
+    fn test_all_fail() {
+        let (selector, c) = selector(&[(10, true, true), (50, true, true)]);
+
+        let resp = selector.exchange(&query()).expect("exchange failed");
+        assert_eq!(resp.rcode, Rcode::ServFail);
+        assert_eq!(counts(&c), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_probes() {
+        let (selector, c) = selector(&[(10, false, true), (100, false, true)]);
+        let selector = selector.with_probe_interval(Duration::from_millis(50));
+
+        selector.exchange(&query()).expect("exchange failed");
+        selector.exchange(&query()).expect("exchange failed");
+        selector.exchange(&query()).expect("exchange failed");
+        assert_eq!(counts(&c), vec![2, 1]);
+
+        // After the probe interval, the slow server is tried again.
+        std::thread::sleep(Duration::from_millis(60));
+        selector.exchange(&query()).expect("exchange failed");
+        assert_eq!(counts(&c), vec![2, 2]);
+    }
+
+    #[test]
+    fn test_prefers_recursion() {
+        let (selector, c) = selector(&[(10, false, false), (50, false, true)]);
+
+        for _ in 0..5 {
+            selector.exchange(&query()).expect("exchange failed");
+        }
+
+        assert_eq!(counts(&c), vec![1, 4]);
+        assert_eq!(selector.stats()[0].recursion_available, Some(false));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let (selector, c) = selector(&[(100, false, true), (10, false, true), (50, true, true)]);
+        let selector = selector.with_rotate(true);
+
+        for _ in 0..9 {
+            selector.exchange(&query()).expect("exchange failed");
+        }
+
+        // The failing server fails over to the next, until it is skipped.
+        assert_eq!(counts(&c), vec![6, 3, 3]);
+    }
+}
@@ -0,0 +1,628 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(feature = "server")]
+#[cfg(feature = "zones")]
+#[cfg(feature = "udp")]
+#[cfg(feature = "tcp")]
+mod tests {
+    use super::common::{self, a, cname, ns, request};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::Exchanger;
+    use rustdns::clients::Transfer;
+    use rustdns::clients::{tcp, udp};
+    use rustdns::server::{Acl, Authority, Handler, Protocol, Request};
+    use rustdns::types::*;
+    use rustdns::zones::File;
+    use rustdns::zones::Zone;
+    use rustdns::Record;
+    use rustdns::Resource;
+    use rustdns::SOA;
+    use std::net::UdpSocket;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@           IN  SOA     ns1 admin 2021010101 7200 3600 1209600 300
+@           IN  NS      ns1
+@           IN  MX      10 mail
+ns1         IN  A       192.0.2.1
+www         IN  A       192.0.2.2
+www         IN  AAAA    2001:db8::2
+mail        IN  A       192.0.2.3
+alias       IN  CNAME   www
+chain       IN  CNAME   alias
+outside     IN  CNAME   www.example.net.
+missing     IN  CNAME   nothing
+loop1       IN  CNAME   loop2
+loop2       IN  CNAME   loop1
+sub         IN  NS      ns.sub
+sub         IN  NS      ns.example.net.
+ns.sub      IN  A       192.0.2.53
+deep.sub    IN  A       192.0.2.99
+*.wild      IN  A       192.0.2.4
+a.b.wild    IN  A       192.0.2.5
+";
+
+    fn authority() -> Authority {
+        common::authority(ZONE)
+    }
+
+    fn query(authority: &Authority, name: &str, r#type: Type) -> Message {
+        let mut message = Message::default();
+        message.add_question(name, r#type, Class::Internet);
+
+        authority
+            .handle(&request(message, Protocol::Udp))
+            .expect("no response")
+    }
+
+    /// The SOA, as included in negative answers, with the minimum TTL.
+    fn negative_soa() -> Vec<Record> {
+        vec![Record::new(
+            "example.com.",
+            Class::Internet,
+            Duration::new(300, 0),
+            Resource::SOA(SOA {
+                mname: "ns1.example.com".to_string(),
+                rname: "admin@example.com".to_string(),
+                serial: 2021010101,
+                refresh: Duration::new(7200, 0),
+                retry: Duration::new(3600, 0),
+                expire: Duration::new(1209600, 0),
+                minimum: Duration::new(300, 0),
+            }),
+        )]
+    }
+
+    #[test]
+    fn test_answer() {
+        let authority = authority();
+        assert_eq!(authority.origin(), "example.com.");
+
+        let resp = query(&authority, "www.example.com", Type::A);
+        assert_eq!(resp.qr, QR::Response);
+        assert_eq!(resp.aa, true);
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.answers, vec![a("www.example.com.", "192.0.2.2")]);
+        assert_eq!(resp.authoritys, vec![]);
+
+        // Names are case insensitive.
+        let resp = query(&authority, "WWW.Example.COM", Type::A);
+        assert_eq!(resp.answers, vec![a("www.example.com.", "192.0.2.2")]);
+
+        let resp = query(&authority, "www.example.com", Type::ANY);
+        assert_eq!(resp.answers.len(), 2);
+    }
+
+    #[test]
+    fn test_nxdomain() {
+        let resp = query(&authority(), "nothing.example.com", Type::A);
+        assert_eq!(resp.aa, true);
+        assert_eq!(resp.rcode, Rcode::NXDomain);
+        assert_eq!(resp.answers, vec![]);
+        assert_eq!(resp.authoritys, negative_soa());
+    }
+
+    #[test]
+    fn test_nodata() {
+        let resp = query(&authority(), "mail.example.com", Type::AAAA);
+        assert_eq!(resp.aa, true);
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.answers, vec![]);
+        assert_eq!(resp.authoritys, negative_soa());
+    }
+
+    #[test]
+    fn test_cname() {
+        let authority = authority();
+
+        let resp = query(&authority, "chain.example.com", Type::A);
+        assert_eq!(resp.aa, true);
+        assert_eq!(
+            resp.answers,
+            vec![
+                cname("chain.example.com.", "alias.example.com"),
+                cname("alias.example.com.", "www.example.com"),
+                a("www.example.com.", "192.0.2.2"),
+            ]
+        );
+
+        // Unless the CNAME itself is asked for.
+        let resp = query(&authority, "alias.example.com", Type::CNAME);
+        assert_eq!(
+            resp.answers,
+            vec![cname("alias.example.com.", "www.example.com")]
+        );
+
+        // Targets outside the zone are left for the resolver.
+        let resp = query(&authority, "outside.example.com", Type::A);
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(
+            resp.answers,
+            vec![cname("outside.example.com.", "www.example.net")]
+        );
+
+        // The target not existing is a NXDOMAIN, see rfc6604.
+        let resp = query(&authority, "missing.example.com", Type::A);
+        assert_eq!(resp.rcode, Rcode::NXDomain);
+        assert_eq!(
+            resp.answers,
+            vec![cname("missing.example.com.", "nothing.example.com")]
+        );
+        assert_eq!(resp.authoritys, negative_soa());
+
+        let resp = query(&authority, "loop1.example.com", Type::A);
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(
+            resp.answers,
+            vec![
+                cname("loop1.example.com.", "loop2.example.com"),
+                cname("loop2.example.com.", "loop1.example.com"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wildcard() {
+        let authority = authority();
+
+        let resp = query(&authority, "x.y.wild.example.com", Type::A);
+        assert_eq!(resp.aa, true);
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.answers, vec![a("x.y.wild.example.com.", "192.0.2.4")]);
+
+        let resp = query(&authority, "x.wild.example.com", Type::AAAA);
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.answers, vec![]);
+        assert_eq!(resp.authoritys, negative_soa());
+
+        // b.wild.example.com exists, so the wildcard doesn't apply to it, or
+        // the names below it.
+        let resp = query(&authority, "b.wild.example.com", Type::A);
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.answers, vec![]);
+
+        let resp = query(&authority, "x.b.wild.example.com", Type::A);
+        assert_eq!(resp.rcode, Rcode::NXDomain);
+        assert_eq!(resp.authoritys, negative_soa());
+    }
+
+    #[test]
+    fn test_referral() {
+        let authority = authority();
+
+        for name in [
+            "sub.example.com",
+            "deep.sub.example.com",
+            "www.sub.example.com",
+        ] {
+            let resp = query(&authority, name, Type::A);
+            assert_eq!(resp.aa, false, "{}", name);
+            assert_eq!(resp.rcode, Rcode::NoError, "{}", name);
+            assert_eq!(resp.answers, vec![], "{}", name);
+            assert_eq!(
+                resp.authoritys,
+                vec![
+                    ns("sub.example.com.", "ns.sub.example.com"),
+                    ns("sub.example.com.", "ns.example.net"),
+                ],
+                "{}",
+                name
+            );
+            assert_eq!(
+                resp.additionals,
+                vec![a("ns.sub.example.com.", "192.0.2.53")],
+                "{}",
+                name
+            );
+        }
+
+        // The NS records at the apex are not a delegation.
+        let resp = query(&authority, "example.com", Type::NS);
+        assert_eq!(resp.aa, true);
+        assert_eq!(resp.answers, vec![ns("example.com.", "ns1.example.com")]);
+    }
+
+    #[test]
+    fn test_refused() {
+        let authority = authority();
+
+        let resp = query(&authority, "www.example.net", Type::A);
+        assert_eq!(resp.aa, false);
+        assert_eq!(resp.rcode, Rcode::Refused);
+
+        let resp = query(&authority, "com", Type::A);
+        assert_eq!(resp.rcode, Rcode::Refused);
+
+        let message = Message {
+            opcode: Opcode::Status,
+            ..Default::default()
+        };
+        let request = request(message, Protocol::Udp);
+        assert_eq!(authority.handle(&request).unwrap().rcode, Rcode::NotImp);
+    }
+
+    #[test]
+    fn test_invalid_zone() {
+        let records = vec![a("www.example.com", "192.0.2.1")];
+        assert!(Authority::from_records(records).is_err(), "no SOA");
+
+        let mut records = negative_soa();
+        records.push(a("www.example.net", "192.0.2.1"));
+        assert!(Authority::from_records(records).is_err(), "outside zone");
+
+        let mut records = negative_soa();
+        records.push(a("www.example.com", "192.0.2.1"));
+        assert!(Authority::from_records(records).is_ok());
+    }
+
+    #[test]
+    fn test_udp_and_tcp() {
+        let authority = Arc::new(authority());
+
+        let server = rustdns::server::udp::Server::bind("127.0.0.1:0", authority.clone()).unwrap();
+        let udp_addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        let server = rustdns::server::tcp::Server::bind("127.0.0.1:0", authority).unwrap();
+        let tcp_addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        let mut message = Message::default();
+        message.add_question("www.example.com", Type::A, Class::Internet);
+        message.add_extension(Extension::default());
+
+        let resp = udp::Client::new(udp_addr)
+            .unwrap()
+            .exchange(&message)
+            .unwrap();
+        assert_eq!(resp.id, message.id);
+        assert_eq!(resp.aa, true);
+        assert_eq!(resp.answers, vec![a("www.example.com.", "192.0.2.2")]);
+        assert!(resp.extension.is_some());
+
+        // Many queries over the one connection.
+        let client = tcp::Client::new(tcp_addr).unwrap();
+        for _ in 0..3 {
+            let resp = client.exchange(&message).unwrap();
+            assert_eq!(resp.answers, vec![a("www.example.com.", "192.0.2.2")]);
+            assert_eq!(
+                resp.extension.unwrap().tcp_keepalive(),
+                Some(Duration::new(10, 0))
+            );
+        }
+    }
+
+    #[test]
+    fn test_udp_truncation() {
+        // Enough records to not fit in 512 bytes.
+        let mut records = negative_soa();
+        for i in 0..40 {
+            records.push(a("big.example.com", &format!("192.0.2.{}", i)));
+        }
+        let authority = Arc::new(Authority::from_records(records).unwrap());
+
+        let server = rustdns::server::udp::Server::bind("127.0.0.1:0", authority.clone()).unwrap();
+        let udp_addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        let mut message = Message::default();
+        message.add_question("big.example.com", Type::A, Class::Internet);
+
+        let client = udp::Client::new(udp_addr).unwrap();
+        let resp = client.exchange(&message).unwrap();
+        assert_eq!(resp.tc, true);
+        assert_eq!(resp.answers, vec![]);
+
+        // Unless the client accepts larger responses.
+        message.add_extension(Extension::default());
+        let resp = client.exchange(&message).unwrap();
+        assert_eq!(resp.tc, false);
+        assert_eq!(resp.answers.len(), 40);
+    }
+
+    #[test]
+    fn test_udp_format_error() {
+        let server = rustdns::server::udp::Server::bind("127.0.0.1:0", authority()).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        // A header claiming one question, that is missing.
+        let request = [0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+        socket.send_to(&request, addr).unwrap();
+
+        let mut buf = [0; 512];
+        let len = socket.recv(&mut buf).unwrap();
+        let resp = Message::from_slice(&buf[..len]).unwrap();
+
+        assert_eq!(resp.id, 0x1234);
+        assert_eq!(resp.qr, QR::Response);
+        assert_eq!(resp.rcode, Rcode::FormErr);
+    }
+
+    /// Answers with a record whose name can't be encoded.
+    struct Unencodable;
+    impl Handler for Unencodable {
+        fn handle(&self, request: &Request) -> Option<Message> {
+            let mut resp = request.response();
+            let name = format!("{}.example.com.", "a".repeat(64));
+            resp.answers.push(a(&name, "192.0.2.1"));
+            Some(resp)
+        }
+    }
+
+    #[test]
+    fn test_udp_encode_error() {
+        let server = rustdns::server::udp::Server::bind("127.0.0.1:0", Unencodable).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        let mut message = Message::default();
+        message.add_question("www.example.com", Type::A, Class::Internet);
+
+        let resp = udp::Client::new(addr).unwrap().exchange(&message).unwrap();
+        assert_eq!(resp.id, message.id);
+        assert_eq!(resp.rcode, Rcode::ServFail);
+        assert_eq!(resp.answers, vec![]);
+    }
+
+    #[test]
+    fn test_tcp_encode_error() {
+        let server = rustdns::server::tcp::Server::bind("127.0.0.1:0", Unencodable).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        let mut message = Message::default();
+        message.add_question("www.example.com", Type::A, Class::Internet);
+
+        // Every request is answered, not just the first.
+        let client = tcp::Client::new(addr).unwrap();
+        for _ in 0..2 {
+            let resp = client.exchange(&message).unwrap();
+            assert_eq!(resp.id, message.id);
+            assert_eq!(resp.rcode, Rcode::ServFail);
+            assert_eq!(resp.answers, vec![]);
+        }
+    }
+
+    /// Returns the zone, with the serial and address of www changed.
+    fn updated(serial: u32, www: &str) -> Zone {
+        let zone = ZONE
+            .replace("2021010101", &serial.to_string())
+            .replace("192.0.2.2\n", &format!("{}\n", www));
+        Zone::from_file(File::from_str(&zone).unwrap()).unwrap()
+    }
+
+    /// Starts a TCP server for the authority, returning its address.
+    fn start_tcp(authority: Authority) -> std::net::SocketAddr {
+        let server = rustdns::server::tcp::Server::bind("127.0.0.1:0", authority).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+        addr
+    }
+
+    /// Returns the records as they would be received, with every name
+    /// fully qualified.
+    fn wire(records: Vec<Record>) -> Vec<Record> {
+        let message = Message {
+            answers: records,
+            ..Default::default()
+        };
+        Message::from_slice(&message.to_vec().unwrap())
+            .unwrap()
+            .answers
+    }
+
+    fn sorted(mut records: Vec<Record>) -> Vec<String> {
+        let mut records: Vec<String> = records.drain(..).map(|r| r.to_string()).collect();
+        records.sort();
+        records
+    }
+
+    #[test]
+    fn test_axfr() {
+        let authority = authority().with_transfer_acl(Acl::any());
+        let zone = wire(authority.zone().iter().cloned().collect());
+
+        let addr = start_tcp(authority);
+        let records = tcp::Client::new(addr).unwrap().axfr("example.com").unwrap();
+        assert_eq!(records[0], zone[0]);
+        assert_eq!(sorted(records), sorted(zone));
+    }
+
+    #[test]
+    fn test_axfr_messages() {
+        let authority = authority()
+            .with_transfer_acl(Acl::any())
+            .with_transfer_message_size(128);
+
+        let mut message = Message::default();
+        message.add_question("example.com", Type::AXFR, Class::Internet);
+        let request = request(message, Protocol::Tcp);
+
+        let messages: Vec<Message> = authority.handle_stream(&request).collect();
+        assert!(messages.len() > 1, "{} messages", messages.len());
+        for (i, resp) in messages.iter().enumerate() {
+            assert_eq!(resp.aa, true);
+            assert_eq!(resp.questions.len(), if i == 0 { 1 } else { 0 });
+            assert!(resp.to_vec().unwrap().len() <= 128);
+        }
+
+        let records: Vec<Record> = messages.into_iter().flat_map(|m| m.answers).collect();
+        assert_eq!(records.len(), authority.zone().iter().count() + 1);
+        assert_eq!(records.first(), records.last());
+
+        // Only over TCP.
+        let mut request = request;
+        request.protocol = Protocol::Udp;
+        let resp = authority.handle(&request).unwrap();
+        assert_eq!(resp.rcode, Rcode::FormErr);
+        assert_eq!(resp.answers, vec![]);
+
+        // Leaving room for the edns-tcp-keepalive option the TCP server adds.
+        request.protocol = Protocol::Tcp;
+        request.message.add_extension(Extension::default());
+        for mut resp in authority.handle_stream(&request) {
+            let ext = resp.extension.as_mut().expect("no extension");
+            ext.options
+                .push(ExtensionOption::TcpKeepalive(Some(Duration::new(10, 0))));
+            assert!(resp.to_vec().unwrap().len() <= 128);
+        }
+    }
+
+    #[test]
+    fn test_axfr_refused() {
+        // Transfers aren't allowed by default.
+        let addr = start_tcp(authority());
+        let err = tcp::Client::new(addr)
+            .unwrap()
+            .axfr("example.com")
+            .unwrap_err();
+        assert!(err.to_string().contains("Refused"), "{}", err);
+
+        let acl = Acl::new(["192.0.2.0/24", "::1"]).unwrap();
+        let addr = start_tcp(authority().with_transfer_acl(acl));
+        let err = tcp::Client::new(addr)
+            .unwrap()
+            .axfr("example.com")
+            .unwrap_err();
+        assert!(err.to_string().contains("Refused"), "{}", err);
+
+        // Nor for other zones.
+        let addr = start_tcp(authority().with_transfer_acl(Acl::any()));
+        let err = tcp::Client::new(addr)
+            .unwrap()
+            .axfr("example.net")
+            .unwrap_err();
+        assert!(err.to_string().contains("NotAuth"), "{}", err);
+    }
+
+    #[test]
+    fn test_acl() {
+        let acl = Acl::new(["192.0.2.0/24", "2001:db8::/32", "198.51.100.7"]).unwrap();
+        for (addr, allowed) in [
+            ("192.0.2.0", true),
+            ("192.0.2.255", true),
+            ("192.0.3.1", false),
+            ("198.51.100.7", true),
+            ("198.51.100.8", false),
+            ("2001:db8:1::1", true),
+            ("2001:db9::1", false),
+            ("::ffff:192.0.2.1", true),
+        ] {
+            assert_eq!(acl.allows(addr.parse().unwrap()), allowed, "{}", addr);
+        }
+
+        assert!(!Acl::default().allows("127.0.0.1".parse().unwrap()));
+        assert!(Acl::any().allows("::1".parse().unwrap()));
+
+        for invalid in ["192.0.2.0/33", "example.com", "192.0.2.0/", "::/129"] {
+            assert!(Acl::new([invalid]).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let authority = authority();
+
+        // Only newer versions of the same zone.
+        assert!(authority.update(updated(2021010101, "192.0.2.20")).is_err());
+        assert!(authority.update(updated(2020010101, "192.0.2.20")).is_err());
+        let other = ZONE.replace("example.com.", "example.net.");
+        let other = Zone::from_file(File::from_str(&other).unwrap()).unwrap();
+        assert!(authority.update(other).is_err());
+
+        authority.update(updated(2021010102, "192.0.2.20")).unwrap();
+        let resp = query(&authority, "www.example.com", Type::A);
+        assert_eq!(resp.answers, vec![a("www.example.com.", "192.0.2.20")]);
+    }
+
+    #[test]
+    fn test_ixfr() {
+        let authority = authority().with_transfer_acl(Acl::any());
+        let mut zone = wire(authority.zone().iter().cloned().collect());
+        let old = zone[0].clone();
+
+        authority.update(updated(2021010102, "192.0.2.20")).unwrap();
+        authority.update(updated(2021010103, "192.0.2.30")).unwrap();
+        let newest = wire(authority.zone().iter().cloned().collect());
+
+        let addr = start_tcp(authority);
+        let client = tcp::Client::new(addr).unwrap();
+
+        let transfer = client.ixfr("example.com", &old).unwrap();
+        match &transfer {
+            Transfer::Incremental(diffs) => {
+                assert_eq!(diffs.len(), 2);
+                assert_eq!(diffs[0].old_serial, 2021010101);
+                assert_eq!(diffs[0].deleted[1], a("www.example.com.", "192.0.2.2"));
+                assert_eq!(diffs[0].added[1], a("www.example.com.", "192.0.2.20"));
+                assert_eq!(diffs[1].new_serial, 2021010103);
+            }
+            _ => panic!("unexpected transfer {:?}", transfer),
+        }
+
+        transfer.apply(&mut zone).unwrap();
+        assert_eq!(zone[0], newest[0]);
+        assert_eq!(sorted(zone), sorted(newest.clone()));
+
+        // Up to date.
+        let transfer = client.ixfr("example.com", &newest[0]).unwrap();
+        assert_eq!(transfer, Transfer::UpToDate(newest[0].clone()));
+    }
+
+    #[test]
+    fn test_ixfr_without_history() {
+        let authority = authority()
+            .with_transfer_acl(Acl::any())
+            .with_max_history(1);
+        let old = authority.zone().soa().clone();
+
+        authority.update(updated(2021010102, "192.0.2.20")).unwrap();
+        authority.update(updated(2021010103, "192.0.2.30")).unwrap();
+        let newest = wire(authority.zone().iter().cloned().collect());
+
+        // The changes from the first version were forgotten.
+        let client = tcp::Client::new(start_tcp(authority)).unwrap();
+        assert_eq!(
+            client.ixfr("example.com", &old).unwrap(),
+            Transfer::Full(newest)
+        );
+    }
+
+    #[test]
+    fn test_ixfr_udp() {
+        let authority = authority().with_transfer_acl(Acl::any());
+        let old = authority.zone().soa().clone();
+        authority.update(updated(2021010102, "192.0.2.20")).unwrap();
+
+        let mut message = Message::default();
+        message.add_question("example.com", Type::IXFR, Class::Internet);
+        message.authoritys.push(old);
+        let mut request = request(message, Protocol::Udp);
+
+        // The changes fit in one message.
+        let resp = authority.handle(&request).unwrap();
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.answers.len(), 6);
+
+        // But the whole zone doesn't, so only the SOA is sent.
+        request.message.authoritys[0] = updated(2020010101, "192.0.2.2").soa().clone();
+        let resp = authority.handle(&request).unwrap();
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.answers, vec![authority.zone().soa().clone()]);
+
+        // Without the client's SOA.
+        request.message.authoritys.clear();
+        let resp = authority.handle(&request).unwrap();
+        assert_eq!(resp.rcode, Rcode::FormErr);
+    }
+}
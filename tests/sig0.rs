@@ -0,0 +1,348 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(feature = "sig0")]
+#[cfg(feature = "udp")]
+#[cfg(feature = "tcp")]
+mod tests {
+    use super::common::{read_frame, response, sig, signature_error, soa_query, write_frame};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::Exchanger;
+    use rustdns::clients::{tcp, udp};
+    use rustdns::sig0::{verify, Algorithm, PrivateKey};
+    use rustdns::types::*;
+    use rustdns::Error;
+    use rustdns::Record;
+    use rustdns::Resource;
+    use rustdns::KEY;
+    use rustdns::SIG;
+    use std::net::{SocketAddr, TcpListener, UdpSocket};
+    use std::str::FromStr;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    // The example keys from rfc8080 section 6 and rfc6605 section 6.
+    const ED25519_KEY: &str = "
+Private-key-format: v1.2
+Algorithm: 15 (ED25519)
+PrivateKey: ODIyNjAzODQ2MjgwODAxMjI2NDUxOTAyMDQxNDIyNjI=
+";
+
+    const ECDSA_KEY: &str = "
+Private-key-format: v1.2
+Algorithm: 13 (ECDSAP256SHA256)
+PrivateKey: GU6SnQ/Ou+xC5RumuIUIuJZteXT2z0O/ok1s38Et6mQ=
+";
+
+    fn keys() -> Vec<PrivateKey> {
+        vec![
+            PrivateKey::parse("update.example.com", ED25519_KEY).unwrap(),
+            PrivateKey::parse("update.example.com", ECDSA_KEY).unwrap(),
+        ]
+    }
+
+    /// Returns the KEY record holding the key's public key.
+    fn record(key: &PrivateKey) -> Record {
+        Record::new(
+            key.name(),
+            Class::Internet,
+            Duration::from_secs(3600),
+            Resource::KEY(key.key()),
+        )
+    }
+
+    /// Returns a query with a OPT record, which the SIG record must follow.
+    fn query() -> Message {
+        let mut query = soa_query();
+        query.add_extension(Extension::default());
+        query
+    }
+
+    #[test]
+    fn test_keys() {
+        let keys = keys();
+
+        assert_eq!(keys[0].name(), "update.example.com.");
+        assert_eq!(keys[0].algorithm(), Algorithm::Ed25519);
+        assert_eq!(
+            hex::encode(keys[0].key().public_key),
+            "974d96a22d224bc01adb915091477d44ccd91c9a41a11430010117d52c59240e"
+        );
+
+        assert_eq!(keys[1].algorithm(), Algorithm::EcdsaP256Sha256);
+        assert_eq!(
+            hex::encode(keys[1].key().public_key),
+            "1a88c88615d437fbb8bf9e1942a1929f28562706ae6c2bd399e7b1bfb6d1e9e7\
+             5b92b4aa42917ae1c61b701ef035c3fe7be3009cbafe5a2f71316c902dcf0d00"
+        );
+
+        // The key tags of the example zone keys.
+        let key = KEY {
+            flags: 257,
+            ..keys[0].key()
+        };
+        assert_eq!(key.key_tag(), 3613);
+        let key = KEY {
+            flags: 257,
+            ..keys[1].key()
+        };
+        assert_eq!(key.key_tag(), 55648);
+
+        // The secret isn't shown.
+        assert!(!format!("{:?}", keys[0]).contains("PrivateKey:"));
+
+        assert!(PrivateKey::parse("update.example.com", "Algorithm: 15").is_err());
+        assert!(PrivateKey::new("update.example.com", Algorithm::Ed25519, &[1; 16]).is_err());
+        assert!(
+            PrivateKey::new("update.example.com", Algorithm::EcdsaP256Sha256, &[0; 32]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_algorithm() {
+        assert_eq!(Algorithm::from_str("ED25519").unwrap(), Algorithm::Ed25519);
+        assert_eq!(Algorithm::from_str("15").unwrap(), Algorithm::Ed25519);
+        assert_eq!(
+            Algorithm::from_str("ecdsap256sha256").unwrap(),
+            Algorithm::EcdsaP256Sha256
+        );
+        assert!(Algorithm::from_str("RSASHA256").is_err());
+
+        assert_eq!(Algorithm::EcdsaP256Sha256.number(), 13);
+        assert_eq!(Algorithm::Ed25519.to_string(), "ED25519");
+    }
+
+    #[test]
+    fn test_sign() {
+        for key in keys() {
+            let mut query = query();
+            key.sign(&mut query).unwrap();
+
+            let sig = sig(&query).clone();
+            assert_eq!(sig.type_covered, Type::Reserved);
+            assert_eq!(sig.algorithm, key.algorithm().number());
+            assert_eq!(sig.key_tag, key.key().key_tag());
+            assert_eq!(sig.signer_name, "update.example.com.");
+            assert_eq!(sig.expiration.wrapping_sub(sig.inception), 600);
+
+            // The SIG record is written after the extension.
+            let buf = query.to_vec().unwrap();
+            let signed = verify(&buf, &record(&key)).unwrap();
+            assert!(signed.extension.is_some());
+            assert_eq!(signed.additionals.len(), 1);
+            assert_eq!(signed.additionals[0].name, ".");
+            assert_eq!(signed.additionals[0].class, Class::Any);
+            assert_eq!(signed.additionals[0].resource, Resource::SIG(sig));
+
+            // Signing again replaces the signature.
+            key.sign(&mut query).unwrap();
+            assert_eq!(query.additionals.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_unsigned() {
+        let key = &keys()[0];
+        let buf = query().to_vec().unwrap();
+        assert!(matches!(verify(&buf, &record(key)), Err(Error::IoError(_))));
+
+        // Only KEY records can verify.
+        let mut query = query();
+        key.sign(&mut query).unwrap();
+        let a = Record::new(
+            key.name(),
+            Class::Internet,
+            Duration::from_secs(3600),
+            Resource::A("192.0.2.1".parse().unwrap()),
+        );
+        assert!(verify(&query.to_vec().unwrap(), &a).is_err());
+    }
+
+    #[test]
+    fn test_bad_sig() {
+        for key in keys() {
+            let mut query = query();
+            key.sign(&mut query).unwrap();
+            let buf = query.to_vec().unwrap();
+
+            let mut changed = buf.clone();
+            changed[0] ^= 1; // The ID is signed.
+            assert_eq!(
+                signature_error(verify(&changed, &record(&key))),
+                Rcode::BadSig
+            );
+
+            let mut changed = buf.clone();
+            let last = changed.len() - 1;
+            changed[last] ^= 1;
+            assert_eq!(
+                signature_error(verify(&changed, &record(&key))),
+                Rcode::BadSig
+            );
+        }
+    }
+
+    #[test]
+    fn test_bad_key() {
+        let keys = keys();
+        let mut query = query();
+        keys[0].sign(&mut query).unwrap();
+        let buf = query.to_vec().unwrap();
+
+        // The wrong algorithm, and key tag.
+        assert_eq!(
+            signature_error(verify(&buf, &record(&keys[1]))),
+            Rcode::BadKey
+        );
+
+        // The wrong name.
+        let mut other = record(&keys[0]);
+        other.name = "other.example.com.".to_string();
+        assert_eq!(signature_error(verify(&buf, &other)), Rcode::BadKey);
+
+        // The right name, in a different case.
+        let mut upper = record(&keys[0]);
+        upper.name = "UPDATE.example.com".to_string();
+        verify(&buf, &upper).unwrap();
+
+        // The same algorithm, but a different key.
+        let other = PrivateKey::new(keys[0].name(), Algorithm::Ed25519, &[7; 32]).unwrap();
+        assert_eq!(
+            signature_error(verify(&buf, &record(&other))),
+            Rcode::BadKey
+        );
+    }
+
+    #[test]
+    fn test_bad_time() {
+        let key = &keys()[0];
+
+        let mut query = query();
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        key.sign_at(&mut query, hour_ago).unwrap();
+        assert_eq!(
+            signature_error(verify(&query.to_vec().unwrap(), &record(key))),
+            Rcode::BadTime
+        );
+
+        let mut query = self::query();
+        let later = SystemTime::now() + Duration::from_secs(3600);
+        key.sign_at(&mut query, later).unwrap();
+        assert_eq!(
+            signature_error(verify(&query.to_vec().unwrap(), &record(key))),
+            Rcode::BadTime
+        );
+
+        // Unless the signature is valid for long enough.
+        let key = key.clone().with_fudge(Duration::from_secs(7200));
+        let mut query = self::query();
+        key.sign_at(&mut query, hour_ago).unwrap();
+        verify(&query.to_vec().unwrap(), &record(&key)).unwrap();
+    }
+
+    #[test]
+    fn test_display() {
+        let key = KEY {
+            flags: 512,
+            protocol: 3,
+            algorithm: 15,
+            public_key: vec![0x12, 0xAB],
+        };
+        assert_eq!(key.to_string(), "512 3 15 12AB");
+
+        let sig = SIG {
+            type_covered: Type::Reserved,
+            algorithm: 15,
+            labels: 0,
+            original_ttl: Duration::ZERO,
+            expiration: 1633073100,
+            inception: 1633072500,
+            key_tag: 12345,
+            signer_name: "update.example.com.".to_string(),
+            signature: vec![0x12, 0xAB],
+        };
+        assert_eq!(
+            sig.to_string(),
+            "Reserved 15 0 0 1633073100 1633072500 12345 update.example.com. 12AB"
+        );
+    }
+
+    /// Answers one query over UDP, and one over TCP, sending the verified
+    /// queries to the returned channel.
+    fn start(key: Record) -> (SocketAddr, mpsc::Receiver<Result<Message, Error>>) {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(addr).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let reply = |buf: &[u8]| response(buf).to_vec().unwrap();
+
+            let mut buf = [0; 4096];
+            let (len, src) = udp.recv_from(&mut buf).unwrap();
+            tx.send(verify(&buf[..len], &key)).unwrap();
+            udp.send_to(&reply(&buf[..len]), src).unwrap();
+
+            let (mut stream, _) = tcp.accept().unwrap();
+            let buf = read_frame(&mut stream).unwrap();
+            tx.send(verify(&buf, &key)).unwrap();
+            write_frame(&mut stream, &reply(&buf));
+        });
+
+        (addr, rx)
+    }
+
+    #[test]
+    fn test_clients() {
+        let key = keys().remove(1);
+        let (addr, rx) = start(record(&key));
+
+        let mut query = query();
+        query.add_question("www.example.com", Type::A, Class::Internet);
+
+        let client = udp::Client::new(addr).unwrap().with_sig0(key.clone());
+        let resp = client.exchange(&query).unwrap();
+        assert_eq!(resp.id, query.id);
+        let signed = rx.recv().unwrap().unwrap();
+        assert_eq!(signed.questions, query.questions);
+
+        // Over TCP the keepalive option is added, after which it's signed.
+        let client = tcp::Client::new(addr).unwrap().with_sig0(key);
+        let resp = client.exchange(&query).unwrap();
+        assert_eq!(resp.id, query.id);
+        let signed = rx.recv().unwrap().unwrap();
+        assert_eq!(
+            signed.extension.unwrap().options,
+            vec![ExtensionOption::TcpKeepalive(None)]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "tsig")]
+    fn test_clients_replace_tsig() {
+        use rustdns::tsig::{self, Key};
+
+        let key = keys().remove(1);
+        let (addr, rx) = start(record(&key));
+        let tsig = Key::new("tsig-key", tsig::Algorithm::HmacSha256, b"secret".to_vec());
+
+        let mut query = query();
+        query.add_question("www.example.com", Type::A, Class::Internet);
+
+        // Only the SIG(0) is sent, and the unsigned responses are accepted.
+        let client = udp::Client::new(addr)
+            .unwrap()
+            .with_tsig(tsig.clone())
+            .with_sig0(key.clone());
+        client.exchange(&query).unwrap();
+        rx.recv().unwrap().unwrap();
+
+        let client = tcp::Client::new(addr)
+            .unwrap()
+            .with_tsig(tsig)
+            .with_sig0(key);
+        client.exchange(&query).unwrap();
+        rx.recv().unwrap().unwrap();
+    }
+}
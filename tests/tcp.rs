@@ -0,0 +1,326 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(feature = "tcp")]
+mod tests {
+    use super::common::{read_message, write_frame, write_message};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::tcp::Client;
+    use rustdns::clients::Exchanger;
+    use rustdns::types::*;
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Clone, Default)]
+    struct Behaviour {
+        /// Include a edns-tcp-keepalive option in each response.
+        keepalive: Option<Duration>,
+
+        /// Wait for this many queries, then answer them in reverse order.
+        batch: usize,
+
+        /// Close the connection after each response.
+        close: bool,
+
+        /// Queries for this name are answered with a invalid message.
+        invalid: Option<&'static str>,
+
+        /// Queries for this name are never answered.
+        ignore: Option<&'static str>,
+
+        /// Queries for this name are answered with a different question.
+        mismatch: Option<&'static str>,
+    }
+
+    fn write_response(stream: &mut TcpStream, query: Message, behaviour: &Behaviour) {
+        let name = query.questions[0].name.trim_end_matches('.');
+        if behaviour.ignore == Some(name) {
+            return;
+        }
+        if behaviour.invalid == Some(name) {
+            // The query's ID, followed by a truncated header.
+            let mut buf = query.id.to_be_bytes().to_vec();
+            buf.extend_from_slice(&[0x81, 0x80, 0x00]);
+            write_frame(stream, &buf);
+            return;
+        }
+
+        let mismatch = behaviour.mismatch == Some(name);
+        let mut resp = query;
+        resp.qr = QR::Response;
+        if mismatch {
+            resp.questions[0].name = "other.example.com.".to_string();
+        }
+        if let Some(timeout) = behaviour.keepalive {
+            resp.add_extension(Extension {
+                options: vec![ExtensionOption::TcpKeepalive(Some(timeout))],
+                ..Default::default()
+            });
+        }
+
+        write_message(stream, &resp);
+    }
+
+    /// Starts a mock DNS server, that echos back each query as the response.
+    /// Returns its address and a counter of accepted connections.
+    fn start_server(behaviour: Behaviour) -> (SocketAddr, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+
+        let counter = accepted.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+
+                let behaviour = behaviour.clone();
+                thread::spawn(move || loop {
+                    let mut queries = Vec::new();
+                    for _ in 0..behaviour.batch.max(1) {
+                        match read_message(&mut stream) {
+                            Some(query) => queries.push(query),
+                            None => return,
+                        }
+                    }
+
+                    for query in queries.into_iter().rev() {
+                        write_response(&mut stream, query, &behaviour);
+                    }
+
+                    if behaviour.close {
+                        return;
+                    }
+                });
+            }
+        });
+
+        (addr, accepted)
+    }
+
+    /// Returns a address with nothing listening on it.
+    fn closed_port() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    fn query(name: &str) -> Message {
+        let mut query = Message::default();
+        query.add_question(name, Type::A, Class::Internet);
+        query.add_extension(Extension::default());
+        query
+    }
+
+    #[test]
+    fn test_reuses_connection() {
+        let (addr, accepted) = start_server(Behaviour::default());
+        let client = Client::new(addr).unwrap();
+
+        for name in &["a.example.com", "b.example.com", "c.example.com"] {
+            let query = query(name);
+            let resp = client.exchange(&query).expect("exchange failed");
+            assert_eq!(resp.id, query.id);
+            assert_eq!(resp.questions, query.questions);
+        }
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_pipelining() {
+        // The server won't respond until it has received both queries.
+        let (addr, accepted) = start_server(Behaviour {
+            batch: 2,
+            ..Default::default()
+        });
+        let client = Arc::new(Client::new(addr).unwrap());
+
+        let handles: Vec<_> = ["a.example.com", "b.example.com"]
+            .iter()
+            .map(|name| {
+                let client = client.clone();
+                thread::spawn(move || {
+                    let mut query = query(name);
+                    query.id = 1234; // Same id, so the client must rewrite one of them.
+                    let resp = client.exchange(&query).expect("exchange failed");
+                    assert_eq!(resp.id, 1234);
+                    assert_eq!(resp.questions, query.questions);
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_invalid_response() {
+        // The invalid response is pipelined with a valid one.
+        let (addr, accepted) = start_server(Behaviour {
+            batch: 2,
+            invalid: Some("bad.example.com"),
+            ..Default::default()
+        });
+        let client = Arc::new(Client::new(addr).unwrap());
+
+        // The second pair of queries reuses the same connection.
+        for names in [
+            ["bad.example.com", "a.example.com"],
+            ["bad.example.com", "b.example.com"],
+        ] {
+            let handles: Vec<_> = names
+                .iter()
+                .map(|&name| {
+                    let client = client.clone();
+                    thread::spawn(move || client.exchange(&query(name)))
+                })
+                .collect();
+
+            let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+            assert!(
+                results[0].is_err(),
+                "expected a error, got {:?}",
+                results[0]
+            );
+            assert!(
+                results[1].is_ok(),
+                "expected a response, got {:?}",
+                results[1]
+            );
+        }
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_timeout_keeps_connection() {
+        let (addr, accepted) = start_server(Behaviour {
+            ignore: Some("slow.example.com"),
+            ..Default::default()
+        });
+        let client = Client::new(addr).unwrap();
+
+        client
+            .exchange(&query("a.example.com"))
+            .expect("exchange failed");
+        assert!(client.exchange(&query("slow.example.com")).is_err());
+        client
+            .exchange(&query("b.example.com"))
+            .expect("exchange failed");
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_failover() {
+        let (addr, _) = start_server(Behaviour::default());
+        let client = Client::new(&[closed_port(), addr][..]).unwrap();
+
+        let resp = client
+            .exchange(&query("a.example.com"))
+            .expect("exchange failed");
+        assert_eq!(resp.stats.unwrap().server, addr);
+    }
+
+    #[test]
+    fn test_reconnects() {
+        let (addr, accepted) = start_server(Behaviour {
+            close: true,
+            ..Default::default()
+        });
+        let client = Client::new(addr).unwrap();
+
+        for name in &["a.example.com", "b.example.com"] {
+            client.exchange(&query(name)).expect("exchange failed");
+            thread::sleep(Duration::from_millis(50)); // Let the server close the connection.
+        }
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_keepalive_timeout() {
+        // The server says it will close the connection once idle.
+        let (addr, accepted) = start_server(Behaviour {
+            keepalive: Some(Duration::from_secs(0)),
+            ..Default::default()
+        });
+        let client = Client::new(addr).unwrap();
+
+        for name in &["a.example.com", "b.example.com"] {
+            client.exchange(&query(name)).expect("exchange failed");
+        }
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_keepalive_zero_while_pending() {
+        let (addr, accepted) = start_server(Behaviour {
+            keepalive: Some(Duration::from_secs(0)),
+            ignore: Some("slow.example.com"),
+            ..Default::default()
+        });
+        let client = Arc::new(
+            Client::new(addr)
+                .unwrap()
+                .with_read_timeout(Some(Duration::from_millis(500))),
+        );
+
+        // A query stays outstanding on the connection.
+        let slow = {
+            let client = client.clone();
+            thread::spawn(move || client.exchange(&query("slow.example.com")))
+        };
+        thread::sleep(Duration::from_millis(50));
+
+        // Once the server asks to close the connection, no more queries are
+        // sent on it, even though the first is still outstanding.
+        for name in &["a.example.com", "b.example.com"] {
+            client.exchange(&query(name)).expect("exchange failed");
+        }
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+
+        assert!(slow.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_mismatched_question() {
+        let (addr, accepted) = start_server(Behaviour {
+            mismatch: Some("a.example.com"),
+            ..Default::default()
+        });
+        let client = Client::new(addr)
+            .unwrap()
+            .with_read_timeout(Some(Duration::from_millis(200)));
+
+        // The response has the right ID, but is for another question.
+        assert!(client.exchange(&query("a.example.com")).is_err());
+        client
+            .exchange(&query("b.example.com"))
+            .expect("exchange failed");
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_sends_keepalive() {
+        // The server echos back the query, so we can see what was sent.
+        let (addr, _) = start_server(Behaviour::default());
+        let client = Client::new(addr).unwrap();
+
+        let resp = client
+            .exchange(&query("a.example.com"))
+            .expect("exchange failed");
+        assert_eq!(
+            resp.extension.unwrap().options,
+            vec![ExtensionOption::TcpKeepalive(None)]
+        );
+    }
+}
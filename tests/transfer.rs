@@ -0,0 +1,333 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(feature = "tcp")]
+mod tests {
+    use super::common::{a, cname, ns, read_message, soa, write_message};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::tcp::Client;
+    use rustdns::clients::{Diff, Transfer};
+    use rustdns::types::*;
+    use rustdns::Record;
+    use std::net::{SocketAddr, TcpListener, UdpSocket};
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// The records of the example.com zone, starting with its SOA.
+    fn zone() -> Vec<Record> {
+        vec![
+            soa(1),
+            ns("example.com.", "ns1.example.com."),
+            a("ns1.example.com.", "192.0.2.1"),
+            a("www.example.com.", "192.0.2.2"),
+            cname("alias.example.com.", "www.example.com."),
+        ]
+    }
+
+    /// Starts a mock server, that answers the first query on each connection
+    /// with the responses (as many messages, built from the query), and then
+    /// closes the connection. Returns its address, and the queries received.
+    fn start_server(responses: Vec<Message>) -> (SocketAddr, mpsc::Receiver<Message>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let query = match read_message(&mut stream) {
+                    Some(query) => query,
+                    None => continue,
+                };
+
+                for (i, response) in responses.iter().enumerate() {
+                    let mut resp = response.clone();
+                    resp.id = query.id;
+                    resp.qr = QR::Response;
+                    resp.aa = true;
+                    if i == 0 {
+                        // Only the first message needs the question.
+                        resp.questions = query.questions.clone();
+                    }
+                    write_message(&mut stream, &resp);
+                }
+
+                let _ = tx.send(query);
+            }
+        });
+
+        (addr, rx)
+    }
+
+    /// Returns a response holding the records.
+    fn answers(records: Vec<Record>) -> Message {
+        Message {
+            answers: records,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_axfr() {
+        let mut records = zone();
+
+        // Split across three messages, ending with the SOA again.
+        let (addr, queries) = start_server(vec![
+            answers(records[..2].to_vec()),
+            answers(records[2..4].to_vec()),
+            answers(vec![records[4].clone(), soa(1)]),
+        ]);
+
+        let client = Client::new(addr).unwrap();
+        assert_eq!(client.axfr("Example.COM").unwrap(), zone());
+
+        let query = queries.recv().unwrap();
+        assert_eq!(query.rd, false);
+        assert_eq!(query.questions.len(), 1);
+        assert_eq!(query.questions[0].name, "example.com.");
+        assert_eq!(query.questions[0].r#type, Type::AXFR);
+
+        // A single message is fine too.
+        records.push(soa(1));
+        let (addr, _) = start_server(vec![answers(records)]);
+        let client = Client::new(addr).unwrap();
+        assert_eq!(client.axfr("example.com").unwrap(), zone());
+    }
+
+    #[test]
+    fn test_axfr_refused() {
+        let (addr, _) = start_server(vec![Message {
+            rcode: Rcode::Refused,
+            ..Default::default()
+        }]);
+
+        let client = Client::new(addr).unwrap();
+        let err = client.axfr("example.com").unwrap_err();
+        assert!(err.to_string().contains("Refused"), "{}", err);
+    }
+
+    #[test]
+    fn test_axfr_invalid() {
+        // Doesn't start with a SOA.
+        let (addr, _) = start_server(vec![answers(zone()[1..].to_vec())]);
+        let client = Client::new(addr).unwrap();
+        assert!(client.axfr("example.com").is_err());
+
+        // Starts with the SOA of another zone.
+        let (addr, _) = start_server(vec![answers(zone())]);
+        let client = Client::new(addr).unwrap();
+        assert!(client.axfr("example.net").is_err());
+    }
+
+    #[test]
+    fn test_axfr_ends_early() {
+        // The server closes the connection before the closing SOA.
+        let (addr, _) = start_server(vec![answers(zone())]);
+
+        let client = Client::new(addr).unwrap();
+        assert!(client.axfr("example.com").is_err());
+    }
+
+    /// The changes from serial 1 to 2, and 2 to 3.
+    fn diffs() -> Vec<Diff> {
+        vec![
+            Diff {
+                old_serial: 1,
+                deleted: vec![soa(1), a("www.example.com.", "192.0.2.2")],
+                new_serial: 2,
+                added: vec![soa(2), a("www.example.com.", "192.0.2.20")],
+            },
+            Diff {
+                old_serial: 2,
+                deleted: vec![soa(2), cname("alias.example.com.", "www.example.com.")],
+                new_serial: 3,
+                added: vec![soa(3), a("mail.example.com.", "192.0.2.3")],
+            },
+        ]
+    }
+
+    /// The records of the IXFR response for the diffs.
+    fn ixfr_records(diffs: &[Diff]) -> Vec<Record> {
+        let mut records = vec![soa(3)];
+        for diff in diffs {
+            records.extend(diff.deleted.clone());
+            records.extend(diff.added.clone());
+        }
+        records.push(soa(3));
+        records
+    }
+
+    #[test]
+    fn test_ixfr() {
+        let records = ixfr_records(&diffs());
+        let (addr, queries) = start_server(vec![
+            answers(records[..3].to_vec()),
+            answers(records[3..7].to_vec()),
+            answers(records[7..].to_vec()),
+        ]);
+
+        let client = Client::new(addr).unwrap();
+        let transfer = client.ixfr("example.com", &soa(1)).unwrap();
+        assert_eq!(transfer, Transfer::Incremental(diffs()));
+
+        let query = queries.recv().unwrap();
+        assert_eq!(query.questions[0].r#type, Type::IXFR);
+        assert_eq!(query.authoritys, vec![soa(1)]);
+
+        let mut zone = zone();
+        transfer.apply(&mut zone).unwrap();
+        assert_eq!(
+            zone,
+            vec![
+                soa(3),
+                ns("example.com.", "ns1.example.com."),
+                a("ns1.example.com.", "192.0.2.1"),
+                a("www.example.com.", "192.0.2.20"),
+                a("mail.example.com.", "192.0.2.3"),
+            ]
+        );
+
+        // The SOA must be the old serial.
+        assert!(client.ixfr("example.com", &zone[1]).is_err(), "not a SOA");
+    }
+
+    #[test]
+    fn test_ixfr_soa_alone() {
+        // Over TCP, a first message with only the newer SOA is not the end.
+        let records = ixfr_records(&diffs());
+        let (addr, _) = start_server(vec![
+            answers(records[..1].to_vec()),
+            answers(records[1..].to_vec()),
+        ]);
+
+        let client = Client::new(addr).unwrap();
+        let transfer = client.ixfr("example.com", &soa(1)).unwrap();
+        assert_eq!(transfer, Transfer::Incremental(diffs()));
+    }
+
+    #[test]
+    fn test_ixfr_up_to_date() {
+        let (addr, _) = start_server(vec![answers(vec![soa(1)])]);
+
+        let client = Client::new(addr).unwrap();
+        let transfer = client.ixfr("example.com", &soa(1)).unwrap();
+        assert_eq!(transfer, Transfer::UpToDate(soa(1)));
+
+        let mut zone = zone();
+        transfer.apply(&mut zone).unwrap();
+        assert_eq!(zone, self::zone());
+    }
+
+    #[test]
+    fn test_ixfr_full() {
+        // The server doesn't have the changes, so sends the whole zone.
+        let mut records = zone();
+        records[0] = soa(3);
+        records.push(soa(3));
+        let (addr, _) = start_server(vec![answers(records.clone())]);
+
+        let client = Client::new(addr).unwrap();
+        let transfer = client.ixfr("example.com", &soa(1)).unwrap();
+        records.pop();
+        assert_eq!(transfer, Transfer::Full(records.clone()));
+
+        let mut zone = zone();
+        transfer.apply(&mut zone).unwrap();
+        assert_eq!(zone, records);
+    }
+
+    #[test]
+    fn test_apply_invalid() {
+        let mut zone = zone();
+
+        // Starting from the wrong serial.
+        let transfer = Transfer::Incremental(diffs()[1..].to_vec());
+        assert!(transfer.apply(&mut zone).is_err());
+        assert_eq!(zone, self::zone());
+
+        // Deleting a record that doesn't exist, after the first diff applied.
+        let mut diffs = diffs();
+        diffs[1]
+            .deleted
+            .push(a("nothing.example.com.", "192.0.2.99"));
+        assert!(Transfer::Incremental(diffs).apply(&mut zone).is_err());
+        assert_eq!(zone, self::zone());
+    }
+
+    #[test]
+    fn test_apply_ignores_case() {
+        let mut zone = zone();
+        let mut expected = zone.clone();
+        Transfer::Incremental(diffs()).apply(&mut expected).unwrap();
+
+        // Names, including those within the data, may be spelt differently.
+        let mut diffs = diffs();
+        diffs[0].deleted[1] = a("WWW.Example.COM", "192.0.2.2");
+        diffs[1].deleted[1] = cname("Alias.Example.com.", "WWW.EXAMPLE.COM");
+        Transfer::Incremental(diffs).apply(&mut zone).unwrap();
+        assert_eq!(zone, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "udp")]
+    fn test_ixfr_udp() {
+        use rustdns::clients::udp;
+
+        // A server listening on both UDP and TCP, that only sends the SOA
+        // over UDP, so the client retries over TCP.
+        let (addr, queries) = start_server(vec![answers(ixfr_records(&diffs()))]);
+        let socket = UdpSocket::bind(addr).unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok((len, src)) = socket.recv_from(&mut buf) {
+                let query = Message::from_slice(&buf[..len]).unwrap();
+                let mut resp = query.clone();
+                resp.qr = QR::Response;
+                resp.authoritys.clear();
+                resp.answers = vec![soa(3)];
+                socket.send_to(&resp.to_vec().unwrap(), src).unwrap();
+            }
+        });
+
+        let client = udp::Client::new(addr).unwrap();
+        let transfer = client.ixfr("example.com", &soa(1)).unwrap();
+        assert_eq!(transfer, Transfer::Incremental(diffs()));
+        assert_eq!(queries.recv().unwrap().questions[0].r#type, Type::IXFR);
+
+        // Unless the zone is up to date.
+        let transfer = client.ixfr("example.com", &soa(3)).unwrap();
+        assert_eq!(transfer, Transfer::UpToDate(soa(3)));
+    }
+
+    #[test]
+    #[cfg(feature = "zones")]
+    fn test_axfr_file() {
+        use rustdns::zones::{File, Zone};
+
+        let mut records = zone();
+        records.push(soa(1));
+        let (addr, _) = start_server(vec![answers(records)]);
+
+        let records = Client::new(addr).unwrap().axfr("example.com").unwrap();
+        let zone = Zone::new(records.clone()).unwrap();
+        assert_eq!(zone.origin(), "example.com.");
+
+        // Records read back from the file have no dot on the end.
+        let file = File::from_records("example.com", records);
+        let records = file.into_records().unwrap();
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[0].name, "example.com");
+        assert_eq!(records[0].resource, {
+            let mut soa = soa(1);
+            if let Resource::SOA(soa) = &mut soa.resource {
+                soa.mname = "ns1.example.com".to_string();
+                soa.rname = "admin@example.com".to_string();
+            }
+            soa.resource
+        });
+        assert_eq!(
+            records[4].resource,
+            Resource::CNAME("www.example.com".to_string())
+        );
+    }
+}
@@ -0,0 +1,472 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(feature = "tsig")]
+#[cfg(feature = "server")]
+#[cfg(feature = "zones")]
+#[cfg(feature = "udp")]
+#[cfg(feature = "tcp")]
+mod tests {
+    use super::common::{authority, request, response, signature_error, soa, soa_query, tsig};
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::Exchanger;
+    use rustdns::clients::{tcp, udp};
+    use rustdns::server::{Acl, Authority, Handler, Protocol, Request};
+    use rustdns::tsig::{verify_request, Algorithm, Key};
+    use rustdns::types::*;
+    use rustdns::Error;
+    use rustdns::Record;
+    use rustdns::Resource;
+    use rustdns::Update;
+    use std::net::SocketAddr;
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@           IN  SOA     ns1 admin 1 7200 3600 1209600 300
+@           IN  NS      ns1
+ns1         IN  A       192.0.2.1
+www         IN  A       192.0.2.2
+mail        IN  A       192.0.2.3
+ftp         IN  A       192.0.2.4
+";
+
+    const KEYS: &str = r#"
+# Generated by tsig-keygen.
+key "transfer-key" {
+	algorithm hmac-sha256;
+	secret "c2VjcmV0LXNoYXJlZC13aXRoLXRoZS1zZWNvbmRhcmllcw==";
+};
+
+// A second key, for updates.
+key update-key {
+	algorithm "hmac-sha512";
+	secret "dXBkYXRl
+	        LWtleQ==";
+};
+"#;
+
+    fn key() -> Key {
+        Key::parse(KEYS).unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let keys = Key::parse(KEYS).unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].name(), "transfer-key.");
+        assert_eq!(keys[0].algorithm(), Algorithm::HmacSha256);
+        assert_eq!(keys[1].name(), "update-key.");
+        assert_eq!(keys[1].algorithm(), Algorithm::HmacSha512);
+
+        // The secret is never shown.
+        assert!(!format!("{:?}", keys[0]).contains("secret"));
+
+        let path = std::env::temp_dir().join(format!("rustdns-{}.key", std::process::id()));
+        std::fs::write(&path, KEYS).unwrap();
+        assert_eq!(Key::from_file(&path).unwrap(), keys);
+        std::fs::remove_file(&path).unwrap();
+
+        // Comment markers within quotes are part of the secret.
+        let keys = Key::parse(
+            r#"/* A old key, that's no longer used.
+            key "old" { algorithm hmac-sha256; secret "b2xk"; }; */
+            key "k" { algorithm hmac-sha256; secret "c2Vj//8="; }; // The "k" key."#,
+        )
+        .unwrap();
+        assert_eq!(
+            keys,
+            vec![Key::new(
+                "k",
+                Algorithm::HmacSha256,
+                b"sec\xff\xff".to_vec()
+            )]
+        );
+
+        for invalid in [
+            "",
+            r#"key "k" { algorithm hmac-md5; secret "c2VjcmV0"; };"#,
+            r#"key "k" { algorithm hmac-sha256; };"#,
+            r#"key "k" { secret "c2VjcmV0"; };"#,
+            r#"key "k" { algorithm hmac-sha256; secret "not base64!"; };"#,
+        ] {
+            assert!(Key::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_algorithm() {
+        for algorithm in [
+            Algorithm::HmacSha256,
+            Algorithm::HmacSha384,
+            Algorithm::HmacSha512,
+        ] {
+            assert_eq!(algorithm.name().parse::<Algorithm>().unwrap(), algorithm);
+            assert_eq!(
+                algorithm.to_string().parse::<Algorithm>().unwrap(),
+                algorithm
+            );
+        }
+        assert_eq!(Algorithm::HmacSha384.name(), "hmac-sha384.");
+        assert!("hmac-md5.sig-alg.reg.int".parse::<Algorithm>().is_err());
+    }
+
+    #[test]
+    fn test_sign() {
+        for algorithm in [
+            Algorithm::HmacSha256,
+            Algorithm::HmacSha384,
+            Algorithm::HmacSha512,
+        ] {
+            let key = Key::new("Test-Key", algorithm, b"secret".to_vec());
+            let keys = [key.clone()];
+
+            let mut query = soa_query();
+            query.add_extension(Extension::default());
+            let mut verifier = key.sign(&mut query).unwrap();
+
+            // The TSIG record is written last, after the EDNS(0) extension.
+            let buf = query.to_vec().unwrap();
+            let parsed = Message::from_slice(&buf).unwrap();
+            assert_eq!(parsed, query);
+            assert_eq!(parsed.additionals.last().unwrap().name, "test-key.");
+            assert_eq!(parsed.additionals.last().unwrap().class, Class::Any);
+            assert_eq!(tsig(&parsed).algorithm, algorithm.name());
+            assert_eq!(tsig(&parsed).original_id, query.id);
+
+            let mut signer = verify_request(&keys, &buf).unwrap().unwrap();
+            assert_eq!(signer.error(), Rcode::NoError);
+            assert_eq!(signer.key(), Some(&key));
+
+            // The ID may change, as the original is signed.
+            let mut resp = response(&buf);
+            resp.id = resp.id.wrapping_add(1);
+            signer.sign(&mut resp).unwrap();
+            assert_eq!(resp.rcode, Rcode::NoError);
+
+            let verified = verifier.verify(&resp.to_vec().unwrap()).unwrap();
+            assert_eq!(verified.questions, query.questions);
+            verifier.finish().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_unsigned() {
+        let key = key();
+        let buf = soa_query().to_vec().unwrap();
+        assert!(verify_request(std::slice::from_ref(&key), &buf)
+            .unwrap()
+            .is_none());
+
+        // Responses must be signed.
+        let mut query = soa_query();
+        let mut verifier = key.sign(&mut query).unwrap();
+        let resp = response(&query.to_vec().unwrap());
+        assert_eq!(
+            signature_error(verifier.verify(&resp.to_vec().unwrap())),
+            Rcode::BadSig
+        );
+        assert!(verifier.finish().is_err());
+    }
+
+    #[test]
+    fn test_bad_sig() {
+        let key = key();
+        let mut query = soa_query();
+        let mut verifier = key.sign(&mut query).unwrap();
+        let buf = query.to_vec().unwrap();
+
+        // A key with the same name, but a different secret.
+        let wrong = Key::new(key.name(), key.algorithm(), b"wrong".to_vec());
+        let mut signer = verify_request(&[wrong], &buf).unwrap().unwrap();
+        assert_eq!(signer.error(), Rcode::BadSig);
+
+        let mut resp = response(&buf);
+        signer.sign(&mut resp).unwrap();
+        assert_eq!(resp.rcode, Rcode::NotAuth);
+        assert_eq!(tsig(&resp).error, Rcode::BadSig);
+        assert!(tsig(&resp).mac.is_empty());
+
+        // Which only fits in the TSIG error, not the header.
+        let mut header = resp.clone();
+        header.rcode = Rcode::BadSig;
+        assert!(header.to_vec().is_err());
+
+        assert_eq!(
+            signature_error(verifier.verify(&resp.to_vec().unwrap())),
+            Rcode::BadSig
+        );
+
+        // A message changed after it was signed.
+        let mut changed = buf.clone();
+        changed[13] ^= 0x20; // "example.com" to "Example.com"
+        let signer = verify_request(std::slice::from_ref(&key), &changed)
+            .unwrap()
+            .unwrap();
+        assert_eq!(signer.error(), Rcode::BadSig);
+
+        // A response signed with a different key.
+        let mut verifier = key.sign(&mut query).unwrap();
+        let other = Key::new(key.name(), key.algorithm(), b"other".to_vec());
+        let mut other_query = query.clone();
+        other.sign(&mut other_query).unwrap();
+        let mut signer = verify_request(&[other], &other_query.to_vec().unwrap())
+            .unwrap()
+            .unwrap();
+        let mut resp = response(&buf);
+        signer.sign(&mut resp).unwrap();
+        assert_eq!(
+            signature_error(verifier.verify(&resp.to_vec().unwrap())),
+            Rcode::BadSig
+        );
+    }
+
+    #[test]
+    fn test_bad_key() {
+        let mut query = soa_query();
+        let mut verifier = key().sign(&mut query).unwrap();
+        let buf = query.to_vec().unwrap();
+
+        let other = Key::parse(KEYS).unwrap().remove(1);
+        let mut signer = verify_request(&[other], &buf).unwrap().unwrap();
+        assert_eq!(signer.error(), Rcode::BadKey);
+        assert_eq!(signer.key(), None);
+
+        let mut resp = response(&buf);
+        signer.sign(&mut resp).unwrap();
+        assert_eq!(resp.rcode, Rcode::NotAuth);
+        assert_eq!(tsig(&resp).error, Rcode::BadKey);
+        assert_eq!(resp.additionals.last().unwrap().name, "transfer-key.");
+
+        assert_eq!(
+            signature_error(verifier.verify(&resp.to_vec().unwrap())),
+            Rcode::BadKey
+        );
+    }
+
+    #[test]
+    fn test_bad_time() {
+        let key = key();
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+
+        let mut query = soa_query();
+        let mut verifier = key.sign_at(&mut query, hour_ago).unwrap();
+        let buf = query.to_vec().unwrap();
+
+        let mut signer = verify_request(std::slice::from_ref(&key), &buf)
+            .unwrap()
+            .unwrap();
+        assert_eq!(signer.error(), Rcode::BadTime);
+
+        // The error response is signed, and includes the server's time.
+        let mut resp = response(&buf);
+        signer.sign(&mut resp).unwrap();
+        assert_eq!(resp.rcode, Rcode::NotAuth);
+        assert_eq!(tsig(&resp).error, Rcode::BadTime);
+        assert_eq!(tsig(&resp).time_signed, tsig(&query).time_signed);
+        assert_eq!(tsig(&resp).other.len(), 6);
+        assert!(!tsig(&resp).mac.is_empty());
+
+        assert_eq!(
+            signature_error(verifier.verify(&resp.to_vec().unwrap())),
+            Rcode::BadTime
+        );
+
+        // A larger fudge allows it.
+        let key = key.with_fudge(Duration::from_secs(7200));
+        key.sign_at(&mut query, hour_ago).unwrap();
+        let signer = verify_request(&[key], &query.to_vec().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(signer.error(), Rcode::NoError);
+    }
+
+    #[test]
+    fn test_many_messages() {
+        let key = key();
+        let mut query = soa_query();
+        let buf = query.to_vec().unwrap();
+        let mut verifier = key.sign(&mut query).unwrap();
+        let mut signer = verify_request(std::slice::from_ref(&key), &query.to_vec().unwrap())
+            .unwrap()
+            .unwrap();
+
+        let messages: Vec<Vec<u8>> = (0..3)
+            .map(|_| {
+                let mut resp = response(&buf);
+                signer.sign(&mut resp).unwrap();
+                resp.to_vec().unwrap()
+            })
+            .collect();
+
+        // Each message is signed following the one before, so they must be
+        // verified in order.
+        let mut out_of_order = key.sign(&mut query.clone()).unwrap();
+        assert!(out_of_order.verify(&messages[1]).is_err());
+
+        for message in &messages {
+            verifier.verify(message).unwrap();
+        }
+        verifier.finish().unwrap();
+
+        // Later messages may be unsigned, but not the last.
+        verifier.verify(&response(&buf).to_vec().unwrap()).unwrap();
+        assert_eq!(
+            verifier.finish().unwrap_err().to_string(),
+            "signature verification failed: BadSig"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let mut query = soa_query();
+        key().sign(&mut query).unwrap();
+        let tsig = tsig(&query);
+
+        let display = Resource::TSIG(tsig.clone()).to_string();
+        assert!(display.starts_with(&format!("hmac-sha256. {} 300 32 ", tsig.time_signed)));
+        assert!(display.ends_with(&format!(" {} NoError 0", query.id)));
+    }
+
+    fn start(authority: Authority, keys: Vec<Key>) -> SocketAddr {
+        let authority = std::sync::Arc::new(authority);
+
+        let server = rustdns::server::tcp::Server::bind("127.0.0.1:0", authority.clone())
+            .unwrap()
+            .with_tsig_keys(keys.clone());
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        let server = rustdns::server::udp::Server::bind(addr, authority)
+            .unwrap()
+            .with_tsig_keys(keys);
+        thread::spawn(move || server.serve());
+
+        addr
+    }
+
+    #[test]
+    fn test_transfer() {
+        let authority = authority(ZONE)
+            .with_transfer_acl(Acl::default().with_key("Transfer-Key"))
+            .with_transfer_message_size(128);
+        let zone: Vec<Record> = authority.zone().iter().cloned().collect();
+
+        let addr = start(authority, Key::parse(KEYS).unwrap());
+
+        // Every message of the transfer is signed, and verified.
+        let records = tcp::Client::new(addr)
+            .unwrap()
+            .with_tsig(key())
+            .axfr("example.com")
+            .unwrap();
+        assert_eq!(records.len(), zone.len());
+
+        // Unsigned, or signed by another key, isn't allowed by the Acl.
+        assert!(tcp::Client::new(addr).unwrap().axfr("example.com").is_err());
+        let other = Key::parse(KEYS).unwrap().remove(1);
+        assert!(tcp::Client::new(addr)
+            .unwrap()
+            .with_tsig(other)
+            .axfr("example.com")
+            .is_err());
+
+        // A key the server doesn't know.
+        let unknown = Key::new("unknown", Algorithm::HmacSha256, b"secret".to_vec());
+        match tcp::Client::new(addr)
+            .unwrap()
+            .with_tsig(unknown)
+            .axfr("example.com")
+        {
+            Err(Error::SignatureError(rcode)) => assert_eq!(rcode, Rcode::BadKey),
+            result => panic!("expected a BADKEY error, got {:?}", result),
+        }
+
+        // And queries over UDP are signed too.
+        let resp = udp::Client::new(addr)
+            .unwrap()
+            .with_tsig(key())
+            .exchange(&soa_query())
+            .unwrap();
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.answers.len(), 1);
+    }
+
+    #[test]
+    fn test_transfer_message_size() {
+        let authority = authority(ZONE)
+            .with_transfer_acl(Acl::default().with_key("transfer-key"))
+            .with_transfer_message_size(192);
+
+        let mut query = Message::default();
+        query.add_question("example.com", Type::AXFR, Class::Internet);
+        key().sign(&mut query).unwrap();
+        let buf = query.to_vec().unwrap();
+
+        let keys = [key()];
+        let mut signer = verify_request(&keys, &buf).unwrap().unwrap();
+        let request = Request {
+            key: signer.key().map(|key| key.name().to_string()),
+            ..request(query, Protocol::Tcp)
+        };
+
+        // Room is left for the signature.
+        let messages: Vec<Message> = authority.handle_stream(&request).collect();
+        assert!(messages.len() > 1, "{} messages", messages.len());
+        for mut resp in messages {
+            signer.sign(&mut resp).unwrap();
+            assert!(resp.to_vec().unwrap().len() <= 192);
+        }
+    }
+
+    #[test]
+    fn test_transfer_large_signature() {
+        let authority = authority(ZONE).with_transfer_acl(Acl::default().with_key("transfer-key"));
+
+        // A signature larger than a UDP message leaves no room for records.
+        let mut query = Message::default();
+        query.add_question("example.com", Type::IXFR, Class::Internet);
+        query.authoritys.push(soa(0));
+        key().sign(&mut query).unwrap();
+        if let Resource::TSIG(tsig) = &mut query.additionals[0].resource {
+            tsig.other = vec![0; 1024];
+        }
+        let request = Request {
+            key: Some(key().name().to_string()),
+            ..request(query, Protocol::Udp)
+        };
+
+        // So the client is told to retry over TCP.
+        let resp = authority.handle(&request).expect("no response");
+        assert_eq!(resp.rcode, Rcode::NoError);
+        assert_eq!(resp.answers, vec![authority.zone().soa().clone()]);
+    }
+
+    #[test]
+    fn test_update() {
+        let authority = authority(ZONE).with_update_acl(Acl::default().with_key("update-key"));
+        let addr = start(authority, Key::parse(KEYS).unwrap());
+
+        let update = Update::new("example.com")
+            .add(Record {
+                name: "new.example.com.".to_string(),
+                class: Class::Internet,
+                ttl: Duration::new(300, 0),
+                resource: Resource::A("192.0.2.20".parse().unwrap()),
+            })
+            .build();
+
+        let client = udp::Client::new(addr).unwrap();
+        assert_eq!(client.exchange(&update).unwrap().rcode, Rcode::Refused);
+
+        let update_key = Key::parse(KEYS).unwrap().remove(1);
+        let client = client.with_tsig(update_key);
+        assert_eq!(client.exchange(&update).unwrap().rcode, Rcode::NoError);
+
+        let mut query = Message::default();
+        query.add_question("new.example.com", Type::A, Class::Internet);
+        assert_eq!(client.exchange(&query).unwrap().answers.len(), 1);
+    }
+}
@@ -0,0 +1,34 @@
+#[cfg(test)]
+#[cfg(feature = "udp")]
+#[cfg(feature = "tokio")]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rustdns::clients::udp::Client;
+    use rustdns::clients::AsyncExchanger;
+    use rustdns::types::*;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    #[tokio::test]
+    async fn test_async_ipv6() {
+        // A server on IPv6 loopback, that echos back the query.
+        let server = UdpSocket::bind("[::1]:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (len, src) = server.recv_from(&mut buf).unwrap();
+            let mut resp = Message::from_slice(&buf[..len]).unwrap();
+            resp.qr = QR::Response;
+            server.send_to(&resp.to_vec().unwrap(), src).unwrap();
+        });
+
+        let mut query = Message::default();
+        query.add_question("bramp.net", Type::A, Class::Internet);
+
+        let client = Client::new(addr).unwrap();
+        let resp = AsyncExchanger::exchange(&client, &query)
+            .await
+            .expect("exchange failed");
+        assert_eq!(resp.stats.unwrap().server, addr);
+    }
+}
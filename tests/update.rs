@@ -0,0 +1,351 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(feature = "server")]
+#[cfg(feature = "zones")]
+mod tests {
+    use super::common::{self, a, record, request, soa};
+    use pretty_assertions::assert_eq;
+    use rustdns::server::{Acl, Authority, Handler, Protocol, Request};
+    use rustdns::types::*;
+    use rustdns::Resource;
+    use rustdns::Update;
+    use std::time::Duration;
+
+    const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@           IN  SOA     ns1 admin 1 7200 3600 1209600 300
+@           IN  NS      ns1
+@           IN  NS      ns2
+ns1         IN  A       192.0.2.1
+ns2         IN  A       192.0.2.2
+www         IN  A       192.0.2.10
+www         IN  A       192.0.2.11
+www         IN  AAAA    2001:db8::10
+alias       IN  CNAME   www
+";
+
+    fn authority() -> Authority {
+        common::authority(ZONE).with_update_acl(Acl::new(["127.0.0.0/8"]).unwrap())
+    }
+
+    /// Sends the update (as it would be over the wire) to the authority,
+    /// returning the rcode.
+    fn send(authority: &Authority, update: Update) -> Rcode {
+        let message = update.build();
+        let message = Message::from_slice(&message.to_vec().unwrap()).unwrap();
+
+        let resp = authority
+            .handle(&request(message, Protocol::Udp))
+            .expect("no response");
+        assert_eq!(resp.opcode, Opcode::Update);
+        resp.rcode
+    }
+
+    /// Returns the records with the name and type, as strings.
+    fn lookup(authority: &Authority, name: &str, r#type: Type) -> Vec<String> {
+        let zone = authority.zone();
+        let mut records: Vec<String> = zone
+            .records(name)
+            .unwrap_or_default()
+            .iter()
+            .filter(|r| r.r#type() == r#type)
+            .map(|r| r.resource.to_string())
+            .collect();
+        records.sort();
+        records
+    }
+
+    fn serial(authority: &Authority) -> u32 {
+        match &authority.zone().soa().resource {
+            Resource::SOA(soa) => soa.serial,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_build() {
+        let message = Update::new("Example.COM")
+            .require_exists("www.example.com", Type::A)
+            .require(a("www.example.com", "192.0.2.10"))
+            .require_absent("www.example.com", Type::CNAME)
+            .require_name_in_use("ns1.example.com")
+            .require_name_not_in_use("new.example.com")
+            .add(a("new.example.com", "192.0.2.20"))
+            .delete_rrset("www.example.com", Type::AAAA)
+            .delete_name("alias.example.com")
+            .delete(a("www.example.com", "192.0.2.11"))
+            .build();
+
+        assert_eq!(message.opcode, Opcode::Update);
+        assert_eq!(message.questions[0].name, "example.com.");
+        assert_eq!(message.questions[0].r#type, Type::SOA);
+        assert_eq!(message.answers.len(), 5);
+        assert_eq!(message.authoritys.len(), 4);
+
+        assert_eq!(message.answers[1].ttl, Duration::ZERO);
+        assert_eq!(message.answers[2].class, Class::None);
+        assert_eq!(message.answers[2].resource, Resource::Empty(Type::CNAME));
+        assert_eq!(message.authoritys[2].class, Class::Any);
+        assert_eq!(message.authoritys[2].resource, Resource::Empty(Type::ANY));
+        assert_eq!(message.authoritys[3].class, Class::None);
+
+        // The empty records survive the trip over the wire.
+        let got = Message::from_slice(&message.to_vec().unwrap()).unwrap();
+        assert_eq!(got.answers[0].resource, Resource::Empty(Type::A));
+        assert_eq!(got.answers[2].class, Class::None);
+        assert_eq!(got.answers[2].resource, Resource::Empty(Type::CNAME));
+        assert_eq!(got.authoritys[1].resource, Resource::Empty(Type::AAAA));
+        assert_eq!(got.authoritys[3].class, Class::None);
+        assert_eq!(got.authoritys[3].resource, message.authoritys[3].resource);
+    }
+
+    #[test]
+    fn test_add() {
+        let authority = authority();
+
+        let update = Update::new("example.com")
+            .add(a("new.example.com", "192.0.2.20"))
+            .add(a("www.example.com", "192.0.2.12"));
+        assert_eq!(send(&authority, update), Rcode::NoError);
+
+        assert_eq!(
+            lookup(&authority, "new.example.com", Type::A),
+            ["192.0.2.20"]
+        );
+        assert_eq!(
+            lookup(&authority, "www.example.com", Type::A),
+            ["192.0.2.10", "192.0.2.11", "192.0.2.12"]
+        );
+        assert_eq!(serial(&authority), 2);
+
+        // Adding a existing record changes nothing.
+        let update = Update::new("example.com").add(a("new.example.com", "192.0.2.20"));
+        assert_eq!(send(&authority, update), Rcode::NoError);
+        assert_eq!(serial(&authority), 2);
+    }
+
+    #[test]
+    fn test_delete() {
+        let authority = authority();
+
+        let update = Update::new("example.com")
+            .delete(a("www.example.com", "192.0.2.11"))
+            .delete_rrset("www.example.com", Type::AAAA)
+            .delete_name("alias.example.com");
+        assert_eq!(send(&authority, update), Rcode::NoError);
+
+        assert_eq!(
+            lookup(&authority, "www.example.com", Type::A),
+            ["192.0.2.10"]
+        );
+        assert_eq!(lookup(&authority, "www.example.com", Type::AAAA), [""; 0]);
+        assert!(authority.zone().records("alias.example.com").is_none());
+        assert_eq!(serial(&authority), 2);
+
+        // The SOA and NS records at the apex are kept, and the last NS.
+        let update = Update::new("example.com")
+            .delete_name("example.com")
+            .delete_rrset("example.com", Type::NS)
+            .delete(record(
+                "example.com",
+                Resource::NS("ns1.example.com".into()),
+            ));
+        assert_eq!(send(&authority, update), Rcode::NoError);
+        assert_eq!(
+            lookup(&authority, "example.com", Type::NS),
+            ["ns2.example.com"]
+        );
+
+        let update = Update::new("example.com").delete(record(
+            "example.com",
+            Resource::NS("ns2.example.com".into()),
+        ));
+        assert_eq!(send(&authority, update), Rcode::NoError);
+        assert_eq!(
+            lookup(&authority, "example.com", Type::NS),
+            ["ns2.example.com"]
+        );
+        assert_eq!(serial(&authority), 3);
+    }
+
+    #[test]
+    fn test_cname_conflicts() {
+        let authority = authority();
+
+        // Neither a CNAME next to other records, nor other records next to
+        // a CNAME are added.
+        let update = Update::new("example.com")
+            .add(record(
+                "www.example.com",
+                Resource::CNAME("ns1.example.com".into()),
+            ))
+            .add(a("alias.example.com", "192.0.2.20"));
+        assert_eq!(send(&authority, update), Rcode::NoError);
+        assert_eq!(lookup(&authority, "www.example.com", Type::CNAME), [""; 0]);
+        assert_eq!(lookup(&authority, "alias.example.com", Type::A), [""; 0]);
+        assert_eq!(serial(&authority), 1);
+
+        // But a CNAME replaces a CNAME.
+        let update = Update::new("example.com").add(record(
+            "alias.example.com",
+            Resource::CNAME("ns1.example.com".into()),
+        ));
+        assert_eq!(send(&authority, update), Rcode::NoError);
+        assert_eq!(
+            lookup(&authority, "alias.example.com", Type::CNAME),
+            ["ns1.example.com."]
+        );
+    }
+
+    #[test]
+    fn test_prerequisites() {
+        let authority = authority();
+        let add = || Update::new("example.com").add(a("new.example.com", "192.0.2.20"));
+
+        for (update, rcode) in [
+            (
+                add().require_exists("www.example.com", Type::MX),
+                Rcode::NXRRSet,
+            ),
+            (
+                add().require_absent("www.example.com", Type::A),
+                Rcode::YXRRSet,
+            ),
+            (
+                add().require_name_in_use("nothing.example.com"),
+                Rcode::NXDomain,
+            ),
+            (
+                add().require_name_not_in_use("www.example.com"),
+                Rcode::YXDomain,
+            ),
+            // The whole RRset must match.
+            (
+                add().require(a("www.example.com", "192.0.2.10")),
+                Rcode::NXRRSet,
+            ),
+            (
+                add()
+                    .require(a("www.example.com", "192.0.2.10"))
+                    .require(a("www.example.com", "192.0.2.11"))
+                    .require(a("www.example.com", "192.0.2.12")),
+                Rcode::NXRRSet,
+            ),
+            (
+                add().require_exists("www.example.net", Type::A),
+                Rcode::NotZone,
+            ),
+        ] {
+            assert_eq!(send(&authority, update), rcode);
+        }
+
+        // Nothing was changed.
+        assert!(authority.zone().records("new.example.com").is_none());
+        assert_eq!(serial(&authority), 1);
+
+        let update = add()
+            .require_exists("www.example.com", Type::A)
+            .require_absent("www.example.com", Type::MX)
+            .require_name_in_use("alias.example.com")
+            .require_name_not_in_use("new.example.com")
+            .require(a("www.example.com", "192.0.2.11"))
+            .require(a("WWW.example.com.", "192.0.2.10"))
+            .require(record(
+                "alias.example.com",
+                Resource::CNAME("www.example.com".into()),
+            ));
+        assert_eq!(send(&authority, update), Rcode::NoError);
+        assert_eq!(
+            lookup(&authority, "new.example.com", Type::A),
+            ["192.0.2.20"]
+        );
+    }
+
+    #[test]
+    fn test_atomic() {
+        let authority = authority();
+
+        // The second change is outside the zone, so neither is made.
+        let update = Update::new("example.com")
+            .add(a("new.example.com", "192.0.2.20"))
+            .add(a("www.example.net", "192.0.2.20"));
+        assert_eq!(send(&authority, update), Rcode::NotZone);
+        assert!(authority.zone().records("new.example.com").is_none());
+        assert_eq!(serial(&authority), 1);
+    }
+
+    #[test]
+    fn test_serial() {
+        let authority = authority();
+
+        // A newer SOA sets the serial.
+        let update = Update::new("example.com")
+            .add(soa(100))
+            .add(a("new.example.com", "192.0.2.20"));
+        assert_eq!(send(&authority, update), Rcode::NoError);
+        assert_eq!(serial(&authority), 100);
+
+        // A older one is ignored.
+        let update = Update::new("example.com").add(soa(1));
+        assert_eq!(send(&authority, update), Rcode::NoError);
+        assert_eq!(serial(&authority), 100);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let authority = authority();
+
+        // Another zone.
+        let update = Update::new("example.net").add(a("www.example.net", "192.0.2.20"));
+        assert_eq!(send(&authority, update), Rcode::NotAuth);
+
+        // Prerequisites must have a zero TTL.
+        let mut message = Update::new("example.com").build();
+        message.answers.push(a("www.example.com", "192.0.2.10"));
+        let request = request(message, Protocol::Udp);
+        assert_eq!(authority.handle(&request).unwrap().rcode, Rcode::FormErr);
+
+        // Only allowed clients can update.
+        let request = Request {
+            src: "192.0.2.99:1234".parse().unwrap(),
+            ..request
+        };
+        assert_eq!(authority.handle(&request).unwrap().rcode, Rcode::Refused);
+        let authority = common::authority(ZONE);
+        let update = Update::new("example.com").add(a("new.example.com", "192.0.2.20"));
+        assert_eq!(send(&authority, update), Rcode::Refused);
+    }
+
+    #[test]
+    #[cfg(feature = "udp")]
+    fn test_udp() {
+        use rustdns::clients::udp;
+        use rustdns::clients::Exchanger;
+        use rustdns::server::udp::Server;
+        use std::thread;
+
+        let server = Server::bind("127.0.0.1:0", authority()).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+
+        let client = udp::Client::new(addr).unwrap();
+        let update = Update::new("example.com")
+            .require_name_not_in_use("new.example.com")
+            .add(a("new.example.com", "192.0.2.20"))
+            .build();
+        let resp = client.exchange(&update).unwrap();
+        assert_eq!(resp.id, update.id);
+        assert_eq!(resp.rcode, Rcode::NoError);
+
+        let mut query = Message::default();
+        query.add_question("new.example.com", Type::A, Class::Internet);
+        let resp = client.exchange(&query).unwrap();
+        assert_eq!(resp.answers, vec![a("new.example.com.", "192.0.2.20")]);
+
+        // The second time, the prerequisite fails.
+        let resp = client.exchange(&update).unwrap();
+        assert_eq!(resp.rcode, Rcode::YXDomain);
+    }
+}